@@ -0,0 +1,201 @@
+use std::{collections::BTreeMap, io::Write, time::Duration};
+
+use anyhow::Result;
+
+use crate::{
+    record::LogRecord,
+    sink::{Entry, OutputWriter, Sink},
+    stats,
+};
+
+/// How the requests are aggregated: the length of the time buckets and the percentiles of the
+/// duration reported for each bucket.
+#[derive(Clone, Debug)]
+pub struct Aggregation {
+    pub period: Duration,
+    pub percentiles: Vec<f64>,
+}
+
+/// The format of the aggregated rows.
+#[derive(Clone, Copy, Debug)]
+pub enum Format {
+    Csv,
+    Influx,
+    Prometheus,
+}
+
+/// The requests of a bucket, by host and status class.
+type Key = (i64, String, &'static str);
+
+#[derive(Default)]
+struct Bucket {
+    count: u64,
+    durations: Vec<f64>,
+}
+
+/// Write one row per time bucket, host and status class with the number of requests and the
+/// duration percentiles, instead of one row per request.
+///
+/// A bucket is written once a record of two buckets later is read, so that the records slightly
+/// out of order are still counted. The remaining buckets are written when the input ends.
+pub struct AggregateSink {
+    writer: Box<dyn OutputWriter>,
+    format: Format,
+    aggregation: Aggregation,
+    buckets: BTreeMap<Key, Bucket>,
+    header: bool,
+}
+
+impl AggregateSink {
+    pub fn new(writer: Box<dyn OutputWriter>, format: Format, aggregation: Aggregation) -> Self {
+        Self {
+            writer,
+            format,
+            aggregation,
+            buckets: BTreeMap::new(),
+            header: false,
+        }
+    }
+
+    /// The start of the bucket of the timestamp, in seconds.
+    fn bucket(&self, timestamp: f64) -> i64 {
+        let period = self.aggregation.period.as_secs_f64();
+        ((timestamp / period).floor() * period) as i64
+    }
+
+    /// Write the buckets which started before the given time.
+    fn flush_before(&mut self, start: i64) -> Result<()> {
+        let pending = self.buckets.split_off(&(start, String::new(), ""));
+        let closed = std::mem::replace(&mut self.buckets, pending);
+        for (key, mut bucket) in closed {
+            bucket.durations.sort_by(f64::total_cmp);
+            self.write_row(&key, &bucket)?;
+        }
+        Ok(())
+    }
+
+    fn write_row(&mut self, (start, host, class): &Key, bucket: &Bucket) -> Result<()> {
+        let percentiles = self
+            .aggregation
+            .percentiles
+            .iter()
+            .map(|&p| (p, stats::percentile_of(&bucket.durations, p)))
+            .collect::<Vec<_>>();
+        match self.format {
+            Format::Csv => {
+                if !self.header {
+                    let mut header = "time,host,status,count".to_string();
+                    for (p, _) in &percentiles {
+                        header.push_str(&format!(",p{p}"));
+                    }
+                    writeln!(self.writer, "{header}")?;
+                    self.header = true;
+                }
+                let time = time::OffsetDateTime::from_unix_timestamp(*start)?
+                    .format(&time::format_description::well_known::Rfc3339)?;
+                let mut row = format!("{time},{},{class},{}", csv_field(host), bucket.count);
+                for (_, value) in &percentiles {
+                    row.push(',');
+                    if let Some(value) = value {
+                        row.push_str(&value.to_string());
+                    }
+                }
+                writeln!(self.writer, "{row}")?;
+            }
+            Format::Influx => {
+                let mut fields = format!("count={}i", bucket.count);
+                for (p, value) in &percentiles {
+                    if let Some(value) = value {
+                        fields.push_str(&format!(
+                            ",p{}={}",
+                            p.to_string().replace('.', "_"),
+                            value
+                        ));
+                    }
+                }
+                writeln!(
+                    self.writer,
+                    "caddy_requests,host={},status={class} {fields} {}",
+                    influx_tag(host),
+                    start * 1_000_000_000
+                )?;
+            }
+            Format::Prometheus => {
+                let host = host.replace('\\', "\\\\").replace('"', "\\\"");
+                let millis = start * 1000;
+                writeln!(
+                    self.writer,
+                    "caddy_requests{{host=\"{host}\",status=\"{class}\"}} {} {millis}",
+                    bucket.count
+                )?;
+                for (p, value) in &percentiles {
+                    if let Some(value) = value {
+                        writeln!(
+                            self.writer,
+                            "caddy_request_duration_seconds{{host=\"{host}\",status=\"{class}\",quantile=\"{}\"}} {value} {millis}",
+                            p / 100.0
+                        )?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Sink for AggregateSink {
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        let record = entry.record()?;
+        let Some(request) = &record.request else {
+            return Ok(());
+        };
+        let start = self.bucket(record.timestamp);
+        let period = self.aggregation.period.as_secs().max(1) as i64;
+        self.flush_before(start - period)?;
+
+        let key = (start, request.host.clone(), status_class(record));
+        let bucket = self.buckets.entry(key).or_default();
+        bucket.count += 1;
+        if let Some(duration) = record.duration {
+            bucket.durations.push(duration);
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.flush_before(i64::MAX)?;
+        self.writer.finish()?;
+        Ok(())
+    }
+
+    fn is_live(&self) -> bool {
+        false
+    }
+}
+
+pub fn status_class(record: &LogRecord) -> &'static str {
+    match record.status.map(|status| status.as_u16() / 100) {
+        Some(1) => "1xx",
+        Some(2) => "2xx",
+        Some(3) => "3xx",
+        Some(4) => "4xx",
+        Some(5) => "5xx",
+        _ => "none",
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn influx_tag(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
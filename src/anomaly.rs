@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use http::header::{HeaderName, USER_AGENT};
+
+use crate::record::{LogRecord, LogRequest};
+
+/// A rolling baseline of the latency of each path, the requests much slower than the usual
+/// ones of their path are reported as anomalies.
+///
+/// The mean and the variance are exponentially weighted, so the baseline follows the slow
+/// changes of the latency while the sudden ones stand out.
+pub struct LatencyBaseline {
+    sigmas: f64,
+    paths: HashMap<String, Ewma>,
+}
+
+/// A request slower than the baseline of its path.
+pub struct Anomaly {
+    /// The distance from the mean, in standard deviations.
+    pub deviation: f64,
+    /// The mean duration of the path, in seconds.
+    pub baseline: f64,
+}
+
+#[derive(Default)]
+struct Ewma {
+    mean: f64,
+    variance: f64,
+    samples: u64,
+}
+
+impl LatencyBaseline {
+    /// The weight of each new sample.
+    const ALPHA: f64 = 0.05;
+    /// The samples required before the baseline of a path is trusted.
+    const WARMUP: u64 = 20;
+    /// The paths tracked at most, the new ones are ignored once it is reached.
+    const MAX_PATHS: usize = 10_000;
+
+    pub fn new(sigmas: f64) -> Self {
+        Self {
+            sigmas,
+            paths: HashMap::new(),
+        }
+    }
+
+    /// Compare the duration of the request with the baseline of its path, then update the
+    /// baseline with it.
+    pub fn observe(&mut self, record: &LogRecord) -> Option<Anomaly> {
+        let (request, duration) = (record.request.as_ref()?, record.duration?);
+        let path = request.uri.split('?').next().unwrap_or_default();
+        if !self.paths.contains_key(path) && self.paths.len() >= Self::MAX_PATHS {
+            return None;
+        }
+        let ewma = self.paths.entry(path.to_string()).or_default();
+
+        let anomaly = (ewma.samples >= Self::WARMUP)
+            .then(|| {
+                // a floor on the deviation, the steady paths would flag any small change
+                let stddev = ewma.variance.sqrt().max(ewma.mean * 0.1).max(0.001);
+                let deviation = (duration - ewma.mean) / stddev;
+                (deviation >= self.sigmas).then_some(Anomaly {
+                    deviation,
+                    baseline: ewma.mean,
+                })
+            })
+            .flatten();
+        ewma.update(duration);
+        anomaly
+    }
+}
+
+impl Ewma {
+    fn update(&mut self, value: f64) {
+        if self.samples == 0 {
+            self.mean = value;
+        } else {
+            let diff = value - self.mean;
+            let increment = LatencyBaseline::ALPHA * diff;
+            self.mean += increment;
+            self.variance = (1.0 - LatencyBaseline::ALPHA) * (self.variance + diff * increment);
+        }
+        self.samples += 1;
+    }
+}
+
+/// The usual values of the request headers, learned from the requests read so far. A request
+/// is unusual when it has a rare value of a header whose values are mostly the same, e.g. an
+/// odd `Accept-Encoding`, or when it misses a header sent by almost all the others.
+///
+/// The user agents are compared by their product only, e.g. `Mozilla` or `curl`.
+#[derive(Default)]
+pub struct HeaderBaseline {
+    requests: u64,
+    headers: HashMap<HeaderName, ValueCounts>,
+}
+
+/// A header of a request unlike the ones of the other requests.
+pub struct UnusualHeader {
+    pub name: HeaderName,
+    /// The value of the request, none when the header is missing.
+    pub value: Option<String>,
+    /// The most common value of the header.
+    pub usual: String,
+}
+
+#[derive(Default)]
+struct ValueCounts {
+    requests: u64,
+    values: HashMap<String, u64>,
+    usual: (String, u64),
+}
+
+impl HeaderBaseline {
+    /// The requests read before the baseline is trusted.
+    const WARMUP: u64 = 100;
+    /// The share of the requests the usual value must have, the headers with a wide range of
+    /// values, like the cookies and the identifiers, are never unusual.
+    const DOMINANT: f64 = 0.5;
+    /// The share of the requests below which a value is unusual.
+    const RARE: f64 = 0.01;
+    /// The share of the requests with the header above which its absence is unusual.
+    const PRESENT: f64 = 0.95;
+    /// The headers and the values of each header tracked at most, the new ones are ignored
+    /// once they are reached.
+    const MAX_HEADERS: usize = 64;
+    const MAX_VALUES: usize = 1_000;
+
+    /// Compare the headers of the request with the usual ones, then update the baseline with
+    /// them.
+    pub fn observe(&mut self, record: &LogRecord) -> Vec<UnusualHeader> {
+        let Some(request) = &record.request else {
+            return Vec::new();
+        };
+        let mut unusual = Vec::new();
+        if self.requests >= Self::WARMUP {
+            let requests = self.requests as f64;
+            for (name, counts) in &self.headers {
+                let Some((usual, count)) = counts.usual() else {
+                    continue;
+                };
+                let value = header_value(request, name);
+                let rare = match &value {
+                    None => counts.requests as f64 / requests >= Self::PRESENT,
+                    Some(value) => {
+                        let share = |count| count as f64 / counts.requests as f64;
+                        share(count) >= Self::DOMINANT
+                            && share(counts.values.get(value).copied().unwrap_or(0)) < Self::RARE
+                    }
+                };
+                if rare {
+                    unusual.push(UnusualHeader {
+                        name: name.clone(),
+                        value,
+                        usual: usual.to_string(),
+                    });
+                }
+            }
+            unusual.sort_by(|a, b| a.name.as_str().cmp(b.name.as_str()));
+        }
+        self.requests += 1;
+        for name in request.headers.keys() {
+            if !self.headers.contains_key(name) && self.headers.len() >= Self::MAX_HEADERS {
+                continue;
+            }
+            if let Some(value) = header_value(request, name) {
+                self.headers.entry(name.clone()).or_default().count(value);
+            }
+        }
+        unusual
+    }
+}
+
+impl ValueCounts {
+    fn count(&mut self, value: String) {
+        self.requests += 1;
+        if !self.values.contains_key(&value) && self.values.len() >= HeaderBaseline::MAX_VALUES {
+            return;
+        }
+        let count = self.values.entry(value.clone()).or_default();
+        *count += 1;
+        if *count > self.usual.1 {
+            self.usual = (value, *count);
+        }
+    }
+
+    fn usual(&self) -> Option<(&str, u64)> {
+        let (value, count) = &self.usual;
+        (*count > 0).then_some((value.as_str(), *count))
+    }
+}
+
+/// The values of the header joined by commas, only the product of the user agents.
+fn header_value(request: &LogRequest, name: &HeaderName) -> Option<String> {
+    let values = request
+        .headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>();
+    if values.is_empty() {
+        return None;
+    }
+    let value = values.join(", ");
+    if name == USER_AGENT {
+        let product = value.split(['/', ' ']).next().unwrap_or_default();
+        return Some(product.to_string());
+    }
+    Some(value)
+}
@@ -0,0 +1,298 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    expr::Expr,
+    record::LogRecord,
+    sink::{Entry, Sink},
+    stats, units,
+};
+
+/// A property of the matched records checked when the input ends, e.g. `p95(duration) < 500ms`
+/// or `count(status >= 500) == 0`. The aggregates are:
+///
+/// - `count()` and `count(CONDITION)`: the number of records, or of the ones satisfying the
+///   condition;
+/// - `rate(CONDITION)`: the share of the records satisfying the condition, e.g. `1%`;
+/// - `p50(FIELD)`, `p95(FIELD)` or any other percentile, `avg(FIELD)`, `min(FIELD)` and
+///   `max(FIELD)` of `duration` or `size`, compared with a duration like `500ms` or a size
+///   like `1MB`.
+pub struct Assertion {
+    text: String,
+    aggregate: Aggregate,
+    op: Op,
+    threshold: f64,
+}
+
+enum Aggregate {
+    Count(Option<Expr>),
+    Rate(Expr),
+    Percentile(f64, Field),
+    Avg(Field),
+    Min(Field),
+    Max(Field),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    Duration,
+    Size,
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl FromStr for Assertion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (aggregate, rest) = parse_call(s.trim())?;
+        let rest = rest.trim_start();
+        let (op, threshold) = [
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ]
+        .into_iter()
+        .find_map(|(symbol, op)| Some((op, rest.strip_prefix(symbol)?)))
+        .context("expected a comparison, one of <, <=, >, >=, == or !=")?;
+        let threshold = threshold.trim();
+        let threshold = match aggregate.field() {
+            Some(Field::Duration) => parse_number(threshold).map_or_else(
+                || units::parse_duration(threshold).map(|duration| duration.as_secs_f64()),
+                Ok,
+            )?,
+            Some(Field::Size) => units::parse_size(threshold)? as f64,
+            None => match threshold.strip_suffix('%') {
+                Some(percent) => parse_number(percent.trim()).map(|percent| percent / 100.0),
+                None => parse_number(threshold),
+            }
+            .with_context(|| format!("invalid number: {}", threshold))?,
+        };
+        Ok(Self {
+            text: s.trim().to_string(),
+            aggregate,
+            op,
+            threshold,
+        })
+    }
+}
+
+/// Parse the aggregate at the start of the assertion, the rest of the text is returned.
+fn parse_call(s: &str) -> Result<(Aggregate, &str)> {
+    let open = s
+        .find('(')
+        .context("expected an aggregate, e.g. p95(duration)")?;
+    let name = s[..open].trim();
+    // the conditions can have parentheses and strings of their own
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut close = None;
+    for (index, c) in s[open..].char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(open + index);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    let close = close.context("unbalanced parentheses")?;
+    let argument = s[open + 1..close].trim();
+    let condition = || {
+        argument
+            .parse::<Expr>()
+            .with_context(|| format!("invalid condition: {}", argument))
+    };
+    let field = || match argument {
+        "duration" => Ok(Field::Duration),
+        "size" => Ok(Field::Size),
+        _ => bail!("invalid field: {}, expected duration or size", argument),
+    };
+    let aggregate = match name {
+        "count" if argument.is_empty() => Aggregate::Count(None),
+        "count" => Aggregate::Count(Some(condition()?)),
+        "rate" => Aggregate::Rate(condition()?),
+        "avg" => Aggregate::Avg(field()?),
+        "min" => Aggregate::Min(field()?),
+        "max" => Aggregate::Max(field()?),
+        _ => match name.strip_prefix('p').map(stats::parse_percentile) {
+            Some(Ok(percentile)) => Aggregate::Percentile(percentile, field()?),
+            _ => bail!(
+                "unknown aggregate: {}, expected count, rate, avg, min, max or a percentile",
+                name
+            ),
+        },
+    };
+    Ok((aggregate, &s[close + 1..]))
+}
+
+fn parse_number(s: &str) -> Option<f64> {
+    s.parse::<f64>().ok().filter(|number| number.is_finite())
+}
+
+impl Aggregate {
+    fn field(&self) -> Option<Field> {
+        match self {
+            Self::Percentile(_, field) | Self::Avg(field) | Self::Min(field) | Self::Max(field) => {
+                Some(*field)
+            }
+            Self::Count(_) | Self::Rate(_) => None,
+        }
+    }
+
+    /// Render a value of the aggregate, with its unit.
+    fn format(&self, value: f64) -> String {
+        match (self, self.field()) {
+            (_, Some(Field::Duration)) => LogRecord::format_duration(value).trim().to_string(),
+            (_, Some(Field::Size)) => units::format_size(value),
+            (Self::Rate(_), _) => format!("{:.2}%", value * 100.0),
+            _ => value.to_string(),
+        }
+    }
+}
+
+impl Op {
+    fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Self::Lt => value < threshold,
+            Self::Le => value <= threshold,
+            Self::Gt => value > threshold,
+            Self::Ge => value >= threshold,
+            Self::Eq => value == threshold,
+            Self::Ne => value != threshold,
+        }
+    }
+}
+
+/// Check the assertions over the matched records when the input ends, the run fails when any
+/// of them does not hold. The report is written on the standard error.
+pub struct AssertSink {
+    assertions: Vec<Assertion>,
+    records: u64,
+    /// The records satisfying the condition of each assertion, when it has one.
+    satisfied: Vec<u64>,
+    /// The values of the fields, collected only when an assertion needs them.
+    durations: Option<Vec<f64>>,
+    sizes: Option<Vec<f64>>,
+}
+
+impl AssertSink {
+    pub fn new(assertions: Vec<Assertion>) -> Self {
+        let needs = |field| {
+            assertions
+                .iter()
+                .any(|assertion| assertion.aggregate.field() == Some(field))
+        };
+        Self {
+            satisfied: vec![0; assertions.len()],
+            durations: needs(Field::Duration).then(Vec::new),
+            sizes: needs(Field::Size).then(Vec::new),
+            assertions,
+            records: 0,
+        }
+    }
+
+    /// The value of the aggregate of an assertion, none when there are no values, e.g. the
+    /// percentiles of no durations. The values are sorted.
+    fn value(&self, index: usize) -> Option<f64> {
+        let values = |field| {
+            let values = match field {
+                Field::Duration => &self.durations,
+                Field::Size => &self.sizes,
+            };
+            values.as_deref().unwrap_or_default()
+        };
+        match &self.assertions[index].aggregate {
+            Aggregate::Count(None) => Some(self.records as f64),
+            Aggregate::Count(Some(_)) => Some(self.satisfied[index] as f64),
+            Aggregate::Rate(_) => {
+                (self.records > 0).then(|| self.satisfied[index] as f64 / self.records as f64)
+            }
+            Aggregate::Percentile(percentile, field) => {
+                stats::percentile_of(values(*field), *percentile)
+            }
+            Aggregate::Avg(field) => {
+                let values = values(*field);
+                (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+            }
+            Aggregate::Min(field) => values(*field).first().copied(),
+            Aggregate::Max(field) => values(*field).last().copied(),
+        }
+    }
+}
+
+impl Sink for AssertSink {
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        let record = entry.record()?;
+        self.records += 1;
+        for (assertion, satisfied) in self.assertions.iter().zip(&mut self.satisfied) {
+            let condition = match &assertion.aggregate {
+                Aggregate::Count(Some(condition)) | Aggregate::Rate(condition) => condition,
+                _ => continue,
+            };
+            if condition.matches(record) {
+                *satisfied += 1;
+            }
+        }
+        if let (Some(durations), Some(duration)) = (&mut self.durations, record.duration) {
+            durations.push(duration);
+        }
+        if let (Some(sizes), Some(size)) = (&mut self.sizes, record.size) {
+            sizes.push(size as f64);
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for values in [&mut self.durations, &mut self.sizes].into_iter().flatten() {
+            values.sort_by(f64::total_cmp);
+        }
+        let mut failed = 0;
+        for (index, assertion) in self.assertions.iter().enumerate() {
+            let value = self.value(index);
+            let holds = value.is_some_and(|value| assertion.op.holds(value, assertion.threshold));
+            let actual = match value {
+                Some(value) => assertion.aggregate.format(value),
+                None => "no values".to_string(),
+            };
+            let result = match holds {
+                true => "PASS",
+                false => "FAIL",
+            };
+            eprintln!("{result}  {}  (actual: {actual})", assertion.text);
+            if !holds {
+                failed += 1;
+            }
+        }
+        if failed > 0 {
+            bail!(
+                "{} of {} assertions failed over {} records",
+                failed,
+                self.assertions.len(),
+                self.records
+            );
+        }
+        Ok(())
+    }
+
+    fn is_live(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,157 @@
+use std::{
+    sync::mpsc::{self, RecvTimeoutError, SyncSender},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+
+/// Send the items in batches from a background thread. A batch is sent when it is full, or when
+/// its first item has been waiting for the timeout, even if no other item comes, so that following
+/// a quiet log does not hold the items back.
+pub struct Batcher<T> {
+    sender: Option<SyncSender<T>>,
+    worker: Option<JoinHandle<u64>>,
+}
+
+impl<T: Send + 'static> Batcher<T> {
+    pub fn new<F>(size: usize, timeout: Duration, send: F) -> Self
+    where
+        F: FnMut(Vec<T>) -> Result<()> + Send + 'static,
+    {
+        // the records are read no faster than the batches are sent
+        let (sender, receiver) = mpsc::sync_channel(size);
+        let worker = std::thread::spawn(move || {
+            let mut send = send;
+            let mut dropped = 0;
+            let mut flush = |batch: &mut Vec<T>| {
+                if batch.is_empty() {
+                    return;
+                }
+                let batch = std::mem::replace(batch, Vec::with_capacity(size));
+                let count = batch.len() as u64;
+                // the failure is reported once, to not flood the output
+                if let Err(err) = send(batch) {
+                    if dropped == 0 {
+                        eprintln!("{err:#}");
+                    }
+                    dropped += count;
+                }
+            };
+            let mut batch = Vec::with_capacity(size);
+            let mut deadline: Option<Instant> = None;
+            loop {
+                let item = match deadline {
+                    Some(deadline) => {
+                        receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                    }
+                    None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                match item {
+                    Ok(item) => {
+                        deadline.get_or_insert_with(|| Instant::now() + timeout);
+                        batch.push(item);
+                        if batch.len() >= size {
+                            flush(&mut batch);
+                            deadline = None;
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {
+                        flush(&mut batch);
+                        deadline = None;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => {
+                        flush(&mut batch);
+                        break;
+                    }
+                }
+            }
+            dropped
+        });
+        Self {
+            sender: Some(sender),
+            worker: Some(worker),
+        }
+    }
+
+    pub fn push(&self, item: T) -> Result<()> {
+        let sender = self.sender.as_ref().expect("the batcher is finished");
+        sender
+            .send(item)
+            .map_err(|_| anyhow!("the batches are no longer sent"))
+    }
+
+    /// Send the last batch, and wait for it. The number of the items not sent is returned.
+    pub fn finish(&mut self) -> u64 {
+        self.sender.take();
+        self.worker
+            .take()
+            .map_or(0, |worker| worker.join().unwrap_or(0))
+    }
+}
+
+impl<T> Drop for Batcher<T> {
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::bail;
+
+    use super::*;
+
+    type Batches = Arc<Mutex<Vec<Vec<u32>>>>;
+
+    fn batcher(size: usize, timeout: Duration) -> (Batcher<u32>, Batches) {
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let sent = batches.clone();
+        let batcher = Batcher::new(size, timeout, move |batch| {
+            sent.lock().unwrap().push(batch);
+            Ok(())
+        });
+        (batcher, batches)
+    }
+
+    #[test]
+    fn send_the_full_batches() {
+        let (mut batcher, batches) = batcher(2, Duration::from_secs(60));
+        for item in 0..5 {
+            batcher.push(item).unwrap();
+        }
+        assert_eq!(batcher.finish(), 0);
+        assert_eq!(*batches.lock().unwrap(), [vec![0, 1], vec![2, 3], vec![4]]);
+    }
+
+    #[test]
+    fn send_the_waiting_items_without_new_ones() {
+        let (mut batcher, batches) = batcher(100, Duration::from_millis(50));
+        batcher.push(1).unwrap();
+        batcher.push(2).unwrap();
+        std::thread::sleep(Duration::from_millis(500));
+        assert_eq!(*batches.lock().unwrap(), [vec![1, 2]]);
+        batcher.push(3).unwrap();
+        assert_eq!(batcher.finish(), 0);
+        assert_eq!(*batches.lock().unwrap(), [vec![1, 2], vec![3]]);
+    }
+
+    #[test]
+    fn count_the_items_not_sent() {
+        let mut batcher = Batcher::new(2, Duration::from_secs(60), |batch: Vec<u32>| {
+            match batch.contains(&0) {
+                true => bail!("refused"),
+                false => Ok(()),
+            }
+        });
+        for item in 0..5 {
+            batcher.push(item).unwrap();
+        }
+        assert_eq!(batcher.finish(), 2);
+    }
+}
@@ -0,0 +1,101 @@
+use crate::record::LogRecord;
+
+/// Parts of the user agents of the crawlers of the search engines, of the bots of the social
+/// networks and of the AI companies, and of the well-known scanners. They are lowercase.
+const KNOWN: &[&str] = &[
+    // search engines
+    "googlebot",
+    "google-inspectiontool",
+    "storebot-google",
+    "adsbot-google",
+    "mediapartners-google",
+    "bingbot",
+    "bingpreview",
+    "slurp",
+    "duckduckbot",
+    "baiduspider",
+    "yandex",
+    "sogou",
+    "exabot",
+    "seznambot",
+    "qwantify",
+    "petalbot",
+    "applebot",
+    // SEO tools
+    "ahrefsbot",
+    "semrushbot",
+    "mj12bot",
+    "dotbot",
+    "rogerbot",
+    "dataforseobot",
+    "serpstatbot",
+    "blexbot",
+    "screaming frog",
+    // social networks and previews
+    "facebookexternalhit",
+    "facebookcatalog",
+    "twitterbot",
+    "linkedinbot",
+    "slackbot",
+    "discordbot",
+    "telegrambot",
+    "whatsapp",
+    "pinterestbot",
+    "redditbot",
+    "embedly",
+    // AI crawlers
+    "gptbot",
+    "chatgpt-user",
+    "oai-searchbot",
+    "claudebot",
+    "claude-web",
+    "anthropic-ai",
+    "ccbot",
+    "perplexitybot",
+    "bytespider",
+    "amazonbot",
+    "cohere-ai",
+    "diffbot",
+    "imagesiftbot",
+    // archives
+    "ia_archiver",
+    "archive.org_bot",
+    // scanners
+    "zgrab",
+    "masscan",
+    "nmap",
+    "nikto",
+    "sqlmap",
+    "nuclei",
+    "wpscan",
+    "gobuster",
+    "dirbuster",
+    "censysinspect",
+    "expanse",
+    "internet-measurement",
+    "l9explore",
+    "leakix",
+    "netcraft",
+    "paloaltonetworks",
+    "shodan",
+];
+
+/// The words of the user agents of the other bots, which usually call themselves so.
+const GENERIC: &[&str] = &["bot/", "bot;", "bot)", "crawler", "spider", "scanner"];
+
+/// Whether the request was sent by a crawler, a bot or a scanner, from its user agent.
+pub fn is_bot(record: &LogRecord) -> bool {
+    let Some(user_agent) = record
+        .request
+        .as_ref()
+        .and_then(|request| request.headers.get(http::header::USER_AGENT))
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let user_agent = user_agent.to_lowercase();
+    KNOWN
+        .iter()
+        .chain(GENERIC)
+        .any(|part| user_agent.contains(part))
+}
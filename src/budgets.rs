@@ -0,0 +1,166 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use serde_with::{serde_as, Map};
+
+use crate::{locale::Locale, record::LogRecord, stats::format_table, units};
+
+/// The latency expected for the routes, set by the `budgets` section of the config file, e.g.
+///
+/// ```yaml
+/// budgets:
+///   "/api/*": 300ms
+///   "/search": 1s
+/// ```
+///
+/// The path of a request, without the query, is matched against the routes in order and the
+/// first matching one sets its budget.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(try_from = "BudgetsConfig")]
+pub struct Budgets {
+    routes: Vec<Route>,
+}
+
+#[derive(Clone, Debug)]
+struct Route {
+    pattern: glob::Pattern,
+    /// The budget, in seconds.
+    limit: f64,
+}
+
+/// The routes as written in the config file, in their order.
+#[serde_as]
+#[derive(Deserialize)]
+struct BudgetsConfig(#[serde_as(as = "Map<_, _>")] Vec<(String, String)>);
+
+impl TryFrom<BudgetsConfig> for Budgets {
+    type Error = anyhow::Error;
+
+    fn try_from(config: BudgetsConfig) -> Result<Self> {
+        let routes = config
+            .0
+            .into_iter()
+            .map(|(route, limit)| {
+                let pattern = glob::Pattern::new(&route)
+                    .with_context(|| format!("invalid budget route: {}", route))?;
+                let limit = units::parse_duration(&limit)
+                    .with_context(|| format!("invalid budget of {}: {}", route, limit))?;
+                Ok(Route {
+                    pattern,
+                    limit: limit.as_secs_f64(),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { routes })
+    }
+}
+
+impl Budgets {
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// The index of the route of the request.
+    fn route(&self, record: &LogRecord) -> Option<usize> {
+        let request = record.request.as_ref()?;
+        let path = request.uri.split('?').next().unwrap_or_default();
+        self.routes
+            .iter()
+            .position(|route| route.pattern.matches(path))
+    }
+}
+
+/// A request slower than the budget of its route.
+pub struct Violation<'a> {
+    pub route: &'a str,
+    /// The budget of the route and the excess of the request, in seconds.
+    pub limit: f64,
+    pub excess: f64,
+    /// The violations of the route so far, this one included, and its requests.
+    pub violations: u64,
+    pub requests: u64,
+}
+
+/// Count the requests of each route and the ones over its budget.
+pub struct BudgetChecks {
+    budgets: Budgets,
+    /// The requests and the violations of each route.
+    counts: Vec<(u64, u64)>,
+}
+
+impl BudgetChecks {
+    pub fn new(budgets: Budgets) -> Self {
+        Self {
+            counts: vec![(0, 0); budgets.routes.len()],
+            budgets,
+        }
+    }
+
+    /// Count the request, the violation is returned when it is over the budget.
+    pub fn observe(&mut self, record: &LogRecord) -> Option<Violation<'_>> {
+        let duration = record.duration?;
+        let index = self.budgets.route(record)?;
+        let route = &self.budgets.routes[index];
+        let (requests, violations) = &mut self.counts[index];
+        *requests += 1;
+        if duration <= route.limit {
+            return None;
+        }
+        *violations += 1;
+        Some(Violation {
+            route: route.pattern.as_str(),
+            limit: route.limit,
+            excess: duration - route.limit,
+            violations: *violations,
+            requests: *requests,
+        })
+    }
+
+    fn rows(&self) -> impl Iterator<Item = (&Route, u64, u64)> {
+        self.budgets
+            .routes
+            .iter()
+            .zip(&self.counts)
+            .map(|(route, &(requests, violations))| (route, requests, violations))
+    }
+
+    /// The violations of each route, in the order of the config. Nothing is returned when no
+    /// request has a route.
+    pub fn render(&self, locale: &Locale) -> Option<String> {
+        if self.counts.iter().all(|&(requests, _)| requests == 0) {
+            return None;
+        }
+        let mut rows = vec![vec![
+            "budget route".to_string(),
+            "budget".into(),
+            "requests".into(),
+            "violations".into(),
+            "share".into(),
+        ]];
+        for (route, requests, violations) in self.rows() {
+            let share = 100.0 * violations as f64 / requests.max(1) as f64;
+            rows.push(vec![
+                route.pattern.to_string(),
+                locale.localize(&LogRecord::format_duration(route.limit)),
+                locale.integer(requests),
+                locale.integer(violations),
+                format!("{}%", locale.decimal(share, 1)),
+            ]);
+        }
+        Some(format_table(&rows))
+    }
+
+    /// The violations of each route, the budgets are in seconds.
+    pub fn to_json(&self) -> Value {
+        self.rows()
+            .map(|(route, requests, violations)| {
+                json!({
+                    "route": route.pattern.as_str(),
+                    "budget": route.limit,
+                    "requests": requests,
+                    "violations": violations,
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,122 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+/// The default address of the Caddy admin API.
+pub const DEFAULT_ADMIN: &str = "http://localhost:2019";
+
+/// The locations where the access log is usually found.
+const COMMON_LOCATIONS: &[&str] = &[
+    "/var/log/caddy/access.log",
+    "/var/log/caddy/caddy.log",
+    "/var/log/caddy.log",
+    "/usr/local/var/log/caddy/access.log",
+    "/opt/homebrew/var/log/caddy/access.log",
+];
+
+/// A log configured in Caddy, written to a file.
+#[derive(Clone, Debug)]
+pub struct LogFile {
+    pub name: String,
+    pub path: PathBuf,
+    /// The sites whose access log is written by this log.
+    pub sites: Vec<String>,
+}
+
+impl LogFile {
+    /// A short label of the log, the sites when known or its name.
+    pub fn label(&self) -> String {
+        match self.sites.is_empty() {
+            true => self.name.clone(),
+            false => self.sites.join(","),
+        }
+    }
+}
+
+/// Query the admin API for the logs written to a file.
+pub fn log_files(admin: &str) -> Result<Vec<LogFile>> {
+    let logs = get_json(admin, "/config/logging/logs")?;
+    // the servers can be missing, the sites are then unknown
+    let servers = get_json(admin, "/config/apps/http/servers").unwrap_or(Value::Null);
+    let mut files = Vec::new();
+    if let Value::Object(logs) = logs {
+        for (name, log) in logs {
+            let writer = &log["writer"];
+            if writer["output"] == "file" {
+                if let Some(filename) = writer["filename"].as_str() {
+                    let sites = sites_of(&servers, &name);
+                    files.push(LogFile {
+                        name,
+                        path: PathBuf::from(filename),
+                        sites,
+                    });
+                }
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// The hosts which use the named logger, according to the `logger_names` of the servers. The
+/// older versions of Caddy map each host to a single logger, the newer ones to a list.
+fn sites_of(servers: &Value, logger: &str) -> Vec<String> {
+    let mut sites = Vec::new();
+    let Value::Object(servers) = servers else {
+        return sites;
+    };
+    for server in servers.values() {
+        let Value::Object(names) = &server["logs"]["logger_names"] else {
+            continue;
+        };
+        for (host, names) in names {
+            let uses_logger = match names {
+                Value::String(name) => name == logger,
+                Value::Array(names) => names.iter().any(|name| name == logger),
+                _ => false,
+            };
+            if uses_logger && !sites.contains(host) {
+                sites.push(host.clone());
+            }
+        }
+    }
+    sites
+}
+
+/// Look for the access log, first in the configuration of the local Caddy instance and then in
+/// the common locations. A description of where the file has been found is returned with it.
+pub fn discover() -> Option<(PathBuf, String)> {
+    if let Ok(files) = log_files(DEFAULT_ADMIN) {
+        // the access logs are usually the ones named after the server
+        let file = files
+            .iter()
+            .find(|file| file.name != "default")
+            .or(files.first());
+        if let Some(file) = file {
+            let source = format!("log `{}` of the Caddy admin config", file.name);
+            return Some((file.path.clone(), source));
+        }
+    }
+    COMMON_LOCATIONS
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.is_file())
+        .map(|path| (path, "common location".to_string()))
+}
+
+/// Request a JSON document to the admin API, which is usually local.
+pub fn get_json(base: &str, path: &str) -> Result<Value> {
+    let url = format!("{}{}", base.trim_end_matches('/'), path);
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(2)))
+        .build()
+        .into();
+    let body = agent
+        .get(&url)
+        .header("Accept", "application/json")
+        .call()
+        .with_context(|| format!("failed to request {}", url))?
+        .body_mut()
+        .read_to_string()?;
+    serde_json::from_str(&body).context("invalid JSON response")
+}
@@ -0,0 +1,612 @@
+use std::{
+    io::{BufReader, BufWriter, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+
+use crate::{
+    batch::Batcher,
+    record::{self, LogRecord},
+    sink::{Entry, Sink},
+};
+
+/// Insert the requests into a ClickHouse table, through the HTTP interface or the native protocol.
+/// The rows are sent in batches with the `JSONEachRow` format, the table is created when it does
+/// not exist.
+pub struct ClickHouseSink {
+    batcher: Batcher<Value>,
+}
+
+impl ClickHouseSink {
+    const BATCH_SIZE: usize = 10_000;
+    const BATCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+    const TIMESTAMP: &'static [time::format_description::FormatItem<'static>] = time::macros::format_description!(
+        "[year]-[month]-[day] [hour]:[minute]:[second].[subsecond digits:6]"
+    );
+
+    /// Connect to the server at the given URL, e.g. `http://localhost:8123` for the HTTP interface
+    /// or `tcp://localhost:9000` for the native protocol. The credentials can be given as `user`
+    /// and `password` query parameters.
+    pub fn new(url: &str, table: &str) -> Result<Self> {
+        if table.is_empty()
+            || !table
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        {
+            bail!("invalid ClickHouse table: {}", table);
+        }
+        let client = Client::new(url)?;
+        client
+            .query(&Self::create_table(table), String::new())
+            .with_context(|| format!("failed to create the table {}", table))?;
+        let insert = format!("INSERT INTO {} FORMAT JSONEachRow", table);
+        let table = table.to_string();
+        let batcher = Batcher::new(Self::BATCH_SIZE, Self::BATCH_TIMEOUT, move |rows| {
+            let data = rows
+                .iter()
+                .map(|row| format!("{row}\n"))
+                .collect::<String>();
+            client
+                .query(&insert, data)
+                .with_context(|| format!("failed to insert the rows into {}", table))
+        });
+        Ok(Self { batcher })
+    }
+
+    fn create_table(table: &str) -> String {
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                timestamp DateTime64(6, 'UTC'),
+                level LowCardinality(String),
+                logger LowCardinality(String),
+                host LowCardinality(String),
+                method LowCardinality(String),
+                uri String,
+                proto LowCardinality(String),
+                remote_ip String,
+                remote_port UInt16,
+                user_agent String,
+                status UInt16,
+                duration Float64,
+                size UInt64
+            ) ENGINE = MergeTree
+            PARTITION BY toDate(timestamp)
+            ORDER BY (host, timestamp)",
+            table
+        )
+    }
+
+    fn row(record: &LogRecord) -> Option<Value> {
+        let request = record.request.as_ref()?;
+        let timestamp = record::to_datetime(record.timestamp)?
+            .format(Self::TIMESTAMP)
+            .ok()?;
+        let user_agent = request
+            .headers
+            .get(http::header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        Some(json!({
+            "timestamp": timestamp,
+            "level": format!("{:?}", record.level).to_lowercase(),
+            "logger": record.logger.as_deref().unwrap_or_default(),
+            "host": request.host,
+            "method": request.method.as_str(),
+            "uri": request.uri,
+            "proto": format!("{:?}", request.version),
+            "remote_ip": request.remote_ip.to_string(),
+            "remote_port": request.remote_port.unwrap_or(0),
+            "user_agent": user_agent,
+            "status": record.status.map_or(0, |status| status.as_u16()),
+            "duration": record.duration.unwrap_or(0.0),
+            "size": record.size.unwrap_or(0),
+        }))
+    }
+}
+
+impl Sink for ClickHouseSink {
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        let Some(row) = Self::row(entry.record()?) else {
+            return Ok(());
+        };
+        self.batcher.push(row)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let dropped = self.batcher.finish();
+        if dropped > 0 {
+            eprintln!("{} rows not inserted", dropped);
+        }
+        Ok(())
+    }
+}
+
+enum Client {
+    Http { url: String, agent: ureq::Agent },
+    Native(Native),
+}
+
+impl Client {
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    fn new(url: &str) -> Result<Self> {
+        let scheme = url.split_once("://").map_or("", |(scheme, _)| scheme);
+        let client = match scheme {
+            "http" | "https" => {
+                let agent = ureq::Agent::config_builder()
+                    .timeout_global(Some(Self::TIMEOUT))
+                    .http_status_as_error(false)
+                    .build()
+                    .into();
+                Self::Http {
+                    url: url.to_string(),
+                    agent,
+                }
+            }
+            "tcp" => Self::Native(Native::new(url)?),
+            _ => bail!(
+                "unsupported ClickHouse URL: {}, expected http:// or https:// for the HTTP interface, tcp:// for the native protocol",
+                url
+            ),
+        };
+        Ok(client)
+    }
+
+    /// Run the query, the data is sent after it.
+    fn query(&self, query: &str, data: String) -> Result<()> {
+        match self {
+            Self::Http { url, agent } => {
+                let separator = match url.contains('?') {
+                    true => '&',
+                    false => '?',
+                };
+                let query = form_urlencoded::Serializer::new(String::new())
+                    .append_pair("query", query)
+                    .finish();
+                let url = format!("{url}{separator}{query}");
+                let mut response = agent.post(&url).send(data)?;
+                if !response.status().is_success() {
+                    let message = response.body_mut().read_to_string().unwrap_or_default();
+                    bail!("{}: {}", response.status(), message.trim());
+                }
+                Ok(())
+            }
+            // the data of an insert can follow the query in its text, the server reads it with
+            // the format of the query, without the blocks of the native format
+            Self::Native(native) => match data.is_empty() {
+                true => native.query(query),
+                false => native.query(&format!("{query}\n{data}")),
+            },
+        }
+    }
+}
+
+/// The client of the native protocol, a connection is opened for each query. The revision of the
+/// protocol is an old one, understood by every server, which sends nothing newer than it.
+struct Native {
+    address: String,
+    database: String,
+    user: String,
+    password: String,
+}
+
+impl Native {
+    const REVISION: u64 = 54213;
+    const REVISION_WITH_CLIENT_INFO: u64 = 54032;
+    const REVISION_WITH_SERVER_TIMEZONE: u64 = 54058;
+    const REVISION_WITH_QUOTA_KEY: u64 = 54060;
+
+    const DEFAULT_PORT: u16 = 9000;
+
+    /// The URL is `tcp://host:port`, the database and the credentials are given as `database`,
+    /// `user` and `password` query parameters, as for the HTTP interface.
+    fn new(url: &str) -> Result<Self> {
+        let rest = url.strip_prefix("tcp://").unwrap_or(url);
+        let (address, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let address = address.trim_end_matches('/');
+        if address.is_empty() || address.contains('/') {
+            bail!("invalid ClickHouse URL: {}, expected tcp://host:port", url);
+        }
+        let has_port = match address.rsplit_once(':') {
+            Some((host, _)) => !host.starts_with('[') || host.ends_with(']'),
+            None => false,
+        };
+        let address = match has_port {
+            true => address.to_string(),
+            false => format!("{address}:{}", Self::DEFAULT_PORT),
+        };
+        let mut native = Self {
+            address,
+            database: String::new(),
+            user: "default".to_string(),
+            password: String::new(),
+        };
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            match key.as_ref() {
+                "database" => native.database = value.into_owned(),
+                "user" => native.user = value.into_owned(),
+                "password" => native.password = value.into_owned(),
+                _ => bail!("invalid ClickHouse URL: {}, unknown parameter {}", url, key),
+            }
+        }
+        Ok(native)
+    }
+
+    fn connect(&self) -> Result<TcpStream> {
+        let mut last_err = None;
+        for address in self.address.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&address, Duration::from_secs(10)) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(Client::TIMEOUT))?;
+                    stream.set_write_timeout(Some(Client::TIMEOUT))?;
+                    return Ok(stream);
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+        match last_err {
+            Some(err) => Err(err.into()),
+            None => bail!("no address found for {}", self.address),
+        }
+    }
+
+    fn query(&self, query: &str) -> Result<()> {
+        let stream = self
+            .connect()
+            .with_context(|| format!("failed to connect to {}", self.address))?;
+        let mut reader = BufReader::new(&stream);
+        let mut writer = BufWriter::new(&stream);
+
+        let mut packet = Vec::new();
+        put_varuint(&mut packet, ClientPacket::HELLO);
+        put_string(&mut packet, env!("CARGO_PKG_NAME"));
+        put_varuint(&mut packet, version(env!("CARGO_PKG_VERSION_MAJOR")));
+        put_varuint(&mut packet, version(env!("CARGO_PKG_VERSION_MINOR")));
+        put_varuint(&mut packet, Self::REVISION);
+        put_string(&mut packet, &self.database);
+        put_string(&mut packet, &self.user);
+        put_string(&mut packet, &self.password);
+        writer.write_all(&packet)?;
+        writer.flush()?;
+
+        let revision = match get_varuint(&mut reader)? {
+            ServerPacket::HELLO => {
+                let _name = get_string(&mut reader)?;
+                let _major = get_varuint(&mut reader)?;
+                let _minor = get_varuint(&mut reader)?;
+                let revision = get_varuint(&mut reader)?.min(Self::REVISION);
+                if revision >= Self::REVISION_WITH_SERVER_TIMEZONE {
+                    let _timezone = get_string(&mut reader)?;
+                }
+                revision
+            }
+            ServerPacket::EXCEPTION => return Err(get_exception(&mut reader)?),
+            packet => bail!("unexpected packet from the server: {}", packet),
+        };
+
+        packet.clear();
+        put_varuint(&mut packet, ClientPacket::QUERY);
+        put_string(&mut packet, ""); // the id of the query, chosen by the server
+        if revision >= Self::REVISION_WITH_CLIENT_INFO {
+            packet.push(1); // an initial query
+            put_string(&mut packet, ""); // the initial user
+            put_string(&mut packet, ""); // the initial query id
+            put_string(&mut packet, "0.0.0.0:0"); // the initial address
+            packet.push(1); // the TCP interface
+            put_string(&mut packet, ""); // the user of the operating system
+            put_string(&mut packet, ""); // the hostname of the client
+            put_string(&mut packet, env!("CARGO_PKG_NAME"));
+            put_varuint(&mut packet, version(env!("CARGO_PKG_VERSION_MAJOR")));
+            put_varuint(&mut packet, version(env!("CARGO_PKG_VERSION_MINOR")));
+            put_varuint(&mut packet, revision);
+            if revision >= Self::REVISION_WITH_QUOTA_KEY {
+                put_string(&mut packet, ""); // the quota key
+            }
+        }
+        put_string(&mut packet, ""); // the end of the settings
+        put_varuint(&mut packet, 2); // the query is completed
+        put_varuint(&mut packet, 0); // without compression
+        put_string(&mut packet, query);
+        // the empty block ending the external tables
+        put_varuint(&mut packet, ClientPacket::DATA);
+        put_string(&mut packet, "");
+        put_empty_block(&mut packet);
+        writer.write_all(&packet)?;
+        writer.flush()?;
+
+        loop {
+            match get_varuint(&mut reader)? {
+                ServerPacket::END_OF_STREAM => return Ok(()),
+                ServerPacket::EXCEPTION => return Err(get_exception(&mut reader)?),
+                ServerPacket::PROGRESS => {
+                    // the rows, the bytes and the total rows
+                    for _ in 0..3 {
+                        get_varuint(&mut reader)?;
+                    }
+                }
+                ServerPacket::PROFILE_INFO => {
+                    get_varuint(&mut reader)?; // the rows
+                    get_varuint(&mut reader)?; // the blocks
+                    get_varuint(&mut reader)?; // the bytes
+                    get_u8(&mut reader)?; // applied limit
+                    get_varuint(&mut reader)?; // the rows before the limit
+                    get_u8(&mut reader)?; // calculated rows before the limit
+                }
+                ServerPacket::DATA
+                | ServerPacket::TOTALS
+                | ServerPacket::EXTREMES
+                | ServerPacket::LOG => {
+                    get_string(&mut reader)?;
+                    skip_block(&mut reader)?;
+                }
+                packet => bail!("unexpected packet from the server: {}", packet),
+            }
+        }
+    }
+}
+
+struct ClientPacket;
+
+impl ClientPacket {
+    const HELLO: u64 = 0;
+    const QUERY: u64 = 1;
+    const DATA: u64 = 2;
+}
+
+struct ServerPacket;
+
+impl ServerPacket {
+    const HELLO: u64 = 0;
+    const DATA: u64 = 1;
+    const EXCEPTION: u64 = 2;
+    const PROGRESS: u64 = 3;
+    const END_OF_STREAM: u64 = 5;
+    const PROFILE_INFO: u64 = 6;
+    const TOTALS: u64 = 7;
+    const EXTREMES: u64 = 8;
+    const LOG: u64 = 10;
+}
+
+fn version(number: &str) -> u64 {
+    number.parse().unwrap_or(0)
+}
+
+fn put_varuint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn put_string(buffer: &mut Vec<u8>, value: &str) {
+    put_varuint(buffer, value.len() as u64);
+    buffer.extend_from_slice(value.as_bytes());
+}
+
+/// A block without columns and rows, with the default information.
+fn put_empty_block(buffer: &mut Vec<u8>) {
+    put_varuint(buffer, 1); // is overflows
+    buffer.push(0);
+    put_varuint(buffer, 2); // the bucket
+    buffer.extend_from_slice(&(-1i32).to_le_bytes());
+    put_varuint(buffer, 0); // the end of the information
+    put_varuint(buffer, 0); // the columns
+    put_varuint(buffer, 0); // the rows
+}
+
+fn get_u8(reader: &mut impl Read) -> Result<u8> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn get_varuint(reader: &mut impl Read) -> Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = get_u8(reader)?;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    bail!("invalid number from the server")
+}
+
+fn get_bytes(reader: &mut impl Read, len: u64) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        bail!("the connection is closed by the server");
+    }
+    Ok(bytes)
+}
+
+fn get_string(reader: &mut impl Read) -> Result<String> {
+    let len = get_varuint(reader)?;
+    Ok(String::from_utf8_lossy(&get_bytes(reader, len)?).into_owned())
+}
+
+fn get_exception(reader: &mut impl Read) -> Result<anyhow::Error> {
+    let code = i32::from_le_bytes(get_bytes(reader, 4)?.try_into().unwrap());
+    let name = get_string(reader)?;
+    let message = get_string(reader)?;
+    let _stack_trace = get_string(reader)?;
+    // the nested exceptions are only the causes of the first one
+    Ok(anyhow::anyhow!(
+        "code {}, {}: {}",
+        code,
+        name,
+        message.trim()
+    ))
+}
+
+/// Skip a block sent by the server, its columns can only have the types of the logs.
+fn skip_block(reader: &mut impl Read) -> Result<()> {
+    loop {
+        match get_varuint(reader)? {
+            0 => break,
+            1 => {
+                get_u8(reader)?;
+            }
+            2 => {
+                get_bytes(reader, 4)?;
+            }
+            field => bail!("unexpected block information from the server: {}", field),
+        }
+    }
+    let columns = get_varuint(reader)?;
+    let rows = get_varuint(reader)?;
+    for _ in 0..columns {
+        let _name = get_string(reader)?;
+        let kind = get_string(reader)?;
+        if rows == 0 {
+            continue;
+        }
+        let width = match kind.as_str() {
+            "String" => {
+                for _ in 0..rows {
+                    get_string(reader)?;
+                }
+                continue;
+            }
+            "UInt8" | "Int8" => 1,
+            "UInt16" | "Int16" => 2,
+            "UInt32" | "Int32" | "Float32" => 4,
+            "UInt64" | "Int64" | "Float64" => 8,
+            kind if kind.starts_with("DateTime64") => 8,
+            kind if kind.starts_with("DateTime") => 4,
+            _ => bail!("unexpected column from the server: {}", kind),
+        };
+        get_bytes(reader, width * rows)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    /// A server answering a single query, the query received is returned.
+    fn serve(
+        reply: impl FnOnce(&mut Vec<u8>) + Send + 'static,
+    ) -> (String, std::thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "tcp://{}?user=caddy&password=secret",
+            listener.local_addr().unwrap()
+        );
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut writer = &stream;
+
+            assert_eq!(get_varuint(&mut reader).unwrap(), ClientPacket::HELLO);
+            assert_eq!(get_string(&mut reader).unwrap(), env!("CARGO_PKG_NAME"));
+            get_varuint(&mut reader).unwrap();
+            get_varuint(&mut reader).unwrap();
+            assert_eq!(get_varuint(&mut reader).unwrap(), Native::REVISION);
+            assert_eq!(get_string(&mut reader).unwrap(), "");
+            assert_eq!(get_string(&mut reader).unwrap(), "caddy");
+            assert_eq!(get_string(&mut reader).unwrap(), "secret");
+            let mut packet = Vec::new();
+            put_varuint(&mut packet, ServerPacket::HELLO);
+            put_string(&mut packet, "ClickHouse");
+            put_varuint(&mut packet, 24);
+            put_varuint(&mut packet, 8);
+            put_varuint(&mut packet, 54470);
+            put_string(&mut packet, "UTC");
+            writer.write_all(&packet).unwrap();
+
+            assert_eq!(get_varuint(&mut reader).unwrap(), ClientPacket::QUERY);
+            assert_eq!(get_string(&mut reader).unwrap(), "");
+            assert_eq!(get_u8(&mut reader).unwrap(), 1);
+            for _ in 0..3 {
+                get_string(&mut reader).unwrap();
+            }
+            assert_eq!(get_u8(&mut reader).unwrap(), 1);
+            for _ in 0..3 {
+                get_string(&mut reader).unwrap();
+            }
+            get_varuint(&mut reader).unwrap();
+            get_varuint(&mut reader).unwrap();
+            assert_eq!(get_varuint(&mut reader).unwrap(), Native::REVISION);
+            assert_eq!(get_string(&mut reader).unwrap(), "");
+            assert_eq!(get_string(&mut reader).unwrap(), "");
+            assert_eq!(get_varuint(&mut reader).unwrap(), 2);
+            assert_eq!(get_varuint(&mut reader).unwrap(), 0);
+            let query = get_string(&mut reader).unwrap();
+            assert_eq!(get_varuint(&mut reader).unwrap(), ClientPacket::DATA);
+            assert_eq!(get_string(&mut reader).unwrap(), "");
+            skip_block(&mut reader).unwrap();
+
+            let mut packet = Vec::new();
+            reply(&mut packet);
+            writer.write_all(&packet).unwrap();
+            query
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn insert_with_the_native_protocol() {
+        let (url, server) = serve(|packet| {
+            put_varuint(packet, ServerPacket::PROGRESS);
+            put_varuint(packet, 2);
+            put_varuint(packet, 120);
+            put_varuint(packet, 0);
+            put_varuint(packet, ServerPacket::END_OF_STREAM);
+        });
+        let client = Client::new(&url).unwrap();
+        let data = "{\"status\":200}\n{\"status\":404}\n".to_string();
+        client
+            .query("INSERT INTO caddy_access FORMAT JSONEachRow", data)
+            .unwrap();
+        assert_eq!(
+            server.join().unwrap(),
+            "INSERT INTO caddy_access FORMAT JSONEachRow\n{\"status\":200}\n{\"status\":404}\n"
+        );
+    }
+
+    #[test]
+    fn report_the_exceptions_of_the_server() {
+        let (url, server) = serve(|packet| {
+            put_varuint(packet, ServerPacket::EXCEPTION);
+            packet.extend_from_slice(&60i32.to_le_bytes());
+            put_string(packet, "DB::Exception");
+            put_string(packet, "Table default.missing does not exist.");
+            put_string(packet, "");
+            packet.push(0);
+        });
+        let client = Client::new(&url).unwrap();
+        let err = client
+            .query("INSERT INTO missing FORMAT JSONEachRow", "{}\n".to_string())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "code 60, DB::Exception: Table default.missing does not exist."
+        );
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn parse_the_native_urls() {
+        let native = Native::new("tcp://localhost?database=logs").unwrap();
+        assert_eq!(native.address, "localhost:9000");
+        assert_eq!(native.database, "logs");
+        assert_eq!(native.user, "default");
+        let native = Native::new("tcp://[::1]:9440/").unwrap();
+        assert_eq!(native.address, "[::1]:9440");
+        let native = Native::new("tcp://[::1]").unwrap();
+        assert_eq!(native.address, "[::1]:9000");
+        assert!(Native::new("tcp://localhost:9000/logs").is_err());
+        assert!(Native::new("tcp://localhost?secure=1").is_err());
+        assert!(Client::new("tcps://localhost:9440").is_err());
+    }
+}
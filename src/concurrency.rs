@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+use time::format_description::well_known::Rfc3339;
+
+use crate::{
+    locale::Locale,
+    record::{self, LogRecord},
+    stats::format_table,
+    trends::sparkline,
+};
+
+/// The requests in flight over time, overall and by host, reconstructed from the timestamps
+/// and the durations: Caddy logs a request when it is completed, so it started `duration`
+/// seconds before its timestamp. The peaks can be compared with the concurrency limits of
+/// Caddy or of the upstreams when the latency rises.
+#[derive(Default)]
+pub struct Concurrency {
+    /// The start and the end of the requests of each host.
+    hosts: HashMap<String, Vec<(f64, f64)>>,
+}
+
+impl Concurrency {
+    const TOP: usize = 10;
+    const BUCKETS: usize = 16;
+    /// The hosts seen after this many are ignored, to bound the memory.
+    const MAX_HOSTS: usize = 10_000;
+    /// The label of the requests of all the hosts.
+    const ALL: &'static str = "*";
+
+    pub fn observe(&mut self, record: &LogRecord) {
+        let (Some(request), Some(duration)) = (&record.request, record.duration) else {
+            return;
+        };
+        if !self.hosts.contains_key(&request.host) && self.hosts.len() >= Self::MAX_HOSTS {
+            return;
+        }
+        let end = record.timestamp;
+        let intervals = self.hosts.entry(request.host.clone()).or_default();
+        intervals.push((end - duration.max(0.0), end));
+    }
+
+    /// The concurrency of all the requests, then of the busiest hosts by their peak. Nothing
+    /// is returned when no duration has been logged.
+    fn estimates(&self) -> Option<Vec<Estimate<'_>>> {
+        let intervals = self.hosts.values().flatten();
+        let start = intervals
+            .clone()
+            .map(|(start, _)| *start)
+            .min_by(f64::total_cmp)?;
+        let end = intervals.map(|(_, end)| *end).max_by(f64::total_cmp)?;
+        let window = (start, end);
+
+        let all = self.hosts.values().flatten().copied().collect::<Vec<_>>();
+        let mut hosts = self
+            .hosts
+            .iter()
+            .map(|(host, intervals)| Estimate::new(host, intervals, window))
+            .collect::<Vec<_>>();
+        hosts.sort_by(|a, b| b.peak.cmp(&a.peak).then(a.host.cmp(b.host)));
+        hosts.truncate(Self::TOP);
+        // a single host would be reported twice
+        if self.hosts.len() == 1 {
+            hosts.clear();
+        }
+        let mut estimates = vec![Estimate::new(Self::ALL, &all, window)];
+        estimates.extend(hosts);
+        Some(estimates)
+    }
+
+    /// The table of the peaks and of the mean concurrency, with their sparkline. Nothing is
+    /// returned when no duration has been logged.
+    pub fn render(&self, locale: &Locale) -> Option<String> {
+        let mut rows = vec![vec![
+            "concurrency".to_string(),
+            "requests".into(),
+            "peak_rps".into(),
+            "mean".into(),
+            "peak".into(),
+            "peak_at".into(),
+            "trend".into(),
+        ]];
+        for estimate in self.estimates()? {
+            let trend = estimate
+                .trend
+                .iter()
+                .map(|peak| Some(*peak as f64))
+                .collect::<Vec<_>>();
+            rows.push(vec![
+                estimate.host.to_string(),
+                locale.integer(estimate.requests),
+                locale.integer(estimate.peak_rate),
+                locale.decimal(estimate.mean, 1),
+                locale.integer(estimate.peak),
+                record::to_datetime(estimate.peak_at)
+                    .map(|peak_at| locale.datetime(peak_at))
+                    .unwrap_or_default(),
+                sparkline(&trend),
+            ]);
+        }
+        Some(format_table(&rows))
+    }
+
+    /// The concurrency of all the requests (the host `*`) and of the busiest hosts, the peak
+    /// of each bucket of the window is listed in `trend`.
+    pub fn to_json(&self) -> Value {
+        let estimates = self.estimates().unwrap_or_default();
+        estimates
+            .into_iter()
+            .map(|estimate| {
+                json!({
+                    "host": estimate.host,
+                    "requests": estimate.requests,
+                    "peak_rps": estimate.peak_rate,
+                    "mean": estimate.mean,
+                    "peak": estimate.peak,
+                    "peak_at": record::to_datetime(estimate.peak_at)
+                        .and_then(|peak_at| peak_at.format(&Rfc3339).ok()),
+                    "trend": estimate.trend,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The requests in flight of a host over the window.
+struct Estimate<'a> {
+    host: &'a str,
+    requests: u64,
+    /// The most requests started within a second.
+    peak_rate: u64,
+    /// The mean number of requests in flight over the window, their total duration divided by
+    /// its length.
+    mean: f64,
+    /// The most requests in flight at once, and when.
+    peak: u64,
+    peak_at: f64,
+    /// The peak of each bucket of the window.
+    trend: Vec<u64>,
+}
+
+impl<'a> Estimate<'a> {
+    fn new(host: &'a str, intervals: &[(f64, f64)], (start, end): (f64, f64)) -> Self {
+        let width = (end - start) / Concurrency::BUCKETS as f64;
+        let bucket = |time: f64| match width > 0.0 {
+            true => (((time - start) / width) as usize).min(Concurrency::BUCKETS - 1),
+            false => 0,
+        };
+
+        let mut rates = HashMap::<i64, u64>::new();
+        let mut events = Vec::with_capacity(intervals.len() * 2);
+        for &(start, end) in intervals {
+            *rates.entry(start.floor() as i64).or_default() += 1;
+            events.push((start, 1));
+            events.push((end, -1));
+        }
+        // the requests ending when others start are not counted together
+        events.sort_by(|(a, a_delta), (b, b_delta)| a.total_cmp(b).then(a_delta.cmp(b_delta)));
+
+        let mut trend = vec![0; Concurrency::BUCKETS];
+        let (mut current, mut peak, mut peak_at) = (0_i64, 0, start);
+        let mut last = 0;
+        for (time, delta) in events {
+            let index = bucket(time);
+            // the buckets without events keep the requests in flight through them
+            for peak in &mut trend[last.min(index)..index] {
+                *peak = (*peak).max(current.max(0) as u64);
+            }
+            last = index;
+            current += delta;
+            let in_flight = current.max(0) as u64;
+            trend[index] = trend[index].max(in_flight);
+            if in_flight > peak {
+                (peak, peak_at) = (in_flight, time);
+            }
+        }
+
+        let busy = intervals
+            .iter()
+            .map(|(start, end)| end - start)
+            .sum::<f64>();
+        let mean = match end > start {
+            true => busy / (end - start),
+            false => 0.0,
+        };
+        Self {
+            host,
+            requests: intervals.len() as u64,
+            peak_rate: rates.values().copied().max().unwrap_or(0),
+            mean,
+            peak,
+            peak_at,
+            trend,
+        }
+    }
+}
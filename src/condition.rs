@@ -0,0 +1,122 @@
+use std::str::FromStr;
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::{expr::Op, selector::Selector, syntax::SyntaxError};
+
+/// A comparison of a value of the JSON document of a record, addressed by a jq-like path, e.g.
+/// `.request.headers."X-Forwarded-For"[0] == "1.2.3.4"`. Any key of the document can be
+/// addressed, also the ones not known by the viewer.
+///
+/// The right side is a JSON value: the strings are glob patterns, the numbers and the strings
+/// can be ordered. Without a comparison the value must be present and not null, e.g.
+/// `.request.tls`.
+#[derive(Debug)]
+pub struct Condition {
+    selector: Selector,
+    comparison: Option<(Op, Value)>,
+    /// The pattern of a string compared for equality, compiled once.
+    pattern: Option<glob::Pattern>,
+}
+
+impl Condition {
+    /// The records with the value missing do not satisfy any comparison.
+    pub fn matches(&self, document: &Value) -> bool {
+        let value = match self.selector.select(document) {
+            None | Some(Value::Null) => return false,
+            Some(value) => value,
+        };
+        let Some((op, literal)) = &self.comparison else {
+            return true;
+        };
+        if let (Value::String(value), Some(pattern)) = (value, &self.pattern) {
+            return pattern.matches(value) == matches!(op, Op::Eq);
+        }
+        match (value, literal, op) {
+            (Value::String(value), Value::String(literal), _) => {
+                op.compare(value.as_str().cmp(literal))
+            }
+            (Value::Number(value), Value::Number(literal), _) => {
+                match (value.as_f64(), literal.as_f64()) {
+                    (Some(value), Some(literal)) => op.compare(value.total_cmp(&literal)),
+                    _ => false,
+                }
+            }
+            (value, literal, Op::Eq) => value == literal,
+            (value, literal, Op::Ne) => value != literal,
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (selector, mut pos) = Selector::parse_prefix(s)?;
+        pos += s[pos..].len() - s[pos..].trim_start().len();
+        if pos == s.len() {
+            return Ok(Self {
+                selector,
+                comparison: None,
+                pattern: None,
+            });
+        }
+        let Some((symbol, op)) = Op::SYMBOLS
+            .iter()
+            .find(|(symbol, _)| s[pos..].starts_with(symbol))
+        else {
+            return Err(SyntaxError::new("expected a comparison operator", s, pos).into());
+        };
+        pos += symbol.len();
+        let literal = s[pos..].trim();
+        let pos = s.len() - s[pos..].trim_start().len();
+        let Ok(literal) = serde_json::from_str::<Value>(literal) else {
+            let message = "expected a JSON value, e.g. \"text\" or 42";
+            return Err(SyntaxError::new(message, s, pos).into());
+        };
+        // a string which is not a valid pattern is compared as it is
+        let pattern = match (op, &literal) {
+            (Op::Eq | Op::Ne, Value::String(literal)) => glob::Pattern::new(literal)
+                .or_else(|_| glob::Pattern::new(&glob::Pattern::escape(literal)))
+                .ok(),
+            _ => None,
+        };
+        Ok(Self {
+            selector,
+            comparison: Some((*op, literal)),
+            pattern,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn matches(condition: &str, document: &Value) -> bool {
+        condition.parse::<Condition>().unwrap().matches(document)
+    }
+
+    #[test]
+    fn compare_the_values() {
+        let document = json!({
+            "status": 404,
+            "request": { "host": "shop.example.com", "uri": "/[draft", "tls": null },
+        });
+        assert!(matches(".request.host == \"shop.*\"", &document));
+        assert!(matches(".request.host != \"api.*\"", &document));
+        // not a valid pattern, compared as it is
+        assert!(matches(".request.uri == \"/[draft\"", &document));
+        assert!(!matches(".request.uri == \"/[other\"", &document));
+        assert!(matches(".request.host > \"api\"", &document));
+        assert!(matches(".status >= 400", &document));
+        assert!(!matches(".status < 400", &document));
+        assert!(matches(".request", &document));
+        assert!(!matches(".request.tls", &document));
+        assert!(!matches(".missing != 1", &document));
+    }
+}
@@ -0,0 +1,56 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::{
+    budgets::Budgets, mapping::FieldsConfig, monitors::MonitorsConfig, severity::SeverityMap,
+    table::TableLayout,
+};
+
+/// The settings read from the config file, written in YAML.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The columns of the table layout.
+    pub table: Option<TableLayout>,
+    /// The user agents of the monitoring services.
+    pub monitors: Option<MonitorsConfig>,
+    /// The latency expected for the routes.
+    pub budgets: Option<Budgets>,
+    /// The severities of the statuses.
+    pub severity: Option<SeverityMap>,
+    /// The MaxMind database of the countries of the addresses.
+    pub geoip_db: Option<PathBuf>,
+    /// The renamed fields and the path of the records wrapped by the log shippers.
+    pub fields: Option<FieldsConfig>,
+}
+
+impl Config {
+    /// Read the given config file, or the default one when it exists.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match Self::default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        serde_yaml::from_str(&content)
+            .with_context(|| format!("invalid config file: {}", path.display()))
+    }
+
+    /// `caddy-pretty-print/config.yaml` in the config directory of the user.
+    fn default_path() -> Option<PathBuf> {
+        let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(dir.join("caddy-pretty-print").join("config.yaml"))
+    }
+}
@@ -0,0 +1,44 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether the output is colored, the colors are reset when the viewer is interrupted.
+static COLORED: AtomicBool = AtomicBool::new(false);
+
+/// Prepare the terminal for the output, whether the colors can be used is returned. The
+/// consoles of Windows interpret the escape sequences of the colors only once the virtual
+/// terminal processing is enabled, the colors are disabled when it cannot be, e.g. on the
+/// legacy console.
+pub fn init(colored: bool) -> bool {
+    #[cfg(windows)]
+    let colored = colored && colored::control::set_virtual_terminal(true).is_ok();
+    COLORED.store(colored, Ordering::Relaxed);
+    #[cfg(windows)]
+    install_ctrl_handler();
+    colored
+}
+
+/// Reset the colors on Ctrl-C, before the process is terminated by the default handler: the
+/// prompt would keep the color of the record being printed.
+#[cfg(windows)]
+fn install_ctrl_handler() {
+    use std::io::Write;
+
+    use windows_sys::Win32::{
+        Foundation::{BOOL, FALSE, TRUE},
+        System::Console::{SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_C_EVENT},
+    };
+
+    unsafe extern "system" fn handler(event: u32) -> BOOL {
+        if matches!(event, CTRL_C_EVENT | CTRL_BREAK_EVENT) && COLORED.load(Ordering::Relaxed) {
+            // the lock waits for the record being written
+            let mut stdout = std::io::stdout().lock();
+            let _ = write!(stdout, "\x1b[0m");
+            let _ = stdout.flush();
+        }
+        FALSE
+    }
+
+    // SAFETY: the handler is a function living as long as the process
+    unsafe {
+        SetConsoleCtrlHandler(Some(handler), TRUE);
+    }
+}
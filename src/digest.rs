@@ -0,0 +1,213 @@
+use std::{collections::HashMap, io::Write};
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{
+    json,
+    locale::Locale,
+    record::{LogLevel, LogRecord},
+    sink::{Entry, Output, Sink},
+    stats::format_table,
+};
+
+/// The error records clustered by their message, with the numbers and the identifiers stripped,
+/// e.g. the TLS handshake errors of all the clients are a single kind of error.
+#[derive(Default)]
+pub struct ErrorDigest {
+    kinds: HashMap<Kind, Occurrences>,
+    /// The errors of the kinds not counted, when there are too many kinds.
+    dropped: u64,
+}
+
+/// A kind of error: its level, its logger and its normalized message.
+type Kind = (LogLevel, String, String);
+
+struct Occurrences {
+    count: u64,
+    first_seen: OffsetDateTime,
+    last_seen: OffsetDateTime,
+    /// The message of the first occurrence.
+    example: String,
+}
+
+impl ErrorDigest {
+    /// The new kinds seen after this many are not counted, to bound the memory.
+    const MAX_KINDS: usize = 10_000;
+
+    pub fn observe(&mut self, record: &LogRecord) {
+        if record.level < LogLevel::Error {
+            return;
+        }
+        // the access logs have the same message, the status tells them apart
+        let (message, normalized) = match record.status {
+            Some(status) => (
+                format!("{} ({})", record.message, status.as_u16()),
+                format!("{} ({})", normalize(&record.message), status.as_u16()),
+            ),
+            None => (record.message.clone(), normalize(&record.message)),
+        };
+        let kind = (
+            record.level,
+            record.logger.clone().unwrap_or_default(),
+            normalized,
+        );
+        let datetime = record.datetime();
+        if self.kinds.len() >= Self::MAX_KINDS && !self.kinds.contains_key(&kind) {
+            self.dropped += 1;
+            return;
+        }
+        let occurrences = self.kinds.entry(kind).or_insert_with(|| Occurrences {
+            count: 0,
+            first_seen: datetime,
+            last_seen: datetime,
+            example: message,
+        });
+        occurrences.count += 1;
+        occurrences.first_seen = occurrences.first_seen.min(datetime);
+        occurrences.last_seen = occurrences.last_seen.max(datetime);
+    }
+
+    /// The kinds of error, the most frequent first.
+    fn sorted(&self) -> Vec<(&Kind, &Occurrences)> {
+        let mut kinds = self.kinds.iter().collect::<Vec<_>>();
+        kinds.sort_by(|(a_kind, a), (b_kind, b)| b.count.cmp(&a.count).then(a_kind.cmp(b_kind)));
+        kinds
+    }
+
+    /// The table of the kinds of error, the most frequent first.
+    pub fn render(&self, locale: &Locale) -> String {
+        let errors = self.kinds.values().map(|o| o.count).sum::<u64>() + self.dropped;
+        let mut output = format!(
+            "{} errors of {} kinds\n",
+            locale.integer(errors),
+            locale.integer(self.kinds.len() as u64)
+        );
+        if self.dropped > 0 {
+            output.push_str(&format!(
+                "{} errors of the kinds after the first {} are not reported\n",
+                locale.integer(self.dropped),
+                locale.integer(Self::MAX_KINDS as u64)
+            ));
+        }
+        if self.kinds.is_empty() {
+            return output;
+        }
+        let mut rows = vec![vec![
+            "logger".to_string(),
+            "level".into(),
+            "count".into(),
+            "first_seen".into(),
+            "last_seen".into(),
+        ]];
+        let mut messages = Vec::new();
+        for ((level, logger, message), occurrences) in self.sorted() {
+            rows.push(vec![
+                logger.clone(),
+                format!("{level:?}").to_lowercase(),
+                locale.integer(occurrences.count),
+                locale.datetime(occurrences.first_seen),
+                locale.datetime(occurrences.last_seen),
+            ]);
+            messages.push(message);
+        }
+        // the messages are appended after the aligned columns, they have variable length
+        let table = format_table(&rows);
+        let mut lines = table.lines();
+        output.push_str(&format!(
+            "\n{}  message\n",
+            lines.next().unwrap_or_default()
+        ));
+        for (line, message) in lines.zip(messages) {
+            output.push_str(&format!("{line}  {message}\n"));
+        }
+        output
+    }
+
+    /// The kinds of error, the most frequent first, with the times in RFC 3339 format.
+    pub fn to_json(&self) -> Value {
+        let kinds = self
+            .sorted()
+            .into_iter()
+            .map(|((level, logger, message), occurrences)| {
+                json!({
+                    "level": format!("{level:?}").to_lowercase(),
+                    "logger": logger,
+                    "message": message,
+                    "example": occurrences.example,
+                    "count": occurrences.count,
+                    "first_seen": occurrences.first_seen.format(&Rfc3339).ok(),
+                    "last_seen": occurrences.last_seen.format(&Rfc3339).ok(),
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({ "errors": kinds, "dropped": self.dropped })
+    }
+}
+
+/// Replace the numbers, and the words with digits like the identifiers and the addresses, by
+/// `#`, e.g. `TLS handshake error from 10.0.0.5:51234: EOF` becomes
+/// `TLS handshake error from #.#.#.#:#: EOF`.
+pub fn normalize(message: &str) -> String {
+    fn flush(word: &mut String, normalized: &mut String) {
+        match word.chars().any(|c| c.is_ascii_digit()) {
+            true => normalized.push('#'),
+            false => normalized.push_str(word),
+        }
+        word.clear();
+    }
+
+    let mut normalized = String::with_capacity(message.len());
+    let mut word = String::new();
+    for c in message.chars() {
+        if c.is_alphanumeric() {
+            word.push(c);
+        } else {
+            flush(&mut word, &mut normalized);
+            normalized.push(c);
+        }
+    }
+    flush(&mut word, &mut normalized);
+    normalized
+}
+
+/// Print the digest of the errors when the input ends, instead of the records.
+pub struct DigestSink {
+    digest: ErrorDigest,
+    output: Output,
+    locale: Locale,
+}
+
+impl DigestSink {
+    pub fn new(output: Output, locale: Locale) -> Self {
+        Self {
+            digest: ErrorDigest::default(),
+            output,
+            locale,
+        }
+    }
+}
+
+impl Sink for DigestSink {
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        self.digest.observe(entry.record()?);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let output = match self.output {
+            Output::Json => format!("{}\n", self.digest.to_json()),
+            Output::JsonPretty => format!("{}\n", json::format_pretty(&self.digest.to_json())),
+            _ => self.digest.render(&self.locale),
+        };
+        let mut stdout = std::io::stdout();
+        stdout.write_all(output.as_bytes())?;
+        stdout.flush()?;
+        Ok(())
+    }
+
+    fn is_live(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,85 @@
+use crate::{bots, record::LogRecord};
+
+/// The families of the user agents, recognized by a part of them. The order matters, e.g. the
+/// user agent of Edge contains the one of Chrome, which contains the one of Safari.
+const FAMILIES: &[(&str, &str)] = &[
+    ("Edg/", "Edge"),
+    ("OPR/", "Opera"),
+    ("SamsungBrowser/", "Samsung Internet"),
+    ("YaBrowser/", "Yandex Browser"),
+    ("Vivaldi/", "Vivaldi"),
+    ("Firefox/", "Firefox"),
+    ("CriOS/", "Chrome"),
+    ("Chrome/", "Chrome"),
+    ("Chromium/", "Chromium"),
+    ("FxiOS/", "Firefox"),
+    ("Safari/", "Safari"),
+    ("curl/", "curl"),
+    ("Wget/", "Wget"),
+    ("Go-http-client/", "Go"),
+    ("python-requests/", "Python"),
+    ("python-urllib/", "Python"),
+    ("aiohttp/", "Python"),
+    ("okhttp/", "OkHttp"),
+    ("Java/", "Java"),
+    ("Apache-HttpClient/", "Java"),
+    ("node-fetch", "Node.js"),
+    ("axios/", "Node.js"),
+    ("PostmanRuntime/", "Postman"),
+];
+
+/// Whether the request was sent by a crawler, a bot or a scanner.
+pub fn bot(record: &LogRecord) -> Option<bool> {
+    record.request.as_ref()?;
+    Some(bots::is_bot(record))
+}
+
+/// The family of the user agent, e.g. `Firefox` or `curl`, `Other` when it is not recognized.
+/// The bots are recognized by [`bot`] instead.
+pub fn user_agent_family(record: &LogRecord) -> Option<&'static str> {
+    let user_agent = record
+        .request
+        .as_ref()?
+        .headers
+        .get(http::header::USER_AGENT)?
+        .to_str()
+        .ok()?;
+    let family = FAMILIES
+        .iter()
+        .find(|(part, _)| user_agent.contains(part))
+        .map_or("Other", |(_, family)| family);
+    Some(family)
+}
+
+/// The headers carrying the identifier of a request, or of its trace, across the services.
+const CORRELATION_HEADERS: &[&str] = &[
+    "x-request-id",
+    "x-correlation-id",
+    "request-id",
+    "x-amzn-trace-id",
+    "traceparent",
+];
+
+/// The identifiers found in the correlation headers of the request and of its response, the
+/// trace id of `traceparent` is one of them too, e.g. `4bf92f3577b34da6a3ce929d0e0e4736`.
+pub fn request_ids(record: &LogRecord) -> Vec<&str> {
+    let Some(request) = &record.request else {
+        return Vec::new();
+    };
+    let mut ids = Vec::new();
+    for headers in [&request.headers, &record.resp_headers] {
+        for name in CORRELATION_HEADERS {
+            for value in headers.get_all(*name) {
+                let Ok(value) = value.to_str() else {
+                    continue;
+                };
+                ids.push(value);
+                // version-trace_id-parent_id-flags
+                if *name == "traceparent" {
+                    ids.extend(value.split('-').nth(1));
+                }
+            }
+        }
+    }
+    ids
+}
@@ -0,0 +1,233 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+    thread::JoinHandle,
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    expr::Expr,
+    rate::RateLimiter,
+    selector::Selector,
+    sink::{Entry, Sink},
+};
+
+/// Run a shell command for each record matching a condition.
+///
+/// The record is written on the standard input of the command, the placeholders in the command
+/// line are replaced by the whole record (`{}`) or by a value selected with a jq-like path (e.g.
+/// `{.request.remote_ip}`). The values come from the clients, so they are never spliced in the
+/// command line: they are given in the `CPP_VALUE_1`, `CPP_VALUE_2`, ... environment variables,
+/// which the placeholders expand as single words.
+pub struct ExecSink {
+    command: Vec<Part>,
+    condition: Option<Expr>,
+    limiter: RateLimiter,
+    skipped: u64,
+    running: Vec<JoinHandle<()>>,
+}
+
+enum Part {
+    Text(String),
+    Record,
+    Selector(Selector),
+}
+
+impl ExecSink {
+    pub fn new(command: &str, condition: Option<Expr>, limiter: RateLimiter) -> Result<Self> {
+        Ok(Self {
+            command: parse_command(command)?,
+            condition,
+            limiter,
+            skipped: 0,
+            running: Vec::new(),
+        })
+    }
+
+    /// The command of the record, the placeholders are replaced by their variables.
+    fn command(&self, entry: &Entry) -> Result<Command> {
+        let mut command_line = String::new();
+        let mut values = Vec::new();
+        for part in &self.command {
+            let value = match part {
+                Part::Text(text) => {
+                    command_line.push_str(text);
+                    continue;
+                }
+                Part::Record => entry.line.to_string(),
+                Part::Selector(selector) => selector.extract(entry.value()?),
+            };
+            let name = format!("CPP_VALUE_{}", values.len() + 1);
+            command_line.push_str(&expand(&name));
+            values.push((name, value));
+        }
+        let mut command = shell(&command_line);
+        command.envs(values);
+        Ok(command)
+    }
+
+    fn run(&mut self, entry: &Entry) -> Result<()> {
+        let mut child = self
+            .command(entry)?
+            .stdin(Stdio::piped())
+            .spawn()
+            .context("failed to run command")?;
+        let mut stdin = child.stdin.take().unwrap();
+        let line = entry.line.to_string();
+        // the command is not awaited, the processing of the records goes on
+        self.running.retain(|handle| !handle.is_finished());
+        self.running.push(std::thread::spawn(move || {
+            let _ = writeln!(stdin, "{line}");
+            drop(stdin);
+            let _ = child.wait();
+        }));
+        Ok(())
+    }
+}
+
+impl Sink for ExecSink {
+    fn needs_record(&self) -> bool {
+        self.condition.is_some()
+    }
+
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        if let Some(condition) = &self.condition {
+            if !condition.matches(entry.record()?) {
+                return Ok(());
+            }
+        }
+        if !self.limiter.try_acquire() {
+            self.skipped += 1;
+            return Ok(());
+        }
+        if let Err(err) = self.run(entry) {
+            eprintln!("{err:#}");
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for handle in self.running.drain(..) {
+            let _ = handle.join();
+        }
+        if self.skipped > 0 {
+            eprintln!("{} commands skipped by the rate limit", self.skipped);
+        }
+        Ok(())
+    }
+}
+
+/// Check the placeholders of the command.
+pub fn check_command(command: &str) -> Result<()> {
+    parse_command(command).map(drop)
+}
+
+fn parse_command(command: &str) -> Result<Vec<Part>> {
+    let mut parts = Vec::new();
+    let mut rest = command;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            bail!("unclosed placeholder in command: {}", command);
+        };
+        parts.push(Part::Text(rest[..start].to_string()));
+        let placeholder = &rest[start + 1..start + len];
+        if placeholder.is_empty() {
+            parts.push(Part::Record);
+        } else {
+            let selector = placeholder
+                .parse()
+                .with_context(|| format!("invalid placeholder in command: {}", command))?;
+            parts.push(Part::Selector(selector));
+        }
+        rest = &rest[start + len + 1..];
+    }
+    parts.push(Part::Text(rest.to_string()));
+    Ok(parts)
+}
+
+#[cfg(unix)]
+fn shell(command_line: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(command_line);
+    command
+}
+
+/// The command line is given to cmd.exe as it is: the quoting of the arguments of the C
+/// runtime is not understood by cmd.exe. The delayed expansion (`/V:ON`) expands the variables
+/// after the command line is parsed, so their values cannot add commands.
+#[cfg(windows)]
+fn shell(command_line: &str) -> Command {
+    use std::os::windows::process::CommandExt;
+
+    let mut command = Command::new("cmd");
+    command.args(["/D", "/V:ON", "/S", "/C"]);
+    command.raw_arg(format!("\"{command_line}\""));
+    command
+}
+
+/// The expansion of a variable as a single word.
+#[cfg(unix)]
+fn expand(name: &str) -> String {
+    format!("\"${name}\"")
+}
+
+#[cfg(windows)]
+fn expand(name: &str) -> String {
+    format!("\"!{name}!\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sink(command: &str) -> ExecSink {
+        ExecSink::new(command, None, RateLimiter::new("1/s".parse().unwrap())).unwrap()
+    }
+
+    #[test]
+    fn parse_placeholders() {
+        let parts = parse_command("notify {.request.uri} {} done").unwrap();
+        assert!(matches!(
+            parts.as_slice(),
+            [
+                Part::Text(_),
+                Part::Selector(_),
+                Part::Text(_),
+                Part::Record,
+                Part::Text(_)
+            ]
+        ));
+        assert!(parse_command("notify {.request.uri").is_err());
+        assert!(parse_command("notify {request}").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pass_values_as_words() {
+        let line = r#"{"request":{"uri":"/'; echo injected; '$(id)`id`\"","host":"a b"}}"#;
+        let entry = Entry::partial(line, None);
+        let output = sink("printf '%s|' {.request.uri} {.request.host}")
+            .command(&entry)
+            .unwrap()
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "/'; echo injected; '$(id)`id`\"|a b|"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pass_the_record() {
+        let line = r#"{"msg":"it's"}"#;
+        let entry = Entry::partial(line, None);
+        let output = sink("printf %s {}")
+            .command(&entry)
+            .unwrap()
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), line);
+    }
+}
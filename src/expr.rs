@@ -0,0 +1,565 @@
+use std::str::FromStr;
+
+use anyhow::Result;
+use clap::ValueEnum;
+
+use crate::{
+    enrich,
+    record::{LogLevel, LogRecord},
+    syntax::SyntaxError,
+    units,
+};
+
+/// A boolean expression over the fields of a record, e.g.
+/// `status >= 500 && host == "shop.*" && duration > 1s`.
+///
+/// Text fields are compared with glob patterns, durations accept a unit suffix and the level is
+/// compared by severity.
+#[derive(Debug)]
+pub struct Expr {
+    root: Node,
+}
+
+#[derive(Debug)]
+enum Node {
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Compare(Field, Op, Literal),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Field {
+    Status,
+    Duration,
+    Size,
+    Level,
+    Host,
+    Method,
+    Uri,
+    RemoteIp,
+    Proto,
+    Message,
+    /// Whether the request was sent by a bot, `true` or `false`.
+    Bot,
+    UserAgentFamily,
+    /// The server name requested by the client (SNI).
+    Sni,
+    /// The backend the reverse proxy forwarded the request to.
+    Upstream,
+}
+
+impl Field {
+    const NAMES: &'static [(&'static str, Field)] = &[
+        ("status", Field::Status),
+        ("duration", Field::Duration),
+        ("size", Field::Size),
+        ("level", Field::Level),
+        ("host", Field::Host),
+        ("method", Field::Method),
+        ("uri", Field::Uri),
+        ("remote_ip", Field::RemoteIp),
+        ("proto", Field::Proto),
+        ("msg", Field::Message),
+        ("bot", Field::Bot),
+        ("ua_family", Field::UserAgentFamily),
+        ("sni", Field::Sni),
+        ("upstream", Field::Upstream),
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map(|(_, field)| *field)
+    }
+
+    fn value(self, record: &LogRecord) -> Option<Value> {
+        let request = record.request.as_ref();
+        let value = match self {
+            Field::Status => Value::Number(record.status?.as_u16() as f64),
+            Field::Duration => Value::Number(record.duration?),
+            Field::Size => Value::Number(record.size? as f64),
+            Field::Level => Value::Level(record.level),
+            Field::Host => Value::Text(request?.host.clone()),
+            Field::Method => Value::Text(request?.method.to_string()),
+            Field::Uri => Value::Text(request?.uri.clone()),
+            Field::RemoteIp => Value::Text(request?.remote_ip.to_string()),
+            Field::Proto => Value::Text(format!("{:?}", request?.version)),
+            Field::Message => Value::Text(record.message.clone()),
+            Field::Bot => Value::Text(enrich::bot(record)?.to_string()),
+            Field::UserAgentFamily => Value::Text(enrich::user_agent_family(record)?.to_string()),
+            Field::Sni => Value::Text(request?.tls.as_ref()?.server_name.clone()?),
+            Field::Upstream => Value::Text(record.upstream.clone()?),
+        };
+        Some(value)
+    }
+}
+
+/// The comparison operators, shared with the conditions on the JSON documents.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug)]
+enum Literal {
+    Number(f64),
+    Level(LogLevel),
+    Pattern(glob::Pattern),
+}
+
+enum Value {
+    Number(f64),
+    Level(LogLevel),
+    Text(String),
+}
+
+impl Expr {
+    pub fn matches(&self, record: &LogRecord) -> bool {
+        self.root.eval(record)
+    }
+}
+
+impl Node {
+    fn eval(&self, record: &LogRecord) -> bool {
+        match self {
+            Node::And(lhs, rhs) => lhs.eval(record) && rhs.eval(record),
+            Node::Or(lhs, rhs) => lhs.eval(record) || rhs.eval(record),
+            Node::Not(node) => !node.eval(record),
+            Node::Compare(field, op, literal) => {
+                let Some(value) = field.value(record) else {
+                    return false;
+                };
+                match (value, literal) {
+                    (Value::Number(value), Literal::Number(literal)) => {
+                        op.compare(value.total_cmp(literal))
+                    }
+                    (Value::Level(value), Literal::Level(literal)) => {
+                        op.compare(value.cmp(literal))
+                    }
+                    (Value::Text(value), Literal::Pattern(pattern)) => {
+                        let matches = pattern.matches(&value);
+                        matches!((op, matches), (Op::Eq, true) | (Op::Ne, false))
+                    }
+                    _ => false,
+                }
+            }
+        }
+    }
+}
+
+impl Op {
+    /// The symbols of the operators, the longest ones first.
+    pub(crate) const SYMBOLS: &'static [(&'static str, Op)] = &[
+        ("==", Op::Eq),
+        ("!=", Op::Ne),
+        ("<=", Op::Le),
+        (">=", Op::Ge),
+        ("<", Op::Lt),
+        (">", Op::Gt),
+    ];
+
+    pub(crate) fn compare(self, ordering: std::cmp::Ordering) -> bool {
+        use std::cmp::Ordering::*;
+        match self {
+            Op::Eq => ordering == Equal,
+            Op::Ne => ordering != Equal,
+            Op::Lt => ordering == Less,
+            Op::Le => ordering != Greater,
+            Op::Gt => ordering == Greater,
+            Op::Ge => ordering != Less,
+        }
+    }
+}
+
+impl FromStr for Expr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser {
+            input: s,
+            tokens,
+            index: 0,
+        };
+        let root = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return parser.fail_at(token.pos, "unexpected token");
+        }
+        Ok(Self { root })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Op(&'static str),
+    Ident(String),
+    Number(String),
+    String(String),
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: TokenKind,
+    pos: usize,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    const SYMBOLS: &[(&str, TokenKind)] = &[
+        ("&&", TokenKind::And),
+        ("||", TokenKind::Or),
+        ("==", TokenKind::Op("==")),
+        ("!=", TokenKind::Op("!=")),
+        (">=", TokenKind::Op(">=")),
+        ("<=", TokenKind::Op("<=")),
+        (">", TokenKind::Op(">")),
+        ("<", TokenKind::Op("<")),
+        ("=", TokenKind::Op("==")),
+        ("!", TokenKind::Not),
+        ("(", TokenKind::LParen),
+        (")", TokenKind::RParen),
+    ];
+
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < input.len() {
+        let rest = &input[pos..];
+        let c = rest.chars().next().unwrap();
+        if c.is_whitespace() {
+            pos += c.len_utf8();
+            continue;
+        }
+        if let Some((symbol, kind)) = SYMBOLS.iter().find(|(symbol, _)| rest.starts_with(symbol)) {
+            tokens.push(Token {
+                kind: kind.clone(),
+                pos,
+            });
+            pos += symbol.len();
+        } else if c == '"' {
+            let mut stream = serde_json::Deserializer::from_str(rest).into_iter::<String>();
+            let Some(Ok(value)) = stream.next() else {
+                return Err(SyntaxError::new("invalid string", input, pos).into());
+            };
+            tokens.push(Token {
+                kind: TokenKind::String(value),
+                pos,
+            });
+            pos += stream.byte_offset();
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '.' | '-' | '*')))
+                .unwrap_or(rest.len());
+            let word = rest[..len].to_string();
+            let kind = if c.is_ascii_digit() {
+                TokenKind::Number(word)
+            } else {
+                TokenKind::Ident(word)
+            };
+            tokens.push(Token { kind, pos });
+            pos += len;
+        } else {
+            return Err(SyntaxError::new("unexpected character", input, pos).into());
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<Token>,
+    index: usize,
+}
+
+impl Parser<'_> {
+    fn or(&mut self) -> Result<Node> {
+        let mut node = self.and()?;
+        while self.eat(&TokenKind::Or) {
+            node = Node::Or(Box::new(node), Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node> {
+        let mut node = self.unary()?;
+        while self.eat(&TokenKind::And) {
+            node = Node::And(Box::new(node), Box::new(self.unary()?));
+        }
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> Result<Node> {
+        if self.eat(&TokenKind::Not) {
+            return Ok(Node::Not(Box::new(self.unary()?)));
+        }
+        if self.eat(&TokenKind::LParen) {
+            let node = self.or()?;
+            if !self.eat(&TokenKind::RParen) {
+                return self.fail("expected ')'");
+            }
+            return Ok(node);
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Node> {
+        let Some(Token {
+            kind: TokenKind::Ident(name),
+            pos,
+        }) = self.next()
+        else {
+            return self.fail_previous("expected a field name");
+        };
+        let Some(field) = Field::from_name(&name) else {
+            let names = Field::NAMES
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>();
+            return self.fail_at(
+                pos,
+                &format!(
+                    "unknown field `{}`, expected one of {}",
+                    name,
+                    names.join(", ")
+                ),
+            );
+        };
+
+        let op = match self.next().map(|token| token.kind) {
+            Some(TokenKind::Op("==")) => Op::Eq,
+            Some(TokenKind::Op("!=")) => Op::Ne,
+            Some(TokenKind::Op("<")) => Op::Lt,
+            Some(TokenKind::Op("<=")) => Op::Le,
+            Some(TokenKind::Op(">")) => Op::Gt,
+            Some(TokenKind::Op(">=")) => Op::Ge,
+            _ => return self.fail_previous("expected a comparison operator"),
+        };
+
+        let Some(token) = self.next() else {
+            return self.fail_previous("expected a value");
+        };
+        let literal = match (field, token.kind) {
+            (Field::Status, TokenKind::Number(value)) => match value.parse() {
+                Ok(value) => Literal::Number(value),
+                Err(_) => return self.fail_at(token.pos, "invalid status code"),
+            },
+            (Field::Duration, TokenKind::Number(value)) => {
+                let duration = units::parse_duration(&value).ok().or_else(|| {
+                    // a plain number is a duration in seconds
+                    let seconds = value.parse().ok()?;
+                    std::time::Duration::try_from_secs_f64(seconds).ok()
+                });
+                match duration {
+                    Some(value) => Literal::Number(value.as_secs_f64()),
+                    None => return self.fail_at(token.pos, "invalid duration"),
+                }
+            }
+            (Field::Size, TokenKind::Number(value)) => match units::parse_size(&value) {
+                Ok(value) => Literal::Number(value as f64),
+                Err(_) => return self.fail_at(token.pos, "invalid size"),
+            },
+            (Field::Level, TokenKind::Ident(value) | TokenKind::String(value)) => {
+                match LogLevel::from_str(&value, true) {
+                    Ok(value) => Literal::Level(value),
+                    Err(_) => return self.fail_at(token.pos, "invalid level"),
+                }
+            }
+            (
+                Field::Host
+                | Field::Method
+                | Field::Uri
+                | Field::RemoteIp
+                | Field::Proto
+                | Field::Message
+                | Field::Bot
+                | Field::UserAgentFamily
+                | Field::Sni
+                | Field::Upstream,
+                TokenKind::String(value) | TokenKind::Ident(value) | TokenKind::Number(value),
+            ) => {
+                if !matches!(op, Op::Eq | Op::Ne) {
+                    return self.fail_at(token.pos, "text fields only support == and !=");
+                }
+                match glob::Pattern::new(&value) {
+                    Ok(pattern) => Literal::Pattern(pattern),
+                    Err(_) => return self.fail_at(token.pos, "invalid glob pattern"),
+                }
+            }
+            _ => return self.fail_at(token.pos, "invalid value for this field"),
+        };
+        Ok(Node::Compare(field, op, literal))
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.index).cloned();
+        self.index += 1;
+        token
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if self.peek().is_some_and(|token| &token.kind == kind) {
+            self.index += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Report an error at the position of the current token.
+    fn fail<T>(&self, message: &str) -> Result<T> {
+        let pos = self.peek().map_or(self.input.len(), |token| token.pos);
+        self.fail_at(pos, message)
+    }
+
+    /// Report an error at the position of the last consumed token.
+    fn fail_previous<T>(&self, message: &str) -> Result<T> {
+        let pos = self
+            .tokens
+            .get(self.index - 1)
+            .map_or(self.input.len(), |token| token.pos);
+        self.fail_at(pos, message)
+    }
+
+    fn fail_at<T>(&self, pos: usize, message: &str) -> Result<T> {
+        Err(SyntaxError::new(message, self.input, pos).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn record(status: u16, duration: f64, host: &str, uri: &str) -> LogRecord {
+        serde_json::from_value(json!({
+            "level": "info",
+            "ts": 1700000000.0,
+            "logger": "http.log.access",
+            "msg": "handled request",
+            "request": {
+                "remote_ip": "10.0.0.5",
+                "remote_port": "51234",
+                "proto": "HTTP/2.0",
+                "method": "GET",
+                "host": host,
+                "uri": uri,
+                "headers": { "User-Agent": ["curl/8.4.0"] }
+            },
+            "duration": duration,
+            "size": 512,
+            "status": status,
+            "resp_headers": {}
+        }))
+        .unwrap()
+    }
+
+    fn matches(expr: &str, record: &LogRecord) -> bool {
+        expr.parse::<Expr>().unwrap().matches(record)
+    }
+
+    /// The byte offset of the syntax error of the expression.
+    fn error_position(expr: &str) -> usize {
+        let err = expr.parse::<Expr>().unwrap_err();
+        err.downcast_ref::<SyntaxError>().unwrap().position
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let record = record(200, 0.1, "shop.example.com", "/");
+        // true || (false && false)
+        assert!(matches(
+            r#"status == 200 || status == 500 && host == "api.*""#,
+            &record
+        ));
+        // (true || false) && false
+        assert!(!matches(
+            r#"(status == 200 || status == 500) && host == "api.*""#,
+            &record
+        ));
+    }
+
+    #[test]
+    fn not_equal_is_not_negation() {
+        let record = record(404, 0.1, "shop.example.com", "/");
+        assert!(matches("status != 200", &record));
+        assert!(matches("!status == 200", &record));
+        assert!(matches("!(status == 200)", &record));
+        assert!(!matches("status != 404", &record));
+        assert!(!matches("!status != 200", &record));
+    }
+
+    #[test]
+    fn quoted_globs() {
+        let record = record(200, 0.1, "shop.example.com", "/api/items?id=42");
+        assert!(matches(r#"host == "*.example.com""#, &record));
+        assert!(matches(r#"uri == "/api/*""#, &record));
+        assert!(matches(r#"uri == "/api/items[?]id=42""#, &record));
+        assert!(!matches(r#"host == "example.com""#, &record));
+        // the quotes keep the spaces and the operators in the pattern
+        assert!(!matches(r#"uri == "/api && status == 500""#, &record));
+    }
+
+    #[test]
+    fn negated_globs() {
+        let record = record(200, 0.1, "shop.example.com", "/api/items");
+        assert!(matches(r#"host != "api.*""#, &record));
+        assert!(!matches(r#"host != "*.example.com""#, &record));
+        assert!(matches(r#"!(uri == "/static/*")"#, &record));
+        assert!(matches(r#"!host == "api.*" && uri == "/api/*""#, &record));
+    }
+
+    #[test]
+    fn text_fields_only_support_equality() {
+        for expr in [
+            r#"host > "a""#,
+            r#"uri <= "/api""#,
+            "method >= GET",
+            r#"remote_ip < "10.0.0.0""#,
+        ] {
+            let err = expr.parse::<Expr>().unwrap_err();
+            let err = err.downcast_ref::<SyntaxError>().unwrap();
+            assert_eq!(err.message, "text fields only support == and !=", "{expr}");
+            // the value is pointed at
+            assert_eq!(err.position, expr.rfind(' ').unwrap() + 1, "{expr}");
+        }
+    }
+
+    #[test]
+    fn durations() {
+        let record = record(200, 1.2, "shop.example.com", "/");
+        assert!(matches("duration > 1s", &record));
+        assert!(matches("duration < 1.5s", &record));
+        assert!(matches("duration >= 1200ms", &record));
+        assert!(matches("duration > 1", &record));
+        assert!(!matches("duration > 1.5", &record));
+    }
+
+    #[test]
+    fn error_positions() {
+        // the missing tokens are reported at the end of the input
+        assert_eq!(error_position("status >"), 8);
+        assert_eq!(error_position("status == 200 &&"), 16);
+        assert_eq!(error_position("colour == red"), 0);
+        assert_eq!(error_position("status == 200 $"), 14);
+        assert_eq!(error_position("(status == 200"), 14);
+        assert_eq!(error_position(r#"host == "unterminated"#), 8);
+        assert_eq!(error_position("duration > 1x"), 11);
+        assert_eq!(error_position("duration > 1e400"), 11);
+        assert_eq!(error_position("duration > 100000000000000000000000s"), 11);
+    }
+}
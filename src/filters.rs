@@ -1,11 +1,319 @@
-use anyhow::{Context, Result};
+use std::{cell::LazyCell, net::IpAddr, time::Duration};
 
-use crate::record::LogRecord;
+use anyhow::{bail, Context, Result};
+use ipnet::IpNet;
+use regex::Regex;
+
+use crate::{
+    bots,
+    condition::Condition,
+    enrich,
+    expr::Expr,
+    geoip::GeoIp,
+    monitors::Monitors,
+    record::{strip_port, LogLevel, LogRecord, RecordView, RemoteIp},
+    rules::{self, Rule},
+    sample::Sample,
+    severity::Severity,
+    tls::{self, CipherPattern},
+};
 
 #[derive(Default)]
 pub struct FiltersBuilder {
     strict: bool,
-    host_patterns: Vec<glob::Pattern>,
+    min_level: Option<LogLevel>,
+    errors_only: bool,
+    loggers: Patterns<LoggerPattern>,
+    min_duration: Option<Duration>,
+    max_duration: Option<Duration>,
+    since: Option<f64>,
+    until: Option<f64>,
+    host_patterns: Patterns<glob::Pattern>,
+    uri_patterns: Patterns<glob::Pattern>,
+    uri_regexes: Vec<String>,
+    raw_regexes: Vec<String>,
+    conditions: Vec<Condition>,
+    methods: Patterns<http::Method>,
+    protos: Patterns<&'static [&'static str]>,
+    statuses: Patterns<StatusPattern>,
+    clients: Patterns<ClientPattern>,
+    countries: Patterns<String>,
+    geoip: Option<GeoIp>,
+    user_agents: Patterns<UserAgentPattern>,
+    headers: Vec<HeaderPattern>,
+    query: Vec<QueryPattern>,
+    request_ids: Vec<String>,
+    tls_versions: Patterns<u16>,
+    tls_ciphers: Patterns<CipherPattern>,
+    server_names: Patterns<glob::Pattern>,
+    sni_mismatch: bool,
+    upstreams: Patterns<glob::Pattern>,
+    content_types: Patterns<glob::Pattern>,
+    referers: Patterns<glob::Pattern>,
+    grep: Patterns<String>,
+    rules: Vec<Rule>,
+    sample: Option<Sample>,
+    hidden_monitors: Option<Monitors>,
+    hidden_bots: bool,
+}
+
+/// A parameter of the query, with a glob pattern of its value.
+struct QueryPattern {
+    name: String,
+    value: Option<glob::Pattern>,
+    negated: bool,
+}
+
+impl QueryPattern {
+    fn parse(s: &str) -> Result<Self> {
+        let (negated, parameter) = match s.strip_prefix('!') {
+            Some(parameter) => (true, parameter),
+            None => (false, s),
+        };
+        let (name, value) = match parameter.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (parameter, None),
+        };
+        if name.is_empty() {
+            bail!("invalid query filter: {}, the name is missing", s);
+        }
+        let value = value
+            .map(glob::Pattern::new)
+            .transpose()
+            .with_context(|| format!("invalid query filter: {}", s))?;
+        Ok(Self {
+            name: name.to_string(),
+            value,
+            negated,
+        })
+    }
+
+    /// Whether any value of the parameter matches, or none when the pattern is negated.
+    fn matches(&self, parameters: &[(String, String)]) -> bool {
+        let mut values = parameters
+            .iter()
+            .filter(|(name, _)| *name == self.name)
+            .map(|(_, value)| value);
+        let found = match &self.value {
+            None => values.next().is_some(),
+            Some(pattern) => values.any(|value| pattern.matches(value)),
+        };
+        found != self.negated
+    }
+}
+
+/// Decode a name or a value of a query, the `+` is a space and the invalid escapes are kept.
+fn decode_component(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+                continue;
+            }
+            (b'+', _) => decoded.push(b' '),
+            (byte, _) => decoded.push(byte),
+        }
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The patterns of a field: the values must match one of the included patterns, when there are
+/// any, and none of the excluded ones, which are written with a `!` prefix, e.g. `!4xx`.
+struct Patterns<T> {
+    included: Vec<T>,
+    excluded: Vec<T>,
+}
+
+impl<T> Default for Patterns<T> {
+    fn default() -> Self {
+        Self {
+            included: Vec::new(),
+            excluded: Vec::new(),
+        }
+    }
+}
+
+impl<T> Patterns<T> {
+    fn push(&mut self, pattern: &str, parse: impl FnOnce(&str) -> Result<T>) -> Result<()> {
+        match pattern.strip_prefix('!') {
+            Some(pattern) => self.excluded.push(parse(pattern)?),
+            None => self.included.push(parse(pattern)?),
+        }
+        Ok(())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.included.is_empty() && self.excluded.is_empty()
+    }
+
+    /// The records without the field match only when no pattern is included.
+    fn matches<V: Copy>(&self, value: Option<V>, matches: impl Fn(&T, V) -> bool) -> bool {
+        let Some(value) = value else {
+            return self.included.is_empty();
+        };
+        (self.included.is_empty() || self.included.iter().any(|p| matches(p, value)))
+            && !self.excluded.iter().any(|p| matches(p, value))
+    }
+}
+
+/// A request header which must be present, with a value matching the glob pattern when given,
+/// or which must not when the pattern is negated.
+struct HeaderPattern {
+    name: http::HeaderName,
+    value: Option<glob::Pattern>,
+    negated: bool,
+}
+
+impl HeaderPattern {
+    fn parse(s: &str) -> Result<Self> {
+        let (negated, header) = match s.strip_prefix('!') {
+            Some(header) => (true, header),
+            None => (false, s),
+        };
+        let (name, value) = match header.split_once(':') {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (header.trim(), None),
+        };
+        let name = http::HeaderName::from_bytes(name.as_bytes())
+            .with_context(|| format!("invalid header filter: {}", s))?;
+        let value = value
+            .map(glob::Pattern::new)
+            .transpose()
+            .with_context(|| format!("invalid header filter: {}", s))?;
+        Ok(Self {
+            name,
+            value,
+            negated,
+        })
+    }
+
+    /// Whether any value of the header matches, or none when the pattern is negated.
+    fn matches(&self, headers: &http::HeaderMap) -> bool {
+        let mut values = headers.get_all(&self.name).iter();
+        let found = match &self.value {
+            None => values.next().is_some(),
+            Some(pattern) => values.any(|value| {
+                value
+                    .to_str()
+                    .is_ok_and(|value| pattern.matches(value.trim()))
+            }),
+        };
+        found != self.negated
+    }
+}
+
+/// A network of the clients, or the clients of a unix socket.
+enum ClientPattern {
+    Network(IpNet),
+    Unix,
+}
+
+impl ClientPattern {
+    fn matches(&self, remote_ip: RemoteIp) -> bool {
+        match (self, remote_ip) {
+            (ClientPattern::Network(network), RemoteIp::Ip(ip)) => network.contains(&ip),
+            (ClientPattern::Unix, RemoteIp::Unix) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A regular expression, which must not match when it is negated.
+struct RegexPattern {
+    regex: Regex,
+    negated: bool,
+}
+
+impl RegexPattern {
+    fn parse(s: &str, kind: &str) -> Result<Self> {
+        let (negated, regex) = match s.strip_prefix('!') {
+            Some(regex) => (true, regex),
+            None => (false, s),
+        };
+        let regex =
+            Regex::new(regex).with_context(|| format!("invalid {} regex: {}", kind, regex))?;
+        Ok(Self { regex, negated })
+    }
+
+    fn matches(&self, value: &str) -> bool {
+        self.regex.is_match(value) != self.negated
+    }
+}
+
+/// A glob pattern of the logger name, or a logger with the ones below it, e.g. `tls` matches
+/// `tls.obtain` and `tls.cache.maintenance`.
+enum LoggerPattern {
+    Glob(glob::Pattern),
+    Name(String),
+}
+
+impl LoggerPattern {
+    fn matches(&self, logger: &str) -> bool {
+        match self {
+            LoggerPattern::Glob(pattern) => pattern.matches(logger),
+            LoggerPattern::Name(name) => logger
+                .strip_prefix(name.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('.')),
+        }
+    }
+}
+
+/// A glob pattern of the whole user agent, or a part of it.
+enum UserAgentPattern {
+    Glob(glob::Pattern),
+    Substring(String),
+}
+
+impl UserAgentPattern {
+    fn matches(&self, user_agent: &str) -> bool {
+        match self {
+            UserAgentPattern::Glob(pattern) => pattern.matches(user_agent),
+            UserAgentPattern::Substring(part) => user_agent.contains(part.as_str()),
+        }
+    }
+}
+
+/// A status code, or a class of status codes like `4xx`.
+#[derive(Clone, Copy, Debug)]
+pub enum StatusPattern {
+    Code(u16),
+    Class(u16),
+}
+
+impl StatusPattern {
+    pub fn parse(s: &str) -> Result<Self> {
+        let pattern = match s.to_ascii_lowercase().as_bytes() {
+            [class @ b'1'..=b'5', b'x', b'x'] => Some(Self::Class(u16::from(class - b'0'))),
+            _ => s
+                .parse::<u16>()
+                .ok()
+                .filter(|code| (100..=599).contains(code))
+                .map(Self::Code),
+        };
+        match pattern {
+            Some(pattern) => Ok(pattern),
+            None => bail!(
+                "invalid status filter: {}, expected a code or a class like 4xx",
+                s
+            ),
+        }
+    }
+
+    pub fn matches(self, status: u16) -> bool {
+        match self {
+            Self::Code(code) => status == code,
+            Self::Class(class) => status / 100 == class,
+        }
+    }
 }
 
 impl FiltersBuilder {
@@ -14,24 +322,396 @@ impl FiltersBuilder {
         self
     }
 
+    pub fn with_min_level(&mut self, level: LogLevel) -> &mut Self {
+        self.min_level = Some(level);
+        self
+    }
+
+    /// The record must be an error: a request whose status is an error, by default the 4xx and
+    /// the 5xx ones, or a record at the error level or above.
+    pub fn with_errors_only(&mut self) -> &mut Self {
+        self.errors_only = true;
+        self
+    }
+
+    /// The logger must be the given one or below it, or match the glob pattern when it has
+    /// wildcards. The loggers are excluded with a `!` prefix, e.g. `!tls`.
+    pub fn with_logger(&mut self, logger: &str) -> Result<&mut Self> {
+        self.loggers.push(logger, |logger| {
+            Ok(match logger.contains(['*', '?', '[']) {
+                true => LoggerPattern::Glob(
+                    glob::Pattern::new(logger)
+                        .with_context(|| format!("invalid logger filter: {}", logger))?,
+                ),
+                false => LoggerPattern::Name(logger.to_string()),
+            })
+        })?;
+        Ok(self)
+    }
+
+    pub fn with_min_duration(&mut self, duration: Duration) -> &mut Self {
+        self.min_duration = Some(duration);
+        self
+    }
+
+    pub fn with_max_duration(&mut self, duration: Duration) -> &mut Self {
+        self.max_duration = Some(duration);
+        self
+    }
+
+    /// The records must be logged at or after the time, in seconds since the epoch.
+    pub fn with_since(&mut self, timestamp: f64) -> &mut Self {
+        self.since = Some(timestamp);
+        self
+    }
+
+    /// The records must be logged at or before the time, in seconds since the epoch.
+    pub fn with_until(&mut self, timestamp: f64) -> &mut Self {
+        self.until = Some(timestamp);
+        self
+    }
+
     pub fn with_host(&mut self, host: &str) -> Result<&mut Self> {
-        let pattern =
-            glob::Pattern::new(host).with_context(|| format!("invalid host filter: {}", host))?;
-        self.host_patterns.push(pattern);
+        self.host_patterns.push(host, |host| {
+            glob::Pattern::new(host).with_context(|| format!("invalid host filter: {}", host))
+        })?;
+        Ok(self)
+    }
+
+    pub fn with_uri(&mut self, uri: &str) -> Result<&mut Self> {
+        self.uri_patterns.push(uri, |uri| {
+            glob::Pattern::new(uri).with_context(|| format!("invalid uri filter: {}", uri))
+        })?;
+        Ok(self)
+    }
+
+    /// The regular expression is compiled when the filters are built, with a `!` prefix the URI
+    /// must not match it.
+    pub fn with_uri_regex(&mut self, regex: &str) -> &mut Self {
+        self.uri_regexes.push(regex.to_string());
+        self
+    }
+
+    /// The original line must match the regular expression, or not match it with a `!` prefix.
+    /// It is compiled when the filters are built.
+    pub fn with_raw_regex(&mut self, regex: &str) -> &mut Self {
+        self.raw_regexes.push(regex.to_string());
+        self
+    }
+
+    /// The JSON document of the record must satisfy the condition, e.g.
+    /// `.request.tls.server_name == "*.example.com"`.
+    pub fn with_where(&mut self, condition: &str) -> Result<&mut Self> {
+        let condition = condition
+            .parse::<Condition>()
+            .with_context(|| format!("invalid where condition: {}", condition))?;
+        self.conditions.push(condition);
+        Ok(self)
+    }
+
+    /// The method is case insensitive, e.g. `post` matches the `POST` requests.
+    pub fn with_method(&mut self, method: &str) -> Result<&mut Self> {
+        self.methods.push(method, |method| {
+            http::Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                .with_context(|| format!("invalid method filter: {}", method))
+        })?;
+        Ok(self)
+    }
+
+    /// The version of HTTP of the request, `h1`, `h2` or `h3`, or an exact one like `1.0`.
+    pub fn with_proto(&mut self, proto: &str) -> Result<&mut Self> {
+        self.protos.push(proto, |proto| {
+            let version = proto.to_ascii_lowercase();
+            let version = version.strip_prefix("http/").unwrap_or(&version);
+            Ok(match version.strip_prefix('h').unwrap_or(version) {
+                "1" => &["HTTP/1.0", "HTTP/1.1"],
+                "1.0" => &["HTTP/1.0"],
+                "1.1" => &["HTTP/1.1"],
+                "2" | "2.0" => &["HTTP/2.0"],
+                "3" | "3.0" => &["HTTP/3.0"],
+                _ => bail!("invalid proto filter: {}, expected h1, h2 or h3", proto),
+            })
+        })?;
+        Ok(self)
+    }
+
+    pub fn with_status(&mut self, status: &str) -> Result<&mut Self> {
+        self.statuses.push(status, StatusPattern::parse)?;
         Ok(self)
     }
 
+    /// The remote address must be in the network, in CIDR notation, or be the given address.
+    /// The clients of a unix socket are matched by `unix`, and `!` excludes the clients.
+    pub fn with_remote_ip(&mut self, network: &str) -> Result<&mut Self> {
+        self.clients.push(network, |network| {
+            if network == "unix" {
+                return Ok(ClientPattern::Unix);
+            }
+            let network = network
+                .strip_prefix('[')
+                .and_then(|network| network.strip_suffix(']'))
+                .unwrap_or(network);
+            rules::parse_network(network).map(ClientPattern::Network)
+        })?;
+        Ok(self)
+    }
+
+    /// The country of the remote address must be the given one, as an ISO code like `DE`, or
+    /// not with a `!` prefix. The countries are looked up in the database of
+    /// [`FiltersBuilder::with_geoip`].
+    pub fn with_country(&mut self, country: &str) -> Result<&mut Self> {
+        self.countries.push(country, |country| {
+            if country.len() != 2 || !country.bytes().all(|b| b.is_ascii_alphabetic()) {
+                bail!(
+                    "invalid country filter: {}, expected an ISO code like DE",
+                    country
+                );
+            }
+            Ok(country.to_ascii_uppercase())
+        })?;
+        Ok(self)
+    }
+
+    pub fn with_geoip(&mut self, geoip: GeoIp) -> &mut Self {
+        self.geoip = Some(geoip);
+        self
+    }
+
+    /// The user agent is matched by the glob pattern, or it must contain the value when it has
+    /// no wildcards.
+    pub fn with_user_agent(&mut self, user_agent: &str) -> Result<&mut Self> {
+        self.user_agents.push(user_agent, |user_agent| {
+            Ok(match user_agent.contains(['*', '?', '[']) {
+                true => UserAgentPattern::Glob(
+                    glob::Pattern::new(user_agent)
+                        .with_context(|| format!("invalid user agent filter: {}", user_agent))?,
+                ),
+                false => UserAgentPattern::Substring(user_agent.to_string()),
+            })
+        })?;
+        Ok(self)
+    }
+
+    /// The header must be present, e.g. `X-Request-Id`, or have a value matching the glob
+    /// pattern after the colon, e.g. `Accept: application/*`. With a `!` prefix it must be
+    /// missing, or have no matching value.
+    pub fn with_header(&mut self, header: &str) -> Result<&mut Self> {
+        self.headers.push(HeaderPattern::parse(header)?);
+        Ok(self)
+    }
+
+    /// The query of the request must have the parameter, e.g. `page`, or have a value
+    /// matching the glob pattern after the equal sign, e.g. `q=*shoes*`; the names and the
+    /// values are decoded. With a `!` prefix it must be missing, or have no matching value.
+    pub fn with_query(&mut self, parameter: &str) -> Result<&mut Self> {
+        self.query.push(QueryPattern::parse(parameter)?);
+        Ok(self)
+    }
+
+    /// The request must carry the identifier in one of its correlation headers, e.g.
+    /// `X-Request-Id`, or be part of the trace of its `traceparent` header.
+    pub fn with_request_id(&mut self, id: &str) -> &mut Self {
+        self.request_ids.push(id.to_string());
+        self
+    }
+
+    /// The TLS version of the request must be the given one, e.g. `1.2`, or not with a `!`
+    /// prefix.
+    pub fn with_tls_version(&mut self, version: &str) -> Result<&mut Self> {
+        self.tls_versions.push(version, tls::parse_version)?;
+        Ok(self)
+    }
+
+    /// The cipher suite of the request must match the pattern, by number or by name, or not
+    /// match it with a `!` prefix.
+    pub fn with_tls_cipher(&mut self, cipher: &str) -> Result<&mut Self> {
+        self.tls_ciphers.push(cipher, |cipher| {
+            CipherPattern::parse(cipher)
+                .with_context(|| format!("invalid cipher suite filter: {}", cipher))
+        })?;
+        Ok(self)
+    }
+
+    /// The server name requested by the client (SNI) must match the glob pattern, or not match
+    /// it with a `!` prefix. The case is ignored.
+    pub fn with_sni(&mut self, server_name: &str) -> Result<&mut Self> {
+        self.server_names.push(server_name, |server_name| {
+            glob::Pattern::new(&server_name.to_ascii_lowercase())
+                .with_context(|| format!("invalid SNI filter: {}", server_name))
+        })?;
+        Ok(self)
+    }
+
+    /// The server name requested by the client (SNI) must differ from the `Host` header.
+    pub fn with_sni_mismatch(&mut self) -> &mut Self {
+        self.sni_mismatch = true;
+        self
+    }
+
+    /// The upstream the request was forwarded to must match the glob pattern, with or without
+    /// its port, e.g. `10.0.0.7` or `*:8080`, or not match it with a `!` prefix.
+    pub fn with_upstream(&mut self, upstream: &str) -> Result<&mut Self> {
+        self.upstreams.push(upstream, |upstream| {
+            glob::Pattern::new(upstream)
+                .with_context(|| format!("invalid upstream filter: {}", upstream))
+        })?;
+        Ok(self)
+    }
+
+    /// The media type of the response, its `Content-Type` header without the parameters, must
+    /// match the glob pattern, e.g. `text/html` or `image/*`, or not match it with a `!`
+    /// prefix. The case is ignored.
+    pub fn with_resp_content_type(&mut self, content_type: &str) -> Result<&mut Self> {
+        self.content_types.push(content_type, |content_type| {
+            glob::Pattern::new(&content_type.to_ascii_lowercase())
+                .with_context(|| format!("invalid content type filter: {}", content_type))
+        })?;
+        Ok(self)
+    }
+
+    /// The `Referer` header of the request must match the glob pattern, e.g.
+    /// `https://blog.example.com/*`, or not match it with a `!` prefix.
+    pub fn with_referer(&mut self, referer: &str) -> Result<&mut Self> {
+        self.referers.push(referer, |referer| {
+            glob::Pattern::new(referer)
+                .with_context(|| format!("invalid referer filter: {}", referer))
+        })?;
+        Ok(self)
+    }
+
+    /// The message, or the request line of the access logs, must contain the text, or not
+    /// contain it with a `!` prefix.
+    pub fn with_grep(&mut self, text: &str) -> &mut Self {
+        // the texts are not parsed, they cannot fail
+        let _ = self.grep.push(text, |text| Ok(text.to_string()));
+        self
+    }
+
+    /// The record must satisfy the expression, e.g. `status >= 500 && duration > 1s`.
+    pub fn with_expr(&mut self, expr: &str) -> Result<&mut Self> {
+        let expr = expr
+            .parse::<Expr>()
+            .with_context(|| format!("invalid filter expression: {}", expr))?;
+        self.rules.push(Rule::Expr(expr));
+        Ok(self)
+    }
+
+    pub fn with_rule(&mut self, rule: Rule) -> &mut Self {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn with_sample(&mut self, sample: Sample) -> &mut Self {
+        self.sample = Some(sample);
+        self
+    }
+
+    /// Drop the requests of the monitoring agents.
+    pub fn with_hidden_monitors(&mut self, monitors: Monitors) -> &mut Self {
+        self.hidden_monitors = Some(monitors);
+        self
+    }
+
+    /// Drop the requests of the crawlers, the bots and the scanners.
+    pub fn with_hidden_bots(&mut self) -> &mut Self {
+        self.hidden_bots = true;
+        self
+    }
+
     pub fn build(self) -> Result<Filters> {
+        if !self.countries.is_empty() && self.geoip.is_none() {
+            bail!("the country filters need a GeoIP database");
+        }
+        let uri_regexes = self
+            .uri_regexes
+            .iter()
+            .map(|regex| RegexPattern::parse(regex, "uri"))
+            .collect::<Result<_>>()?;
+        let raw_regexes = self
+            .raw_regexes
+            .iter()
+            .map(|regex| RegexPattern::parse(regex, "raw"))
+            .collect::<Result<_>>()?;
         Ok(Filters {
             strict: self.strict,
+            min_level: self.min_level,
+            errors_only: self.errors_only,
+            loggers: self.loggers,
+            min_duration: self.min_duration.map(|duration| duration.as_secs_f64()),
+            max_duration: self.max_duration.map(|duration| duration.as_secs_f64()),
+            since: self.since,
+            until: self.until,
             host_patterns: self.host_patterns,
+            uri_patterns: self.uri_patterns,
+            uri_regexes,
+            raw_regexes,
+            conditions: self.conditions,
+            methods: self.methods,
+            protos: self.protos,
+            statuses: self.statuses,
+            clients: self.clients,
+            countries: self.countries,
+            geoip: self.geoip,
+            user_agents: self.user_agents,
+            headers: self.headers,
+            query: self.query,
+            request_ids: self.request_ids,
+            tls_versions: self.tls_versions,
+            tls_ciphers: self.tls_ciphers,
+            server_names: self.server_names,
+            sni_mismatch: self.sni_mismatch,
+            upstreams: self.upstreams,
+            content_types: self.content_types,
+            referers: self.referers,
+            grep: self.grep,
+            rules: self.rules,
+            sample: self.sample,
+            hidden_monitors: self.hidden_monitors,
+            hidden_bots: self.hidden_bots,
         })
     }
 }
 
 pub struct Filters {
     strict: bool,
-    host_patterns: Vec<glob::Pattern>,
+    min_level: Option<LogLevel>,
+    errors_only: bool,
+    loggers: Patterns<LoggerPattern>,
+    /// The bounds of the duration, in seconds.
+    min_duration: Option<f64>,
+    max_duration: Option<f64>,
+    /// The bounds of the time of the records, in seconds since the epoch.
+    since: Option<f64>,
+    until: Option<f64>,
+    host_patterns: Patterns<glob::Pattern>,
+    uri_patterns: Patterns<glob::Pattern>,
+    uri_regexes: Vec<RegexPattern>,
+    raw_regexes: Vec<RegexPattern>,
+    /// The conditions over the JSON documents, they are checked before the lines are parsed as
+    /// records.
+    conditions: Vec<Condition>,
+    methods: Patterns<http::Method>,
+    protos: Patterns<&'static [&'static str]>,
+    statuses: Patterns<StatusPattern>,
+    clients: Patterns<ClientPattern>,
+    countries: Patterns<String>,
+    geoip: Option<GeoIp>,
+    user_agents: Patterns<UserAgentPattern>,
+    headers: Vec<HeaderPattern>,
+    query: Vec<QueryPattern>,
+    request_ids: Vec<String>,
+    tls_versions: Patterns<u16>,
+    tls_ciphers: Patterns<CipherPattern>,
+    server_names: Patterns<glob::Pattern>,
+    sni_mismatch: bool,
+    upstreams: Patterns<glob::Pattern>,
+    content_types: Patterns<glob::Pattern>,
+    referers: Patterns<glob::Pattern>,
+    grep: Patterns<String>,
+    rules: Vec<Rule>,
+    sample: Option<Sample>,
+    hidden_monitors: Option<Monitors>,
+    hidden_bots: bool,
 }
 
 impl Filters {
@@ -43,20 +723,438 @@ impl Filters {
         self.strict
     }
 
+    /// Whether the original line matches the regular expressions and the conditions, it is
+    /// checked before the line is parsed.
+    pub fn matches_line(&self, line: &str) -> bool {
+        if !self.raw_regexes.iter().all(|regex| regex.matches(line)) {
+            return false;
+        }
+        // the identifiers are searched before the records are parsed, most lines lack them
+        if !self.request_ids.is_empty() && !self.request_ids.iter().any(|id| line.contains(id)) {
+            return false;
+        }
+        if self.conditions.is_empty() {
+            return true;
+        }
+        let Ok(document) = serde_json::from_str::<serde_json::Value>(line) else {
+            return false;
+        };
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(&document))
+    }
+
     pub fn matches(&self, record: &LogRecord) -> bool {
-        self.matches_host(record)
+        let host = record.request.as_ref().map(|req| req.host.as_str());
+        self.matches_level(record.level)
+            && (!self.errors_only
+                || record.level >= LogLevel::Error
+                || record.severity().is_some_and(Severity::is_error))
+            && self.matches_time(record.timestamp)
+            && self.matches_logger(record.logger.as_deref())
+            && self.matches_duration(record.duration)
+            && self.matches_host(host)
+            && self.matches_uri(record.request.as_ref().map(|req| req.uri.as_str()))
+            && self.matches_method(record.request.as_ref().map(|req| req.method.as_str()))
+            && (self.protos.is_empty()
+                || self.matches_proto(
+                    record
+                        .request
+                        .as_ref()
+                        .map(|req| format!("{:?}", req.version))
+                        .as_deref(),
+                ))
+            && self.matches_status(record.status.map(|status| status.as_u16()))
+            && self.matches_remote_ip(record.request.as_ref().map(|req| req.remote_ip))
+            && self.matches_country(record.request.as_ref().and_then(|req| req.remote_ip.ip()))
+            && self.matches_user_agent(record)
+            && self.matches_headers(record)
+            && self.matches_query(record)
+            && self.matches_request_id(record)
+            && self.matches_tls(record)
+            && self
+                .upstreams
+                .matches(record.upstream.as_deref(), |pattern, upstream| {
+                    pattern.matches(upstream) || pattern.matches(strip_port(upstream))
+                })
+            && self.matches_content_type(record)
+            && self.matches_referer(record)
+            && self.matches_grep(&record.message, || {
+                record.request.as_ref().map(LogRecord::request_line)
+            })
+            && self.rules.iter().all(|rule| rule.matches(record))
+            && self
+                .sample
+                .as_ref()
+                .is_none_or(|sample| sample.matches(record))
+            && self
+                .hidden_monitors
+                .as_ref()
+                .is_none_or(|monitors| monitors.find(record).is_none())
+            && !(self.hidden_bots && bots::is_bot(record))
+    }
+
+    /// Whether the filters can be evaluated on a [`RecordView`], without parsing the whole
+    /// record.
+    pub fn is_simple(&self) -> bool {
+        self.rules.is_empty()
+            && !self.errors_only
+            && self.user_agents.is_empty()
+            && self.headers.is_empty()
+            && self.query.is_empty()
+            && self.request_ids.is_empty()
+            && self.tls_versions.is_empty()
+            && self.tls_ciphers.is_empty()
+            && self.server_names.is_empty()
+            && !self.sni_mismatch
+            && self.upstreams.is_empty()
+            && self.content_types.is_empty()
+            && self.referers.is_empty()
+            && self.countries.is_empty()
+            && self.sample.is_none()
+            && self.hidden_monitors.is_none()
+            && !self.hidden_bots
+    }
+
+    /// The same as [`Filters::matches`] for the simple filters.
+    pub fn matches_view(&self, view: &RecordView) -> bool {
+        self.matches_level(view.level)
+            && self.matches_time(view.timestamp)
+            && self.matches_logger(view.logger.as_deref())
+            && self.matches_duration(view.duration)
+            && self.matches_host(view.request.as_ref().map(|req| req.host.as_ref()))
+            && self.matches_uri(view.request.as_ref().map(|req| req.uri.as_ref()))
+            && self.matches_method(view.request.as_ref().map(|req| req.method.as_str()))
+            && (self.protos.is_empty()
+                || self.matches_proto(
+                    view.request
+                        .as_ref()
+                        .map(|req| format!("{:?}", req.version))
+                        .as_deref(),
+                ))
+            && self.matches_status(view.status.map(|status| status.as_u16()))
+            && self.matches_remote_ip(view.request.as_ref().map(|req| req.remote_ip))
+            && self.matches_grep(&view.message, || {
+                let request = view.request.as_ref()?;
+                Some(format!(
+                    "{} {} {:?}",
+                    request.method, request.uri, request.version
+                ))
+            })
+    }
+
+    fn matches_level(&self, level: LogLevel) -> bool {
+        self.min_level.is_none_or(|min_level| level >= min_level)
+    }
+
+    fn matches_time(&self, timestamp: f64) -> bool {
+        self.since.is_none_or(|since| timestamp >= since)
+            && self.until.is_none_or(|until| timestamp <= until)
+    }
+
+    fn matches_logger(&self, logger: Option<&str>) -> bool {
+        self.loggers
+            .matches(logger, |pattern, logger| pattern.matches(logger))
     }
 
-    fn matches_host(&self, record: &LogRecord) -> bool {
-        if self.host_patterns.is_empty() {
+    /// The records without a duration are dropped when a bound is given.
+    fn matches_duration(&self, duration: Option<f64>) -> bool {
+        if self.min_duration.is_none() && self.max_duration.is_none() {
             return true;
-        };
-        let Some(host) = record.request.as_ref().map(|req| req.host.as_str()) else {
+        }
+        let Some(duration) = duration else {
             return false;
         };
+        self.min_duration.is_none_or(|min| duration >= min)
+            && self.max_duration.is_none_or(|max| duration <= max)
+    }
 
-        self.host_patterns
+    /// The URI must match one of the patterns, when given, and all the regular expressions.
+    fn matches_uri(&self, uri: Option<&str>) -> bool {
+        if self.uri_patterns.is_empty() && self.uri_regexes.is_empty() {
+            return true;
+        }
+        let Some(uri) = uri else {
+            return self.uri_patterns.included.is_empty()
+                && self.uri_regexes.iter().all(|regex| regex.negated);
+        };
+        self.uri_patterns
+            .matches(Some(uri), |pattern, uri| pattern.matches(uri))
+            && self.uri_regexes.iter().all(|regex| regex.matches(uri))
+    }
+
+    fn matches_method(&self, method: Option<&str>) -> bool {
+        self.methods
+            .matches(method, |expected, method| expected == method)
+    }
+
+    fn matches_proto(&self, proto: Option<&str>) -> bool {
+        self.protos
+            .matches(proto, |versions, proto| versions.contains(&proto))
+    }
+
+    fn matches_status(&self, status: Option<u16>) -> bool {
+        self.statuses
+            .matches(status, |pattern, status| pattern.matches(status))
+    }
+
+    fn matches_remote_ip(&self, remote_ip: Option<RemoteIp>) -> bool {
+        self.clients
+            .matches(remote_ip, |pattern, remote_ip| pattern.matches(remote_ip))
+    }
+
+    /// The addresses without a country, e.g. the private ones, are not in any country.
+    fn matches_country(&self, ip: Option<IpAddr>) -> bool {
+        if self.countries.is_empty() {
+            return true;
+        }
+        let country = ip
+            .zip(self.geoip.as_ref())
+            .and_then(|(ip, geoip)| geoip.country(ip));
+        self.countries
+            .matches(country, |pattern, country| pattern == country)
+    }
+
+    fn matches_user_agent(&self, record: &LogRecord) -> bool {
+        if self.user_agents.is_empty() {
+            return true;
+        }
+        let user_agent = record
+            .request
+            .as_ref()
+            .and_then(|request| request.headers.get(http::header::USER_AGENT))
+            .and_then(|value| value.to_str().ok());
+        self.user_agents.matches(user_agent, |pattern, user_agent| {
+            pattern.matches(user_agent)
+        })
+    }
+
+    /// The responses without a body, like the redirects and the 304, have no content type.
+    fn matches_content_type(&self, record: &LogRecord) -> bool {
+        if self.content_types.is_empty() {
+            return true;
+        }
+        let content_type = record
+            .resp_headers
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| {
+                let media_type = value.split(';').next().unwrap_or_default();
+                media_type.trim().to_ascii_lowercase()
+            });
+        self.content_types
+            .matches(content_type.as_deref(), |pattern, content_type| {
+                pattern.matches(content_type)
+            })
+    }
+
+    /// The requests typed by hand, or by the clients hiding it, have no referer.
+    fn matches_referer(&self, record: &LogRecord) -> bool {
+        if self.referers.is_empty() {
+            return true;
+        }
+        let referer = record
+            .request
+            .as_ref()
+            .and_then(|request| request.headers.get(http::header::REFERER))
+            .and_then(|value| value.to_str().ok());
+        self.referers
+            .matches(referer, |pattern, referer| pattern.matches(referer))
+    }
+
+    /// The plain HTTP requests have no TLS version, no cipher suite and no server name.
+    fn matches_tls(&self, record: &LogRecord) -> bool {
+        let request = record.request.as_ref();
+        let tls = request.and_then(|request| request.tls.as_ref());
+        self.tls_versions
+            .matches(tls.and_then(|tls| tls.version), |&pattern, version| {
+                pattern == version
+            })
+            && self.tls_ciphers.matches(
+                tls.and_then(|tls| tls.cipher_suite),
+                |pattern, cipher_suite| pattern.matches(cipher_suite),
+            )
+            && self.server_names.matches(
+                tls.and_then(|tls| tls.server_name.as_deref()),
+                |pattern, server_name| pattern.matches(&server_name.to_ascii_lowercase()),
+            )
+            && (!self.sni_mismatch
+                || request.is_some_and(|request| {
+                    tls.is_some_and(|tls| tls.server_name_mismatch(&request.host))
+                }))
+    }
+
+    fn matches_request_id(&self, record: &LogRecord) -> bool {
+        self.request_ids.is_empty()
+            || enrich::request_ids(record)
+                .into_iter()
+                .any(|id| self.request_ids.iter().any(|wanted| wanted == id))
+    }
+
+    /// The request must match all the header filters.
+    fn matches_headers(&self, record: &LogRecord) -> bool {
+        if self.headers.is_empty() {
+            return true;
+        }
+        let Some(request) = &record.request else {
+            return self.headers.iter().all(|pattern| pattern.negated);
+        };
+        self.headers
+            .iter()
+            .all(|pattern| pattern.matches(&request.headers))
+    }
+
+    fn matches_query(&self, record: &LogRecord) -> bool {
+        if self.query.is_empty() {
+            return true;
+        }
+        let query = record
+            .request
+            .as_ref()
+            .and_then(|request| request.uri.split_once('?'))
+            .map(|(_, query)| query)
+            .unwrap_or_default();
+        let parameters = query
+            .split('&')
+            .filter(|parameter| !parameter.is_empty())
+            .map(|parameter| {
+                let (name, value) = parameter.split_once('=').unwrap_or((parameter, ""));
+                (decode_component(name), decode_component(value))
+            })
+            .collect::<Vec<_>>();
+        self.query
             .iter()
-            .any(|pattern| pattern.matches(host))
+            .all(|pattern| pattern.matches(&parameters))
+    }
+
+    /// The message or the request line, built only when needed, must contain one of the texts.
+    fn matches_grep(&self, message: &str, request_line: impl FnOnce() -> Option<String>) -> bool {
+        if self.grep.is_empty() {
+            return true;
+        }
+        let request_line = LazyCell::new(request_line);
+        self.grep.matches(Some(()), |text, ()| {
+            message.contains(text.as_str())
+                || request_line
+                    .as_deref()
+                    .is_some_and(|line| line.contains(text.as_str()))
+        })
+    }
+
+    fn matches_host(&self, host: Option<&str>) -> bool {
+        self.host_patterns
+            .matches(host, |pattern, host| pattern.matches(host))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn record(remote_ip: &str, status: u16) -> LogRecord {
+        serde_json::from_value(json!({
+            "level": "info",
+            "ts": 1700000000.0,
+            "logger": "http.log.access",
+            "msg": "handled request",
+            "request": {
+                "remote_ip": remote_ip,
+                "remote_port": "51234",
+                "proto": "HTTP/1.1",
+                "method": "GET",
+                "host": "example.com",
+                "uri": "/",
+                "headers": {}
+            },
+            "duration": 0.1,
+            "size": 512,
+            "status": status,
+            "resp_headers": {}
+        }))
+        .unwrap()
+    }
+
+    fn remote_ips(networks: &[&str]) -> Filters {
+        let mut builder = Filters::builder();
+        for network in networks {
+            builder.with_remote_ip(network).unwrap();
+        }
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn parse_status_patterns() {
+        assert!(matches!(
+            StatusPattern::parse("404").unwrap(),
+            StatusPattern::Code(404)
+        ));
+        assert!(matches!(
+            StatusPattern::parse("5xx").unwrap(),
+            StatusPattern::Class(5)
+        ));
+        assert!(matches!(
+            StatusPattern::parse("4XX").unwrap(),
+            StatusPattern::Class(4)
+        ));
+        for pattern in ["", "99", "600", "6xx", "0xx", "4x", "40x", "abc", "-404"] {
+            let err = StatusPattern::parse(pattern).unwrap_err();
+            assert!(
+                err.to_string().contains("invalid status filter"),
+                "{pattern}"
+            );
+        }
+    }
+
+    #[test]
+    fn match_status_patterns() {
+        let class = StatusPattern::parse("4xx").unwrap();
+        assert!(class.matches(400));
+        assert!(class.matches(499));
+        assert!(!class.matches(500));
+        assert!(!class.matches(399));
+        let code = StatusPattern::parse("404").unwrap();
+        assert!(code.matches(404));
+        assert!(!code.matches(403));
+    }
+
+    #[test]
+    fn match_networks() {
+        let filters = remote_ips(&["10.0.0.0/8", "2001:db8::/32"]);
+        assert!(filters.matches(&record("10.1.2.3", 200)));
+        assert!(filters.matches(&record("2001:db8::1", 200)));
+        // the IPv4 addresses mapped to IPv6 are matched as IPv4
+        assert!(filters.matches(&record("::ffff:10.0.0.1", 200)));
+        assert!(!filters.matches(&record("192.168.1.1", 200)));
+        assert!(!filters.matches(&record("2001:db9::1", 200)));
+        assert!(!filters.matches(&record("@", 200)));
+    }
+
+    #[test]
+    fn match_addresses() {
+        let filters = remote_ips(&["192.168.1.1", "[2001:db8::1]"]);
+        assert!(filters.matches(&record("192.168.1.1", 200)));
+        assert!(filters.matches(&record("[2001:db8::1]", 200)));
+        assert!(!filters.matches(&record("192.168.1.2", 200)));
+        assert!(!filters.matches(&record("2001:db8::2", 200)));
+    }
+
+    #[test]
+    fn exclude_networks() {
+        let filters = remote_ips(&["!10.0.0.0/8"]);
+        assert!(!filters.matches(&record("10.1.2.3", 200)));
+        assert!(filters.matches(&record("192.168.1.1", 200)));
+        let filters = remote_ips(&["unix"]);
+        assert!(filters.matches(&record("@", 200)));
+        assert!(!filters.matches(&record("10.1.2.3", 200)));
+    }
+
+    #[test]
+    fn reject_invalid_networks() {
+        for network in ["10.0.0.0/33", "10.0.0", "2001:db8::/129", "example.com"] {
+            let err = Filters::builder().with_remote_ip(network).err().unwrap();
+            assert!(format!("{err:#}").contains(network), "{network}: {err:#}");
+        }
     }
 }
@@ -0,0 +1,432 @@
+use std::{fs, net::IpAddr, path::Path};
+
+use anyhow::{bail, Context, Result};
+
+/// The marker placed before the metadata, at the end of the databases.
+const METADATA_MARKER: &[u8] = b"\xab\xcd\xefMaxMind.com";
+
+/// A MaxMind database, e.g. GeoLite2 Country or City, read in memory. Only the countries of
+/// the addresses are looked up.
+///
+/// The file is a binary tree over the bits of the addresses, whose leaves point into a data
+/// section holding the records, in the format described at
+/// https://maxmind.github.io/MaxMind-DB/.
+pub struct GeoIp {
+    data: Vec<u8>,
+    node_count: usize,
+    record_size: usize,
+    /// The bounds of the data section, its pointers are relative to the start.
+    data_section: (usize, usize),
+    /// The node of the IPv4 addresses, they are mapped to `::a.b.c.d` in the IPv6 databases.
+    ipv4_start: usize,
+    ipv6: bool,
+}
+
+impl GeoIp {
+    pub fn open(path: &Path) -> Result<Self> {
+        let data = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(data).with_context(|| format!("invalid GeoIP database: {}", path.display()))
+    }
+
+    fn parse(data: Vec<u8>) -> Result<Self> {
+        let Some(marker) = data
+            .windows(METADATA_MARKER.len())
+            .rposition(|window| window == METADATA_MARKER)
+        else {
+            bail!("the metadata are missing");
+        };
+        let metadata = Decoder {
+            section: &data[marker + METADATA_MARKER.len()..],
+        };
+        let field = |key| {
+            metadata
+                .get(0, key)
+                .and_then(|offset| metadata.uint(offset))
+                .with_context(|| format!("the metadata have no {}", key))
+        };
+        let node_count = field("node_count")? as usize;
+        let record_size = field("record_size")? as usize;
+        let ipv6 = match field("ip_version")? {
+            4 => false,
+            6 => true,
+            version => bail!("unsupported IP version: {}", version),
+        };
+        if ![24, 28, 32].contains(&record_size) {
+            bail!("unsupported record size: {}", record_size);
+        }
+        // the tree is followed by 16 zero bytes
+        let data_start = node_count
+            .checked_mul(record_size / 4)
+            .and_then(|size| size.checked_add(16));
+        let data_start = match data_start {
+            Some(data_start) if data_start <= marker => data_start,
+            _ => bail!("the search tree is truncated"),
+        };
+        let mut geoip = Self {
+            data,
+            node_count,
+            record_size,
+            data_section: (data_start, marker),
+            ipv4_start: 0,
+            ipv6,
+        };
+        if ipv6 {
+            let mut node = 0;
+            for _ in 0..96 {
+                if node >= node_count {
+                    break;
+                }
+                node = geoip
+                    .record(node, 0)
+                    .context("the search tree is truncated")?;
+            }
+            geoip.ipv4_start = node;
+        }
+        Ok(geoip)
+    }
+
+    /// The ISO code of the country of the address, e.g. `DE`, the registered country is used
+    /// when the actual one is unknown.
+    pub fn country(&self, ip: IpAddr) -> Option<&str> {
+        let offset = self.lookup(ip)?;
+        let decoder = Decoder {
+            section: &self.data[self.data_section.0..self.data_section.1],
+        };
+        let country = decoder
+            .get(offset, "country")
+            .or_else(|| decoder.get(offset, "registered_country"))?;
+        decoder.string(decoder.get(country, "iso_code")?)
+    }
+
+    /// The offset of the record of the address in the data section.
+    fn lookup(&self, ip: IpAddr) -> Option<usize> {
+        let (bytes, bits, mut node) = match ip.to_canonical() {
+            IpAddr::V4(ip) => {
+                let mut bytes = [0; 16];
+                bytes[..4].copy_from_slice(&ip.octets());
+                (bytes, 32, self.ipv4_start)
+            }
+            IpAddr::V6(_) if !self.ipv6 => return None,
+            IpAddr::V6(ip) => (ip.octets(), 128, 0),
+        };
+        for index in 0..bits {
+            if node >= self.node_count {
+                break;
+            }
+            let bit = (bytes[index / 8] >> (7 - index % 8)) & 1;
+            node = self.record(node, bit)?;
+        }
+        // the node count itself marks the addresses without a record
+        match node.checked_sub(self.node_count + 16) {
+            Some(offset) if node > self.node_count => Some(offset),
+            _ => None,
+        }
+    }
+
+    /// The left (0) or the right (1) record of a node of the tree.
+    fn record(&self, node: usize, bit: u8) -> Option<usize> {
+        let size = self.record_size / 4;
+        let bytes = self.data.get(node * size..(node + 1) * size)?;
+        let number = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .fold(0, |number, &byte| number << 8 | byte as usize)
+        };
+        let record = match (self.record_size, bit) {
+            (24, 0) => number(&bytes[..3]),
+            (24, _) => number(&bytes[3..]),
+            (28, 0) => (bytes[3] as usize & 0xf0) << 20 | number(&bytes[..3]),
+            (28, _) => (bytes[3] as usize & 0x0f) << 24 | number(&bytes[4..]),
+            (_, 0) => number(&bytes[..4]),
+            (_, _) => number(&bytes[4..]),
+        };
+        Some(record)
+    }
+}
+
+/// The types of the values of the data section.
+const POINTER: u8 = 1;
+const STRING: u8 = 2;
+const UINT16: u8 = 5;
+const UINT32: u8 = 6;
+const MAP: u8 = 7;
+const UINT64: u8 = 9;
+const UINT128: u8 = 10;
+const ARRAY: u8 = 11;
+const BOOLEAN: u8 = 14;
+
+/// The values of a data section, addressed by their offset.
+struct Decoder<'a> {
+    section: &'a [u8],
+}
+
+/// The control byte of a value: its type, its size (the number of bytes, or of entries of the
+/// maps and of the arrays, or the target of the pointers) and where its payload starts.
+struct Header {
+    kind: u8,
+    size: usize,
+    payload: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn header(&self, offset: usize) -> Option<Header> {
+        let control = *self.section.get(offset)?;
+        let mut payload = offset + 1;
+        let mut kind = control >> 5;
+        if kind == 0 {
+            // the extended types, the values overflowing a byte are invalid
+            kind = 7u8.checked_add(*self.section.get(payload)?)?;
+            payload += 1;
+        }
+        if kind == POINTER {
+            let (len, base) = match (control >> 3) & 3 {
+                0 => (1, 0),
+                1 => (2, 2048),
+                2 => (3, 526336),
+                _ => (4, 0),
+            };
+            let high = if len == 4 { 0 } else { control as usize & 7 };
+            let size = base + self.number(high, payload, len)?;
+            return Some(Header {
+                kind,
+                size,
+                payload: payload + len,
+            });
+        }
+        let mut size = control as usize & 0x1f;
+        if size >= 29 {
+            let len = size - 28;
+            let base = [29, 285, 65821][len - 1];
+            size = base + self.number(0, payload, len)?;
+            payload += len;
+        }
+        Some(Header {
+            kind,
+            size,
+            payload,
+        })
+    }
+
+    /// The big endian number of `len` bytes, after the given high bits.
+    fn number(&self, high: usize, offset: usize, len: usize) -> Option<usize> {
+        let bytes = self.section.get(offset..offset + len)?;
+        Some(
+            bytes
+                .iter()
+                .fold(high, |number, &byte| number << 8 | byte as usize),
+        )
+    }
+
+    /// The header of the value, the pointers are followed.
+    fn resolve(&self, offset: usize) -> Option<Header> {
+        let header = self.header(offset)?;
+        match header.kind {
+            POINTER => self.header(header.size),
+            _ => Some(header),
+        }
+    }
+
+    /// The offset after the value.
+    fn skip(&self, offset: usize) -> Option<usize> {
+        let header = self.header(offset)?;
+        match header.kind {
+            POINTER => Some(header.payload),
+            MAP => (0..header.size * 2).try_fold(header.payload, |offset, _| self.skip(offset)),
+            ARRAY => (0..header.size).try_fold(header.payload, |offset, _| self.skip(offset)),
+            BOOLEAN => Some(header.payload),
+            _ => Some(header.payload + header.size),
+        }
+    }
+
+    /// The offset of the value of the key in the map.
+    fn get(&self, offset: usize, key: &str) -> Option<usize> {
+        let header = self.resolve(offset)?;
+        if header.kind != MAP {
+            return None;
+        }
+        let mut offset = header.payload;
+        for _ in 0..header.size {
+            let value = self.skip(offset)?;
+            if self.string(offset) == Some(key) {
+                return Some(value);
+            }
+            offset = self.skip(value)?;
+        }
+        None
+    }
+
+    fn string(&self, offset: usize) -> Option<&'a str> {
+        let header = self.resolve(offset)?;
+        if header.kind != STRING {
+            return None;
+        }
+        let bytes = self
+            .section
+            .get(header.payload..header.payload + header.size)?;
+        std::str::from_utf8(bytes).ok()
+    }
+
+    fn uint(&self, offset: usize) -> Option<u64> {
+        let header = self.resolve(offset)?;
+        if !matches!(header.kind, UINT16 | UINT32 | UINT64 | UINT128) || header.size > 8 {
+            return None;
+        }
+        self.number(0, header.payload, header.size)
+            .map(|number| number as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A child of a node of the search tree being built.
+    #[derive(Clone, Copy)]
+    enum Child {
+        Empty,
+        Node(usize),
+        Data(usize),
+    }
+
+    /// A database with the given networks, the IPv4 ones are placed at `::a.b.c.d` in the IPv6
+    /// databases.
+    fn database(record_size: usize, ipv6: bool, networks: &[(&str, &str)]) -> Vec<u8> {
+        let mut nodes = vec![[Child::Empty; 2]];
+        let mut section = Vec::new();
+        for (network, country) in networks {
+            let (ip, prefix) = network.split_once('/').unwrap();
+            let mut prefix = prefix.parse::<usize>().unwrap();
+            let bytes = match ip.parse::<IpAddr>().unwrap() {
+                IpAddr::V4(ip) if ipv6 => {
+                    prefix += 96;
+                    let mut bytes = [0; 16];
+                    bytes[12..].copy_from_slice(&ip.octets());
+                    bytes.to_vec()
+                }
+                IpAddr::V4(ip) => ip.octets().to_vec(),
+                IpAddr::V6(ip) => ip.octets().to_vec(),
+            };
+            // {"country": {"iso_code": country}}
+            let data = section.len();
+            section.extend([MAP << 5 | 1, STRING << 5 | 7]);
+            section.extend(b"country");
+            section.extend([MAP << 5 | 1, STRING << 5 | 8]);
+            section.extend(b"iso_code");
+            section.push(STRING << 5 | country.len() as u8);
+            section.extend(country.as_bytes());
+
+            let mut node = 0;
+            for index in 0..prefix {
+                let bit = ((bytes[index / 8] >> (7 - index % 8)) & 1) as usize;
+                if index == prefix - 1 {
+                    nodes[node][bit] = Child::Data(data);
+                    break;
+                }
+                node = match nodes[node][bit] {
+                    Child::Node(next) => next,
+                    _ => {
+                        nodes.push([Child::Empty; 2]);
+                        nodes[node][bit] = Child::Node(nodes.len() - 1);
+                        nodes.len() - 1
+                    }
+                };
+            }
+        }
+
+        let node_count = nodes.len();
+        let mut file = Vec::new();
+        for [left, right] in nodes {
+            let [left, right] = [left, right].map(|child| match child {
+                Child::Empty => node_count as u32,
+                Child::Node(node) => node as u32,
+                Child::Data(offset) => (node_count + 16 + offset) as u32,
+            });
+            match record_size {
+                24 => {
+                    file.extend(&left.to_be_bytes()[1..]);
+                    file.extend(&right.to_be_bytes()[1..]);
+                }
+                28 => {
+                    file.extend(&left.to_be_bytes()[1..]);
+                    file.push((left >> 20) as u8 & 0xf0 | (right >> 24) as u8 & 0x0f);
+                    file.extend(&right.to_be_bytes()[1..]);
+                }
+                _ => {
+                    file.extend(left.to_be_bytes());
+                    file.extend(right.to_be_bytes());
+                }
+            }
+        }
+        file.extend([0; 16]);
+        file.extend(section);
+
+        // {"node_count": u32, "record_size": u16, "ip_version": u16}
+        file.extend(METADATA_MARKER);
+        file.extend([MAP << 5 | 3, STRING << 5 | 10]);
+        file.extend(b"node_count");
+        file.push(UINT32 << 5 | 4);
+        file.extend((node_count as u32).to_be_bytes());
+        file.push(STRING << 5 | 11);
+        file.extend(b"record_size");
+        file.extend([UINT16 << 5 | 1, record_size as u8]);
+        file.push(STRING << 5 | 10);
+        file.extend(b"ip_version");
+        file.extend([UINT16 << 5 | 1, if ipv6 { 6 } else { 4 }]);
+        file
+    }
+
+    fn country(geoip: &GeoIp, ip: &str) -> Option<String> {
+        geoip.country(ip.parse().unwrap()).map(str::to_string)
+    }
+
+    #[test]
+    fn lookup_ipv4_in_ipv6() {
+        for record_size in [24, 28, 32] {
+            let networks = [("1.2.3.0/24", "DE"), ("2001:db8::/32", "FR")];
+            let geoip = GeoIp::parse(database(record_size, true, &networks)).unwrap();
+            assert_eq!(country(&geoip, "1.2.3.4").as_deref(), Some("DE"));
+            assert_eq!(country(&geoip, "::ffff:1.2.3.4").as_deref(), Some("DE"));
+            assert_eq!(country(&geoip, "1.2.4.1"), None);
+            assert_eq!(country(&geoip, "2001:db8::1").as_deref(), Some("FR"));
+            assert_eq!(country(&geoip, "2001:db9::1"), None);
+        }
+    }
+
+    #[test]
+    fn lookup_ipv4() {
+        let networks = [("10.0.0.0/8", "IT"), ("192.168.1.0/24", "NL")];
+        let geoip = GeoIp::parse(database(24, false, &networks)).unwrap();
+        assert_eq!(country(&geoip, "10.1.2.3").as_deref(), Some("IT"));
+        assert_eq!(country(&geoip, "192.168.1.1").as_deref(), Some("NL"));
+        assert_eq!(country(&geoip, "192.168.2.1"), None);
+        assert_eq!(country(&geoip, "2001:db8::1"), None);
+    }
+
+    #[test]
+    fn read_28_bit_records() {
+        // the high bits of the left record are the high nibble of the middle byte
+        let geoip = GeoIp {
+            data: vec![0x12, 0x34, 0x56, 0xab, 0x78, 0x9a, 0xbc],
+            node_count: 1,
+            record_size: 28,
+            data_section: (0, 0),
+            ipv4_start: 0,
+            ipv6: false,
+        };
+        assert_eq!(geoip.record(0, 0), Some(0xa123456));
+        assert_eq!(geoip.record(0, 1), Some(0xb789abc));
+    }
+
+    #[test]
+    fn reject_invalid_types() {
+        let mut file = database(24, false, &[("10.0.0.0/8", "IT")]);
+        let marker = file
+            .windows(METADATA_MARKER.len())
+            .rposition(|window| window == METADATA_MARKER)
+            .unwrap();
+        // an extended type overflowing a byte
+        file.truncate(marker + METADATA_MARKER.len());
+        file.extend([0, 0xff]);
+        assert!(GeoIp::parse(file).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::Result;
+
+use crate::{
+    aggregate,
+    sink::{self, Entry, Sink},
+};
+
+/// Count the requests and their durations in histogram buckets by host and status class, the
+/// snapshot is written in the Prometheus text format when the input ends, to be read by the
+/// textfile collector of node_exporter.
+pub struct HistogramSink {
+    path: PathBuf,
+    histograms: BTreeMap<(String, &'static str), Histogram>,
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// The requests of each bucket, not cumulative.
+    buckets: [u64; HistogramSink::BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl HistogramSink {
+    /// The upper bounds of the buckets, in seconds, the default ones of the Prometheus clients.
+    const BUCKETS: [f64; 11] = [
+        0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+    ];
+
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            histograms: BTreeMap::new(),
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut output = String::new();
+        output.push_str(
+            "# HELP caddy_log_requests_total Number of logged requests by host and status class.\n",
+        );
+        output.push_str("# TYPE caddy_log_requests_total counter\n");
+        for ((host, class), histogram) in &self.histograms {
+            output.push_str(&format!(
+                "caddy_log_requests_total{{host=\"{}\",status=\"{class}\"}} {}\n",
+                sink::escape_label(host),
+                histogram.count
+            ));
+        }
+        output.push_str(
+            "# HELP caddy_log_request_duration_seconds Time spent handling the logged requests.\n",
+        );
+        output.push_str("# TYPE caddy_log_request_duration_seconds histogram\n");
+        for ((host, class), histogram) in &self.histograms {
+            let labels = format!("host=\"{}\",status=\"{class}\"", sink::escape_label(host));
+            let mut cumulative = 0;
+            for (bound, count) in Self::BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                output.push_str(&format!(
+                    "caddy_log_request_duration_seconds_bucket{{{labels},le=\"{bound}\"}} {cumulative}\n"
+                ));
+            }
+            output.push_str(&format!(
+                "caddy_log_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}\n",
+                histogram.count
+            ));
+            output.push_str(&format!(
+                "caddy_log_request_duration_seconds_sum{{{labels}}} {}\n",
+                histogram.sum
+            ));
+            output.push_str(&format!(
+                "caddy_log_request_duration_seconds_count{{{labels}}} {}\n",
+                histogram.count
+            ));
+        }
+        output
+    }
+}
+
+impl Sink for HistogramSink {
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        let record = entry.record()?;
+        let (Some(request), Some(duration)) = (&record.request, record.duration) else {
+            return Ok(());
+        };
+        let class = aggregate::status_class(record);
+        let histogram = self
+            .histograms
+            .entry((request.host.clone(), class))
+            .or_default();
+        // the requests slower than the last bound are counted only by the +Inf bucket
+        if let Some(bucket) = Self::BUCKETS.iter().position(|&bound| duration <= bound) {
+            histogram.buckets[bucket] += 1;
+        }
+        histogram.sum += duration;
+        histogram.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        sink::replace_file(&self.path, &self.render())
+    }
+
+    fn is_live(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,1076 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, IsTerminal, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, TrySendError},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+
+use crate::{
+    caddy::{self, LogFile},
+    filters::Filters,
+    mapping::{self, FieldMap, FieldsConfig},
+    record::LogRecord,
+    skew::{self, SourceOffsets},
+    ssh::{Remote, RemoteTail},
+};
+
+/// The flags used to select where the log lines are read from.
+#[derive(Clone, Debug, Default, clap::Args)]
+pub struct InputArgs {
+    /// The log files to read, the standard input is used when missing. When the standard input
+    /// is a terminal, the access log of the local Caddy instance is looked for and read, it is
+    /// followed by the viewer and by `serve`.
+    #[arg(value_name = "FILE")]
+    files: Vec<PathBuf>,
+
+    /// Keep reading the files as they grow, like `tail -f`. The files are reopened when they are
+    /// rotated or truncated, the records of multiple files are labeled with their names.
+    #[arg(short, long)]
+    follow: bool,
+
+    /// Read from named pipes, which are reopened when their writer closes them instead of
+    /// ending the input. The viewer waits for Caddy to be restarted. On Windows the pipes are
+    /// named `\\.\pipe\NAME` and they are created by the viewer.
+    #[arg(long, conflicts_with_all = ["follow", "from_config"])]
+    reopen: bool,
+
+    /// The number of lines waiting to be processed, when it is reached the input is not read
+    /// or the new lines are dropped, according to the queue policy.
+    #[arg(long, value_name = "N", default_value_t = 1024)]
+    queue_size: usize,
+
+    /// What to do with the new lines when the output cannot keep up with the input. The lines
+    /// are dropped only when following, the files and the standard input are read in full.
+    #[arg(long, value_name = "POLICY", default_value = "block")]
+    queue_policy: QueuePolicy,
+
+    /// When following, start by showing the last N matching records of each file.
+    #[arg(long, value_name = "N")]
+    tail_lines: Option<usize>,
+
+    /// Read the log files configured in Caddy, the configuration is requested to the admin API
+    /// listening at the given address.
+    #[arg(
+        long,
+        value_name = "ADMIN",
+        num_args = 0..=1,
+        default_missing_value = caddy::DEFAULT_ADMIN,
+        conflicts_with = "files"
+    )]
+    from_config: Option<String>,
+
+    /// The log of the Caddy configuration to read, by name or by site. This flag can be repeated
+    /// to read multiple logs, when missing the log is asked.
+    #[arg(long = "log", value_name = "NAME", requires = "from_config")]
+    logs: Vec<String>,
+
+    /// Read all the logs of the Caddy configuration.
+    #[arg(long, requires = "from_config", conflicts_with = "logs")]
+    all: bool,
+
+    /// Follow a log file of a remote host, e.g. `user@host:/var/log/caddy/access.log`. The file
+    /// is read with `tail -F` through the system `ssh` binary, which must be able to log in
+    /// without a password, and the connection is opened again when it is lost. This flag can be
+    /// repeated to follow multiple files, the local files given with it are followed too and
+    /// the records are labeled with their sources.
+    #[arg(
+        long,
+        value_name = "[USER@]HOST:PATH",
+        conflicts_with_all = ["from_config", "reopen"]
+    )]
+    ssh: Vec<String>,
+
+    /// Shift the timestamps of the records of a source, to correct the clock skew of the servers
+    /// whose logs are merged, e.g. `web2=-1.5s` when the clock of `web2` is 1.5 seconds ahead.
+    /// The sources are named by their labels or by their files. With `auto` the offsets of the
+    /// other sources are estimated from the requests logged by more than one of them, told
+    /// apart by their `X-Request-Id` header, and they are reported when the input ends. This
+    /// flag can be repeated.
+    #[arg(long, value_name = "SOURCE=OFFSET|auto")]
+    source_offset: Vec<String>,
+
+    /// Read the records nested in another document by a log shipper, at this jq-like path (e.g.
+    /// `.log`). The record can be an object or a string with the original line.
+    #[arg(long, value_name = "PATH")]
+    unwrap: Option<String>,
+
+    /// Move a renamed field back to the path written by Caddy, e.g. `time=ts` or
+    /// `client=request.remote_ip`, the paths are jq-like. This flag can be repeated.
+    #[arg(long, value_name = "FROM=TO")]
+    field_map: Vec<String>,
+
+    /// Whether the lines are numbered, the followed files are counted only then.
+    #[arg(skip)]
+    line_numbers: bool,
+
+    /// Whether the records are shown as they come, the discovered log is followed only then.
+    #[arg(skip)]
+    live: bool,
+}
+
+/// What happens to the lines read while the queue is full.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum QueuePolicy {
+    /// Stop reading the input until the queue is drained.
+    #[default]
+    Block,
+    /// Drop the new lines, the number of dropped lines is reported.
+    Drop,
+}
+
+/// A line read from the input.
+pub struct Line {
+    /// The label of the source of the line, set only when multiple sources are read.
+    pub source: Option<Arc<str>>,
+    pub position: Position,
+    pub text: String,
+}
+
+/// Where a line is in its file, to find the original line from the output.
+#[derive(Clone, Debug)]
+pub struct Position {
+    /// The path of the file as given, `stdin` for the standard input.
+    pub path: Arc<str>,
+    /// The number of the line in the file, starting from one.
+    pub number: u64,
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.path, self.number)
+    }
+}
+
+/// The lines read from the input, in order of arrival.
+pub type Lines = Box<dyn Iterator<Item = Result<Line>> + Send>;
+
+type Reader = Box<dyn BufRead + Send>;
+
+/// A file to read, with its label.
+struct Source {
+    label: Option<Arc<str>>,
+    path: PathBuf,
+}
+
+/// A source being read, the lines before `skipped` are not read.
+struct Opened {
+    label: Option<Arc<str>>,
+    path: Arc<str>,
+    skipped: u64,
+    reader: Reader,
+    /// Set by a followed file when it is rotated, its lines are numbered again from one.
+    rotated: Option<Arc<AtomicBool>>,
+}
+
+impl Opened {
+    fn new(label: Option<Arc<str>>, path: &Path, skipped: u64, reader: Reader) -> Self {
+        Self {
+            label,
+            path: path.to_string_lossy().into(),
+            skipped,
+            reader,
+            rotated: None,
+        }
+    }
+
+    fn stdin() -> Self {
+        Self {
+            label: None,
+            path: "stdin".into(),
+            skipped: 0,
+            reader: Box::new(BufReader::new(std::io::stdin())),
+            rotated: None,
+        }
+    }
+}
+
+impl InputArgs {
+    /// Open the input, the lines of multiple files are concatenated or interleaved when they
+    /// are followed. The filters are used to find where the followed files start to be read.
+    pub fn open(&self, filters: &Filters) -> Result<Lines> {
+        if self.queue_size == 0 {
+            bail!("--queue-size must be greater than zero");
+        }
+        let offsets = SourceOffsets::parse(&self.source_offset)?;
+        let fields = FieldMap::new(self.unwrap.as_deref(), &self.field_map)?;
+        let mut lines = mapping::apply(self.lines(filters)?, fields);
+        if !offsets.is_empty() {
+            lines = skew::shift(lines, offsets);
+        }
+        Ok(match self.queue_policy {
+            QueuePolicy::Drop if self.is_live() => drop_when_full(lines, self.queue_size),
+            _ => lines,
+        })
+    }
+
+    /// Whether the input never ends: the followed files, the named pipes and the remote files.
+    /// The discovered log of the local Caddy instance is followed when the input is live.
+    fn is_live(&self) -> bool {
+        self.follow
+            || self.reopen
+            || !self.ssh.is_empty()
+            || (self.live
+                && self.files.is_empty()
+                && self.from_config.is_none()
+                && std::io::stdin().is_terminal())
+    }
+
+    /// Add the field map of the config file, the flags take precedence.
+    pub fn with_fields(&mut self, config: FieldsConfig) {
+        if self.unwrap.is_none() {
+            self.unwrap = config.unwrap;
+        }
+        let renames = config
+            .map
+            .into_iter()
+            .map(|(from, to)| format!("{from}={to}"));
+        self.field_map.splice(0..0, renames);
+    }
+
+    /// Number the lines of the followed files too, which are counted up to where they start
+    /// to be read.
+    pub fn with_line_numbers(&mut self) {
+        self.line_numbers = true;
+    }
+
+    /// Follow the discovered log of the local Caddy instance, without `--follow` it is read
+    /// once by the reports written when the input ends.
+    pub fn with_live(&mut self) {
+        self.live = true;
+    }
+
+    /// The size of the input, known only when files are read without following them.
+    pub fn size(&self) -> Option<u64> {
+        if self.follow || self.reopen || self.from_config.is_some() || !self.ssh.is_empty() {
+            return None;
+        }
+        if self.files.is_empty() {
+            return None;
+        }
+        self.files
+            .iter()
+            .map(|path| std::fs::metadata(path).ok().map(|metadata| metadata.len()))
+            .sum()
+    }
+
+    fn lines(&self, filters: &Filters) -> Result<Lines> {
+        if self.tail_lines.is_some() && !self.follow && self.ssh.is_empty() {
+            bail!("--tail-lines can be used only when following");
+        }
+        if !self.ssh.is_empty() {
+            let remotes = self
+                .ssh
+                .iter()
+                .map(|remote| remote.parse::<Remote>())
+                .collect::<Result<Vec<_>>>()?;
+            return tail_remotes(
+                &self.files,
+                remotes,
+                self.tail_lines.unwrap_or(0),
+                filters,
+                self.queue_size,
+                self.line_numbers,
+            );
+        }
+        if let Some(admin) = &self.from_config {
+            let sources = self.config_sources(admin)?;
+            return match self.follow {
+                true => follow_all(
+                    sources,
+                    self.tail_lines.unwrap_or(0),
+                    filters,
+                    self.queue_size,
+                    self.line_numbers,
+                ),
+                false => chain(sources),
+            };
+        }
+        if self.follow {
+            return match self.files.as_slice() {
+                [] if std::io::stdin().is_terminal() => self.discovered(filters),
+                [] => bail!("a file is required when following"),
+                [path] => {
+                    let tail_lines = self.tail_lines.unwrap_or(0);
+                    let opened = follow(None, path, tail_lines, filters, self.line_numbers)?;
+                    Ok(read_lines(opened))
+                }
+                paths => follow_all(
+                    labeled(paths),
+                    self.tail_lines.unwrap_or(0),
+                    filters,
+                    self.queue_size,
+                    self.line_numbers,
+                ),
+            };
+        }
+        if self.reopen {
+            return match self.files.as_slice() {
+                [] => bail!("a named pipe is required with --reopen"),
+                [path] => {
+                    let reader = Box::new(BufReader::new(Reopen::new(path)?));
+                    Ok(read_lines(Opened::new(None, path, 0, reader)))
+                }
+                paths => reopen_all(labeled(paths), self.queue_size),
+            };
+        }
+        if self.files.is_empty() {
+            if std::io::stdin().is_terminal() {
+                return self.discovered(filters);
+            }
+            return Ok(read_lines(Opened::stdin()));
+        }
+        let sources = self
+            .files
+            .iter()
+            .map(|path| Source {
+                label: None,
+                path: path.clone(),
+            })
+            .collect();
+        chain(sources)
+    }
+
+    /// The access log of the local Caddy instance, it is followed from its last records when
+    /// following or when the input is live, otherwise it is read once.
+    fn discovered(&self, filters: &Filters) -> Result<Lines> {
+        let Some((path, source)) = caddy::discover() else {
+            bail!("no input: the Caddy log file cannot be found, pass it as argument");
+        };
+        if !self.follow && !self.live {
+            eprintln!("reading {} ({})", path.display(), source);
+            return chain(vec![Source { label: None, path }]);
+        }
+        eprintln!("following {} ({})", path.display(), source);
+        let tail_lines = self.tail_lines.unwrap_or(10);
+        let opened = follow(None, &path, tail_lines, filters, self.line_numbers)?;
+        Ok(read_lines(opened))
+    }
+
+    /// The log files of the Caddy configuration selected by the flags, they are labeled with
+    /// their sites when more than one is read.
+    fn config_sources(&self, admin: &str) -> Result<Vec<Source>> {
+        let files = caddy::log_files(admin)
+            .with_context(|| format!("failed to read the Caddy config from {}", admin))?;
+        if files.is_empty() {
+            bail!("no log is written to a file in the Caddy config");
+        }
+        let selected = if self.all || files.len() == 1 {
+            files
+        } else if self.logs.is_empty() {
+            pick(files)?
+        } else {
+            self.logs
+                .iter()
+                .map(|name| {
+                    files
+                        .iter()
+                        .find(|file| file.name == *name || file.sites.contains(name))
+                        .cloned()
+                        .with_context(|| {
+                            format!("unknown log: {}, expected one of {}", name, names(&files))
+                        })
+                })
+                .collect::<Result<_>>()?
+        };
+        let labeled = selected.len() > 1;
+        Ok(selected
+            .into_iter()
+            .map(|file| Source {
+                label: labeled.then(|| file.label().into()),
+                path: file.path,
+            })
+            .collect())
+    }
+}
+
+/// Ask which of the logs have to be read, all of them are read when the answer is empty.
+fn pick(files: Vec<LogFile>) -> Result<Vec<LogFile>> {
+    if !std::io::stdin().is_terminal() {
+        bail!(
+            "multiple logs in the Caddy config, select them with --log or --all: {}",
+            names(&files)
+        );
+    }
+    for (index, file) in files.iter().enumerate() {
+        eprintln!(
+            "{:>3}) {} ({})",
+            index + 1,
+            file.label(),
+            file.path.display()
+        );
+    }
+    eprint!("logs to read [1-{}, empty for all]: ", files.len());
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if answer.trim().is_empty() {
+        return Ok(files);
+    }
+    answer
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|choice| !choice.is_empty())
+        .map(|choice| {
+            choice
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| files.get(index.checked_sub(1)?))
+                .cloned()
+                .with_context(|| format!("invalid choice: {}", choice))
+        })
+        .collect()
+}
+
+/// Label the files with their names, or with the whole paths when the names are ambiguous.
+fn labeled(paths: &[PathBuf]) -> Vec<Source> {
+    let names = paths
+        .iter()
+        .map(|path| path.file_name().unwrap_or(path.as_os_str()))
+        .collect::<Vec<_>>();
+    paths
+        .iter()
+        .zip(&names)
+        .map(|(path, name)| {
+            let unique = names.iter().filter(|other| *other == name).count() == 1;
+            let label = match unique {
+                true => name.to_string_lossy(),
+                false => path.to_string_lossy(),
+            };
+            Source {
+                label: Some(label.into()),
+                path: path.clone(),
+            }
+        })
+        .collect()
+}
+
+fn names(files: &[LogFile]) -> String {
+    let names = files.iter().map(|file| file.label()).collect::<Vec<_>>();
+    names.join(", ")
+}
+
+fn read_lines(opened: Opened) -> Lines {
+    let Opened {
+        label,
+        path,
+        skipped,
+        reader,
+        rotated,
+    } = opened;
+    let mut number = skipped;
+    Box::new(reader.lines().map(move |text| {
+        let mut text = text?;
+        // the line has been read from the new file
+        if rotated
+            .as_ref()
+            .is_some_and(|rotated| rotated.swap(false, Ordering::Relaxed))
+        {
+            number = 0;
+        }
+        number += 1;
+        // the files written by PowerShell and by some editors start with a byte order mark
+        if number == 1 && text.starts_with('\u{feff}') {
+            text.drain(..'\u{feff}'.len_utf8());
+        }
+        Ok(Line {
+            source: label.clone(),
+            position: Position {
+                path: Arc::clone(&path),
+                number,
+            },
+            text,
+        })
+    }))
+}
+
+/// Read the files one after the other.
+fn chain(sources: Vec<Source>) -> Result<Lines> {
+    let mut lines: Lines = Box::new(std::iter::empty());
+    for source in sources {
+        let reader = Box::new(BufReader::new(open_file(&source.path)?));
+        let opened = Opened::new(source.label, &source.path, 0, reader);
+        lines = Box::new(lines.chain(read_lines(opened)));
+    }
+    Ok(lines)
+}
+
+/// Follow the files concurrently, the lines are interleaved as they are written.
+fn follow_all(
+    sources: Vec<Source>,
+    tail_lines: usize,
+    filters: &Filters,
+    queue_size: usize,
+    numbered: bool,
+) -> Result<Lines> {
+    let readers = sources
+        .into_iter()
+        .map(|source| follow(source.label, &source.path, tail_lines, filters, numbered))
+        .collect::<Result<_>>()?;
+    Ok(merge(readers, queue_size))
+}
+
+/// Read the named pipes concurrently, reopening them when their writers go away.
+fn reopen_all(sources: Vec<Source>, queue_size: usize) -> Result<Lines> {
+    let readers = sources
+        .into_iter()
+        .map(|source| {
+            let reader = Box::new(BufReader::new(Reopen::new(&source.path)?));
+            Ok(Opened::new(source.label, &source.path, 0, reader))
+        })
+        .collect::<Result<_>>()?;
+    Ok(merge(readers, queue_size))
+}
+
+/// Follow the remote files and the local ones concurrently, the records are labeled with their
+/// sources when more than one is followed.
+fn tail_remotes(
+    paths: &[PathBuf],
+    remotes: Vec<Remote>,
+    tail_lines: usize,
+    filters: &Filters,
+    queue_size: usize,
+    numbered: bool,
+) -> Result<Lines> {
+    let multiple = paths.len() + remotes.len() > 1;
+    let mut readers = labeled_remotes(&remotes)
+        .into_iter()
+        .zip(remotes)
+        .map(|(label, remote)| {
+            let path = remote.to_string();
+            let reader = Box::new(BufReader::new(RemoteTail::new(remote, tail_lines)));
+            Opened {
+                label: multiple.then(|| label.into()),
+                path: path.into(),
+                skipped: 0,
+                reader,
+                rotated: None,
+            }
+        })
+        .collect::<Vec<_>>();
+    for source in labeled(paths) {
+        let label = source.label.filter(|_| multiple);
+        readers.push(follow(label, &source.path, tail_lines, filters, numbered)?);
+    }
+    Ok(match readers.len() {
+        1 => read_lines(readers.pop().expect("a source is followed")),
+        _ => merge(readers, queue_size),
+    })
+}
+
+/// Label the remote files with their hosts, or with the whole paths when a host has more files.
+fn labeled_remotes(remotes: &[Remote]) -> Vec<String> {
+    remotes
+        .iter()
+        .map(|remote| {
+            let shared = remotes
+                .iter()
+                .filter(|other| other.host() == remote.host())
+                .count()
+                > 1;
+            match shared {
+                true => remote.to_string(),
+                false => remote.host().to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Read each source in its own thread, the lines are interleaved as they are read. The readers
+/// wait when the queue is full.
+fn merge(readers: Vec<Opened>, queue_size: usize) -> Lines {
+    let (sender, receiver) = mpsc::sync_channel(queue_size);
+    for opened in readers {
+        let sender = sender.clone();
+        std::thread::spawn(move || {
+            for line in read_lines(opened) {
+                let failed = line.is_err();
+                if sender.send(line).is_err() || failed {
+                    break;
+                }
+            }
+        });
+    }
+    Box::new(receiver.into_iter())
+}
+
+/// Move the lines through a bounded queue, the lines read while the queue is full are dropped.
+/// The number of dropped lines is reported as soon as the queue is drained.
+fn drop_when_full(lines: Lines, queue_size: usize) -> Lines {
+    let (sender, receiver) = mpsc::sync_channel(queue_size);
+    let dropped = Arc::new(AtomicU64::new(0));
+    {
+        let dropped = Arc::clone(&dropped);
+        std::thread::spawn(move || {
+            for line in lines {
+                match sender.try_send(line) {
+                    Ok(()) => {}
+                    // the errors are never dropped, they end the input
+                    Err(TrySendError::Full(line @ Err(_))) => {
+                        let _ = sender.send(line);
+                        break;
+                    }
+                    Err(TrySendError::Full(_)) => {
+                        dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(TrySendError::Disconnected(_)) => break,
+                }
+            }
+        });
+    }
+    let mut reported = 0;
+    Box::new(std::iter::from_fn(move || {
+        let line = receiver.recv().ok();
+        let total = dropped.load(Ordering::Relaxed);
+        if total > reported {
+            eprintln!(
+                "{} lines dropped, the output cannot keep up",
+                total - reported
+            );
+            reported = total;
+        }
+        line
+    }))
+}
+
+/// Follow the file, starting from the last `tail_lines` matching records. The lines before them
+/// are counted only when the lines are numbered, since the whole file is read to count them.
+fn follow(
+    label: Option<Arc<str>>,
+    path: &Path,
+    tail_lines: usize,
+    filters: &Filters,
+    numbered: bool,
+) -> Result<Opened> {
+    let start = tail_offset(path, tail_lines, |line| {
+        filters.matches_line(line)
+            && serde_json::from_str::<LogRecord>(line).is_ok_and(|record| filters.matches(&record))
+    })?;
+    let skipped = match numbered {
+        true => count_lines(path, start)?,
+        false => 0,
+    };
+    let follow = Follow::open(path, start)?;
+    let rotated = Arc::clone(&follow.rotated);
+    let reader = Box::new(BufReader::new(follow));
+    Ok(Opened {
+        rotated: Some(rotated),
+        ..Opened::new(label, path, skipped, reader)
+    })
+}
+
+/// The number of lines before the offset, to number the lines of a file read from the middle.
+fn count_lines(path: &Path, offset: u64) -> Result<u64> {
+    let mut reader = BufReader::new(open_file(path)?.take(offset));
+    let mut count = 0;
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(count);
+        }
+        count += buffer.iter().filter(|&&b| b == b'\n').count() as u64;
+        let length = buffer.len();
+        reader.consume(length);
+    }
+}
+
+fn open_file(path: &Path) -> Result<File> {
+    // the named pipes of Windows do not exist until the reader creates them
+    let file = match cfg!(windows) && is_named_pipe(path)? {
+        true => open_pipe(path),
+        false => File::open(path),
+    };
+    file.with_context(|| format!("failed to open {}", path.display()))
+}
+
+/// A reader that waits for new data at the end of the file instead of returning EOF.
+pub struct Follow {
+    path: PathBuf,
+    file: File,
+    position: u64,
+    /// Set when the file is reopened, cleared by the reader of the lines.
+    rotated: Arc<AtomicBool>,
+}
+
+impl Follow {
+    const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+    /// Open the file, the reading starts from the given offset.
+    pub fn open(path: &Path, start: u64) -> Result<Self> {
+        let mut file = open_file(path)?;
+        let position = file.seek(SeekFrom::Start(start))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            position,
+            rotated: Arc::default(),
+        })
+    }
+
+    /// Reopen the file if it has been truncated or replaced by a new one.
+    fn check_rotation(&mut self) -> std::io::Result<()> {
+        let Ok(metadata) = std::fs::metadata(&self.path) else {
+            // the file has been removed, wait for a new one
+            return Ok(());
+        };
+        if metadata.len() < self.position || !same_file(&self.file, &metadata)? {
+            self.file = File::open(&self.path)?;
+            self.position = 0;
+            self.rotated.store(true, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+}
+
+impl Read for Follow {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let read = self.file.read(buf)?;
+            if read > 0 {
+                self.position += read as u64;
+                return Ok(read);
+            }
+            std::thread::sleep(Self::POLL_INTERVAL);
+            self.check_rotation()?;
+        }
+    }
+}
+
+/// A reader of a named pipe, the pipe is reopened when the writer closes it, waiting for a new
+/// writer instead of returning EOF.
+pub struct Reopen {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl Reopen {
+    /// The pipe is opened on the first read, since opening blocks until a writer connects.
+    pub fn new(path: &Path) -> Result<Self> {
+        if !is_named_pipe(path)? {
+            bail!("not a named pipe: {}", path.display());
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: None,
+        })
+    }
+}
+
+impl Read for Reopen {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let file = match &mut self.file {
+                Some(file) => file,
+                None => self.file.insert(open_pipe(&self.path)?),
+            };
+            let read = file.read(buf)?;
+            if read > 0 {
+                return Ok(read);
+            }
+            // the writer has gone away
+            self.file = None;
+        }
+    }
+}
+
+/// Find the offset of the `count`-th last line accepted by the predicate, scanning the file
+/// backwards. The end of the file is returned when `count` is zero.
+fn tail_offset(path: &Path, count: usize, mut accept: impl FnMut(&str) -> bool) -> Result<u64> {
+    const CHUNK_SIZE: u64 = 64 * 1024;
+
+    let mut file = open_file(path)?;
+    let end = file.seek(SeekFrom::End(0))?;
+    if count == 0 {
+        return Ok(end);
+    }
+
+    let mut found = 0;
+    let mut position = end;
+    // the bytes of the line being scanned, which can span multiple chunks
+    let mut partial = Vec::new();
+    while position > 0 {
+        let size = CHUNK_SIZE.min(position);
+        position -= size;
+        let mut chunk = vec![0; size as usize];
+        file.seek(SeekFrom::Start(position))?;
+        file.read_exact(&mut chunk)?;
+        chunk.append(&mut partial);
+
+        let mut line_end = chunk.len();
+        while let Some(newline) = chunk[..line_end].iter().rposition(|&b| b == b'\n') {
+            let line = String::from_utf8_lossy(&chunk[newline + 1..line_end]);
+            if accept(line.trim_end_matches('\r')) {
+                found += 1;
+                if found == count {
+                    return Ok(position + newline as u64 + 1);
+                }
+            }
+            line_end = newline;
+        }
+        chunk.truncate(line_end);
+        partial = chunk;
+    }
+    // the first line of the file has no newline before it
+    Ok(0)
+}
+
+#[cfg(unix)]
+fn same_file(file: &File, metadata: &std::fs::Metadata) -> std::io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+    let current = file.metadata()?;
+    Ok(current.dev() == metadata.dev() && current.ino() == metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn same_file(_file: &File, _metadata: &std::fs::Metadata) -> std::io::Result<bool> {
+    Ok(true)
+}
+
+#[cfg(unix)]
+fn is_named_pipe(path: &Path) -> Result<bool> {
+    use std::os::unix::fs::FileTypeExt;
+    let metadata =
+        std::fs::metadata(path).with_context(|| format!("failed to open {}", path.display()))?;
+    Ok(metadata.file_type().is_fifo())
+}
+
+/// The named pipes of Windows live in their own namespace, e.g. `\\.\pipe\caddy`.
+#[cfg(windows)]
+fn is_named_pipe(path: &Path) -> Result<bool> {
+    let path = path.to_string_lossy().to_ascii_lowercase();
+    Ok(path.starts_with(r"\\.\pipe\") || path.starts_with(r"\\?\pipe\"))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn is_named_pipe(_path: &Path) -> Result<bool> {
+    Ok(false)
+}
+
+#[cfg(not(windows))]
+fn open_pipe(path: &Path) -> std::io::Result<File> {
+    File::open(path)
+}
+
+/// Create an instance of the pipe and wait for a writer to connect, e.g. Caddy writing its log
+/// to `\\.\pipe\caddy`. The end of the input is read when the writer disconnects.
+#[cfg(windows)]
+fn open_pipe(path: &Path) -> std::io::Result<File> {
+    use std::os::windows::{ffi::OsStrExt, io::FromRawHandle};
+
+    use windows_sys::Win32::{
+        Foundation::{ERROR_PIPE_CONNECTED, INVALID_HANDLE_VALUE},
+        Storage::FileSystem::PIPE_ACCESS_INBOUND,
+        System::Pipes::{
+            ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_TYPE_BYTE,
+            PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+        },
+    };
+
+    const BUFFER_SIZE: u32 = 64 * 1024;
+
+    let name = path
+        .as_os_str()
+        .encode_wide()
+        .chain(Some(0))
+        .collect::<Vec<_>>();
+    // SAFETY: the name is a null-terminated wide string, no security attributes are given
+    let handle = unsafe {
+        CreateNamedPipeW(
+            name.as_ptr(),
+            PIPE_ACCESS_INBOUND,
+            PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT,
+            PIPE_UNLIMITED_INSTANCES,
+            0,
+            BUFFER_SIZE,
+            0,
+            std::ptr::null(),
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: the handle is valid and owned by the file from now on, which closes it
+    let file = unsafe { File::from_raw_handle(handle as _) };
+    // SAFETY: the handle is a pipe opened for synchronous I/O, no overlapped structure is needed
+    if unsafe { ConnectNamedPipe(handle, std::ptr::null_mut()) } == 0 {
+        // a writer connected before the wait started
+        let error = std::io::Error::last_os_error();
+        if error.raw_os_error() != Some(ERROR_PIPE_CONNECTED as i32) {
+            return Err(error);
+        }
+    }
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, sync::mpsc::Receiver};
+
+    use super::*;
+
+    /// An empty directory of the temporary directory.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("caddy-pretty-print-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn record(n: u32) -> String {
+        format!(
+            r#"{{"level":"info","ts":1710000000.{n},"logger":"http.log.access","msg":"handled request","request":{{"remote_ip":"10.0.0.5","proto":"HTTP/1.1","method":"GET","host":"example.com","uri":"/{n}","headers":{{}}}},"status":200}}"#
+        )
+    }
+
+    fn append(path: &Path, lines: &[String]) {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        for line in lines {
+            writeln!(file, "{line}").unwrap();
+        }
+    }
+
+    /// Read the lines in the background, the followed files never end.
+    fn read_in_background(opened: Opened) -> Receiver<(u64, String)> {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in read_lines(opened) {
+                let line = line.unwrap();
+                if sender.send((line.position.number, line.text)).is_err() {
+                    break;
+                }
+            }
+        });
+        receiver
+    }
+
+    fn next(lines: &Receiver<(u64, String)>) -> (u64, String) {
+        lines.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    #[test]
+    fn find_the_last_lines() {
+        let dir = temp_dir("tail");
+        let path = dir.join("access.log");
+        // a line longer than the chunks scanned backwards
+        let long = "x".repeat(100 * 1024);
+        std::fs::write(&path, format!("first\n{long}\nkept\nskipped\nlast\n")).unwrap();
+        // the predicates of the records never accept the empty line after the last newline
+        let offset = |count, accept: fn(&str) -> bool| tail_offset(&path, count, accept).unwrap();
+        let any = |line: &str| !line.is_empty();
+        let end = std::fs::metadata(&path).unwrap().len();
+        assert_eq!(offset(0, any), end);
+        assert_eq!(offset(1, any), end - "last\n".len() as u64);
+        let kept = offset(2, |line| !line.is_empty() && line != "skipped");
+        assert_eq!(kept, end - "kept\nskipped\nlast\n".len() as u64);
+        assert_eq!(offset(4, any), "first\n".len() as u64);
+        assert_eq!(offset(10, any), 0);
+        assert_eq!(count_lines(&path, kept).unwrap(), 2);
+        assert_eq!(count_lines(&path, end).unwrap(), 5);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn read_all(text: &'static str, skipped: u64) -> Vec<(String, String)> {
+        let reader = Box::new(std::io::Cursor::new(text));
+        let opened = Opened::new(None, Path::new("access.log"), skipped, reader);
+        read_lines(opened)
+            .map(|line| {
+                let line = line.unwrap();
+                (line.position.to_string(), line.text)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn number_the_lines() {
+        // the byte order mark is removed from the start of the file only
+        assert_eq!(
+            read_all("\u{feff}first\r\nsecond\n", 0),
+            [
+                ("access.log:1".to_string(), "first".to_string()),
+                ("access.log:2".to_string(), "second".to_string()),
+            ]
+        );
+        assert_eq!(
+            read_all("\u{feff}middle\n", 10),
+            [("access.log:11".to_string(), "\u{feff}middle".to_string())]
+        );
+    }
+
+    #[test]
+    fn merge_the_sources() {
+        let readers = ["a", "b"]
+            .into_iter()
+            .map(|label| {
+                let text = format!("{label}1\n{label}2\n");
+                let reader = Box::new(std::io::Cursor::new(text));
+                Opened::new(Some(label.into()), Path::new(label), 0, reader)
+            })
+            .collect();
+        let mut lines = merge(readers, 1)
+            .map(|line| {
+                let line = line.unwrap();
+                format!("{}:{}", line.source.unwrap(), line.text)
+            })
+            .collect::<Vec<_>>();
+        lines.sort();
+        assert_eq!(lines, ["a:a1", "a:a2", "b:b1", "b:b2"]);
+    }
+
+    #[test]
+    fn follow_the_rotated_files() {
+        let dir = temp_dir("follow");
+        let path = dir.join("access.log");
+        append(&path, &[record(1), record(2), record(3)]);
+        let filters = Filters::builder().build().unwrap();
+        // the last record is read again, numbered after the lines skipped
+        let lines = read_in_background(follow(None, &path, 1, &filters, true).unwrap());
+        assert_eq!(next(&lines), (3, record(3)));
+
+        append(&path, &[record(4)]);
+        assert_eq!(next(&lines), (4, record(4)));
+
+        // the file is replaced by a new one
+        std::fs::rename(&path, dir.join("access.log.1")).unwrap();
+        append(&path, &[record(5)]);
+        assert_eq!(next(&lines), (1, record(5)));
+
+        // the file is truncated
+        File::create(&path).unwrap();
+        std::thread::sleep(Follow::POLL_INTERVAL * 3);
+        append(&path, &[record(6)]);
+        assert_eq!(next(&lines), (1, record(6)));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reopen_the_pipes() {
+        let dir = temp_dir("pipe");
+        let path = dir.join("caddy.pipe");
+        let status = std::process::Command::new("mkfifo")
+            .arg(&path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert!(Reopen::new(&dir).is_err());
+
+        let reader = Box::new(BufReader::new(Reopen::new(&path).unwrap()));
+        let lines = read_in_background(Opened::new(None, &path, 0, reader));
+        // each writer closes the pipe once it is done
+        for n in 1..=2 {
+            append(&path, &[record(n)]);
+            assert_eq!(next(&lines), (u64::from(n), record(n)));
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
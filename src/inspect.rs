@@ -0,0 +1,530 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+
+use crate::{
+    input::InputArgs,
+    locale::Locale,
+    record::LogRecord,
+    selector::Selector,
+    sink::{Entry, Sink},
+    sketches::{HyperLogLog, TopValues},
+    stats::format_table,
+    FilterArgs,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct InspectArgs {
+    /// Report the number of distinct values of a field and its most frequent values, instead of
+    /// the fields of the records. The field is a jq-like path (e.g. `.request.uri`) and this
+    /// flag can be repeated. The memory used is bounded, so the numbers are estimates.
+    #[arg(long, value_name = "FIELD")]
+    cardinality: Vec<String>,
+
+    /// The number of most frequent values reported by `--cardinality`.
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    top: usize,
+
+    /// Render the numbers with the conventions of a locale (e.g. `de_DE`), or of the locale of
+    /// the environment (`LC_ALL` or `LANG`) when no value is given.
+    #[arg(
+        long,
+        value_name = "LOCALE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    locale: Option<String>,
+
+    /// How the report is printed.
+    #[arg(long, default_value = "pretty")]
+    output: ReportOutput,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    filters: FilterArgs,
+}
+
+/// The formats of the reports.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum ReportOutput {
+    /// Aligned tables.
+    Pretty,
+    /// A JSON document, the shares are fractions between 0 and 1.
+    Json,
+}
+
+/// The optional features of the Caddy logs, with the field which reveals them.
+const FEATURES: &[(&str, &str, &str)] = &[
+    (
+        "response headers",
+        ".resp_headers",
+        "the headers of the responses, e.g. Location and Content-Type",
+    ),
+    (
+        "TLS details",
+        ".request.tls",
+        "the protocol, the cipher suite and the server name of TLS connections",
+    ),
+    (
+        "authenticated users",
+        ".user_id",
+        "the user authenticated by the server, when not empty",
+    ),
+    (
+        "client address",
+        ".request.client_ip",
+        "the address of the client behind trusted proxies",
+    ),
+    (
+        "upstream",
+        "upstream",
+        "the upstream of reverse proxied requests, added with log_append",
+    ),
+];
+
+/// The fields found so far, by path.
+#[derive(Default)]
+struct Schema {
+    records: u64,
+    unparsed: u64,
+    fields: BTreeMap<String, Field>,
+}
+
+#[derive(Default)]
+struct Field {
+    types: BTreeSet<&'static str>,
+    present: u64,
+}
+
+/// Scan the input and report the fields of the records, their types and how often they are
+/// present, with the optional features of the Caddy logs which are enabled.
+pub fn run(args: InspectArgs) -> Result<()> {
+    let filters = args.filters.build()?;
+    let locale = Locale::from_arg(args.locale.as_deref())?;
+    let mut cardinalities = args
+        .cardinality
+        .iter()
+        .map(|field| Cardinality::new(field, args.top))
+        .collect::<Result<Vec<_>>>()?;
+    let input = args.input.open(&filters)?;
+
+    let mut schema = Schema::default();
+    for line in input {
+        let line = line?;
+        if !filters.matches_line(&line.text) {
+            continue;
+        }
+        let Ok(record) = serde_json::from_str::<LogRecord>(&line.text) else {
+            schema.unparsed += 1;
+            continue;
+        };
+        if !filters.matches(&record) {
+            continue;
+        }
+        let Ok(value) = serde_json::from_str::<Value>(&line.text) else {
+            continue;
+        };
+        if cardinalities.is_empty() {
+            schema.add(&value);
+        }
+        for cardinality in &mut cardinalities {
+            cardinality.add(&value);
+        }
+    }
+    if args.output == ReportOutput::Json {
+        let report = match cardinalities.is_empty() {
+            true => schema.to_json(),
+            false => cardinalities
+                .iter()
+                .map(|cardinality| cardinality.to_json(args.top))
+                .collect(),
+        };
+        println!("{report}");
+        return Ok(());
+    }
+    if cardinalities.is_empty() {
+        print!("{}", schema.report(&locale));
+    }
+    for (index, cardinality) in cardinalities.iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+        print!("{}", cardinality.report(args.top, &locale));
+    }
+    Ok(())
+}
+
+/// The distinct and the most frequent values of a field.
+struct Cardinality {
+    field: String,
+    selector: Selector,
+    records: u64,
+    distinct: HyperLogLog,
+    top: TopValues,
+}
+
+impl Cardinality {
+    fn new(field: &str, top: usize) -> Result<Self> {
+        // the leading dot can be omitted, e.g. `request.uri`
+        let path = match field.starts_with(['.', '[']) {
+            true => field.to_string(),
+            false => format!(".{field}"),
+        };
+        let selector = path
+            .parse::<Selector>()
+            .with_context(|| format!("invalid field: {}", field))?;
+        Ok(Self {
+            field: path,
+            selector,
+            records: 0,
+            distinct: HyperLogLog::new(),
+            // more counters than reported ones make the top values more accurate
+            top: TopValues::new(top.max(1) * 10),
+        })
+    }
+
+    fn add(&mut self, value: &Value) {
+        let value = match self.selector.select(value) {
+            None | Some(Value::Null) => return,
+            Some(Value::String(value)) => value.clone(),
+            Some(value) => value.to_string(),
+        };
+        self.records += 1;
+        self.distinct.insert(&value);
+        self.top.insert(&value);
+    }
+
+    fn report(&self, top: usize, locale: &Locale) -> String {
+        let mut output = format!(
+            "{}: ~{} distinct values in {} records\n",
+            self.field,
+            locale.integer(self.distinct.estimate()),
+            locale.integer(self.records)
+        );
+        let mut rows = vec![vec![
+            "VALUE".to_string(),
+            "COUNT".to_string(),
+            "SHARE".to_string(),
+        ]];
+        for (value, counter) in self.top.top(top) {
+            let count = match counter.error {
+                0 => locale.integer(counter.count),
+                error => format!(
+                    "{}±{}",
+                    locale.integer(counter.count),
+                    locale.integer(error)
+                ),
+            };
+            let share = 100.0 * counter.count as f64 / self.records.max(1) as f64;
+            let share = format!("{}%", locale.decimal(share, 1));
+            rows.push(vec![value.to_string(), count, share]);
+        }
+        output.push_str(&format_table(&rows));
+        output
+    }
+
+    fn to_json(&self, top: usize) -> Value {
+        let values = self
+            .top
+            .top(top)
+            .into_iter()
+            .map(|(value, counter)| {
+                json!({
+                    "value": value,
+                    "count": counter.count,
+                    "error": counter.error,
+                    "share": counter.count as f64 / self.records.max(1) as f64,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "field": self.field,
+            "records": self.records,
+            "distinct": self.distinct.estimate(),
+            "top": values,
+        })
+    }
+}
+
+impl Schema {
+    fn add(&mut self, value: &Value) {
+        let mut fields = BTreeMap::new();
+        walk(value, String::new(), &mut fields);
+        for (path, types) in fields {
+            let field = self.fields.entry(path).or_default();
+            field.types.extend(types);
+            field.present += 1;
+        }
+        self.records += 1;
+    }
+
+    fn report(&self, locale: &Locale) -> String {
+        self.report_fields(locale, |_| true)
+    }
+
+    /// The report of the fields accepted by the predicate, with the features.
+    fn report_fields(&self, locale: &Locale, include: impl Fn(&str) -> bool) -> String {
+        let mut output = format!(
+            "{} records, {} lines not parsed\n\n",
+            locale.integer(self.records),
+            locale.integer(self.unparsed)
+        );
+        let mut rows = vec![vec![
+            "FIELD".to_string(),
+            "TYPE".to_string(),
+            "PRESENT".to_string(),
+        ]];
+        for (path, field) in self.fields.iter().filter(|(path, _)| include(path)) {
+            let types = field.types.iter().copied().collect::<Vec<_>>();
+            rows.push(vec![
+                path.clone(),
+                types.join("|"),
+                format!("{}%", locale.decimal(self.share(field.present), 1)),
+            ]);
+        }
+        output.push_str(&format_table(&rows));
+
+        output.push('\n');
+        for (name, path, description) in FEATURES {
+            let records = match self.feature_present(path) {
+                0 => "disabled".to_string(),
+                present => format!("{}%", locale.decimal(self.share(present), 1)),
+            };
+            output.push_str(&format!("{name:<20} {records:>8}  {description}\n"));
+        }
+        output
+    }
+
+    /// The fields and the features, the features which are disabled have a null share.
+    fn to_json(&self) -> Value {
+        let fields = self
+            .fields
+            .iter()
+            .map(|(path, field)| {
+                json!({
+                    "path": path,
+                    "types": field.types,
+                    "present": self.share(field.present) / 100.0,
+                })
+            })
+            .collect::<Vec<_>>();
+        let features = FEATURES
+            .iter()
+            .map(|(name, path, _)| {
+                let present = match self.feature_present(path) {
+                    0 => None,
+                    present => Some(self.share(present) / 100.0),
+                };
+                json!({ "name": name, "field": path, "present": present })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "records": self.records,
+            "unparsed": self.unparsed,
+            "fields": fields,
+            "features": features,
+        })
+    }
+
+    /// The number of records with the feature, the upstream can be logged by any field whose
+    /// name contains it.
+    fn feature_present(&self, path: &str) -> u64 {
+        if !path.starts_with('.') {
+            return self
+                .fields
+                .iter()
+                .filter(|(field, _)| field.contains(path))
+                .map(|(_, field)| field.present)
+                .max()
+                .unwrap_or(0);
+        }
+        // empty values are logged when the feature is not used, e.g. the user id
+        match self.fields.get(path) {
+            Some(field) if field.types.iter().any(|t| *t != "null" && *t != "empty") => {
+                field.present
+            }
+            _ => 0,
+        }
+    }
+
+    fn share(&self, count: u64) -> f64 {
+        100.0 * count as f64 / self.records.max(1) as f64
+    }
+}
+
+/// Print the records given to another sink, then a short report of the fields found in them:
+/// the headers are not listed one by one, only their presence is.
+pub struct PreviewSink {
+    inner: Box<dyn Sink>,
+    schema: Schema,
+}
+
+impl PreviewSink {
+    pub fn new(inner: Box<dyn Sink>) -> Self {
+        Self {
+            inner,
+            schema: Schema::default(),
+        }
+    }
+}
+
+impl Sink for PreviewSink {
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        if let Ok(value) = serde_json::from_str::<Value>(entry.line) {
+            self.schema.add(&value);
+        }
+        self.inner.write(entry)
+    }
+
+    fn needs_record(&self) -> bool {
+        self.inner.needs_record()
+    }
+
+    fn is_live(&self) -> bool {
+        self.inner.is_live()
+    }
+
+    fn write_unparsed(&mut self, line: &str) -> Result<()> {
+        self.schema.unparsed += 1;
+        self.inner.write_unparsed(line)
+    }
+
+    /// The report is written on the standard error, not to be mixed with the records.
+    fn finish(&mut self) -> Result<()> {
+        self.inner.finish()?;
+        let report = self.schema.report_fields(&Locale::C, |path| {
+            !path.contains("headers.") && !path.contains("headers[") && !path.ends_with("[]")
+        });
+        eprint!("\n{report}");
+        Ok(())
+    }
+}
+
+/// Collect the paths and the types of the values, the elements of the arrays are collected
+/// under the `[]` suffix.
+fn walk(value: &Value, path: String, fields: &mut BTreeMap<String, BTreeSet<&'static str>>) {
+    let kind = match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(value) if value.is_empty() => "empty",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    };
+    if !path.is_empty() {
+        fields.entry(path.clone()).or_default().insert(kind);
+    }
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                walk(value, format!("{path}{}", key_segment(key)), fields);
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                walk(value, format!("{path}[]"), fields);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The segment of a path addressing the key, in the syntax of the selectors.
+fn key_segment(key: &str) -> String {
+    let identifier = !key.is_empty()
+        && !key.starts_with(|c: char| c.is_ascii_digit())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    match identifier {
+        true => format!(".{key}"),
+        false => format!("[{}]", Value::String(key.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(values: &[Value]) -> Schema {
+        let mut schema = Schema::default();
+        for value in values {
+            schema.add(value);
+        }
+        schema
+    }
+
+    #[test]
+    fn collect_the_paths_and_the_types() {
+        let schema = schema(&[
+            json!({ "request": { "uri": "/", "headers": { "X-Forwarded-For": ["10.0.0.1"] } } }),
+            json!({ "request": { "uri": "" }, "1st": null }),
+        ]);
+        let fields = schema
+            .fields
+            .iter()
+            .map(|(path, field)| (path.as_str(), field.types.iter().copied().collect()))
+            .collect::<Vec<(&str, Vec<&str>)>>();
+        assert_eq!(
+            fields,
+            [
+                (".request", vec!["object"]),
+                (".request.headers", vec!["object"]),
+                (r#".request.headers["X-Forwarded-For"]"#, vec!["array"]),
+                (r#".request.headers["X-Forwarded-For"][]"#, vec!["string"]),
+                (".request.uri", vec!["empty", "string"]),
+                (r#"["1st"]"#, vec!["null"]),
+            ]
+        );
+        assert_eq!(schema.fields[".request.headers"].present, 1);
+        assert_eq!(schema.share(schema.fields[".request.uri"].present), 100.0);
+    }
+
+    #[test]
+    fn detect_the_features() {
+        let schema = schema(&[
+            json!({ "user_id": "", "resp_headers": {}, "upstream_addr": "10.0.0.2:8080" }),
+            json!({ "user_id": "", "resp_headers": {} }),
+        ]);
+        let features = schema.to_json()["features"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|feature| (feature["field"].clone(), feature["present"].clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            features,
+            [
+                (json!(".resp_headers"), json!(1.0)),
+                (json!(".request.tls"), Value::Null),
+                // the empty user id is logged when no user is authenticated
+                (json!(".user_id"), Value::Null),
+                (json!(".request.client_ip"), Value::Null),
+                (json!("upstream"), json!(0.5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn count_the_values_of_a_field() {
+        let mut cardinality = Cardinality::new("request.host", 2).unwrap();
+        for host in ["a", "b", "a", "c", "a"] {
+            cardinality.add(&json!({ "request": { "host": host } }));
+        }
+        cardinality.add(&json!({ "request": { "host": null } }));
+        cardinality.add(&json!({ "request": {} }));
+        let report = cardinality.to_json(2);
+        assert_eq!(report["field"], ".request.host");
+        assert_eq!(report["records"], 5);
+        assert_eq!(report["distinct"], 3);
+        assert_eq!(report["top"][0]["value"], "a");
+        assert_eq!(report["top"][0]["count"], 3);
+        assert_eq!(report["top"][0]["share"], 0.6);
+        assert_eq!(report["top"].as_array().unwrap().len(), 2);
+
+        assert!(Cardinality::new("request[", 2).is_err());
+    }
+}
@@ -0,0 +1,127 @@
+use std::{
+    os::unix::net::UnixDatagram,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    record::{LogLevel, LogRecord},
+    sink::{Entry, Sink},
+};
+
+/// The socket of the native protocol of the systemd journal.
+const SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Send each record to the systemd journal, with its priority and the request metadata as
+/// structured fields, e.g. `journalctl -t caddy-pretty-print HOST=shop.example.com STATUS=502`.
+pub struct JournaldSink {
+    socket: UnixDatagram,
+    path: PathBuf,
+    failed: u64,
+}
+
+impl JournaldSink {
+    /// Connect to the journal, the default socket is used when the path is missing.
+    pub fn new(path: Option<&Path>) -> Result<Self> {
+        let path = path.unwrap_or(Path::new(SOCKET)).to_path_buf();
+        let socket = UnixDatagram::unbound()?;
+        socket
+            .connect(&path)
+            .with_context(|| format!("failed to connect to the journal: {}", path.display()))?;
+        Ok(Self {
+            socket,
+            path,
+            failed: 0,
+        })
+    }
+
+    fn fields(entry: &Entry) -> Result<Vec<(&'static str, String)>> {
+        let record = entry.record()?;
+        let mut fields = vec![
+            ("MESSAGE", Self::message(record)),
+            ("PRIORITY", Self::priority(record.level).to_string()),
+            ("SYSLOG_IDENTIFIER", "caddy-pretty-print".to_string()),
+        ];
+        if let Some(request) = &record.request {
+            fields.push(("HOST", request.host.clone()));
+            fields.push(("METHOD", request.method.to_string()));
+            fields.push(("URI", request.uri.clone()));
+            fields.push(("REMOTE_IP", request.remote_ip.to_string()));
+        }
+        if let Some(status) = record.status {
+            fields.push(("STATUS", status.as_u16().to_string()));
+        }
+        if let Some(duration) = record.duration {
+            fields.push(("DURATION_MS", format!("{:.3}", duration * 1000.0)));
+        }
+        fields.push(("CADDY_RECORD", entry.line.to_string()));
+        Ok(fields)
+    }
+
+    fn message(record: &LogRecord) -> String {
+        match (&record.request, record.status) {
+            (Some(request), Some(status)) => format!(
+                "{} {}{} {}",
+                request.method,
+                request.host,
+                request.uri,
+                status.as_u16()
+            ),
+            (Some(request), None) => {
+                format!("{} {}{}", request.method, request.host, request.uri)
+            }
+            _ => record.message.clone(),
+        }
+    }
+
+    /// The syslog priority of the level.
+    fn priority(level: LogLevel) -> u8 {
+        match level {
+            LogLevel::Debug => 7,
+            LogLevel::Info => 6,
+            LogLevel::Warn => 4,
+            LogLevel::Error => 3,
+            LogLevel::Panic | LogLevel::Fatal => 2,
+        }
+    }
+}
+
+/// Serialize the fields with the native protocol, the values spanning multiple lines are
+/// prefixed by their length.
+fn encode(fields: &[(&str, String)]) -> Vec<u8> {
+    let mut datagram = Vec::new();
+    for (name, value) in fields {
+        datagram.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            datagram.push(b'\n');
+            datagram.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            datagram.push(b'=');
+        }
+        datagram.extend_from_slice(value.as_bytes());
+        datagram.push(b'\n');
+    }
+    datagram
+}
+
+impl Sink for JournaldSink {
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        let datagram = encode(&Self::fields(entry)?);
+        if let Err(err) = self.socket.send(&datagram) {
+            // the journal can go away or refuse the oversized records, the others are sent
+            if self.failed == 0 {
+                eprintln!("failed to write to {}: {err}", self.path.display());
+            }
+            self.failed += 1;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.failed > 0 {
+            eprintln!("{} records not written to the journal", self.failed);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+use colored::Colorize;
+use serde_json::Value;
+
+/// Format a JSON value on multiple lines, with indentation and syntax highlighting.
+pub fn format_pretty(value: &Value) -> String {
+    let mut output = String::new();
+    write_value(&mut output, value, 0);
+    output
+}
+
+fn write_value(output: &mut String, value: &Value, indent: usize) {
+    const INDENT: usize = 2;
+
+    match value {
+        Value::Null => output.push_str(&"null".yellow().to_string()),
+        Value::Bool(value) => output.push_str(&value.to_string().yellow().to_string()),
+        Value::Number(value) => output.push_str(&value.to_string().cyan().to_string()),
+        Value::String(value) => output.push_str(&quote(value).green().to_string()),
+        Value::Array(values) if values.is_empty() => output.push_str("[]"),
+        Value::Array(values) => {
+            output.push('[');
+            for (index, value) in values.iter().enumerate() {
+                if index > 0 {
+                    output.push(',');
+                }
+                output.push('\n');
+                output.push_str(&" ".repeat(indent + INDENT));
+                write_value(output, value, indent + INDENT);
+            }
+            output.push('\n');
+            output.push_str(&" ".repeat(indent));
+            output.push(']');
+        }
+        Value::Object(entries) if entries.is_empty() => output.push_str("{}"),
+        Value::Object(entries) => {
+            output.push('{');
+            for (index, (key, value)) in entries.iter().enumerate() {
+                if index > 0 {
+                    output.push(',');
+                }
+                output.push('\n');
+                output.push_str(&" ".repeat(indent + INDENT));
+                output.push_str(&quote(key).blue().bold().to_string());
+                output.push_str(": ");
+                write_value(output, value, indent + INDENT);
+            }
+            output.push('\n');
+            output.push_str(&" ".repeat(indent));
+            output.push('}');
+        }
+    }
+}
+
+fn quote(value: &str) -> String {
+    serde_json::to_string(value).unwrap()
+}
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{
+    locale::Locale,
+    record::{LogRecord, RemoteIp},
+    stats::format_table,
+};
+
+/// The clients which received a `429 Too Many Requests` response, to judge whether the rate
+/// limits are catching abusive clients or legitimate ones.
+#[derive(Default)]
+pub struct RateLimitedClients {
+    clients: HashMap<RemoteIp, Client>,
+}
+
+pub struct Client {
+    pub count: u64,
+    pub first_seen: OffsetDateTime,
+    pub last_seen: OffsetDateTime,
+    paths: HashMap<String, u64>,
+}
+
+impl RateLimitedClients {
+    const TOP_PATHS: usize = 3;
+
+    /// Count the record when it is a rate limited request, its client is returned.
+    pub fn observe(&mut self, record: &LogRecord) -> Option<&Client> {
+        let request = record.request.as_ref()?;
+        if record.status != Some(http::StatusCode::TOO_MANY_REQUESTS) {
+            return None;
+        }
+        let datetime = record.datetime();
+        let client = self
+            .clients
+            .entry(request.remote_ip)
+            .or_insert_with(|| Client {
+                count: 0,
+                first_seen: datetime,
+                last_seen: datetime,
+                paths: HashMap::new(),
+            });
+        client.count += 1;
+        client.first_seen = client.first_seen.min(datetime);
+        client.last_seen = client.last_seen.max(datetime);
+        let path = request.uri.split('?').next().unwrap_or_default();
+        *client.paths.entry(path.to_string()).or_default() += 1;
+        Some(client)
+    }
+
+    /// The rate limited clients, the most limited first.
+    fn sorted(&self) -> Vec<(&RemoteIp, &Client)> {
+        let mut clients = self.clients.iter().collect::<Vec<_>>();
+        clients.sort_by(|(a_ip, a), (b_ip, b)| b.count.cmp(&a.count).then(a_ip.cmp(b_ip)));
+        clients
+    }
+
+    /// The summary of the rate limited clients, the most limited first. Nothing is returned
+    /// when no request has been rate limited.
+    pub fn render(&self, locale: &Locale) -> Option<String> {
+        if self.clients.is_empty() {
+            return None;
+        }
+        let mut rows = vec![vec![
+            "rate limited client".to_string(),
+            "count".into(),
+            "first_seen".into(),
+            "last_seen".into(),
+        ]];
+        let mut paths = Vec::new();
+        for (ip, client) in self.sorted() {
+            rows.push(vec![
+                ip.to_string(),
+                locale.integer(client.count),
+                locale.datetime(client.first_seen),
+                locale.datetime(client.last_seen),
+            ]);
+            paths.push(client.top_paths(Self::TOP_PATHS));
+        }
+        // the paths are appended after the aligned columns, they have variable length
+        let table = format_table(&rows);
+        let mut lines = table.lines();
+        let mut output = format!("{}  top_paths\n", lines.next().unwrap_or_default());
+        for (line, paths) in lines.zip(paths) {
+            output.push_str(&format!("{line}  {paths}\n"));
+        }
+        Some(output)
+    }
+
+    /// The rate limited clients, the most limited first, with the times in RFC 3339 format.
+    pub fn to_json(&self) -> Value {
+        self.sorted()
+            .into_iter()
+            .map(|(ip, client)| {
+                let top_paths = client
+                    .sorted_paths()
+                    .into_iter()
+                    .take(Self::TOP_PATHS)
+                    .map(|(path, count)| json!({ "path": path, "count": count }))
+                    .collect::<Vec<_>>();
+                json!({
+                    "client": ip.to_string(),
+                    "count": client.count,
+                    "first_seen": client.first_seen.format(&Rfc3339).ok(),
+                    "last_seen": client.last_seen.format(&Rfc3339).ok(),
+                    "top_paths": top_paths,
+                })
+            })
+            .collect()
+    }
+}
+
+impl Client {
+    /// The most limited paths of the client, with their counts.
+    pub fn top_paths(&self, n: usize) -> String {
+        self.sorted_paths()
+            .into_iter()
+            .take(n)
+            .map(|(path, count)| format!("{path} ({count})"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn sorted_paths(&self) -> Vec<(&String, &u64)> {
+        let mut paths = self.paths.iter().collect::<Vec<_>>();
+        paths.sort_by(|(a_path, a), (b_path, b)| b.cmp(a).then(a_path.cmp(b_path)));
+        paths
+    }
+}
+
+pub fn format_datetime(datetime: OffsetDateTime) -> String {
+    const FORMAT: &[time::format_description::FormatItem<'static>] =
+        time::macros::format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+    datetime.format(FORMAT).unwrap_or_default()
+}
@@ -0,0 +1,293 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use time::OffsetDateTime;
+
+/// The conventions used to render the numbers and the dates of the reports, e.g. the statistics
+/// pasted into documents. The `C` locale renders them as the machine readable outputs do.
+#[derive(Clone, Copy, Debug)]
+pub struct Locale {
+    name: &'static str,
+    thousands: &'static str,
+    decimal: char,
+    months: [&'static str; 12],
+    /// The date with the `{day}`, `{month}` and `{year}` placeholders.
+    date: &'static str,
+    twelve_hour: bool,
+}
+
+const ENGLISH_MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+const LOCALES: &[Locale] = &[
+    Locale::C,
+    Locale {
+        name: "en_US",
+        thousands: ",",
+        decimal: '.',
+        months: ENGLISH_MONTHS,
+        date: "{month} {day}, {year}",
+        twelve_hour: true,
+    },
+    Locale {
+        name: "en_GB",
+        thousands: ",",
+        decimal: '.',
+        months: ENGLISH_MONTHS,
+        date: "{day} {month} {year}",
+        twelve_hour: false,
+    },
+    Locale {
+        name: "de_DE",
+        thousands: ".",
+        decimal: ',',
+        months: [
+            "Jan.", "Feb.", "März", "Apr.", "Mai", "Juni", "Juli", "Aug.", "Sept.", "Okt.", "Nov.",
+            "Dez.",
+        ],
+        date: "{day}. {month} {year}",
+        twelve_hour: false,
+    },
+    Locale {
+        name: "fr_FR",
+        thousands: "\u{202f}",
+        decimal: ',',
+        months: [
+            "janv.", "févr.", "mars", "avr.", "mai", "juin", "juil.", "août", "sept.", "oct.",
+            "nov.", "déc.",
+        ],
+        date: "{day} {month} {year}",
+        twelve_hour: false,
+    },
+    Locale {
+        name: "it_IT",
+        thousands: ".",
+        decimal: ',',
+        months: [
+            "gen", "feb", "mar", "apr", "mag", "giu", "lug", "ago", "set", "ott", "nov", "dic",
+        ],
+        date: "{day} {month} {year}",
+        twelve_hour: false,
+    },
+    Locale {
+        name: "es_ES",
+        thousands: ".",
+        decimal: ',',
+        months: [
+            "ene", "feb", "mar", "abr", "may", "jun", "jul", "ago", "sept", "oct", "nov", "dic",
+        ],
+        date: "{day} {month} {year}",
+        twelve_hour: false,
+    },
+    Locale {
+        name: "pt_BR",
+        thousands: ".",
+        decimal: ',',
+        months: [
+            "jan.", "fev.", "mar.", "abr.", "mai.", "jun.", "jul.", "ago.", "set.", "out.", "nov.",
+            "dez.",
+        ],
+        date: "{day} de {month} de {year}",
+        twelve_hour: false,
+    },
+    Locale {
+        name: "nl_NL",
+        thousands: ".",
+        decimal: ',',
+        months: [
+            "jan", "feb", "mrt", "apr", "mei", "jun", "jul", "aug", "sep", "okt", "nov", "dec",
+        ],
+        date: "{day} {month} {year}",
+        twelve_hour: false,
+    },
+];
+
+impl Locale {
+    pub const C: Locale = Locale {
+        name: "C",
+        thousands: "",
+        decimal: '.',
+        months: ENGLISH_MONTHS,
+        date: "",
+        twelve_hour: false,
+    };
+
+    /// The locale of a `--locale` flag: `C` when missing, the locale of the environment when
+    /// empty.
+    pub fn from_arg(arg: Option<&str>) -> Result<Self> {
+        match arg {
+            None => Ok(Locale::C),
+            Some("") => Ok(Locale::from_env()),
+            Some(name) => name.parse(),
+        }
+    }
+
+    /// The locale of the environment, given by `LC_ALL` or `LANG`. The unsupported locales fall
+    /// back to `C`.
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(Locale::C)
+    }
+
+    /// The integer with the thousands separated.
+    pub fn integer(&self, value: u64) -> String {
+        let digits = value.to_string();
+        if self.thousands.is_empty() {
+            return digits;
+        }
+        let mut output = String::new();
+        for (index, digit) in digits.chars().enumerate() {
+            if index > 0 && (digits.len() - index).is_multiple_of(3) {
+                output.push_str(self.thousands);
+            }
+            output.push(digit);
+        }
+        output
+    }
+
+    /// The number with the given number of decimals.
+    pub fn decimal(&self, value: f64, decimals: usize) -> String {
+        self.localize(&format!("{value:.decimals$}"))
+    }
+
+    /// Replace the decimal point of the numbers in an already formatted text, e.g. a duration.
+    pub fn localize(&self, formatted: &str) -> String {
+        let (integer, fraction) = match formatted.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (formatted, None),
+        };
+        // the thousands of the integer part are separated as well, when it is a number
+        let mut output = match integer.parse::<u64>() {
+            Ok(value) => self.integer(value),
+            Err(_) => integer.to_string(),
+        };
+        if let Some(fraction) = fraction {
+            output.push(self.decimal);
+            output.push_str(fraction);
+        }
+        output
+    }
+
+    pub fn datetime(&self, datetime: OffsetDateTime) -> String {
+        if self.date.is_empty() {
+            return crate::limited::format_datetime(datetime);
+        }
+        let date = self
+            .date
+            .replace("{day}", &datetime.day().to_string())
+            .replace("{month}", self.months[datetime.month() as usize - 1])
+            .replace("{year}", &datetime.year().to_string());
+        let (hour, minute, second) = datetime.to_hms();
+        let time = match self.twelve_hour {
+            true => {
+                let period = if hour < 12 { "AM" } else { "PM" };
+                let hour = (hour + 11) % 12 + 1;
+                format!("{hour}:{minute:02}:{second:02} {period}")
+            }
+            false => format!("{hour:02}:{minute:02}:{second:02}"),
+        };
+        format!("{date} {time}")
+    }
+}
+
+impl FromStr for Locale {
+    type Err = anyhow::Error;
+
+    /// The encoding and the modifier are ignored, e.g. `de_DE.UTF-8`, and a language alone
+    /// selects its first supported country.
+    fn from_str(s: &str) -> Result<Self> {
+        let name = s.split(['.', '@']).next().unwrap_or_default();
+        if name == "POSIX" {
+            return Ok(Locale::C);
+        }
+        let locale = LOCALES
+            .iter()
+            .find(|locale| locale.name == name)
+            .or_else(|| {
+                LOCALES
+                    .iter()
+                    .find(|locale| locale.name.split('_').next() == Some(name))
+            });
+        match locale {
+            Some(locale) => Ok(*locale),
+            None => {
+                let names = LOCALES.iter().map(|locale| locale.name);
+                bail!(
+                    "unsupported locale: {} (supported: {})",
+                    s,
+                    names.collect::<Vec<_>>().join(", ")
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_digits() {
+        let expected = [
+            ("C", "1234567"),
+            ("en_US", "1,234,567"),
+            ("en_GB", "1,234,567"),
+            ("de_DE", "1.234.567"),
+            ("fr_FR", "1\u{202f}234\u{202f}567"),
+            ("it_IT", "1.234.567"),
+            ("es_ES", "1.234.567"),
+            ("pt_BR", "1.234.567"),
+            ("nl_NL", "1.234.567"),
+        ];
+        assert_eq!(expected.len(), LOCALES.len());
+        for (name, grouped) in expected {
+            let locale = name.parse::<Locale>().unwrap();
+            assert_eq!(locale.integer(1234567), grouped, "{name}");
+            assert_eq!(locale.integer(0), "0", "{name}");
+            assert_eq!(locale.integer(999), "999", "{name}");
+        }
+        let locale = "en_US".parse::<Locale>().unwrap();
+        assert_eq!(locale.integer(1000), "1,000");
+        assert_eq!(locale.integer(100000), "100,000");
+    }
+
+    #[test]
+    fn separate_decimals() {
+        let expected = [
+            ("C", "1234.50"),
+            ("en_US", "1,234.50"),
+            ("en_GB", "1,234.50"),
+            ("de_DE", "1.234,50"),
+            ("fr_FR", "1\u{202f}234,50"),
+            ("it_IT", "1.234,50"),
+            ("es_ES", "1.234,50"),
+            ("pt_BR", "1.234,50"),
+            ("nl_NL", "1.234,50"),
+        ];
+        assert_eq!(expected.len(), LOCALES.len());
+        for (name, decimal) in expected {
+            let locale = name.parse::<Locale>().unwrap();
+            assert_eq!(locale.decimal(1234.5, 2), decimal, "{name}");
+        }
+    }
+
+    #[test]
+    fn localize_durations() {
+        let locale = "de_DE".parse::<Locale>().unwrap();
+        assert_eq!(locale.localize("12.5ms"), "12,5ms");
+        assert_eq!(locale.localize("1500"), "1.500");
+    }
+
+    #[test]
+    fn parse_names() {
+        assert_eq!("de_DE.UTF-8".parse::<Locale>().unwrap().name, "de_DE");
+        assert_eq!("en".parse::<Locale>().unwrap().name, "en_US");
+        assert_eq!("POSIX".parse::<Locale>().unwrap().name, "C");
+        assert!("xx_XX".parse::<Locale>().is_err());
+    }
+}
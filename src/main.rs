@@ -1,40 +1,689 @@
-use std::io::{BufRead, IsTerminal, Write};
+use std::{
+    io::IsTerminal,
+    path::{Path, PathBuf},
+};
 
-use anyhow::Result;
-use clap::{Parser, ValueEnum};
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
 use filters::Filters;
+use geoip::GeoIp;
+use terminal_size::{terminal_size, Width};
 
-use self::record::LogRecord;
+use self::{
+    aggregate::Aggregation,
+    assertions::{AssertSink, Assertion},
+    clickhouse::ClickHouseSink,
+    config::Config,
+    digest::DigestSink,
+    exec::ExecSink,
+    expr::Expr,
+    histogram::HistogramSink,
+    input::{InputArgs, Line, Lines},
+    inspect::PreviewSink,
+    locale::Locale,
+    monitors::Monitors,
+    notify::NotifySink,
+    otlp::OtlpSink,
+    progress::Progress,
+    rate::{Rate, RateLimitedSink, RateLimiter},
+    record::{Detail, FormatOptions, GroupBy, Layout, LogLevel, LogRecord, RecordView},
+    redact::Redaction,
+    rules::RuleSet,
+    sample::{Sample, SampleKey},
+    selector::Selector,
+    sink::{Compression, Entry, ExtractSink, Output, Sink, SinkSpec},
+    stats::{Column, StatsOptions, StatsSink},
+    summary::SummarySink,
+};
 
+mod aggregate;
+mod anomaly;
+mod assertions;
+mod batch;
+mod bots;
+mod budgets;
+mod caddy;
+mod clickhouse;
+mod concurrency;
+mod condition;
+mod config;
+mod console;
+mod digest;
+mod enrich;
+mod exec;
+mod expr;
 mod filters;
+mod geoip;
+mod histogram;
+mod input;
+mod inspect;
+#[cfg(unix)]
+mod journald;
+mod json;
+mod limited;
+mod locale;
+mod management;
+mod mapping;
+mod monitors;
+mod ndjson;
+mod notify;
+mod otlp;
+mod palette;
+mod progress;
+mod rate;
 mod record;
+mod redact;
+mod redirects;
+mod replay;
+mod retries;
+mod rules;
+mod sample;
+mod selector;
+mod serve;
+mod sessions;
+mod severity;
+mod sink;
+mod sketches;
+mod skew;
+mod ssh;
+mod stats;
+mod summary;
+mod syntax;
+mod table;
+mod tls;
+mod top;
+mod trends;
+mod units;
+mod validate;
 
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let mut args = Args::parse();
     let stdout = std::io::stdout();
-    let stdin = std::io::stdin().lock();
-    match args.color {
-        Color::Always | Color::Auto if stdout.is_terminal() => colored::control::set_override(true),
-        _ => colored::control::set_override(false),
+    let colored = matches!(args.color, Color::Always | Color::Auto) && stdout.is_terminal();
+    colored::control::set_override(console::init(colored));
+    palette::install(args.palette);
+
+    match args.command {
+        Some(Command::Serve(serve)) => return serve::run(serve),
+        Some(Command::Inspect(inspect)) => return inspect::run(inspect),
+        Some(Command::Validate(validate)) => return validate::run(validate),
+        Some(Command::Replay(replay)) => return replay::run(replay),
+        Some(Command::Sessions(sessions)) => return sessions::run(sessions),
+        Some(Command::Top(top)) => return top::run(top),
+        None => {}
+    }
+    let config = Config::load(args.config.as_deref())?;
+    let monitors = Monitors::from_config(config.monitors);
+    let budgets = config.budgets.unwrap_or_default();
+    severity::install(config.severity.unwrap_or_default());
+    let filters = args
+        .filters
+        .build_with(monitors.clone(), config.geoip_db.as_deref())?;
+    args.input.with_fields(config.fields.unwrap_or_default());
+    if args.line_numbers {
+        args.input.with_line_numbers();
+    }
+    let extract = args
+        .extract
+        .iter()
+        .map(|path| {
+            path.parse::<Selector>()
+                .with_context(|| format!("invalid extract expression: {}", path))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let retry_window = args
+        .retries
+        .as_deref()
+        .map(units::parse_duration)
+        .transpose()
+        .context("invalid --retries")?;
+    let locale = Locale::from_arg(args.locale.as_deref())?;
+    let options = FormatOptions {
+        layout: args.layout,
+        width: terminal_size().map(|(Width(width), _)| width as usize),
+        day_banners: args.day_banners,
+        hourly_subtotals: args.hourly_subtotals,
+        throughput_min_size: units::parse_size(&args.throughput_min_size)
+            .context("invalid --throughput-min-size")?,
+        latency_anomalies: args.latency_anomalies,
+        unusual_headers: args.unusual_headers,
+        retry_window,
+        group_by: args.group_by,
+        detail: Detail::from_verbosity(args.verbose),
+        line_numbers: args.line_numbers,
+        table: config.table.unwrap_or_default(),
+        monitors,
+        budgets: budgets.clone(),
+        locale,
+    };
+    let percentiles = args
+        .percentiles
+        .iter()
+        .map(|p| stats::parse_percentile(p))
+        .collect::<Result<Vec<_>>>()?;
+    let aggregation = args
+        .aggregate
+        .as_deref()
+        .map(|period| parse_aggregation(period, percentiles.clone()))
+        .transpose()?;
+    let mut sinks = Vec::<Box<dyn Sink>>::new();
+    if args.stats {
+        let sort = args.sort_stats.parse::<Column>()?;
+        if !matches!(
+            args.output,
+            Output::Pretty | Output::Json | Output::JsonPretty
+        ) {
+            bail!("the statistics can be printed only as pretty, json or json-pretty");
+        }
+        let options = StatsOptions {
+            retry_window,
+            path_trends: args.path_trends,
+            redirects: args.redirects,
+            segment: args.segment,
+            concurrency: args.concurrency,
+            output: args.output,
+            budgets,
+            ..StatsOptions::new(percentiles, sort, locale)?
+        };
+        sinks.push(Box::new(StatsSink::new(options)));
+    } else if args.error_digest {
+        if !matches!(
+            args.output,
+            Output::Pretty | Output::Json | Output::JsonPretty
+        ) {
+            bail!("the error digest can be printed only as pretty, json or json-pretty");
+        }
+        sinks.push(Box::new(DigestSink::new(args.output, locale)));
+    } else if !extract.is_empty() {
+        sinks.push(Box::new(ExtractSink::new(extract)));
+    } else if args.raw || (args.sink.is_empty() && !args.quiet) {
+        let spec = match args.raw {
+            true => SinkSpec::stdout(Output::Json),
+            false => SinkSpec::stdout(args.output).aggregated(aggregation.clone()),
+        };
+        let sink = spec.open(&options)?;
+        match &args.rate_limit {
+            Some(rate) => {
+                let rate = rate.parse::<Rate>().context("invalid --rate-limit")?;
+                sinks.push(Box::new(RateLimitedSink::new(sink, rate)));
+            }
+            None => sinks.push(sink),
+        }
+    }
+    if let Some(command) = &args.exec {
+        let condition = parse_condition(args.exec_when.as_deref())?;
+        let rate = args.exec_rate.parse::<Rate>()?;
+        sinks.push(Box::new(ExecSink::new(
+            command,
+            condition,
+            RateLimiter::new(rate),
+        )?));
+    }
+    if args.notify_desktop {
+        let condition = parse_condition(args.notify_on.as_deref())?;
+        let rate = args.notify_rate.parse::<Rate>()?;
+        sinks.push(Box::new(NotifySink::new(condition, RateLimiter::new(rate))));
+    }
+    if args.summary_every.is_some() || args.alert_on.is_some() {
+        let period = args
+            .summary_every
+            .as_deref()
+            .map(units::parse_duration)
+            .transpose()
+            .context("invalid --summary-every")?;
+        if period.is_some_and(|period| period.is_zero()) {
+            bail!("the summary period must be greater than zero");
+        }
+        let alert = parse_condition(args.alert_on.as_deref())?;
+        let rate = args.alert_rate.parse::<Rate>()?;
+        sinks.push(Box::new(SummarySink::new(
+            period,
+            alert,
+            RateLimiter::new(rate),
+        )));
+    }
+    if let Some(endpoint) = &args.otlp_traces {
+        sinks.push(Box::new(OtlpSink::new(endpoint)));
+    }
+    if let Some(path) = &args.histogram_out {
+        sinks.push(Box::new(HistogramSink::new(path.clone())));
+    }
+    if let Some(url) = &args.clickhouse {
+        sinks.push(Box::new(ClickHouseSink::new(url, &args.clickhouse_table)?));
+    }
+    for sink in &args.sink {
+        let spec = sink
+            .parse::<SinkSpec>()
+            .with_context(|| format!("invalid sink: {}", sink))?;
+        let spec = spec
+            .compressed(args.compress)
+            .aggregated(aggregation.clone());
+        sinks.push(spec.open(&options)?);
+    }
+    if !args.assert.is_empty() {
+        let assertions = args
+            .assert
+            .iter()
+            .map(|assertion| {
+                assertion
+                    .parse::<Assertion>()
+                    .with_context(|| format!("invalid assertion: {}", assertion))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // the other sinks are finished first, the failed assertions end the run
+        sinks.push(Box::new(AssertSink::new(assertions)));
+    }
+    if args.preview.is_some() {
+        sinks = sinks
+            .into_iter()
+            .map(|sink| Box::new(PreviewSink::new(sink)) as Box<dyn Sink>)
+            .collect();
     }
 
-    let mut filters = Filters::builder();
-    filters.with_strict(args.strict);
-    for host in args.host {
-        filters.with_host(&host)?;
+    // the reports are written when the input ends, it must end
+    if sinks.iter().all(|sink| sink.is_live()) {
+        args.input.with_live();
     }
+    let input = args.input.open(&filters)?;
+    let input: Lines = match args.preview {
+        Some(count) => Box::new(input.take(count)),
+        None => input,
+    };
 
-    process_lines(stdin, stdout, filters.build()?)
+    // the progress is shown when the output is not mixed with it
+    let progress = match args.input.size() {
+        Some(size)
+            if !args.no_progress
+                && std::io::stderr().is_terminal()
+                && (args.stats || args.error_digest || !stdout.is_terminal()) =>
+        {
+            Some(Progress::new(size))
+        }
+        _ => None,
+    };
+    let redaction = Redaction::new(args.drop_fields, args.hash_fields, args.hash_key)?;
+    process_lines(input, filters, &redaction, &mut sinks, progress)
 }
 
 /// caddy-pretty-print is a simple tool for nicely viewing caddy JSON logs.
 #[derive(Debug, Parser)]
-#[command(version, about, max_term_width = 120)]
+#[command(
+    version,
+    about,
+    max_term_width = 120,
+    args_conflicts_with_subcommands = true
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Read only the first N lines of the input, print the records among them which pass the
+    /// filters and a short report of the fields they have, then exit. A quick check of the
+    /// filters and of the output before a long run over an archive.
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = [
+            "follow", "stats", "error_digest", "extract", "sink", "exec", "notify_desktop",
+            "otlp_traces", "histogram_out", "clickhouse"
+        ]
+    )]
+    preview: Option<usize>,
+
     /// When to use terminal colors.
     #[arg(long, default_value = "auto")]
     color: Color,
 
+    /// The colors and the styles of the levels and of the statuses, `colorblind` tells the
+    /// errors apart by bold, underlined and reversed text too.
+    #[arg(long, default_value = "default")]
+    palette: palette::Palette,
+
+    /// Do not show the progress of the files being read. The progress is shown on the standard
+    /// error when it is a terminal, while computing the statistics or when the output is
+    /// redirected.
+    #[arg(long)]
+    no_progress: bool,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    filters: FilterArgs,
+
+    /// How matched log lines are printed.
+    #[arg(long, default_value = "pretty")]
+    output: Output,
+
+    /// Print the original JSON line of each record which passes the filters, without any
+    /// formatting. The records are not fully parsed, unless the filters need it.
+    #[arg(
+        long,
+        conflicts_with_all = ["output", "stats", "error_digest", "extract", "aggregate", "group_by"]
+    )]
+    raw: bool,
+
+    /// The config file, `caddy-pretty-print/config.yaml` in the config directory of the user is
+    /// read when it exists.
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// How the fields of each record are arranged by the pretty output.
+    #[arg(long, default_value = "stacked")]
+    layout: Layout,
+
+    /// Print a banner at each day boundary, the first day of a new week is highlighted.
+    #[arg(long)]
+    day_banners: bool,
+
+    /// Print the number of records and errors of each hour, when the hour ends.
+    #[arg(long)]
+    hourly_subtotals: bool,
+
+    /// Print the records under a heading for each group, with the number of records of the
+    /// group. The records are printed once the whole input has been read.
+    #[arg(
+        long,
+        value_name = "FIELD",
+        conflicts_with_all = ["follow", "day_banners", "hourly_subtotals"]
+    )]
+    group_by: Option<GroupBy>,
+
+    /// Print more details of each record: `-v` adds the headers, `-vv` the TLS connection and
+    /// the fields not otherwise displayed, `-vvv` the original JSON record.
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Prefix each record with the file and the number of its original line (e.g.
+    /// `access.log:42`), to find it with `sed -n '42p' access.log`.
+    #[arg(long)]
+    line_numbers: bool,
+
+    /// Display the transfer rate of the responses of at least this size (e.g. `500kB`,
+    /// `2MiB`).
+    #[arg(long, value_name = "SIZE", default_value = "1MB")]
+    throughput_min_size: String,
+
+    /// Mark the requests much slower than the usual ones of their path, by the given number of
+    /// standard deviations. The baseline of each path is updated as the records are read.
+    #[arg(
+        long,
+        value_name = "SIGMAS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "3"
+    )]
+    latency_anomalies: Option<f64>,
+
+    /// Mark the requests whose headers deviate from the usual ones, learned from the records
+    /// read so far: a rare value of a header mostly sent with the same one (e.g. an odd
+    /// `Accept-Encoding` or user agent), or a header missing while almost all the clients send
+    /// it.
+    #[arg(long)]
+    unusual_headers: bool,
+
+    /// Mark the identical requests (same client, method and URI) sent within the given window
+    /// of the previous one, which are usually retries. The statistics report the clients
+    /// repeating the most requests.
+    #[arg(
+        long,
+        value_name = "WINDOW",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "2s"
+    )]
+    retries: Option<String>,
+
+    /// Print only the values addressed by a jq-like path (e.g. `.request.headers["X-Tenant"][0]`)
+    /// instead of the whole record. This flag can be repeated, multiple values are separated by
+    /// tabs.
+    #[arg(long, value_name = "PATH")]
+    extract: Vec<String>,
+
+    /// Print a per-host summary of the matched requests, instead of the records. The summary is
+    /// printed as a JSON document with `--output json` or `--output json-pretty`.
+    #[arg(long)]
+    stats: bool,
+
+    /// Print a digest of the errors instead of the records: the error records are grouped by
+    /// their message, with the numbers and the identifiers stripped, and each kind of error is
+    /// reported with its count and its first and last occurrence. The digest is printed as a JSON
+    /// document with `--output json` or `--output json-pretty`.
+    #[arg(long, conflicts_with_all = ["stats", "extract"])]
+    error_digest: bool,
+
+    /// Add to the statistics the busiest paths, with a sparkline of the p95 of their duration
+    /// over the analyzed window.
+    #[arg(long, requires = "stats")]
+    path_trends: bool,
+
+    /// Add to the statistics the redirects served, from their sources to the targets of their
+    /// `Location` header, and the redirect loops among them.
+    #[arg(long, requires = "stats")]
+    redirects: bool,
+
+    /// Add to the statistics the requests of each hour of the day (in UTC) or of each day of
+    /// the week, with their error rate and their duration percentiles, e.g. to size the peaks.
+    #[arg(long, value_name = "SEGMENT", requires = "stats")]
+    segment: Option<stats::Segment>,
+
+    /// Add to the statistics the requests in flight over time, overall and for the busiest
+    /// hosts, reconstructed from the timestamps and the durations: their peak, their mean and
+    /// the most requests started within a second.
+    #[arg(long, requires = "stats")]
+    concurrency: bool,
+
+    /// The column used to sort the statistics: `host`, `count`, `error_rate`, `cache_hit_ratio`
+    /// or one of the reported percentiles (e.g. `p95`). Numeric columns are sorted in
+    /// descending order.
+    #[arg(long, value_name = "COLUMN", default_value = "host")]
+    sort_stats: String,
+
+    /// Render the numbers and the dates of the statistics and of the other reports, like the
+    /// one of the latency budgets, with the conventions of a locale (e.g. `de_DE`), or of the
+    /// locale of the environment (`LC_ALL` or `LANG`) when no value is given.
+    #[arg(
+        long,
+        value_name = "LOCALE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    locale: Option<String>,
+
+    /// The percentiles of the request duration reported in the statistics and in the
+    /// aggregated exports.
+    #[arg(long, value_delimiter = ',', default_value = "50,95,99")]
+    percentiles: Vec<String>,
+
+    /// Do not print the records, e.g. to run unattended with `--summary-every` and `--alert-on`.
+    /// The sinks, the commands and the notifications are not affected.
+    #[arg(
+        long,
+        conflicts_with_all = ["raw", "stats", "error_digest", "extract", "group_by", "preview"]
+    )]
+    quiet: bool,
+
+    /// Print a one-line summary of the matched records every period of time (e.g. `1m`): their
+    /// number, the server errors, the duration percentiles and the alerts. The periods follow
+    /// the clock, the summary of the last one is printed when the input ends.
+    #[arg(long, value_name = "PERIOD")]
+    summary_every: Option<String>,
+
+    /// Print a line for each matched record satisfying this condition (e.g. `status >= 500 ||
+    /// duration > 5s`), prefixed with `ALERT`.
+    #[arg(long, value_name = "CONDITION")]
+    alert_on: Option<String>,
+
+    /// The maximum rate of the alert lines (e.g. `10/m`), the exceeding ones are counted.
+    #[arg(
+        long,
+        value_name = "RATE",
+        default_value = "1/s",
+        requires = "alert_on"
+    )]
+    alert_rate: String,
+
+    /// The maximum rate of the records printed when following (e.g. `50/s`), the exceeding ones
+    /// are dropped and their number is written on the standard error.
+    #[arg(
+        long,
+        value_name = "RATE",
+        requires = "follow",
+        conflicts_with_all = ["stats", "error_digest", "extract", "quiet", "aggregate"]
+    )]
+    rate_limit: Option<String>,
+
+    /// Check a property of the matched records when the input ends, e.g. `p95(duration) <
+    /// 500ms` or `count(status >= 500) == 0`, and exit with an error when it does not hold. The
+    /// aggregates are `count()`, `count(CONDITION)`, `rate(CONDITION)` and the percentiles,
+    /// `avg`, `min` and `max` of `duration` or `size`. This flag can be repeated, a report of
+    /// the assertions is written on the standard error.
+    #[arg(long, value_name = "ASSERTION")]
+    assert: Vec<String>,
+
+    /// Send the matched records to a destination, in the form `OUTPUT[:PATH]` (e.g.
+    /// `json:filtered.log` or `prometheus:caddy.prom`); without a path the standard output is
+    /// used. This flag can be repeated, when present `--output` is ignored.
+    #[arg(long, value_name = "OUTPUT[:PATH]")]
+    sink: Vec<String>,
+
+    /// Write a snapshot of the request counts and of the latency histograms, by host and status
+    /// class, in the Prometheus text format when the input ends, e.g. for the textfile
+    /// collector of node_exporter. The file is replaced atomically.
+    #[arg(long, value_name = "FILE")]
+    histogram_out: Option<PathBuf>,
+
+    /// Drop fields of the records before they are written by the output and the sinks, e.g.
+    /// `uri_query,user_agent`. The required fields, like `remote_ip` and `uri`, are emptied.
+    #[arg(long, value_enum, value_name = "FIELDS", value_delimiter = ',')]
+    drop_fields: Vec<redact::Field>,
+
+    /// Replace fields of the records by a keyed hash before they are written by the output and
+    /// the sinks, e.g. `remote_ip`. The same values have the same hash, so the clients can still
+    /// be told apart; the addresses are hashed to IPv6 unique local addresses.
+    #[arg(long, value_enum, value_name = "FIELDS", value_delimiter = ',')]
+    hash_fields: Vec<redact::Field>,
+
+    /// The key of the hashes of `--hash-fields` (HMAC-SHA256), the hashes are the same in every
+    /// run with the same key. A random key is used when it is not given.
+    #[arg(long, value_name = "KEY", requires = "hash_fields")]
+    hash_key: Option<String>,
+
+    /// Aggregate the requests written by the metric outputs (`csv`, `influx` and `prometheus`)
+    /// in time buckets of this length (e.g. `1m`): one row is written for each bucket, host and
+    /// status class, with the number of requests and the duration percentiles.
+    #[arg(long, value_name = "PERIOD")]
+    aggregate: Option<String>,
+
+    /// Compress the files written by the sinks, e.g. to archive the filtered records. The
+    /// Prometheus files are not compressed, to be read by the collectors.
+    #[arg(long, value_name = "FORMAT")]
+    compress: Option<Compression>,
+
+    /// Run a shell command for each matched record, the record is written on its standard input.
+    /// The placeholder `{}` is replaced by the whole record, a jq-like path (e.g.
+    /// `{.request.remote_ip}`) by the selected value. The values are passed in environment
+    /// variables, never spliced in the command line, so they cannot add commands.
+    #[arg(long, value_name = "COMMAND")]
+    exec: Option<String>,
+
+    /// Run the command only for the records matching this condition (e.g. `status >= 500 &&
+    /// host == "shop.*"`).
+    #[arg(long, value_name = "CONDITION", requires = "exec")]
+    exec_when: Option<String>,
+
+    /// The maximum rate of executed commands (e.g. `1/s`, `10/m`), the exceeding records are
+    /// skipped.
+    #[arg(long, value_name = "RATE", default_value = "1/s", requires = "exec")]
+    exec_rate: String,
+
+    /// Raise a desktop notification for each matched record.
+    #[arg(long)]
+    notify_desktop: bool,
+
+    /// Raise the notification only for the records matching this condition (e.g. `status >= 500
+    /// && host == "shop.*"`).
+    #[arg(long, value_name = "CONDITION", requires = "notify_desktop")]
+    notify_on: Option<String>,
+
+    /// The maximum rate of desktop notifications (e.g. `1/10s`), the exceeding records are
+    /// skipped.
+    #[arg(
+        long,
+        value_name = "RATE",
+        default_value = "1/10s",
+        requires = "notify_desktop"
+    )]
+    notify_rate: String,
+
+    /// Export each request as a span to an OpenTelemetry collector, with OTLP over HTTP (e.g.
+    /// `http://localhost:4318`).
+    #[arg(long, value_name = "ENDPOINT")]
+    otlp_traces: Option<String>,
+
+    /// Insert the requests into a ClickHouse table, through the HTTP interface (e.g.
+    /// `http://localhost:8123`) or the native protocol (e.g. `tcp://localhost:9000`). The
+    /// credentials can be given as `user` and `password` query parameters.
+    #[arg(long, value_name = "URL")]
+    clickhouse: Option<String>,
+
+    /// The ClickHouse table the requests are inserted into, it is created when missing.
+    #[arg(
+        long,
+        value_name = "TABLE",
+        default_value = "caddy_access",
+        requires = "clickhouse"
+    )]
+    clickhouse_table: String,
+}
+
+fn parse_aggregation(period: &str, percentiles: Vec<f64>) -> Result<Aggregation> {
+    let period = units::parse_duration(period).context("invalid --aggregate")?;
+    if period.as_secs() == 0 || period.subsec_nanos() != 0 {
+        bail!("the aggregation period must be a whole number of seconds");
+    }
+    Ok(Aggregation {
+        period,
+        percentiles,
+    })
+}
+
+fn parse_condition(condition: Option<&str>) -> Result<Option<Expr>> {
+    condition
+        .map(|condition| {
+            condition
+                .parse::<Expr>()
+                .with_context(|| format!("invalid condition: {}", condition))
+        })
+        .transpose()
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Serve the records through a small web UI, the filters are available as query parameters.
+    Serve(serve::ServeArgs),
+    /// Report the fields of the records, their types and how often they are present, with the
+    /// optional features of the Caddy logs which are enabled.
+    Inspect(inspect::InspectArgs),
+    /// Check the expressions, the paths and the other definitions given as flags without
+    /// reading any input, the errors are reported with their position.
+    Validate(validate::ValidateArgs),
+    /// Send the requests of the log to another server, e.g. a staging environment, and report
+    /// how the statuses and the durations differ from the logged ones.
+    Replay(replay::ReplayArgs),
+    /// Group the requests of each client in sessions, split by a gap of inactivity, and report
+    /// their durations, their entry and exit paths and the most common flows between pages.
+    Sessions(sessions::SessionsArgs),
+    /// Report the most frequent paths, clients or other keys of the requests in a bounded
+    /// memory, the counts are estimated when there are too many distinct values.
+    Top(top::TopArgs),
+}
+
+/// The flags used to select the log lines.
+#[derive(Clone, Debug, Default, clap::Args)]
+pub struct FilterArgs {
     /// Suppress all but legal log lines. By default lines that cannot be parsed are passed
     /// through.
     #[arg(long)]
@@ -42,8 +691,366 @@ struct Args {
 
     /// Filter the log lines by `host` header value. This flag can be repeated to search for
     /// multiples hosts or the glob syntax can be used to search hosts matching a given pattern.
+    /// The hosts prefixed with `!` are hidden, e.g. `!*.internal`.
     #[arg(long)]
     host: Vec<String>,
+
+    /// Show only the log lines at or above the given level, e.g. `--level warn` shows the
+    /// warnings and the errors.
+    #[arg(long, value_enum)]
+    level: Option<LogLevel>,
+
+    /// Show only the errors: the requests with a 4xx or a 5xx status, or the ones counted as
+    /// errors by the `severity` section of the config file, and the records at the error level
+    /// or above.
+    #[arg(long)]
+    errors_only: bool,
+
+    /// Show only the records of a logger and of the ones below it, e.g. `http.log.access` or
+    /// `tls`, or of the loggers matching a glob pattern. This flag can be repeated to search for
+    /// multiple loggers, `!tls` hides the records of a logger.
+    #[arg(long)]
+    logger: Vec<String>,
+
+    /// Filter the log lines by response status, e.g. `404`. Classes of statuses can be given as
+    /// `4xx`, and multiple statuses as a comma separated list like `401,403,5xx`. The statuses
+    /// prefixed with `!` are hidden, e.g. `!2xx,!304`.
+    #[arg(long, value_name = "STATUS", value_delimiter = ',')]
+    status: Vec<String>,
+
+    /// Show only the records logged at or after this time, given as an RFC 3339 timestamp (e.g.
+    /// `2024-03-09T16:00:00Z`) or as a duration before now (e.g. `15m` or `2h`).
+    #[arg(long, value_name = "TIME")]
+    since: Option<String>,
+
+    /// Show only the records logged at or before this time, given as an RFC 3339 timestamp or
+    /// as a duration before now, like `--since`.
+    #[arg(long, value_name = "TIME")]
+    until: Option<String>,
+
+    /// Show only the requests lasting at least this long, e.g. `500ms` or `2s`.
+    #[arg(long, value_name = "DURATION")]
+    min_duration: Option<String>,
+
+    /// Show only the requests lasting at most this long, e.g. `100ms`.
+    #[arg(long, value_name = "DURATION")]
+    max_duration: Option<String>,
+
+    /// Filter the log lines by remote address, given as a single address or as a network in
+    /// CIDR notation (e.g. `10.0.0.0/8` or `2001:db8::/32`), `unix` matches the clients of a unix
+    /// socket. This flag can be repeated to search for multiple addresses. The addresses prefixed
+    /// with `!` are hidden, e.g. `!10.0.0.0/8` for the internal traffic.
+    #[arg(long, value_name = "ADDRESS")]
+    remote_ip: Vec<String>,
+
+    /// Filter the log lines by the country of the remote address, as an ISO code like `DE`.
+    /// This flag can be repeated to search for multiple countries, or to hide them with a `!`
+    /// prefix like `!US`. The countries are read from a MaxMind database.
+    #[arg(long, value_name = "CODE")]
+    country: Vec<String>,
+
+    /// The MaxMind database (e.g. GeoLite2-Country.mmdb) the countries of the addresses are
+    /// read from, by default the one of the config file.
+    #[arg(long, value_name = "PATH")]
+    geoip_db: Option<PathBuf>,
+
+    /// Filter the log lines by `User-Agent` header, which must contain the given value (e.g.
+    /// `python-requests`) or match it when it is a glob pattern (e.g. `curl/*`). This flag can be
+    /// repeated to search for multiple user agents. With a `!` prefix the user agent is hidden,
+    /// e.g. `!UptimeRobot`.
+    #[arg(long)]
+    user_agent: Vec<String>,
+
+    /// Show only the requests with a header, e.g. `X-Request-Id`, or with a header value
+    /// matching a glob pattern, e.g. `Accept: application/json` or `Referer: *example.com*`.
+    /// This flag can be repeated, the requests must match all the headers. With a `!` prefix
+    /// the requests with the header are hidden, e.g. `!Authorization`.
+    #[arg(long, value_name = "NAME[: VALUE]")]
+    header: Vec<String>,
+
+    /// Show only the requests with a parameter in the query, e.g. `page`, or with a value
+    /// matching a glob pattern, e.g. `q=*shoes*`; the names and the values are decoded. This
+    /// flag can be repeated, the requests must match all the parameters. With a `!` prefix the
+    /// requests with the parameter are hidden, e.g. `!utm_source`.
+    #[arg(long, value_name = "NAME[=VALUE]")]
+    query: Vec<String>,
+
+    /// Show only the records of a request, found by the identifier in its correlation headers
+    /// (`X-Request-Id`, `X-Correlation-Id`, `Request-Id` and `X-Amzn-Trace-Id`, of the request
+    /// or of the response) or by the trace id of its `traceparent` header. This flag can be
+    /// repeated to search for multiple requests.
+    #[arg(long, value_name = "ID")]
+    request_id: Vec<String>,
+
+    /// Filter the log lines by the TLS version of the connection, e.g. `1.2`. This flag can be
+    /// repeated to search for multiple versions, or to hide them with a `!` prefix like `!1.3`.
+    #[arg(long, value_name = "VERSION")]
+    tls_version: Vec<String>,
+
+    /// Filter the log lines by the cipher suite of the TLS connection, by its number (e.g.
+    /// `0xc013`) or by its IANA name, the glob syntax can be used and a part of the name is
+    /// enough, e.g. `CBC`. This flag can be repeated, the suites prefixed with `!` are hidden.
+    #[arg(long, value_name = "CIPHER")]
+    tls_cipher: Vec<String>,
+
+    /// Filter the log lines by the server name requested by the client (SNI), which can differ
+    /// from the host header, the glob syntax can be used. This flag can be repeated, the names
+    /// prefixed with `!` are hidden.
+    #[arg(long, value_name = "SERVER_NAME")]
+    sni: Vec<String>,
+
+    /// Show only the requests whose server name (SNI) differs from the host header, e.g. to
+    /// debug the selection of the certificates.
+    #[arg(long)]
+    sni_mismatch: bool,
+
+    /// Filter the log lines by the upstream the reverse proxy forwarded the request to, with or
+    /// without its port, the glob syntax can be used, e.g. `10.0.0.7` or `*:8080`. This flag can
+    /// be repeated, the upstreams prefixed with `!` are hidden.
+    #[arg(long)]
+    upstream: Vec<String>,
+
+    /// Filter the log lines by the media type of the response, its `Content-Type` header
+    /// without the parameters, the glob syntax can be used, e.g. `text/html` to see only the
+    /// pages. This flag can be repeated, the types prefixed with `!` are hidden, like
+    /// `!image/*`.
+    #[arg(long, value_name = "TYPE")]
+    resp_content_type: Vec<String>,
+
+    /// Filter the log lines by the `Referer` header of the request, the glob syntax can be
+    /// used, e.g. `https://blog.example.com/*` to find where the broken links come from. This
+    /// flag can be repeated, the referers prefixed with `!` are hidden.
+    #[arg(long, value_name = "URL")]
+    referer: Vec<String>,
+
+    /// Filter the log lines by request method, e.g. `POST`. This flag can be repeated to search
+    /// for multiple methods, or to hide them with a `!` prefix like `!OPTIONS`.
+    #[arg(long)]
+    method: Vec<String>,
+
+    /// Filter the log lines by the version of HTTP of the request: `h1` (HTTP/1.0 and
+    /// HTTP/1.1), `h2` or `h3`. This flag can be repeated to search for multiple versions, or to
+    /// hide them with a `!` prefix like `!h3`.
+    #[arg(long, value_name = "VERSION")]
+    proto: Vec<String>,
+
+    /// Filter the log lines by request URI, the query included. This flag can be repeated to
+    /// search for multiple URIs or the glob syntax can be used, e.g. `/api/*`. The URIs prefixed
+    /// with `!` are hidden, e.g. `!/health*`.
+    #[arg(long)]
+    uri: Vec<String>,
+
+    /// Hide the requests whose URI, the query included, matches the glob pattern, e.g. the
+    /// health checks and the probes of the load balancers with `/healthz*`. This flag can be
+    /// repeated, it is the same as `--uri` with a `!` prefix.
+    #[arg(long, value_name = "URI")]
+    exclude_uri: Vec<String>,
+
+    /// Filter the log lines by a regular expression matched against the request URI, e.g.
+    /// `^/users/\d+$`. This flag can be repeated, the URI must match all the expressions, and
+    /// must not match the ones prefixed with `!`.
+    #[arg(long, value_name = "REGEX")]
+    uri_regex: Vec<String>,
+
+    /// Filter the log lines by a regular expression matched against the original line, before
+    /// it is parsed, e.g. `"upstream_latency":\s*[1-9]` to search the fields not shown. This
+    /// flag can be repeated, the line must match all the expressions, and must not match the
+    /// ones prefixed with `!`.
+    #[arg(long, value_name = "REGEX")]
+    match_raw: Vec<String>,
+
+    /// Show only the records whose message, or request line for the access logs (e.g. `GET
+    /// /login HTTP/2.0`), contains the text. This flag can be repeated to search for multiple
+    /// texts, the records containing the texts prefixed with `!` are hidden.
+    #[arg(long, value_name = "TEXT")]
+    grep: Vec<String>,
+
+    /// Show only the records satisfying an expression over their fields, e.g. `status >= 500 &&
+    /// host == "*.example.com" && duration > 1s`. The fields are status, duration, size, level,
+    /// host, method, uri, remote_ip, proto, sni, upstream and msg, with bot (`true` or `false`) and
+    /// ua_family (e.g. `Firefox` or `curl`) derived from the user agent. The text fields are
+    /// compared with glob patterns. This flag can be repeated, the records must satisfy all the expressions.
+    #[arg(long, value_name = "EXPR")]
+    filter: Vec<String>,
+
+    /// Show only the records whose JSON document satisfies a condition, e.g.
+    /// `.request.headers."X-Forwarded-For"[0] == "1.2.3.4"`. The jq-like path addresses any key
+    /// of the document and is compared with a JSON value, the strings are glob patterns. Without
+    /// a comparison the key must be present, e.g. `.request.tls`. This flag can be repeated, the
+    /// records must satisfy all the conditions.
+    #[arg(long = "where", value_name = "CONDITION")]
+    conditions: Vec<String>,
+
+    /// Keep the records of a fraction of the clients, or of the other keys, with all their
+    /// records. The same keys are kept by every run.
+    #[arg(long, value_enum, value_name = "KEY", requires = "sample")]
+    sample_by: Option<SampleKey>,
+
+    /// Keep a random fraction of the matched records, as a ratio like `1/100` or a number like
+    /// `0.01`, or the fraction of the keys with `--sample-by`. Each run keeps other records.
+    #[arg(long, value_name = "RATIO")]
+    sample: Option<String>,
+
+    /// Hide the requests of the monitoring and uptime services, like Pingdom or the probes of
+    /// Kubernetes. The agents can be changed in the `monitors` section of the config file.
+    #[arg(long)]
+    hide_monitors: bool,
+
+    /// Hide the requests of the crawlers of the search engines and of the AI companies, of the
+    /// bots of the social networks and of the well-known scanners, recognized by their user
+    /// agent, so that the human traffic is left.
+    #[arg(long)]
+    exclude_bots: bool,
+
+    /// Load named filter definitions from a YAML file, or a TOML one with the `.toml`
+    /// extension. The definitions combine host, status and URI patterns, networks and
+    /// expressions, and can reference each other.
+    #[arg(long, value_name = "FILE")]
+    filter_file: Option<PathBuf>,
+
+    /// The definition of the filter file applied to the log lines, `default` when not given.
+    /// This flag can be repeated, the log lines must match all the definitions.
+    #[arg(long, value_name = "NAME", requires = "filter_file")]
+    filter_rule: Vec<String>,
+}
+
+impl FilterArgs {
+    pub fn build(&self) -> Result<Filters> {
+        self.build_with(Monitors::default(), None)
+    }
+
+    /// Build the filters, the given agents are hidden by `--hide-monitors` and the GeoIP
+    /// database is used unless another one is given with `--geoip-db`.
+    pub fn build_with(&self, monitors: Monitors, geoip_db: Option<&Path>) -> Result<Filters> {
+        let mut filters = Filters::builder();
+        filters.with_strict(self.strict);
+        if let Some(level) = self.level {
+            filters.with_min_level(level);
+        }
+        if self.errors_only {
+            filters.with_errors_only();
+        }
+        for logger in &self.logger {
+            filters.with_logger(logger)?;
+        }
+        for host in &self.host {
+            filters.with_host(host)?;
+        }
+        for uri in &self.uri {
+            filters.with_uri(uri)?;
+        }
+        for uri in &self.exclude_uri {
+            filters.with_uri(&format!("!{}", uri))?;
+        }
+        for regex in &self.uri_regex {
+            filters.with_uri_regex(regex);
+        }
+        for regex in &self.match_raw {
+            filters.with_raw_regex(regex);
+        }
+        for text in &self.grep {
+            filters.with_grep(text);
+        }
+        for method in &self.method {
+            filters.with_method(method)?;
+        }
+        for proto in &self.proto {
+            filters.with_proto(proto)?;
+        }
+        for status in &self.status {
+            filters.with_status(status)?;
+        }
+        let now = time::OffsetDateTime::now_utc();
+        if let Some(since) = &self.since {
+            filters.with_since(units::parse_time(since, now).context("invalid --since")?);
+        }
+        if let Some(until) = &self.until {
+            filters.with_until(units::parse_time(until, now).context("invalid --until")?);
+        }
+        if let Some(duration) = &self.min_duration {
+            filters.with_min_duration(
+                units::parse_duration(duration).context("invalid --min-duration")?,
+            );
+        }
+        if let Some(duration) = &self.max_duration {
+            filters.with_max_duration(
+                units::parse_duration(duration).context("invalid --max-duration")?,
+            );
+        }
+        for network in &self.remote_ip {
+            filters.with_remote_ip(network)?;
+        }
+        if !self.country.is_empty() {
+            let Some(path) = self.geoip_db.as_deref().or(geoip_db) else {
+                bail!(
+                    "--country needs a GeoIP database, given with --geoip-db or in the config file"
+                );
+            };
+            filters.with_geoip(GeoIp::open(path)?);
+            for country in &self.country {
+                filters.with_country(country)?;
+            }
+        }
+        for user_agent in &self.user_agent {
+            filters.with_user_agent(user_agent)?;
+        }
+        for header in &self.header {
+            filters.with_header(header)?;
+        }
+        for parameter in &self.query {
+            filters.with_query(parameter)?;
+        }
+        for id in &self.request_id {
+            filters.with_request_id(id);
+        }
+        for version in &self.tls_version {
+            filters.with_tls_version(version)?;
+        }
+        for cipher in &self.tls_cipher {
+            filters.with_tls_cipher(cipher)?;
+        }
+        for server_name in &self.sni {
+            filters.with_sni(server_name)?;
+        }
+        if self.sni_mismatch {
+            filters.with_sni_mismatch();
+        }
+        for upstream in &self.upstream {
+            filters.with_upstream(upstream)?;
+        }
+        for content_type in &self.resp_content_type {
+            filters.with_resp_content_type(content_type)?;
+        }
+        for referer in &self.referer {
+            filters.with_referer(referer)?;
+        }
+        for expr in &self.filter {
+            filters.with_expr(expr)?;
+        }
+        for condition in &self.conditions {
+            filters.with_where(condition)?;
+        }
+        if let Some(ratio) = &self.sample {
+            filters.with_sample(Sample::new(self.sample_by, sample::parse_ratio(ratio)?));
+        }
+        if self.hide_monitors {
+            filters.with_hidden_monitors(monitors);
+        }
+        if self.exclude_bots {
+            filters.with_hidden_bots();
+        }
+        if let Some(path) = &self.filter_file {
+            let rules = RuleSet::load(path)?;
+            let mut names = self.filter_rule.clone();
+            if names.is_empty() && rules.contains(RuleSet::DEFAULT) {
+                names.push(RuleSet::DEFAULT.to_string());
+            }
+            for name in &names {
+                filters.with_rule(rules.rule(name)?);
+            }
+        }
+        filters.build()
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default, ValueEnum)]
@@ -54,24 +1061,80 @@ enum Color {
     Never,
 }
 
-fn process_lines<I, O>(input: I, mut output: O, filters: Filters) -> Result<()>
-where
-    I: BufRead,
-    O: Write,
-{
-    for line in input.lines() {
-        let line = line.unwrap();
+fn process_lines(
+    input: Lines,
+    filters: Filters,
+    redaction: &Redaction,
+    sinks: &mut [Box<dyn Sink>],
+    mut progress: Option<Progress>,
+) -> Result<()> {
+    // the records are not fully parsed when nothing but the original line is written
+    let partial = filters.is_simple() && sinks.iter().all(|sink| !sink.needs_record());
+    for line in input {
+        let Line {
+            source,
+            position,
+            text: line,
+        } = line?;
+        if let Some(progress) = &mut progress {
+            progress.tick(&line);
+        }
+        if !filters.matches_line(&line) {
+            continue;
+        }
+        if partial {
+            match serde_json::from_str::<RecordView>(&line) {
+                Ok(view) => {
+                    if filters.matches_view(&view) {
+                        let redacted = redaction.redact(&line);
+                        let line = redacted.as_deref().unwrap_or(&line);
+                        let entry = Entry::partial(line, source.as_deref()).at(&position);
+                        for sink in sinks.iter_mut() {
+                            sink.write(&entry)?;
+                        }
+                    }
+                }
+                Err(_) => write_unparsed(&line, &filters, sinks)?,
+            }
+            continue;
+        }
         match serde_json::from_str::<LogRecord>(&line) {
             Ok(record) => {
-                if filters.matches(&record) {
-                    writeln!(output, "{}", record.format())?;
+                if !filters.matches(&record) {
+                    continue;
                 }
-            }
-            Err(_) => {
-                if !filters.is_strict() {
-                    writeln!(output, "{line}")?;
+                // the sinks are given the redacted record, never the original one
+                let (line, record) = match redaction.redact(&line) {
+                    Some(redacted) => {
+                        let record =
+                            serde_json::from_str::<LogRecord>(&redacted).with_context(|| {
+                                format!("invalid redacted record at {}: {}", position, redacted)
+                            })?;
+                        (redacted, record)
+                    }
+                    None => (line, record),
+                };
+                let entry = Entry::new(&line, &record, source.as_deref()).at(&position);
+                for sink in sinks.iter_mut() {
+                    sink.write(&entry)?;
                 }
             }
+            Err(_) => write_unparsed(&line, &filters, sinks)?,
+        }
+    }
+    if let Some(progress) = &mut progress {
+        progress.clear();
+    }
+    for sink in sinks.iter_mut() {
+        sink.finish()?;
+    }
+    Ok(())
+}
+
+fn write_unparsed(line: &str, filters: &Filters, sinks: &mut [Box<dyn Sink>]) -> Result<()> {
+    if !filters.is_strict() {
+        for sink in sinks.iter_mut() {
+            sink.write_unparsed(line)?;
         }
     }
     Ok(())
@@ -0,0 +1,110 @@
+use colored::Colorize;
+use serde_json::Value;
+
+use crate::record::{self, LogRecord, RemoteIp};
+
+/// The layout of the records of the admin API and of the certificate management, which have
+/// their own fields instead of a request. The message line and the fields are returned, nothing
+/// is returned for the other records.
+pub fn summary(record: &LogRecord, value: &Value) -> Option<(String, Vec<(&'static str, String)>)> {
+    let logger = record.logger.as_deref()?;
+    if logger == "admin.api" {
+        return admin_request(value);
+    }
+    if logger.starts_with("tls") || logger.contains("acme") {
+        return certificate(record, logger, value);
+    }
+    None
+}
+
+/// The records of the requests received by the admin API, e.g. to load a new configuration.
+fn admin_request(value: &Value) -> Option<(String, Vec<(&'static str, String)>)> {
+    let method = string(value, "method")?;
+    let uri = string(value, "uri")?;
+    let message = format!("{} {method} {uri}", "admin".bold());
+
+    let mut fields = Vec::new();
+    if let Some(remote_ip) = string(value, "remote_ip") {
+        let port = string(value, "remote_port").and_then(|port| port.parse().ok());
+        let address = match RemoteIp::parse(&remote_ip) {
+            Some(remote_ip) => remote_ip.with_port(port),
+            None => remote_ip,
+        };
+        fields.push(("remote address", address));
+    }
+    if let Some(host) = string(value, "host") {
+        fields.push(("host", host));
+    }
+    if let Some(Value::String(user_agent)) = value.pointer("/headers/User-Agent/0") {
+        fields.push(("user-agent", user_agent.clone()));
+    }
+    Some((message, fields))
+}
+
+/// The records of the certificates being obtained, renewed and maintained, and of the ACME
+/// challenges solved for them.
+fn certificate(
+    record: &LogRecord,
+    logger: &str,
+    value: &Value,
+) -> Option<(String, Vec<(&'static str, String)>)> {
+    let names = match (value.get("identifier"), value.get("identifiers")) {
+        (Some(Value::String(name)), _) => name.clone(),
+        (_, Some(Value::Array(names))) => names
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => return None,
+    };
+    let message = format!("{} {}", names.bold(), record.message);
+
+    let mut fields = vec![("logger", logger.to_string())];
+    if let Some(issuer) = string(value, "issuer").or_else(|| string(value, "ca")) {
+        fields.push(("issuer", issuer));
+    }
+    if let Some(challenge) = string(value, "challenge_type") {
+        fields.push(("challenge", challenge));
+    }
+    // the certificates expiring soon are queued for renewal
+    let remaining = value.get("remaining").and_then(Value::as_f64);
+    let expiry = remaining.and_then(|remaining| {
+        record::to_datetime(record.timestamp + remaining).map(|expiry| (remaining, expiry))
+    });
+    if let Some((remaining, expiry)) = expiry {
+        let days = remaining / 86_400.0;
+        fields.push((
+            "expires",
+            format!(
+                "{} (in {days:.1} days)",
+                crate::limited::format_datetime(expiry)
+            ),
+        ));
+    }
+    if let Some(renewal) = value
+        .get("renewal_info")
+        .and_then(|info| info.pointer("/suggestedWindow/start"))
+        .and_then(Value::as_str)
+    {
+        fields.push(("next renewal", renewal.to_string()));
+    }
+    if let Some(attempt) = value.get("attempt").and_then(Value::as_u64) {
+        fields.push(("attempt", attempt.to_string()));
+    }
+    if let Some(error) = string(value, "error") {
+        fields.push(("error", error.red().to_string()));
+    }
+    if let Some(detail) = value.pointer("/problem/detail").and_then(Value::as_str) {
+        fields.push(("problem", detail.red().to_string()));
+    }
+    Some((message, fields))
+}
+
+/// The value of the field, the numbers are rendered as text.
+fn string(value: &Value, key: &str) -> Option<String> {
+    match value.get(key)? {
+        Value::String(value) if !value.is_empty() => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
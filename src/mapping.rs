@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    input::{Line, Lines},
+    selector::Selector,
+    units,
+};
+
+/// The `fields` section of the config file, the same as `--unwrap` and `--field-map`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldsConfig {
+    /// The path of the record inside the documents, e.g. `.log`.
+    pub unwrap: Option<String>,
+    /// The fields moved to the ones of Caddy, e.g. `time: ts`.
+    #[serde(default)]
+    pub map: BTreeMap<String, String>,
+}
+
+/// The changes turning the records of a customized logger, or of a log shipper, into the ones
+/// written by Caddy: the record is taken out of the document wrapping it, then the renamed
+/// fields are moved back to their original path. The lines which are not JSON documents are
+/// left as they are, the ones lacking the wrapped record are only renamed.
+#[derive(Default)]
+pub struct FieldMap {
+    unwrap: Option<Selector>,
+    /// The paths of the renamed fields, with the path of the field of Caddy.
+    renames: Vec<(Selector, Selector)>,
+}
+
+impl FieldMap {
+    /// Parse the path of the record and the renames written as `FROM=TO`, e.g.
+    /// `.time=ts`; the leading dot of the paths can be omitted.
+    pub fn new(unwrap: Option<&str>, renames: &[String]) -> Result<Self> {
+        let unwrap = unwrap
+            .map(|path| parse_path(path).with_context(|| format!("invalid --unwrap: {}", path)))
+            .transpose()?;
+        let renames = renames
+            .iter()
+            .map(|rename| {
+                let (from, to) = rename
+                    .split_once('=')
+                    .with_context(|| format!("invalid field map: {}, expected FROM=TO", rename))?;
+                let path = |path: &str| {
+                    parse_path(path.trim())
+                        .with_context(|| format!("invalid field map: {}", rename))
+                };
+                Ok((path(from)?, path(to)?))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { unwrap, renames })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.unwrap.is_none() && self.renames.is_empty()
+    }
+
+    /// The line of the record as written by Caddy, nothing is returned when it is unchanged.
+    fn apply(&self, line: &str) -> Option<String> {
+        let mut document = serde_json::from_str::<Value>(line).ok()?;
+        let mut changed = false;
+        // the records already unwrapped from the envelope of a container lack the path
+        if let Some(record) = self.unwrap.as_ref().and_then(|u| u.take(&mut document)) {
+            // the shippers often keep the original line as a string
+            document = match record {
+                Value::String(line) => serde_json::from_str::<Value>(&line).ok()?,
+                record => record,
+            };
+            if !document.is_object() {
+                return None;
+            }
+            changed = true;
+        }
+        for (from, to) in &self.renames {
+            if let Some(value) = from.take(&mut document) {
+                to.insert(&mut document, value);
+                changed = true;
+            }
+        }
+        changed.then(|| document.to_string())
+    }
+}
+
+/// The keys of the envelopes of Docker (`log`) and of the JSON export of journald (`MESSAGE`).
+const ENVELOPE_KEYS: &[&str] = &["\"log\"", "\"MESSAGE\""];
+
+/// The record wrapped by a container runtime or by journald, nothing is returned when the line
+/// is not wrapped. The envelopes recognized are:
+///
+/// - the JSON documents of the `json-file` driver of Docker and of the JSON export of journald,
+///   whose `log` or `MESSAGE` string is the record;
+/// - the lines of the CRI format of Kubernetes, `<time> <stream> <tag> <record>`, the records
+///   split in partial lines (the `P` tag) are not joined;
+/// - the lines of `docker logs --details`, prefixed with the attributes of the container, e.g.
+///   `tag=web,env=prod <record>`.
+fn unwrap_envelope(line: &str) -> Option<String> {
+    if line.starts_with('{') {
+        // most lines are records of Caddy, they are not parsed twice
+        if !ENVELOPE_KEYS.iter().any(|key| line.contains(key)) {
+            return None;
+        }
+        let document = serde_json::from_str::<Value>(line).ok()?;
+        let record = ["log", "MESSAGE"]
+            .iter()
+            .find_map(|key| document.get(key)?.as_str())?
+            .trim_end();
+        return record.starts_with('{').then(|| record.to_string());
+    }
+    let (prefix, record) = line.split_at(line.find('{')?);
+    let prefix = prefix.strip_suffix(' ')?;
+    let cri = match prefix.split(' ').collect::<Vec<_>>().as_slice() {
+        [time, "stdout" | "stderr", "F" | "P"] => units::parse_timestamp(time).is_some(),
+        _ => false,
+    };
+    let details = !prefix.contains(' ') && prefix.split(',').all(|attr| attr.contains('='));
+    (cri || details).then(|| record.to_string())
+}
+
+/// Rewrite the lines with the field map, after taking the records out of the envelopes of the
+/// container runtimes and of journald.
+pub fn apply(lines: Lines, fields: FieldMap) -> Lines {
+    Box::new(lines.map(move |line| {
+        line.map(|mut line| {
+            if let Some(record) = unwrap_envelope(&line.text) {
+                line.text = record;
+            }
+            if fields.is_empty() {
+                return line;
+            }
+            match fields.apply(&line.text) {
+                Some(text) => Line { text, ..line },
+                None => line,
+            }
+        })
+    }))
+}
+
+/// A jq-like path, the leading dot can be omitted, e.g. `request.remote_ip`.
+fn parse_path(path: &str) -> Result<Selector> {
+    match path.starts_with(['.', '[']) {
+        true => path.parse(),
+        false => format!(".{path}").parse(),
+    }
+}
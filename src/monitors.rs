@@ -0,0 +1,106 @@
+use serde::Deserialize;
+
+use crate::record::LogRecord;
+
+/// The user agents of the monitoring and uptime services, and of the health checks of the
+/// orchestrators, whose requests are routine noise.
+#[derive(Clone, Debug)]
+pub struct Monitors {
+    agents: Vec<Agent>,
+}
+
+/// A monitoring agent, recognized by a part of its user agent.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Agent {
+    /// The name shown beside the requests.
+    name: String,
+    /// A part of the `User-Agent` header, the case is ignored.
+    user_agent: String,
+}
+
+/// The `monitors` section of the config file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MonitorsConfig {
+    /// The agents recognized besides the known ones.
+    #[serde(default)]
+    agents: Vec<Agent>,
+    /// Whether the known agents are recognized, `false` to use only the given ones.
+    #[serde(default = "MonitorsConfig::default_known")]
+    known: bool,
+}
+
+impl MonitorsConfig {
+    fn default_known() -> bool {
+        true
+    }
+}
+
+impl Default for Monitors {
+    fn default() -> Self {
+        const KNOWN: &[(&str, &str)] = &[
+            ("Pingdom", "pingdom"),
+            ("UptimeRobot", "uptimerobot"),
+            ("kube-probe", "kube-probe"),
+            ("Prometheus blackbox", "blackbox exporter"),
+            ("Prometheus blackbox", "blackbox-exporter"),
+            ("StatusCake", "statuscake"),
+            ("Site24x7", "site24x7"),
+            ("Better Stack", "better uptime"),
+            ("Uptime Kuma", "uptime-kuma"),
+            ("Datadog", "datadog agent"),
+            ("Datadog Synthetics", "datadogsynthetics"),
+            ("New Relic", "newrelicpinger"),
+            ("Checkly", "checkly"),
+            ("Zabbix", "zabbix"),
+            ("Nagios", "check_http"),
+            ("Consul", "consul health check"),
+            ("AWS ELB", "elb-healthchecker"),
+            ("Google Cloud", "googlehc"),
+            ("Azure Front Door", "edge health probe"),
+        ];
+        Self {
+            agents: KNOWN
+                .iter()
+                .map(|(name, user_agent)| Agent {
+                    name: name.to_string(),
+                    user_agent: user_agent.to_string(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Monitors {
+    pub fn from_config(config: Option<MonitorsConfig>) -> Self {
+        let Some(config) = config else {
+            return Self::default();
+        };
+        let mut monitors = match config.known {
+            true => Self::default(),
+            false => Self { agents: Vec::new() },
+        };
+        for mut agent in config.agents {
+            agent.user_agent = agent.user_agent.to_lowercase();
+            monitors.agents.push(agent);
+        }
+        monitors
+    }
+
+    /// The name of the monitoring agent which sent the request.
+    pub fn find(&self, record: &LogRecord) -> Option<&str> {
+        let user_agent = record
+            .request
+            .as_ref()?
+            .headers
+            .get(http::header::USER_AGENT)?
+            .to_str()
+            .ok()?
+            .to_lowercase();
+        self.agents
+            .iter()
+            .find(|agent| user_agent.contains(&agent.user_agent))
+            .map(|agent| agent.name.as_str())
+    }
+}
@@ -0,0 +1,88 @@
+use std::io::Write;
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use time::format_description::well_known::Rfc3339;
+
+use crate::{
+    enrich,
+    record::{self, LogRecord},
+    sink::{Entry, OutputWriter, Sink},
+    tls,
+};
+
+/// The version of the schema of the normalized records, it is increased when a field is
+/// renamed, removed or changes its meaning; the new fields do not change it.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Write the records normalized, one JSON object per line, with the same fields whatever the
+/// logging options of Caddy. All the fields are always present, null when they are unknown:
+///
+/// - `schema_version`: the version of this schema, currently 1;
+/// - `timestamp`: the time of the record, as an RFC 3339 timestamp in UTC;
+/// - `level`, `logger`, `message`: the level in lowercase, the logger and the message;
+/// - `method`, `host`, `uri`, `path`, `query`, `proto`: the request, the query without `?`;
+/// - `remote_ip`, `remote_port`: the client, `unix` for the clients of a unix socket;
+/// - `user_agent`, `request_id`: the user agent and the first correlation identifier;
+/// - `status`, `duration_ms`, `size_bytes`: the response and the duration in milliseconds;
+/// - `tls_version`, `tls_cipher_suite`, `tls_server_name`: the TLS connection, by name;
+/// - `upstream`: the backend the request was forwarded to.
+pub struct NdjsonSink {
+    writer: Box<dyn OutputWriter>,
+}
+
+impl NdjsonSink {
+    pub fn new(writer: Box<dyn OutputWriter>) -> Self {
+        Self { writer }
+    }
+}
+
+/// The normalized record.
+fn normalize(record: &LogRecord) -> Value {
+    let request = record.request.as_ref();
+    let (path, query) = match request.map(|request| request.uri.split_once('?')) {
+        Some(Some((path, query))) => (Some(path), Some(query)),
+        Some(None) => (request.map(|request| request.uri.as_str()), None),
+        None => (None, None),
+    };
+    let tls = request.and_then(|request| request.tls.as_ref());
+    json!({
+        "schema_version": SCHEMA_VERSION,
+        "timestamp": record::to_datetime(record.timestamp)
+            .and_then(|timestamp| timestamp.format(&Rfc3339).ok()),
+        "level": format!("{:?}", record.level).to_lowercase(),
+        "logger": record.logger,
+        "message": record.message,
+        "method": request.map(|request| request.method.as_str()),
+        "host": request.map(|request| &request.host),
+        "uri": request.map(|request| &request.uri),
+        "path": path,
+        "query": query,
+        "proto": request.map(|request| format!("{:?}", request.version)),
+        "remote_ip": request.map(|request| request.remote_ip.to_string()),
+        "remote_port": request.and_then(|request| request.remote_port),
+        "user_agent": request
+            .and_then(|request| request.headers.get(http::header::USER_AGENT))
+            .and_then(|value| value.to_str().ok()),
+        "request_id": enrich::request_ids(record).first(),
+        "status": record.status.map(|status| status.as_u16()),
+        "duration_ms": record.duration.map(|duration| duration * 1e3),
+        "size_bytes": record.size,
+        "tls_version": tls.and_then(|tls| tls.version).map(tls::version_name),
+        "tls_cipher_suite": tls.and_then(|tls| tls.cipher_suite).map(tls::cipher_suite_name),
+        "tls_server_name": tls.and_then(|tls| tls.server_name.as_deref()),
+        "upstream": record.upstream,
+    })
+}
+
+impl Sink for NdjsonSink {
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        writeln!(self.writer, "{}", normalize(entry.record()?))?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.finish()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use anyhow::Result;
+use notify_rust::Notification;
+
+use crate::{
+    expr::Expr,
+    rate::RateLimiter,
+    sink::{Entry, Sink},
+};
+
+/// Raise a desktop notification for each record matching a condition.
+pub struct NotifySink {
+    condition: Option<Expr>,
+    limiter: RateLimiter,
+    skipped: u64,
+    failed: bool,
+}
+
+impl NotifySink {
+    pub fn new(condition: Option<Expr>, limiter: RateLimiter) -> Self {
+        Self {
+            condition,
+            limiter,
+            skipped: 0,
+            failed: false,
+        }
+    }
+
+    fn summary(entry: &Entry) -> Result<String> {
+        let record = entry.record()?;
+        Ok(match (&record.request, record.status) {
+            (Some(request), Some(status)) => {
+                format!("{} {} {}", status.as_u16(), request.method, request.host)
+            }
+            (Some(request), None) => format!("{} {}", request.method, request.host),
+            _ => format!("{:?}", record.level).to_uppercase(),
+        })
+    }
+
+    fn body(entry: &Entry) -> Result<String> {
+        let record = entry.record()?;
+        Ok(match &record.request {
+            Some(request) => request.uri.clone(),
+            None => record.message.clone(),
+        })
+    }
+}
+
+impl Sink for NotifySink {
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        if let Some(condition) = &self.condition {
+            if !condition.matches(entry.record()?) {
+                return Ok(());
+            }
+        }
+        if !self.limiter.try_acquire() {
+            self.skipped += 1;
+            return Ok(());
+        }
+        let result = Notification::new()
+            .appname("caddy-pretty-print")
+            .summary(&Self::summary(entry)?)
+            .body(&Self::body(entry)?)
+            .show();
+        // the failure is reported once, to not flood the output
+        if let Err(err) = result {
+            if !self.failed {
+                eprintln!("failed to show the desktop notification: {err}");
+                self.failed = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.skipped > 0 {
+            eprintln!("{} notifications skipped by the rate limit", self.skipped);
+        }
+        Ok(())
+    }
+}
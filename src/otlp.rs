@@ -0,0 +1,254 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    batch::Batcher,
+    record::{LogRecord, LogRequest},
+    severity::Severity,
+    sink::{Entry, Sink},
+};
+
+/// Export each request as a span to an OpenTelemetry collector, with the OTLP/HTTP JSON
+/// encoding. The span ends at the timestamp of the record and lasts the duration of the request.
+///
+/// The trace of a request carrying a `traceparent` header is kept, so that the span is attached
+/// to the trace of the client. Otherwise the identifiers are derived from the SHA-256 hash of the
+/// record, replaying the same log does not create new traces.
+pub struct OtlpSink {
+    batcher: Batcher<Value>,
+}
+
+impl OtlpSink {
+    const BATCH_SIZE: usize = 256;
+    const BATCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// The traces are posted to `/v1/traces` of the endpoint, unless the path is given.
+    pub fn new(endpoint: &str) -> Self {
+        let endpoint = endpoint.trim_end_matches('/');
+        let url = match endpoint.ends_with("/v1/traces") {
+            true => endpoint.to_string(),
+            false => format!("{endpoint}/v1/traces"),
+        };
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(10)))
+            .build()
+            .into();
+        let batcher = Batcher::new(Self::BATCH_SIZE, Self::BATCH_TIMEOUT, move |spans| {
+            export(&agent, &url, spans)
+        });
+        Self { batcher }
+    }
+
+    fn span(entry: &Entry, request: &LogRequest) -> Result<Value> {
+        let record = entry.record()?;
+        let end = (record.timestamp * 1e9) as u64;
+        let start = end.saturating_sub((record.duration.unwrap_or(0.0) * 1e9) as u64);
+        let digest = Sha256::digest(entry.line.as_bytes());
+        let (trace_id, parent_span_id) = match traceparent(request) {
+            Some((trace_id, parent)) => (trace_id, parent),
+            None => (hex(&digest[..16]), String::new()),
+        };
+        let status = match record.severity() {
+            Some(Severity::ServerError) => json!({ "code": 2 }),
+            _ => json!({}),
+        };
+        Ok(json!({
+            "traceId": trace_id,
+            "spanId": hex(&digest[16..24]),
+            "parentSpanId": parent_span_id,
+            "name": format!("{} {}", request.method, request.host),
+            "kind": 2,
+            "startTimeUnixNano": start.to_string(),
+            "endTimeUnixNano": end.to_string(),
+            "attributes": attributes(record, request),
+            "status": status,
+        }))
+    }
+}
+
+impl Sink for OtlpSink {
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        let Some(request) = &entry.record()?.request else {
+            return Ok(());
+        };
+        self.batcher.push(Self::span(entry, request)?)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let dropped = self.batcher.finish();
+        if dropped > 0 {
+            eprintln!("{} spans not exported", dropped);
+        }
+        Ok(())
+    }
+}
+
+fn export(agent: &ureq::Agent, url: &str, spans: Vec<Value>) -> Result<()> {
+    let body = json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [string_attribute("service.name", "caddy")],
+            },
+            "scopeSpans": [{
+                "scope": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }],
+        }],
+    });
+    agent
+        .post(url)
+        .header("Content-Type", "application/json")
+        .send(body.to_string())
+        .with_context(|| format!("failed to export the spans to {}", url))?;
+    Ok(())
+}
+
+/// The attributes of the span, named after the semantic conventions of HTTP servers.
+fn attributes(record: &LogRecord, request: &LogRequest) -> Vec<Value> {
+    let (path, query) = match request.uri.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (request.uri.as_str(), None),
+    };
+    let mut attributes = vec![
+        string_attribute("http.request.method", request.method.as_str()),
+        string_attribute("url.path", path),
+        string_attribute("server.address", &request.host),
+        string_attribute("client.address", &request.remote_ip.to_string()),
+        string_attribute(
+            "network.protocol.version",
+            format!("{:?}", request.version).trim_start_matches("HTTP/"),
+        ),
+    ];
+    if let Some(port) = request.remote_port {
+        attributes.push(int_attribute("client.port", port.into()));
+    }
+    if let Some(query) = query {
+        attributes.push(string_attribute("url.query", query));
+    }
+    if let Some(user_agent) = request
+        .headers
+        .get(http::header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+    {
+        attributes.push(string_attribute("user_agent.original", user_agent));
+    }
+    if let Some(status) = record.status {
+        attributes.push(int_attribute(
+            "http.response.status_code",
+            status.as_u16().into(),
+        ));
+    }
+    if let Some(size) = record.size {
+        attributes.push(int_attribute("http.response.body.size", size));
+    }
+    attributes
+}
+
+fn string_attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+fn int_attribute(key: &str, value: u64) -> Value {
+    json!({ "key": key, "value": { "intValue": value.to_string() } })
+}
+
+/// The trace and the parent span of the W3C `traceparent` header of the request.
+fn traceparent(request: &LogRequest) -> Option<(String, String)> {
+    let value = request.headers.get("traceparent")?.to_str().ok()?;
+    let mut parts = value.split('-');
+    let (_version, trace_id, span_id) = (parts.next()?, parts.next()?, parts.next()?);
+    let is_hex = |id: &str, len| id.len() == len && id.bytes().all(|b| b.is_ascii_hexdigit());
+    if !is_hex(trace_id, 32) || !is_hex(span_id, 16) {
+        return None;
+    }
+    Some((trace_id.to_lowercase(), span_id.to_lowercase()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    const LINE: &str = r#"{"level":"info","ts":1710000000.5,"logger":"http.log.access","msg":"handled request","request":{"remote_ip":"10.0.0.5","remote_port":"51234","proto":"HTTP/2.0","method":"GET","host":"example.com","uri":"/api?id=1","headers":{"User-Agent":["curl/8.4.0"]}},"duration":0.25,"size":512,"status":503,"resp_headers":{}}"#;
+
+    fn span(line: &str) -> Value {
+        let record = serde_json::from_str::<LogRecord>(line).unwrap();
+        let entry = Entry::new(line, &record, None);
+        OtlpSink::span(&entry, record.request.as_ref().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn derive_the_ids_from_the_record() {
+        let span = span(LINE);
+        // the SHA-256 of the line, the same in every run and with every toolchain
+        let digest = hex(&Sha256::digest(LINE.as_bytes()));
+        assert_eq!(span["traceId"], digest[..32]);
+        assert_eq!(span["spanId"], digest[32..48]);
+        assert_eq!(span["parentSpanId"], "");
+        assert_eq!(span["startTimeUnixNano"], "1710000000250000000");
+        assert_eq!(span["endTimeUnixNano"], "1710000000500000000");
+        assert_eq!(span["status"]["code"], 2);
+    }
+
+    #[test]
+    fn keep_the_trace_of_the_client() {
+        let line = LINE.replace(
+            r#""User-Agent":["curl/8.4.0"]"#,
+            r#""Traceparent":["00-4BF92F3577B34DA6A3CE929D0E0E4736-00F067AA0BA902B7-01"]"#,
+        );
+        let span = span(&line);
+        assert_eq!(span["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(span["parentSpanId"], "00f067aa0ba902b7");
+    }
+
+    #[test]
+    fn export_the_spans_in_batches() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}/", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut request = String::new();
+            reader.read_line(&mut request).unwrap();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                match line.to_lowercase().strip_prefix("content-length:") {
+                    Some(value) => length = value.trim().parse().unwrap(),
+                    None if line.trim_end().is_empty() => break,
+                    None => {}
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            (&stream)
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            (request, serde_json::from_slice::<Value>(&body).unwrap())
+        });
+
+        let mut sink = OtlpSink::new(&endpoint);
+        for line in [LINE.to_string(), LINE.replace("/api?id=1", "/health")] {
+            let record = serde_json::from_str::<LogRecord>(&line).unwrap();
+            sink.write(&Entry::new(&line, &record, None)).unwrap();
+        }
+        sink.finish().unwrap();
+        let (request, body) = server.join().unwrap();
+        assert_eq!(request.trim_end(), "POST /v1/traces HTTP/1.1");
+        let spans = &body["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        assert_eq!(spans.as_array().unwrap().len(), 2);
+        assert_eq!(spans[0]["name"], "GET example.com");
+    }
+}
@@ -0,0 +1,58 @@
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+use colored::{ColoredString, Colorize};
+
+use crate::{record::LogLevel, severity::Severity};
+
+/// The colors and the styles of the levels and of the statuses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum Palette {
+    /// A hue for each level and for each class of statuses.
+    #[default]
+    Default,
+    /// Blue and yellow, which most color-blind people tell apart, with bold, underlined and
+    /// reversed text for the errors, so that they stand out without the hue.
+    Colorblind,
+}
+
+static PALETTE: OnceLock<Palette> = OnceLock::new();
+
+/// Set the palette, once when the arguments are parsed.
+pub fn install(palette: Palette) {
+    let _ = PALETTE.set(palette);
+}
+
+fn current() -> Palette {
+    PALETTE.get().copied().unwrap_or_default()
+}
+
+/// Style a text, like the level of a record, by the level.
+pub fn level(level: LogLevel, text: &str) -> ColoredString {
+    match (current(), level) {
+        (Palette::Default, LogLevel::Debug) => text.yellow(),
+        (Palette::Default, LogLevel::Info) => text.cyan(),
+        (Palette::Default, LogLevel::Warn) => text.magenta(),
+        (Palette::Default, LogLevel::Error) => text.red(),
+        (Palette::Colorblind, LogLevel::Debug) => text.dimmed(),
+        (Palette::Colorblind, LogLevel::Info) => text.blue(),
+        (Palette::Colorblind, LogLevel::Warn) => text.yellow().bold(),
+        (Palette::Colorblind, LogLevel::Error) => text.yellow().bold().underline(),
+        (_, LogLevel::Panic | LogLevel::Fatal) => text.bold().reversed(),
+    }
+}
+
+/// Style a text, like the status of a request, by the severity of the response.
+pub fn severity(severity: Severity, text: &str) -> ColoredString {
+    match (current(), severity) {
+        (Palette::Default, Severity::Success) => text.green(),
+        (Palette::Default, Severity::Redirect) => text.cyan(),
+        (Palette::Default, Severity::ClientError | Severity::ServerError) => text.red(),
+        (Palette::Default, Severity::ClientAbort) => text.yellow(),
+        (Palette::Colorblind, Severity::Success) => text.blue(),
+        (Palette::Colorblind, Severity::Redirect) => text.blue().italic(),
+        (Palette::Colorblind, Severity::ClientError) => text.yellow().bold().underline(),
+        (Palette::Colorblind, Severity::ServerError) => text.yellow().bold().reversed(),
+        (Palette::Colorblind, Severity::ClientAbort) => text.dimmed(),
+    }
+}
@@ -0,0 +1,96 @@
+use std::{
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use crate::units;
+
+/// A progress bar of the input, drawn on the standard error while files of known size are read.
+pub struct Progress {
+    /// The size of the input, in bytes.
+    total: u64,
+    read: u64,
+    lines: u64,
+    start: Instant,
+    last_draw: Instant,
+    drawn: bool,
+}
+
+impl Progress {
+    const BAR_WIDTH: usize = 30;
+    const REFRESH_INTERVAL: Duration = Duration::from_millis(200);
+
+    pub fn new(total: u64) -> Self {
+        let now = Instant::now();
+        Self {
+            total,
+            read: 0,
+            lines: 0,
+            start: now,
+            last_draw: now,
+            drawn: false,
+        }
+    }
+
+    /// Count a line of the input, the bar is redrawn from time to time.
+    pub fn tick(&mut self, line: &str) {
+        // the newline is not part of the line
+        self.read += line.len() as u64 + 1;
+        self.lines += 1;
+        if self.last_draw.elapsed() >= Self::REFRESH_INTERVAL {
+            self.draw();
+        }
+    }
+
+    fn draw(&mut self) {
+        self.last_draw = Instant::now();
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let fraction = match self.total {
+            0 => 1.0,
+            total => (self.read as f64 / total as f64).min(1.0),
+        };
+        let filled = (fraction * Self::BAR_WIDTH as f64) as usize;
+        let bytes_rate = self.read as f64 / elapsed;
+        let lines_rate = self.lines as f64 / elapsed;
+        let eta = match bytes_rate > 0.0 {
+            true => format_eta((self.total.saturating_sub(self.read)) as f64 / bytes_rate),
+            false => "-".to_string(),
+        };
+        let mut stderr = std::io::stderr().lock();
+        let _ = write!(
+            stderr,
+            "\r\x1b[K[{}{}] {:>3.0}%  {}/s  {:.0} lines/s  ETA {}",
+            "#".repeat(filled),
+            "-".repeat(Self::BAR_WIDTH - filled),
+            fraction * 100.0,
+            units::format_size(bytes_rate),
+            lines_rate,
+            eta
+        );
+        let _ = stderr.flush();
+        self.drawn = true;
+    }
+
+    /// Remove the bar, before the final output is written.
+    pub fn clear(&mut self) {
+        if self.drawn {
+            let mut stderr = std::io::stderr().lock();
+            let _ = write!(stderr, "\r\x1b[K");
+            let _ = stderr.flush();
+            self.drawn = false;
+        }
+    }
+}
+
+fn format_eta(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    match seconds {
+        0..=3599 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        _ => format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        ),
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    sink::{Entry, Sink},
+    units,
+};
+
+/// A number of events per period of time, e.g. `50/s`, `10/m` or `5/30s`.
+#[derive(Clone, Copy, Debug)]
+pub struct Rate {
+    count: f64,
+    period: Duration,
+}
+
+impl FromStr for Rate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (count, period) = s
+            .split_once('/')
+            .with_context(|| format!("invalid rate: {}, expected COUNT/PERIOD", s))?;
+        let count = count
+            .trim()
+            .parse::<f64>()
+            .with_context(|| format!("invalid rate: {}", s))?;
+        let period = period.trim();
+        // a bare unit is a period of one unit
+        let period = match period.starts_with(|c: char| c.is_ascii_digit()) {
+            true => units::parse_duration(period),
+            false => units::parse_duration(&format!("1{period}")),
+        }
+        .with_context(|| format!("invalid rate: {}", s))?;
+        // a bucket without tokens, or refilled at an infinite rate, would drop every event
+        if !count.is_finite() || count <= 0.0 {
+            bail!(
+                "invalid rate: {}, the count must be a number greater than zero",
+                s
+            );
+        }
+        if period.is_zero() {
+            bail!("invalid rate: {}, the period must be greater than zero", s);
+        }
+        Ok(Self { count, period })
+    }
+}
+
+/// A token bucket which allows bursts up to the count of the rate.
+pub struct RateLimiter {
+    capacity: f64,
+    tokens: f64,
+    refill: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: Rate) -> Self {
+        Self {
+            capacity: rate.count,
+            tokens: rate.count,
+            refill: rate.count / rate.period.as_secs_f64(),
+            last: Instant::now(),
+        }
+    }
+
+    /// Consume a token, false is returned when the rate is exceeded.
+    pub fn try_acquire(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.last = now;
+        self.tokens = (self.tokens + elapsed * self.refill).min(self.capacity);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Print at most a number of records per period of time through another sink, the exceeding
+/// ones are dropped and counted: a spike of traffic does not flood the terminal. The records
+/// dropped are reported before the next one printed, since the followed input may never end.
+pub struct RateLimitedSink {
+    inner: Box<dyn Sink>,
+    limiter: RateLimiter,
+    /// The records dropped since the last one printed.
+    dropped: u64,
+}
+
+impl RateLimitedSink {
+    pub fn new(inner: Box<dyn Sink>, rate: Rate) -> Self {
+        Self {
+            inner,
+            limiter: RateLimiter::new(rate),
+            dropped: 0,
+        }
+    }
+
+    fn report_dropped(&mut self) {
+        if self.dropped > 0 {
+            eprintln!("... {} records dropped by the rate limit", self.dropped);
+            self.dropped = 0;
+        }
+    }
+}
+
+impl Sink for RateLimitedSink {
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        if !self.limiter.try_acquire() {
+            self.dropped += 1;
+            return Ok(());
+        }
+        self.report_dropped();
+        self.inner.write(entry)
+    }
+
+    fn needs_record(&self) -> bool {
+        self.inner.needs_record()
+    }
+
+    fn is_live(&self) -> bool {
+        self.inner.is_live()
+    }
+
+    fn write_unparsed(&mut self, line: &str) -> Result<()> {
+        if !self.limiter.try_acquire() {
+            self.dropped += 1;
+            return Ok(());
+        }
+        self.report_dropped();
+        self.inner.write_unparsed(line)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.inner.finish()?;
+        self.report_dropped();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rate() {
+        let rate = "50/s".parse::<Rate>().unwrap();
+        assert_eq!(rate.count, 50.0);
+        assert_eq!(rate.period, Duration::from_secs(1));
+        let rate = "5/30s".parse::<Rate>().unwrap();
+        assert_eq!(rate.period, Duration::from_secs(30));
+        let rate = "10/m".parse::<Rate>().unwrap();
+        assert_eq!(rate.period, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn reject_invalid_rates() {
+        for rate in [
+            "0/1s", "-3/1m", "inf/1s", "NaN/1s", "5/0s", "5", "x/1s", "5/1x",
+        ] {
+            let err = rate.parse::<Rate>().unwrap_err();
+            assert!(
+                format!("{err:#}").contains(rate),
+                "the error of {rate} does not name it: {err:#}"
+            );
+        }
+    }
+
+    #[test]
+    fn limit_bursts() {
+        let mut limiter = RateLimiter::new("2/h".parse().unwrap());
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+    }
+}
@@ -1,45 +1,496 @@
-use std::net::{IpAddr, SocketAddr};
+use std::{borrow::Cow, net::IpAddr, time::Duration};
 
+use clap::ValueEnum;
 use colored::Colorize;
 use serde::Deserialize;
+use serde_json::Value;
 use serde_with::{serde_as, DefaultOnError, DeserializeAs, DisplayFromStr};
-use terminal_size::{terminal_size, Width};
 use time::OffsetDateTime;
 
+use crate::{
+    budgets::Budgets,
+    enrich,
+    locale::Locale,
+    monitors::Monitors,
+    palette,
+    severity::{self, Severity},
+    table::TableLayout,
+    tls::{self, LogTls},
+    units,
+};
+
 #[serde_as]
 #[derive(Deserialize)]
 pub struct LogRecord {
     #[serde(rename = "ts")]
-    timestamp: f64,
-    level: LogLevel,
+    #[serde_as(as = "Timestamp")]
+    pub timestamp: f64,
+    pub level: LogLevel,
+    /// The component of Caddy which wrote the record, e.g. `http.log.access` or `tls.obtain`.
+    pub logger: Option<String>,
     #[serde(rename = "msg")]
-    message: String,
+    pub message: String,
     pub request: Option<LogRequest>,
-    duration: Option<f64>,
+    /// The duration of the request, in seconds.
+    #[serde(default)]
+    #[serde_as(as = "Option<Seconds>")]
+    pub duration: Option<f64>,
+    /// The size of the response body in bytes.
+    pub size: Option<u64>,
     #[serde(default)]
     #[serde_as(as = "DefaultOnError<Option<SerdeHttpStatusCode>>")]
-    status: Option<http::StatusCode>,
+    pub status: Option<http::StatusCode>,
+    #[serde(default, with = "http_serde::header_map")]
+    pub resp_headers: http::HeaderMap,
+    /// The backend the request was forwarded to by the reverse proxy, e.g. `10.0.0.7:8080`, it
+    /// is logged by the proxy itself or added with `log_append`.
+    #[serde(default)]
+    #[serde_as(as = "DefaultOnError")]
+    pub upstream: Option<String>,
+}
+
+/// The few fields of a record read by the simple filters, the strings are borrowed from the
+/// line. The fields which can reject a line are read as [`LogRecord`] reads them, so a line is
+/// a record for every sink or for none of them; the ones not read by the filters are only
+/// checked.
+#[serde_as]
+#[derive(Deserialize)]
+pub struct RecordView<'a> {
+    #[serde(rename = "ts")]
+    #[serde_as(as = "Timestamp")]
+    pub timestamp: f64,
+    pub level: LogLevel,
+    #[serde(borrow)]
+    pub logger: Option<Cow<'a, str>>,
+    #[serde(rename = "msg", borrow)]
+    pub message: Cow<'a, str>,
+    #[serde(borrow)]
+    pub request: Option<RequestView<'a>>,
+    #[serde(default)]
+    #[serde_as(as = "Option<Seconds>")]
+    pub duration: Option<f64>,
+    #[serde(rename = "size")]
+    _size: Option<u64>,
+    #[serde(default)]
+    #[serde_as(as = "DefaultOnError<Option<SerdeHttpStatusCode>>")]
+    pub status: Option<http::StatusCode>,
+    #[serde(rename = "resp_headers", default, with = "http_serde::header_map")]
+    _resp_headers: http::HeaderMap,
+}
+
+/// The fields of the request read by the simple filters, the required ones are the same as the
+/// ones of [`LogRequest`].
+#[derive(Deserialize)]
+pub struct RequestView<'a> {
+    pub remote_ip: RemoteIp,
+    #[serde(with = "http_serde::method")]
+    pub method: http::Method,
+    #[serde(borrow)]
+    pub host: Cow<'a, str>,
+    #[serde(borrow)]
+    pub uri: Cow<'a, str>,
+    #[serde(rename = "proto", with = "http_serde::version")]
+    pub version: http::Version,
+    #[serde(rename = "headers", with = "http_serde::header_map")]
+    _headers: http::HeaderMap,
+    #[serde(rename = "tls", default)]
+    _tls: Option<LogTls>,
 }
 
 #[serde_as]
 #[derive(Deserialize)]
 pub struct LogRequest {
-    remote_ip: IpAddr,
-    #[serde_as(as = "DisplayFromStr")]
-    remote_port: u16,
+    pub remote_ip: RemoteIp,
+    /// The port of the client, missing for the clients of a unix socket.
+    #[serde(default)]
+    #[serde_as(as = "DefaultOnError<Option<DisplayFromStr>>")]
+    pub remote_port: Option<u16>,
     #[serde(with = "http_serde::method")]
-    method: http::Method,
+    pub method: http::Method,
     pub host: String,
-    uri: String,
+    pub uri: String,
     #[serde(rename = "proto", with = "http_serde::version")]
-    version: http::Version,
+    pub version: http::Version,
     #[serde(with = "http_serde::header_map")]
-    headers: http::HeaderMap,
+    pub headers: http::HeaderMap,
+    /// The TLS connection, missing for the plain HTTP requests.
+    #[serde(default)]
+    pub tls: Option<LogTls>,
+}
+
+/// Whether the response was served by a cache, as told by the headers of the caches in front of
+/// the upstream or of Caddy itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheStatus {
+    Hit,
+    /// A stale response served by the cache, e.g. while it is revalidated.
+    Stale,
+    Miss,
+    /// The response is not cacheable, or the cache has been skipped.
+    Bypass,
+}
+
+impl CacheStatus {
+    /// Whether the response was served from the cache, stale or not.
+    pub fn is_hit(self) -> bool {
+        matches!(self, CacheStatus::Hit | CacheStatus::Stale)
+    }
+
+    /// Parse the value of `Cf-Cache-Status` or of `X-Cache`, like `HIT` or `Miss from
+    /// cloudfront`.
+    fn parse(value: &str) -> Option<Self> {
+        let value = value.trim().to_ascii_lowercase();
+        let status = value.split_whitespace().next()?;
+        match status {
+            "hit" | "revalidated" | "tcp_hit" | "tcp_mem_hit" => Some(CacheStatus::Hit),
+            "stale" | "updating" => Some(CacheStatus::Stale),
+            "miss" | "expired" | "tcp_miss" | "refreshhit" => Some(CacheStatus::Miss),
+            "bypass" | "dynamic" | "pass" | "none" | "uncacheable" => Some(CacheStatus::Bypass),
+            _ if value.contains("hit") => Some(CacheStatus::Hit),
+            _ if value.contains("miss") => Some(CacheStatus::Miss),
+            _ => None,
+        }
+    }
+
+    /// Parse a member of the `Cache-Status` header of RFC 9211, like `Caddy; hit` or
+    /// `Caddy; fwd=uri-miss; stored`.
+    fn parse_rfc9211(member: &str) -> Option<Self> {
+        let mut params = member.split(';').skip(1).map(str::trim);
+        params.find_map(|param| match param.split_once('=') {
+            None if param.eq_ignore_ascii_case("hit") => Some(CacheStatus::Hit),
+            Some((key, value)) if key.eq_ignore_ascii_case("fwd") => {
+                match value.trim_matches('"').to_ascii_lowercase().as_str() {
+                    "bypass" => Some(CacheStatus::Bypass),
+                    "stale" => Some(CacheStatus::Stale),
+                    _ => Some(CacheStatus::Miss),
+                }
+            }
+            _ => None,
+        })
+    }
+}
+
+impl std::fmt::Display for CacheStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self {
+            CacheStatus::Hit => "HIT",
+            CacheStatus::Stale => "STALE",
+            CacheStatus::Miss => "MISS",
+            CacheStatus::Bypass => "BYPASS",
+        };
+        f.write_str(status)
+    }
+}
+
+/// The address of the client, the clients connected through a unix socket have none: Caddy
+/// logs an empty address, or the name of the socket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum RemoteIp {
+    Ip(IpAddr),
+    Unix,
+}
+
+impl RemoteIp {
+    pub fn ip(self) -> Option<IpAddr> {
+        match self {
+            RemoteIp::Ip(ip) => Some(ip),
+            RemoteIp::Unix => None,
+        }
+    }
+
+    /// Parse the address, with or without the brackets of IPv6 and the zone. The IPv6
+    /// addresses mapping an IPv4 one are converted to IPv4.
+    pub fn parse(s: &str) -> Option<Self> {
+        if s.is_empty() || s.starts_with('@') || s.starts_with('/') || s == "unix" {
+            return Some(RemoteIp::Unix);
+        }
+        let s = s
+            .strip_prefix('[')
+            .and_then(|s| s.strip_suffix(']'))
+            .unwrap_or(s);
+        let s = s.split_once('%').map_or(s, |(address, _zone)| address);
+        let ip = s.parse::<IpAddr>().ok()?;
+        Some(RemoteIp::Ip(ip.to_canonical()))
+    }
+
+    /// The address with the port, the IPv6 addresses are bracketed.
+    pub fn with_port(self, port: Option<u16>) -> String {
+        match (self, port) {
+            (RemoteIp::Ip(IpAddr::V6(ip)), Some(port)) => format!("[{ip}]:{port}"),
+            (RemoteIp::Ip(ip), Some(port)) => format!("{ip}:{port}"),
+            (RemoteIp::Ip(ip), None) => ip.to_string(),
+            (RemoteIp::Unix, _) => "unix socket".to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for RemoteIp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemoteIp::Ip(ip) => ip.fmt(f),
+            RemoteIp::Unix => f.write_str("unix"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for RemoteIp {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = Cow::<str>::deserialize(deserializer)?;
+        Self::parse(&s)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid remote address: {s}")))
+    }
+}
+
+/// How the records are rendered by [`LogRecord::format`].
+#[derive(Clone, Debug)]
+pub struct FormatOptions {
+    pub layout: Layout,
+    /// The lines are truncated to this width, usually the width of the terminal.
+    pub width: Option<usize>,
+    /// Print a banner when the day changes.
+    pub day_banners: bool,
+    /// Print the number of records and errors when the hour changes.
+    pub hourly_subtotals: bool,
+    /// The transfer rate is displayed for the responses of at least this size, in bytes.
+    pub throughput_min_size: u64,
+    /// Mark the requests slower than the baseline of their path by this number of standard
+    /// deviations.
+    pub latency_anomalies: Option<f64>,
+    /// Mark the requests whose headers deviate from the usual ones.
+    pub unusual_headers: bool,
+    /// Mark the identical requests of a client repeated within this window.
+    pub retry_window: Option<Duration>,
+    /// Print the records under a heading for each group, when the input ends.
+    pub group_by: Option<GroupBy>,
+    /// The fields printed beside the summary of each record.
+    pub detail: Detail,
+    /// Prefix the records with the file and the number of their original line.
+    pub line_numbers: bool,
+    /// The columns of the table layout.
+    pub table: TableLayout,
+    /// The monitoring agents, whose requests are marked.
+    pub monitors: Monitors,
+    /// The latency expected for the routes, the slower requests are marked.
+    pub budgets: Budgets,
+    /// The formatting of the numbers and of the dates of the reports printed at the end.
+    pub locale: Locale,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            layout: Layout::default(),
+            width: None,
+            day_banners: false,
+            hourly_subtotals: false,
+            throughput_min_size: 1_000_000,
+            latency_anomalies: None,
+            unusual_headers: false,
+            retry_window: None,
+            group_by: None,
+            detail: Detail::default(),
+            line_numbers: false,
+            table: TableLayout::default(),
+            monitors: Monitors::default(),
+            budgets: Budgets::default(),
+            locale: Locale::C,
+        }
+    }
+}
+
+/// The tiers of detail of the records, each one adds fields to the previous one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Detail {
+    /// The request line, the status, the duration and the client.
+    #[default]
+    Summary,
+    /// The headers of the request and of the response.
+    Headers,
+    /// The TLS connection and the fields not otherwise displayed.
+    Extra,
+    /// The original JSON record.
+    Raw,
+}
+
+impl Detail {
+    /// The tier selected by the number of `-v` flags.
+    pub fn from_verbosity(verbosity: u8) -> Self {
+        match verbosity {
+            0 => Detail::Summary,
+            1 => Detail::Headers,
+            2 => Detail::Extra,
+            _ => Detail::Raw,
+        }
+    }
+
+    /// The fields added to the summary of the record by this tier, they are read from the
+    /// original JSON record.
+    pub fn fields(self, value: &Value, line: &str) -> Vec<(&'static str, String)> {
+        let mut fields = Vec::new();
+        if self >= Detail::Headers {
+            let request = value.pointer("/request/headers");
+            // the user agent is already part of the summary
+            push_headers(&mut fields, "headers", request, &["User-Agent"]);
+            push_headers(&mut fields, "resp headers", value.get("resp_headers"), &[]);
+        }
+        if self >= Detail::Extra {
+            if let Some(Value::Object(tls)) = value.pointer("/request/tls") {
+                // the numbers of the version and of the cipher suite are named
+                let tls = tls
+                    .iter()
+                    .map(|(key, value)| {
+                        let number = value.as_u64().and_then(|number| u16::try_from(number).ok());
+                        match (key.as_str(), number) {
+                            ("version", Some(version)) => {
+                                format!("{key}={}", tls::version_name(version).replace(' ', ""))
+                            }
+                            ("cipher_suite", Some(cipher_suite)) => {
+                                format!("{key}={}", tls::cipher_suite_name(cipher_suite))
+                            }
+                            _ => format!("{key}={}", scalar(value)),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                fields.push(("tls", tls.join(" ")));
+            }
+            let extra = extra_fields(value);
+            for (index, (key, value)) in extra.into_iter().enumerate() {
+                let label = if index == 0 { "extra" } else { "" };
+                fields.push((label, format!("{key}: {}", scalar(value))));
+            }
+        }
+        if self >= Detail::Raw {
+            fields.push(("raw", line.to_string()));
+        }
+        fields
+    }
+}
+
+/// The fields of the record and of its request displayed by the summary or by a tier of
+/// detail.
+const KNOWN_FIELDS: &[&str] = &[
+    "level",
+    "ts",
+    "logger",
+    "msg",
+    "request",
+    "duration",
+    "size",
+    "status",
+    "resp_headers",
+    "upstream",
+    "request.remote_ip",
+    "request.remote_port",
+    "request.proto",
+    "request.method",
+    "request.host",
+    "request.uri",
+    "request.headers",
+    "request.tls",
+];
+
+/// The fields of the record not displayed by the other tiers, by path.
+fn extra_fields(value: &Value) -> Vec<(String, &Value)> {
+    let mut fields = Vec::new();
+    let Value::Object(record) = value else {
+        return fields;
+    };
+    for (key, value) in record {
+        if !KNOWN_FIELDS.contains(&key.as_str()) {
+            fields.push((key.clone(), value));
+        }
+    }
+    if let Some(Value::Object(request)) = record.get("request") {
+        for (key, value) in request {
+            let key = format!("request.{key}");
+            if !KNOWN_FIELDS.contains(&key.as_str()) {
+                fields.push((key, value));
+            }
+        }
+    }
+    fields
+}
+
+/// One field per header, the values of the header are joined by commas.
+fn push_headers(
+    fields: &mut Vec<(&'static str, String)>,
+    label: &'static str,
+    headers: Option<&Value>,
+    skipped: &[&str],
+) {
+    let Some(Value::Object(headers)) = headers else {
+        return;
+    };
+    let headers = headers
+        .iter()
+        .filter(|(name, _)| !skipped.iter().any(|s| s.eq_ignore_ascii_case(name)));
+    for (index, (name, values)) in headers.enumerate() {
+        let values = match values {
+            Value::Array(values) => values.iter().map(scalar).collect::<Vec<_>>().join(", "),
+            value => scalar(value),
+        };
+        let label = if index == 0 { label } else { "" };
+        fields.push((label, format!("{name}: {values}")));
+    }
 }
 
-#[derive(Clone, Copy, Deserialize)]
+/// The value as displayed, the strings are not quoted.
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum Layout {
+    /// Metadata fields are stacked below the request line.
+    #[default]
+    Stacked,
+    /// Metadata fields are placed in a right-hand column beside the request line, on narrow
+    /// terminals it falls back to the stacked layout.
+    Columns,
+    /// One line per record with aligned columns, they are set by the `table` section of the
+    /// config file.
+    Table,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum GroupBy {
+    /// The value of the `host` header, the records without a request are grouped together.
+    Host,
+    /// The family of the user agent, e.g. `Firefox` or `curl`.
+    UaFamily,
+    /// Whether the request was sent by a crawler, a bot or a scanner.
+    Bot,
+}
+
+impl GroupBy {
+    /// The group of the record.
+    pub fn key(self, record: &LogRecord) -> String {
+        match self {
+            GroupBy::Host => match &record.request {
+                Some(request) => request.host.clone(),
+                None => "(no host)".to_string(),
+            },
+            GroupBy::UaFamily => enrich::user_agent_family(record)
+                .unwrap_or("(no user agent)")
+                .to_string(),
+            GroupBy::Bot => match enrich::bot(record) {
+                Some(true) => "bots".to_string(),
+                Some(false) => "humans".to_string(),
+                None => "(no request)".to_string(),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord, ValueEnum)]
 #[serde(rename_all = "lowercase")]
-enum LogLevel {
+pub enum LogLevel {
     Debug,
     Info,
     Warn,
@@ -53,99 +504,259 @@ impl LogRecord {
         "[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond digits:6][offset_hour sign:mandatory]:[offset_minute]"
     );
 
-    pub fn format(self) -> String {
-        let timestamp = Self::format_timestamp(self.timestamp);
-        let level = Self::format_level(self.level);
-        let indent = 4;
-        let message = self
-            .request
-            .map(|req| Self::format_request(req, indent))
-            .unwrap_or_else(|| self.message);
-        let mut lines = vec![format!("[{timestamp}] {level} {message}")];
+    /// The target of a redirect, the `Location` header of the response.
+    pub fn location(&self) -> Option<&str> {
+        let location = self.resp_headers.get(http::header::LOCATION)?;
+        location.to_str().ok()
+    }
+
+    /// How bad the response is, by its status and the `severity` section of the config file.
+    pub fn severity(&self) -> Option<Severity> {
+        severity::of(self)
+    }
+
+    /// Whether the response was served by a cache, from the `Cache-Status`, `Cf-Cache-Status` or
+    /// `X-Cache` headers of the response. A positive `Age` is a hit when they are missing.
+    pub fn cache_status(&self) -> Option<CacheStatus> {
+        let header = |name: &str| {
+            self.resp_headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        // the last member is the cache closest to the client
+        if let Some(status) = header("cache-status")
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(CacheStatus::parse_rfc9211)
+        {
+            return Some(status);
+        }
+        if let Some(status) = header("cf-cache-status").and_then(CacheStatus::parse) {
+            return Some(status);
+        }
+        if let Some(status) = header("x-cache")
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(CacheStatus::parse)
+        {
+            return Some(status);
+        }
+        header("age")
+            .and_then(|age| age.trim().parse::<u64>().ok())
+            .filter(|&age| age > 0)
+            .map(|_| CacheStatus::Hit)
+    }
+
+    /// The time the record was logged at, the range of the timestamps is checked when the
+    /// records are parsed.
+    pub fn datetime(&self) -> OffsetDateTime {
+        to_datetime(self.timestamp).unwrap_or(OffsetDateTime::UNIX_EPOCH)
+    }
+
+    pub fn format(&self, options: &FormatOptions) -> String {
+        self.format_annotated(options, Vec::new())
+    }
+
+    /// Format the record with additional fields, e.g. the remarks of the analyses done by the
+    /// sinks. They are placed after the fields of the record.
+    pub fn format_annotated(
+        &self,
+        options: &FormatOptions,
+        annotations: Vec<(&'static str, String)>,
+    ) -> String {
+        let (message, mut fields) = match &self.request {
+            Some(request) => Self::format_request(request),
+            None => (self.message.clone(), vec![]),
+        };
+        // the fields of the request have their own columns in the table
+        if let Layout::Table = options.layout {
+            return self.format_message(options, message, annotations);
+        }
+        if let Some(upstream) = &self.upstream {
+            fields.push(("upstream", upstream.clone()));
+        }
         if let Some(status) = self.status {
-            lines.push(format!(
-                "{:indent$}status          {}",
-                ' ',
-                Self::format_status(status),
-                indent = indent
-            ));
+            fields.push(("status", self.format_status(status)));
+            if let (true, Some(location)) = (status.is_redirection(), self.location()) {
+                fields.push(("location", location.cyan().to_string()));
+            }
+        }
+        if let Some(cache) = self.cache_status() {
+            fields.push(("cache", Self::format_cache_status(cache)));
         }
         if let Some(duration) = self.duration {
-            lines.push(format!(
-                "{:indent$}duration        {}",
-                ' ',
-                Self::format_duration(duration),
-                indent = indent
-            ));
+            fields.push(("duration", Self::format_duration(duration)));
+        }
+        if let (Some(size), Some(duration)) = (self.size, self.duration) {
+            if size >= options.throughput_min_size && size > 0 && duration > 0.0 {
+                let rate = size as f64 / duration;
+                fields.push(("throughput", format!("{}/s", units::format_size(rate))));
+            }
+        }
+        fields.extend(annotations);
+        self.format_message(options, message, fields)
+    }
+
+    /// Format the record with the given message line and fields, instead of the ones of its
+    /// request.
+    pub fn format_message(
+        &self,
+        options: &FormatOptions,
+        message: String,
+        fields: Vec<(&'static str, String)>,
+    ) -> String {
+        if let Layout::Table = options.layout {
+            let mut line = options.table.format(self, &message, &fields, options.width);
+            if let Some(width) = options.width {
+                truncate_line(&mut line, width);
+            }
+            return line;
+        }
+        let timestamp = Self::format_timestamp(self.timestamp);
+        let level = Self::format_level(self.level);
+        let header = format!("[{timestamp}] {level} {message}");
+        // the query of the request line is shortened before its path and its protocol
+        let request = self.request.is_some();
+
+        let width = options.width;
+        let mut lines = match (options.layout, width) {
+            (Layout::Columns, Some(width)) if width >= Self::COLUMNS_MIN_WIDTH => {
+                Self::layout_columns(header, fields, width, request)
+            }
+            _ => Self::layout_stacked(header, fields),
+        };
+        if let Some(width) = width {
+            for (index, line) in lines.iter_mut().enumerate() {
+                match index == 0 && request {
+                    true => truncate_request_line(line, width),
+                    false => truncate_line(line, width),
+                }
+            }
         }
         lines.join("\n")
     }
 
-    fn format_timestamp(ts: f64) -> String {
-        let ts = (ts * 1_000_000.0) as i128 * 1_000;
-        let ts = OffsetDateTime::from_unix_timestamp_nanos(ts).unwrap();
-        ts.format(&Self::TIMESTAMP).unwrap()
+    const INDENT: usize = 4;
+    const LABEL_WIDTH: usize = 16;
+    const COLUMNS_MIN_WIDTH: usize = 140;
+
+    /// The metadata fields moved on the right-hand side by the columns layout.
+    const RIGHT_COLUMN: &'static [&'static str] = &["status", "duration", "remote address", "host"];
+
+    fn layout_stacked(header: String, fields: Vec<(&str, String)>) -> Vec<String> {
+        std::iter::once(header)
+            .chain(fields.into_iter().map(|(label, value)| {
+                format!(
+                    "{:indent$}{:label_width$}{}",
+                    "",
+                    label,
+                    value,
+                    indent = Self::INDENT,
+                    label_width = Self::LABEL_WIDTH
+                )
+            }))
+            .collect()
+    }
+
+    fn layout_columns(
+        header: String,
+        fields: Vec<(&str, String)>,
+        width: usize,
+        request: bool,
+    ) -> Vec<String> {
+        let (right, left): (Vec<_>, Vec<_>) = fields
+            .into_iter()
+            .partition(|(label, _)| Self::RIGHT_COLUMN.contains(label));
+        let left = Self::layout_stacked(header, left);
+        let right = right
+            .into_iter()
+            .map(|(label, value)| {
+                format!(
+                    "{:label_width$}{}",
+                    label,
+                    value,
+                    label_width = Self::LABEL_WIDTH
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let right_width = right.iter().map(|line| visible_width(line)).max();
+        let Some(right_width) = right_width else {
+            return left;
+        };
+        let left_width = width.saturating_sub(right_width + 4);
+        (0..left.len().max(right.len()))
+            .map(|index| {
+                let mut line = left.get(index).cloned().unwrap_or_default();
+                match index == 0 && request {
+                    true => truncate_request_line(&mut line, left_width),
+                    false => truncate_line(&mut line, left_width),
+                }
+                match right.get(index) {
+                    Some(right) => {
+                        let padding = left_width.saturating_sub(visible_width(&line));
+                        format!("{line}{:padding$} │ {right}", "")
+                    }
+                    None => line,
+                }
+            })
+            .collect()
+    }
+
+    pub fn format_timestamp(ts: f64) -> String {
+        to_datetime(ts)
+            .and_then(|datetime| datetime.format(&Self::TIMESTAMP).ok())
+            .unwrap_or_else(|| ts.to_string())
     }
 
     fn format_level(level: LogLevel) -> String {
-        match level {
-            LogLevel::Debug => "DEBUG".yellow(),
-            LogLevel::Info => " INFO".cyan(),
-            LogLevel::Warn => " WARN".magenta(),
-            LogLevel::Error => "ERROR".red(),
-            LogLevel::Panic => "PANIC".reversed(),
-            LogLevel::Fatal => "FATAL".reversed(),
-        }
-        .to_string()
-    }
-
-    fn format_request(request: LogRequest, indent: usize) -> String {
-        let mut lines = vec![format!(
-            "{} {} {:?}",
-            request.method, request.uri, request.version
-        )];
-
-        let remote_addr = SocketAddr::from((request.remote_ip, request.remote_port));
-        lines.push(format!(
-            "{:indent$}remote address  {}",
-            "",
-            remote_addr,
-            indent = indent
-        ));
-        lines.push(format!(
-            "{:indent$}host            {}",
-            "",
-            request.host,
-            indent = indent
-        ));
+        let text = match level {
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Info => " INFO",
+            LogLevel::Warn => " WARN",
+            LogLevel::Error => "ERROR",
+            LogLevel::Panic => "PANIC",
+            LogLevel::Fatal => "FATAL",
+        };
+        palette::level(level, text).to_string()
+    }
+
+    /// The request line, e.g. `GET /index.html HTTP/2.0`.
+    pub fn request_line(request: &LogRequest) -> String {
+        format!("{} {} {:?}", request.method, request.uri, request.version)
+    }
+
+    fn format_request(request: &LogRequest) -> (String, Vec<(&'static str, String)>) {
+        let message = Self::request_line(request);
+
+        let mut fields = vec![
+            (
+                "remote address",
+                request.remote_ip.with_port(request.remote_port),
+            ),
+            ("host", request.host.clone()),
+        ];
+        // the server name is shown only when it is not the host, a hint of a wrong certificate
+        if let Some(tls) = request
+            .tls
+            .as_ref()
+            .filter(|tls| tls.server_name_mismatch(&request.host))
+        {
+            let server_name = tls.server_name.as_deref().unwrap_or_default();
+            fields.push(("sni", server_name.yellow().to_string()));
+        }
         if let Some(user_agent) = request
             .headers
             .get(http::header::USER_AGENT)
             .and_then(|h| h.to_str().ok())
         {
-            lines.push(format!(
-                "{:indent$}user-agent      {}",
-                "",
-                user_agent,
-                indent = indent
-            ));
-        }
-
-        if let Some((Width(width), _)) = terminal_size() {
-            lines.iter_mut().for_each(|line| truncate_line(line, width));
+            fields.push(("user-agent", user_agent.to_string()));
         }
-        lines.join("\n")
+        (message, fields)
     }
 
-    fn format_status(status: http::StatusCode) -> String {
-        let code = if status.is_informational() || status.is_success() {
-            status.as_u16().to_string().green().to_string()
-        } else if status.is_redirection() {
-            status.as_u16().to_string().cyan().to_string()
-        } else if status.is_server_error() || status.is_client_error() {
-            status.as_u16().to_string().red().to_string()
-        } else {
-            status.as_u16().to_string()
+    fn format_status(&self, status: http::StatusCode) -> String {
+        let code = match self.severity() {
+            Some(severity) => severity.colorize(&status.as_u16().to_string()).to_string(),
+            None => status.as_u16().to_string(),
         };
 
         match status.canonical_reason() {
@@ -154,7 +765,16 @@ impl LogRecord {
         }
     }
 
-    fn format_duration(duration: f64) -> String {
+    pub fn format_cache_status(cache: CacheStatus) -> String {
+        let badge = cache.to_string();
+        match cache {
+            CacheStatus::Hit => badge.green().to_string(),
+            CacheStatus::Stale | CacheStatus::Miss => badge.yellow().to_string(),
+            CacheStatus::Bypass => badge.dimmed().to_string(),
+        }
+    }
+
+    pub fn format_duration(duration: f64) -> String {
         if duration * 1_000.0 < 1.0 {
             let micros = duration * 1_000_000.0;
             format!("{:.03} us", micros)
@@ -171,14 +791,131 @@ impl LogRecord {
     }
 }
 
-fn truncate_line(str: &mut String, width: u16) {
-    let width = width as usize;
-    if str.len() + 1 > width {
-        *str = str
-            .chars()
-            .take(width.saturating_sub(2))
-            .chain(['…'])
-            .collect();
+/// The host without the port, and without the brackets of the IPv6 addresses.
+pub fn strip_port(host: &str) -> &str {
+    if let Some(rest) = host.strip_prefix('[') {
+        return rest.split(']').next().unwrap_or(rest);
+    }
+    match host.rsplit_once(':') {
+        Some((name, port)) if port.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => host,
+    }
+}
+
+/// The time of a timestamp in seconds since the epoch, nothing when it is out of the range of the
+/// dates (from the year -9999 to 9999).
+pub fn to_datetime(ts: f64) -> Option<OffsetDateTime> {
+    if !ts.is_finite() {
+        return None;
+    }
+    let ts = ((ts * 1_000_000.0) as i128).checked_mul(1_000)?;
+    OffsetDateTime::from_unix_timestamp_nanos(ts).ok()
+}
+
+/// Truncate the line to fit the given width, the escape sequences used for colors are preserved
+/// and they are not counted in the width.
+pub fn truncate_line(str: &mut String, width: usize) {
+    if visible_width(str) < width {
+        return;
+    }
+    let mut visible = 0;
+    let mut elided = false;
+    let mut truncated = String::with_capacity(str.len());
+    let mut chars = str.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            truncated.push(c);
+            for c in chars.by_ref() {
+                truncated.push(c);
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else if elided {
+            continue;
+        } else if visible + char_width(c) <= width.saturating_sub(2) {
+            truncated.push(c);
+            visible += char_width(c);
+        } else {
+            truncated.push('…');
+            elided = true;
+        }
+    }
+    *str = truncated;
+}
+
+/// Truncate the line holding a request line to fit the given width, its query is shortened
+/// first, so the path and the protocol are kept when possible.
+pub fn truncate_request_line(str: &mut String, width: usize) {
+    let excess = (visible_width(str) + 1).saturating_sub(width);
+    if excess > 0 {
+        elide_query(str, excess);
+    }
+    truncate_line(str, width);
+}
+
+/// Shorten the query of the URI in the line by the given number of characters, the rest of the
+/// line is kept. The whole query is replaced by an ellipsis when it is not long enough.
+pub fn elide_query(str: &mut String, excess: usize) {
+    let Some(start) = str.find('?') else {
+        return;
+    };
+    let end = str[start..].find(' ').map_or(str.len(), |end| start + end);
+    let query = &str[start + 1..end];
+    let length = query.chars().count();
+    if excess == 0 || length <= 1 {
+        return;
+    }
+    // the ellipsis takes the place of a character
+    let kept = query
+        .chars()
+        .take(length.saturating_sub(excess + 1))
+        .collect::<String>();
+    str.replace_range(start + 1..end, &format!("{kept}…"));
+}
+
+/// The number of columns displayed on the terminal, escape sequences excluded.
+pub fn visible_width(str: &str) -> usize {
+    let mut width = 0;
+    let mut chars = str.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            width += char_width(c);
+        }
+    }
+    width
+}
+
+/// The number of columns taken by the character: the East Asian wide characters and the emoji
+/// take two, the combining marks and the zero width characters none.
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x0300..=0x036F
+        | 0x1AB0..=0x1AFF
+        | 0x1DC0..=0x1DFF
+        | 0x200B..=0x200F
+        | 0x20D0..=0x20FF
+        | 0xFE00..=0xFE0F
+        | 0xFE20..=0xFE2F => 0,
+        0x1100..=0x115F
+        | 0x231A..=0x231B
+        | 0x2329..=0x232A
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
     }
 }
 
@@ -192,3 +929,91 @@ impl<'de> DeserializeAs<'de, http::StatusCode> for SerdeHttpStatusCode {
         http_serde::status_code::deserialize(deserializer)
     }
 }
+
+/// A number or a text, the fields whose type depends on the options of the logs.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumberOrText {
+    Number(f64),
+    Text(String),
+}
+
+/// The time of a record, in seconds since the epoch, whatever the time format of the logs: the
+/// numbers of the `unix_*` formats are told apart by their magnitude, the texts are RFC 3339
+/// timestamps like the ones of `iso8601` and `rfc3339`.
+struct Timestamp;
+
+impl<'de> DeserializeAs<'de, f64> for Timestamp {
+    fn deserialize_as<D>(deserializer: D) -> Result<f64, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let ts = match NumberOrText::deserialize(deserializer)? {
+            // nanoseconds since 2001, milliseconds since 1973
+            NumberOrText::Number(ts) if ts.abs() >= 1e18 => ts / 1e9,
+            NumberOrText::Number(ts) if ts.abs() >= 1e11 => ts / 1e3,
+            NumberOrText::Number(ts) => ts,
+            NumberOrText::Text(ts) => units::parse_timestamp(&ts)
+                .ok_or_else(|| serde::de::Error::custom(format!("invalid timestamp: {ts}")))?,
+        };
+        match to_datetime(ts) {
+            Some(_) => Ok(ts),
+            None => Err(serde::de::Error::custom(format!(
+                "timestamp out of range: {ts}"
+            ))),
+        }
+    }
+}
+
+/// A duration in seconds, whatever the duration format of the logs: the numbers are seconds, or
+/// nanoseconds for the `nano` format when they are too large to be seconds (more than a day),
+/// the texts are Go durations like `1.5ms`.
+struct Seconds;
+
+impl<'de> DeserializeAs<'de, f64> for Seconds {
+    fn deserialize_as<D>(deserializer: D) -> Result<f64, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        match NumberOrText::deserialize(deserializer)? {
+            NumberOrText::Number(duration) if duration >= 86_400.0 => Ok(duration / 1e9),
+            NumberOrText::Number(duration) => Ok(duration),
+            NumberOrText::Text(duration) => units::parse_go_duration(&duration)
+                .ok_or_else(|| serde::de::Error::custom(format!("invalid duration: {duration}"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timestamp(ts: &str) -> Option<f64> {
+        let line = format!(r#"{{"level":"info","ts":{ts},"msg":"x"}}"#);
+        serde_json::from_str::<LogRecord>(&line)
+            .ok()
+            .map(|record| record.timestamp)
+    }
+
+    #[test]
+    fn parse_timestamps() {
+        assert_eq!(timestamp("1710000000.5"), Some(1710000000.5));
+        assert_eq!(timestamp("1710000000500"), Some(1710000000.5));
+        assert_eq!(timestamp("1710000000500000000"), Some(1710000000.5));
+        assert_eq!(timestamp(r#""2024-03-09T16:00:00.5Z""#), Some(1710000000.5));
+        assert_eq!(
+            timestamp(r#""2024-03-09T17:00:00.5+0100""#),
+            Some(1710000000.5)
+        );
+    }
+
+    #[test]
+    fn reject_timestamps_out_of_range() {
+        assert_eq!(timestamp("1e17"), None);
+        assert_eq!(timestamp("-1e17"), None);
+        assert_eq!(timestamp("1e300"), None);
+        assert!(to_datetime(f64::INFINITY).is_none());
+        assert!(to_datetime(f64::NAN).is_none());
+        assert!(to_datetime(1e15).is_none());
+    }
+}
@@ -0,0 +1,309 @@
+use std::net::Ipv6Addr;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use hmac::{Hmac, Mac};
+use serde_json::{Map, Value};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// A field of the records which can be dropped or hashed before they are written.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Field {
+    /// The address of the client.
+    #[value(name = "remote_ip")]
+    RemoteIp,
+    /// The port of the client.
+    #[value(name = "remote_port")]
+    RemotePort,
+    /// The address of the client behind the trusted proxies.
+    #[value(name = "client_ip")]
+    ClientIp,
+    /// The `host` header.
+    Host,
+    /// The request URI, the query included.
+    Uri,
+    /// The query of the request URI.
+    #[value(name = "uri_query")]
+    UriQuery,
+    /// The `User-Agent` header.
+    #[value(name = "user_agent")]
+    UserAgent,
+    /// All the headers of the request.
+    Headers,
+    /// All the headers of the response.
+    #[value(name = "resp_headers")]
+    RespHeaders,
+    /// The user authenticated by the server.
+    #[value(name = "user_id")]
+    UserId,
+}
+
+/// Drop or hash the fields of the records before they are given to the sinks, so the exports
+/// follow the privacy policies whatever their destination.
+///
+/// The hashes are the HMAC-SHA256 of the values: with the same key the same value has the same
+/// hash in every run and on every platform, without a key a random one is used and they are
+/// consistent only within a run. The hashed addresses are written as IPv6 unique
+/// local addresses (`fd00::/8`), so they still identify the clients.
+pub struct Redaction {
+    drop: Vec<Field>,
+    hash: Vec<Field>,
+    hasher: HmacSha256,
+}
+
+impl Redaction {
+    pub fn new(drop: Vec<Field>, hash: Vec<Field>, key: Option<String>) -> Result<Self> {
+        let key = match key {
+            Some(key) => key.into_bytes(),
+            None => {
+                let mut key = vec![0; 32];
+                getrandom::getrandom(&mut key)
+                    .context("failed to generate a random key for the hashes")?;
+                key
+            }
+        };
+        let hasher = HmacSha256::new_from_slice(&key).expect("HMAC accepts keys of any length");
+        Ok(Self { drop, hash, hasher })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.drop.is_empty() && self.hash.is_empty()
+    }
+
+    /// Redact the fields of the original line of a record, nothing is returned when there is
+    /// nothing to redact.
+    pub fn redact(&self, line: &str) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut value = serde_json::from_str::<Value>(line).ok()?;
+        self.apply(&mut value);
+        Some(value.to_string())
+    }
+
+    /// Redact the fields of the JSON document of a record. The required fields of the records
+    /// are emptied instead of being dropped, so the records can still be parsed.
+    pub fn apply(&self, value: &mut Value) {
+        for &field in &self.drop {
+            self.drop_field(value, field);
+        }
+        for &field in &self.hash {
+            self.hash_field(value, field);
+        }
+    }
+
+    fn drop_field(&self, value: &mut Value, field: Field) {
+        let Some((parent, key)) = location(value, field) else {
+            return;
+        };
+        match field {
+            Field::UriQuery => {
+                if let Some(Value::String(uri)) = parent.get_mut(&key) {
+                    uri.truncate(uri.find('?').unwrap_or(uri.len()));
+                }
+            }
+            // the address of the clients of a unix socket is empty too
+            Field::RemoteIp | Field::Host | Field::Uri => {
+                parent.insert(key, Value::String(String::new()));
+            }
+            Field::Headers => {
+                parent.insert(key, Value::Object(Map::new()));
+            }
+            _ => {
+                parent.shift_remove(&key);
+            }
+        }
+    }
+
+    fn hash_field(&self, value: &mut Value, field: Field) {
+        let Some(target) = location(value, field).and_then(|(parent, key)| parent.get_mut(&key))
+        else {
+            return;
+        };
+        match (field, target) {
+            (Field::RemoteIp | Field::ClientIp, Value::String(ip)) if !ip.is_empty() => {
+                *ip = self.hash_ip(ip);
+            }
+            (Field::RemoteIp | Field::ClientIp, _) => {}
+            (Field::UriQuery, Value::String(uri)) => {
+                if let Some((path, query)) = uri.split_once('?') {
+                    *uri = format!("{path}?{}", self.hash(query));
+                }
+            }
+            (Field::UriQuery, _) => {}
+            (_, target) => self.hash_strings(target),
+        }
+    }
+
+    /// Hash the strings of the value, recursively.
+    fn hash_strings(&self, value: &mut Value) {
+        match value {
+            Value::String(s) => *s = self.hash(s),
+            Value::Array(values) => values.iter_mut().for_each(|v| self.hash_strings(v)),
+            Value::Object(entries) => entries.values_mut().for_each(|v| self.hash_strings(v)),
+            _ => {}
+        }
+    }
+
+    fn digest(&self, value: &str) -> u64 {
+        let mut hasher = self.hasher.clone();
+        hasher.update(value.as_bytes());
+        // the first 64 bits of the digest
+        u64::from_be_bytes(hasher.finalize().into_bytes()[..8].try_into().unwrap())
+    }
+
+    fn hash(&self, value: &str) -> String {
+        format!("{:016x}", self.digest(value))
+    }
+
+    fn hash_ip(&self, ip: &str) -> String {
+        let address = (0xfd_u128 << 120) | u128::from(self.digest(ip));
+        Ipv6Addr::from(address).to_string()
+    }
+}
+
+/// The object holding the field, with the key of the field.
+fn location(value: &mut Value, field: Field) -> Option<(&mut Map<String, Value>, String)> {
+    let (parent, key) = match field {
+        Field::RemoteIp => ("/request", "remote_ip"),
+        Field::RemotePort => ("/request", "remote_port"),
+        Field::ClientIp => ("/request", "client_ip"),
+        Field::Host => ("/request", "host"),
+        Field::Uri | Field::UriQuery => ("/request", "uri"),
+        Field::UserAgent => ("/request/headers", "User-Agent"),
+        Field::Headers => ("/request", "headers"),
+        Field::RespHeaders => ("", "resp_headers"),
+        Field::UserId => ("", "user_id"),
+    };
+    let parent = value.pointer_mut(parent)?.as_object_mut()?;
+    // the names of the headers are case insensitive
+    let key = parent
+        .keys()
+        .find(|name| name.eq_ignore_ascii_case(key))?
+        .clone();
+    Some((parent, key))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::record::LogRecord;
+
+    fn record() -> Value {
+        json!({
+            "level": "info",
+            "ts": 1710000000.5,
+            "logger": "http.log.access",
+            "msg": "handled request",
+            "user_id": "alice",
+            "request": {
+                "remote_ip": "10.0.0.5",
+                "remote_port": "51234",
+                "client_ip": "203.0.113.7",
+                "proto": "HTTP/1.1",
+                "method": "GET",
+                "host": "example.com",
+                "uri": "/search?q=caddy&page=2",
+                "headers": { "user-agent": ["curl/8.4.0"], "Accept": ["*/*"] },
+            },
+            "duration": 0.25,
+            "size": 512,
+            "status": 200,
+            "resp_headers": { "Server": ["Caddy"] },
+        })
+    }
+
+    #[test]
+    fn hash_with_the_key() {
+        // the test case 2 of RFC 4231, the first 64 bits of the HMAC-SHA256
+        let redaction = Redaction::new(vec![], vec![], Some("Jefe".to_string())).unwrap();
+        assert_eq!(
+            redaction.hash("what do ya want for nothing?"),
+            "5bdcc146bf60754e"
+        );
+        assert_eq!(redaction.hash_ip("10.0.0.5"), redaction.hash_ip("10.0.0.5"));
+        assert!(redaction.hash_ip("10.0.0.5").starts_with("fd"));
+    }
+
+    #[test]
+    fn hash_with_a_random_key() {
+        let first = Redaction::new(vec![], vec![], None).unwrap();
+        let second = Redaction::new(vec![], vec![], None).unwrap();
+        assert_eq!(first.hash("10.0.0.5"), first.hash("10.0.0.5"));
+        assert_ne!(first.hash("10.0.0.5"), second.hash("10.0.0.5"));
+    }
+
+    #[test]
+    fn drop_the_fields() {
+        let drop = vec![
+            Field::RemoteIp,
+            Field::RemotePort,
+            Field::UriQuery,
+            Field::UserAgent,
+            Field::RespHeaders,
+            Field::UserId,
+        ];
+        let redaction = Redaction::new(drop, vec![], None).unwrap();
+        let mut value = record();
+        redaction.apply(&mut value);
+        let request = &value["request"];
+        assert_eq!(request["remote_ip"], "");
+        assert_eq!(request.get("remote_port"), None);
+        assert_eq!(request["uri"], "/search");
+        // the names of the headers are case insensitive
+        assert_eq!(request["headers"], json!({ "Accept": ["*/*"] }));
+        assert_eq!(value.get("resp_headers"), None);
+        assert_eq!(value.get("user_id"), None);
+        // the required fields are emptied, the record can still be parsed
+        let line = redaction.redact(&record().to_string()).unwrap();
+        serde_json::from_str::<LogRecord>(&line).unwrap();
+
+        let redaction = Redaction::new(vec![Field::Headers, Field::Host], vec![], None).unwrap();
+        let mut value = record();
+        redaction.apply(&mut value);
+        assert_eq!(value["request"]["headers"], json!({}));
+        assert_eq!(value["request"]["host"], "");
+    }
+
+    #[test]
+    fn hash_the_fields() {
+        let key = Some("secret".to_string());
+        let hash = vec![
+            Field::ClientIp,
+            Field::UriQuery,
+            Field::Headers,
+            Field::UserId,
+        ];
+        let redaction = Redaction::new(vec![], hash, key).unwrap();
+        let mut value = record();
+        redaction.apply(&mut value);
+        let request = &value["request"];
+        assert_eq!(request["remote_ip"], "10.0.0.5");
+        assert_eq!(request["client_ip"], redaction.hash_ip("203.0.113.7"));
+        assert_eq!(
+            request["uri"],
+            format!("/search?{}", redaction.hash("q=caddy&page=2"))
+        );
+        assert_eq!(request["headers"]["Accept"], json!([redaction.hash("*/*")]));
+        assert_eq!(value["user_id"], redaction.hash("alice"));
+        // the hashed addresses are still addresses
+        let line = redaction.redact(&record().to_string()).unwrap();
+        serde_json::from_str::<LogRecord>(&line).unwrap();
+    }
+
+    #[test]
+    fn leave_the_records_without_the_fields() {
+        let redaction = Redaction::new(vec![Field::UserId], vec![Field::Uri], None).unwrap();
+        let mut value = json!({ "level": "info", "msg": "started" });
+        redaction.apply(&mut value);
+        assert_eq!(value, json!({ "level": "info", "msg": "started" }));
+        assert!(Redaction::new(vec![], vec![], None)
+            .unwrap()
+            .redact("{}")
+            .is_none());
+    }
+}
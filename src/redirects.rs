@@ -0,0 +1,146 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde_json::{json, Value};
+
+use crate::{locale::Locale, record::LogRecord, stats::format_table};
+
+/// The source and the target of a redirect.
+type Key = (String, String);
+
+/// The number of redirects and their statuses.
+type Counts = (u64, BTreeSet<u16>);
+
+/// The redirects served, from their source to their target, to audit the redirect rules and to
+/// find the loops.
+#[derive(Default)]
+pub struct Redirects {
+    /// The number of redirects and their statuses, by source and target.
+    redirects: HashMap<Key, Counts>,
+}
+
+impl Redirects {
+    const TOP: usize = 20;
+    /// The chains longer than this are not followed further.
+    const MAX_HOPS: usize = 10;
+
+    /// Count the record when it is a redirect with a `Location` header.
+    pub fn observe(&mut self, record: &LogRecord) {
+        let (Some(request), Some(status)) = (&record.request, record.status) else {
+            return;
+        };
+        if !status.is_redirection() {
+            return;
+        }
+        let Some(location) = record.location() else {
+            return;
+        };
+        let source = format!("{}{}", request.host, request.uri);
+        let target = resolve(&request.host, location);
+        let redirect = self.redirects.entry((source, target)).or_default();
+        redirect.0 += 1;
+        redirect.1.insert(status.as_u16());
+    }
+
+    /// The most frequent redirects, with their count and their statuses.
+    fn top(&self) -> Vec<(&Key, &Counts)> {
+        let mut redirects = self.redirects.iter().collect::<Vec<_>>();
+        redirects.sort_by(|(a_key, a), (b_key, b)| b.0.cmp(&a.0).then(a_key.cmp(b_key)));
+        redirects.truncate(Self::TOP);
+        redirects
+    }
+
+    /// The most frequent redirects and the loops among them. Nothing is returned when no
+    /// redirect has been served.
+    pub fn render(&self, locale: &Locale) -> Option<String> {
+        if self.redirects.is_empty() {
+            return None;
+        }
+        let mut rows = vec![vec![
+            "redirect source".to_string(),
+            "count".into(),
+            "status".into(),
+        ]];
+        let mut targets = Vec::new();
+        for ((source, target), (count, statuses)) in self.top() {
+            let statuses = statuses.iter().map(u16::to_string).collect::<Vec<_>>();
+            rows.push(vec![
+                source.clone(),
+                locale.integer(*count),
+                statuses.join(","),
+            ]);
+            targets.push(target);
+        }
+        // the targets are appended after the aligned columns, they have variable length
+        let table = format_table(&rows);
+        let mut lines = table.lines();
+        let mut output = format!("{}  target\n", lines.next().unwrap_or_default());
+        for (line, target) in lines.zip(targets) {
+            output.push_str(&format!("{line}  → {target}\n"));
+        }
+        for chain in self.loops() {
+            output.push_str(&format!("redirect loop: {}\n", chain.join(" → ")));
+        }
+        Some(output)
+    }
+
+    /// The most frequent redirects, from their sources to their targets.
+    pub fn to_json(&self) -> Value {
+        self.top()
+            .into_iter()
+            .map(|((source, target), (count, statuses))| {
+                json!({
+                    "source": source,
+                    "target": target,
+                    "count": count,
+                    "statuses": statuses,
+                })
+            })
+            .collect()
+    }
+
+    /// The chains of redirects which end where they started, each loop is reported once.
+    pub fn loops(&self) -> Vec<Vec<String>> {
+        let mut next = BTreeMap::<&str, &str>::new();
+        for (source, target) in self.redirects.keys() {
+            // a source redirected to multiple targets is followed to one of them
+            next.entry(source).or_insert(target);
+        }
+        let mut loops = BTreeSet::new();
+        for &start in next.keys() {
+            let mut chain = vec![start];
+            let mut current = start;
+            while let Some(&target) = next.get(current) {
+                if let Some(index) = chain.iter().position(|&url| url == target) {
+                    let mut cycle = chain[index..].to_vec();
+                    // the same loop is found from each of its urls, it starts from the smallest
+                    let min = (0..cycle.len()).min_by_key(|&i| cycle[i]).unwrap_or(0);
+                    cycle.rotate_left(min);
+                    cycle.push(cycle[0]);
+                    loops.insert(cycle.into_iter().map(String::from).collect::<Vec<_>>());
+                    break;
+                }
+                if chain.len() > Self::MAX_HOPS {
+                    break;
+                }
+                chain.push(target);
+                current = target;
+            }
+        }
+        loops.into_iter().collect()
+    }
+}
+
+/// The target of the redirect, as host and path, the relative locations are resolved against
+/// the host of the request.
+fn resolve(host: &str, location: &str) -> String {
+    let absolute = location
+        .strip_prefix("https://")
+        .or_else(|| location.strip_prefix("http://"));
+    match absolute {
+        Some(target) if target.contains('/') => target.to_string(),
+        Some(target) => format!("{target}/"),
+        None if location.starts_with("//") => location[2..].to_string(),
+        None if location.starts_with('/') => format!("{host}{location}"),
+        None => format!("{host}/{location}"),
+    }
+}
@@ -0,0 +1,443 @@
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    input::InputArgs,
+    record::LogRecord,
+    stats::{self, format_table},
+    FilterArgs,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct ReplayArgs {
+    /// The server the requests are sent to, e.g. `https://staging.example.com`. The path and
+    /// the query of the logged requests are appended to it.
+    #[arg(long, value_name = "URL")]
+    target: String,
+
+    /// The number of requests sent at the same time.
+    #[arg(long, value_name = "N", default_value_t = 4)]
+    concurrency: usize,
+
+    /// The methods of the requests replayed, the others are skipped. Only the methods without a
+    /// body can be replayed, since the bodies are not logged.
+    #[arg(long, value_delimiter = ',', default_value = "GET,HEAD")]
+    methods: Vec<http::Method>,
+
+    /// A header of the logged requests sent with the replayed ones, e.g. `Accept`. This flag can
+    /// be repeated, the `Host` header is set by `--keep-host`.
+    #[arg(long = "carry-header", value_name = "NAME")]
+    carry_headers: Vec<http::HeaderName>,
+
+    /// Send the headers of the logged requests, except the ones of the connection and of the
+    /// authentication.
+    #[arg(long, conflicts_with = "carry_headers")]
+    carry_all_headers: bool,
+
+    /// Send the host of the logged requests as `Host` header, instead of the host of the target.
+    #[arg(long)]
+    keep_host: bool,
+
+    /// The time waited for each response.
+    #[arg(long, value_name = "SECONDS", default_value_t = 10)]
+    timeout: u64,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    filters: FilterArgs,
+}
+
+/// The headers never carried over, they belong to the connection or they grant access.
+const SKIPPED_HEADERS: &[&str] = &[
+    "authorization",
+    "connection",
+    "content-length",
+    "cookie",
+    "host",
+    "keep-alive",
+    "proxy-authorization",
+    "te",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// A request to replay, with the outcome of the logged one.
+struct Job {
+    method: http::Method,
+    uri: String,
+    headers: Vec<(http::HeaderName, http::HeaderValue)>,
+    status: Option<u16>,
+    duration: Option<f64>,
+}
+
+/// The outcome of a replayed request.
+struct Outcome {
+    job: Job,
+    result: Result<(u16, f64)>,
+}
+
+/// Send the matched requests of the log to the target, and report how the statuses and the
+/// durations differ from the logged ones.
+pub fn run(args: ReplayArgs) -> Result<()> {
+    if args.concurrency == 0 {
+        bail!("--concurrency must be greater than zero");
+    }
+    for method in &args.methods {
+        if !matches!(
+            *method,
+            http::Method::GET | http::Method::HEAD | http::Method::OPTIONS
+        ) {
+            bail!(
+                "cannot replay {} requests, their bodies are not logged",
+                method
+            );
+        }
+    }
+    let target = args.target.trim_end_matches('/').to_string();
+    let filters = args.filters.build()?;
+    let input = args.input.open(&filters)?;
+
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(args.timeout)))
+        .http_status_as_error(false)
+        .max_redirects(0)
+        .build()
+        .into();
+
+    let (jobs, queue) = mpsc::sync_channel::<Job>(args.concurrency);
+    let (outcomes, results) = mpsc::channel::<Outcome>();
+    let queue = Arc::new(Mutex::new(queue));
+    let workers = (0..args.concurrency)
+        .map(|_| {
+            let queue = Arc::clone(&queue);
+            let outcomes = outcomes.clone();
+            let agent = agent.clone();
+            let target = target.clone();
+            std::thread::spawn(move || loop {
+                let Ok(job) = queue.lock().unwrap().recv() else {
+                    break;
+                };
+                let result = send(&agent, &target, &job);
+                if outcomes.send(Outcome { job, result }).is_err() {
+                    break;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    drop(outcomes);
+
+    let feeder = {
+        let carry = Carry {
+            headers: args.carry_headers,
+            all: args.carry_all_headers,
+            keep_host: args.keep_host,
+        };
+        let methods = args.methods;
+        std::thread::spawn(move || -> Result<()> {
+            for line in input {
+                let line = line?;
+                if !filters.matches_line(&line.text) {
+                    continue;
+                }
+                let Ok(record) = serde_json::from_str::<LogRecord>(&line.text) else {
+                    continue;
+                };
+                if !filters.matches(&record) {
+                    continue;
+                }
+                let Some(job) = carry.job(&record, &methods) else {
+                    continue;
+                };
+                if jobs.send(job).is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        })
+    };
+
+    let mut report = Report::default();
+    for outcome in results {
+        report.add(outcome);
+    }
+    for worker in workers {
+        let _ = worker.join();
+    }
+    feeder.join().unwrap()?;
+    print!("{}", report.render());
+    Ok(())
+}
+
+/// Which headers of the logged requests are sent.
+struct Carry {
+    headers: Vec<http::HeaderName>,
+    all: bool,
+    keep_host: bool,
+}
+
+impl Carry {
+    fn job(&self, record: &LogRecord, methods: &[http::Method]) -> Option<Job> {
+        let request = record.request.as_ref()?;
+        if !methods.contains(&request.method) {
+            return None;
+        }
+        let mut headers = request
+            .headers
+            .iter()
+            .filter(|(name, _)| match self.all {
+                true => !SKIPPED_HEADERS.contains(&name.as_str()),
+                false => self.headers.contains(name),
+            })
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect::<Vec<_>>();
+        if self.keep_host {
+            let host = http::HeaderValue::from_str(&request.host).ok()?;
+            headers.push((http::header::HOST, host));
+        }
+        Some(Job {
+            method: request.method.clone(),
+            uri: request.uri.clone(),
+            headers,
+            status: record.status.map(|status| status.as_u16()),
+            duration: record.duration,
+        })
+    }
+}
+
+/// Send the request, the duration includes the download of the body.
+fn send(agent: &ureq::Agent, target: &str, job: &Job) -> Result<(u16, f64)> {
+    let url = format!("{target}{}", job.uri);
+    let mut request = http::Request::builder()
+        .method(job.method.clone())
+        .uri(&url);
+    for (name, value) in &job.headers {
+        request = request.header(name, value);
+    }
+    let request = request
+        .body(())
+        .with_context(|| format!("invalid request: {}", url))?;
+    let start = Instant::now();
+    let mut response = agent
+        .run(request)
+        .with_context(|| format!("{} {} failed", job.method, url))?;
+    std::io::copy(&mut response.body_mut().as_reader(), &mut std::io::sink())
+        .with_context(|| format!("{} {} failed", job.method, url))?;
+    Ok((response.status().as_u16(), start.elapsed().as_secs_f64()))
+}
+
+/// The differences between the logged requests and the replayed ones.
+#[derive(Default)]
+struct Report {
+    replayed: u64,
+    failed: u64,
+    same_status: u64,
+    logged_durations: Vec<f64>,
+    replayed_durations: Vec<f64>,
+}
+
+impl Report {
+    /// Count the outcome, the failures and the different statuses are printed as they come.
+    fn add(&mut self, outcome: Outcome) {
+        let Outcome { job, result } = outcome;
+        let (status, duration) = match result {
+            Ok(result) => result,
+            Err(err) => {
+                self.failed += 1;
+                eprintln!("{err:#}");
+                return;
+            }
+        };
+        self.replayed += 1;
+        if job.status == Some(status) {
+            self.same_status += 1;
+        } else {
+            let logged = job
+                .status
+                .map_or("-".to_string(), |status| status.to_string());
+            println!(
+                "{} {}: {} instead of {} ({})",
+                job.method,
+                job.uri,
+                status,
+                logged,
+                LogRecord::format_duration(duration)
+            );
+        }
+        if let Some(logged) = job.duration {
+            self.logged_durations.push(logged);
+            self.replayed_durations.push(duration);
+        }
+    }
+
+    fn render(&mut self) -> String {
+        self.logged_durations.sort_by(f64::total_cmp);
+        self.replayed_durations.sort_by(f64::total_cmp);
+        let mut output = format!(
+            "\n{} requests replayed, {} with the same status, {} failed\n\n",
+            self.replayed, self.same_status, self.failed
+        );
+        let mut rows = vec![vec![
+            "duration".to_string(),
+            "logged".to_string(),
+            "replayed".to_string(),
+        ]];
+        for percentile in [50.0, 95.0, 99.0] {
+            let format = |durations: &[f64]| {
+                stats::percentile_of(durations, percentile)
+                    .map(LogRecord::format_duration)
+                    .unwrap_or_else(|| "-".into())
+            };
+            rows.push(vec![
+                format!("p{percentile}"),
+                format(&self.logged_durations),
+                format(&self.replayed_durations),
+            ]);
+        }
+        output.push_str(&format_table(&rows));
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+    };
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn record(method: &str) -> LogRecord {
+        let line = json!({
+            "level": "info",
+            "ts": 1710000000.5,
+            "logger": "http.log.access",
+            "msg": "handled request",
+            "request": {
+                "remote_ip": "10.0.0.5",
+                "proto": "HTTP/1.1",
+                "method": method,
+                "host": "example.com",
+                "uri": "/search?q=caddy",
+                "headers": {
+                    "Accept": ["text/html"],
+                    "Cookie": ["session=secret"],
+                    "User-Agent": ["curl/8.4.0"],
+                },
+            },
+            "duration": 0.25,
+            "size": 512,
+            "status": 200,
+            "resp_headers": {},
+        });
+        serde_json::from_value(line).unwrap()
+    }
+
+    fn header_names(job: &Job) -> Vec<&str> {
+        let mut names = job
+            .headers
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn carry_the_headers() {
+        let methods = [http::Method::GET];
+        let carry = Carry {
+            headers: vec![http::header::ACCEPT],
+            all: false,
+            keep_host: false,
+        };
+        let job = carry.job(&record("GET"), &methods).unwrap();
+        assert_eq!(job.uri, "/search?q=caddy");
+        assert_eq!(job.status, Some(200));
+        assert_eq!(header_names(&job), ["accept"]);
+        assert!(carry.job(&record("POST"), &methods).is_none());
+
+        // the credentials are never carried
+        let carry = Carry {
+            headers: Vec::new(),
+            all: true,
+            keep_host: true,
+        };
+        let job = carry.job(&record("GET"), &methods).unwrap();
+        assert_eq!(header_names(&job), ["accept", "host", "user-agent"]);
+    }
+
+    #[test]
+    fn send_the_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut request = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim_end().is_empty() {
+                    break;
+                }
+                request.push(line.trim_end().to_lowercase());
+            }
+            (&stream)
+                .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            request
+        });
+
+        let carry = Carry {
+            headers: vec![http::header::ACCEPT],
+            all: false,
+            keep_host: true,
+        };
+        let job = carry.job(&record("GET"), &[http::Method::GET]).unwrap();
+        let agent = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .build()
+            .into();
+        let (status, _) = send(&agent, &target, &job).unwrap();
+        assert_eq!(status, 404);
+        let request = server.join().unwrap();
+        assert_eq!(request[0], "get /search?q=caddy http/1.1");
+        assert!(request.contains(&"host: example.com".to_string()));
+        assert!(request.contains(&"accept: text/html".to_string()));
+    }
+
+    #[test]
+    fn compare_the_outcomes() {
+        let carry = Carry {
+            headers: Vec::new(),
+            all: false,
+            keep_host: false,
+        };
+        let mut report = Report::default();
+        for result in [
+            Ok((200, 0.5)),
+            Ok((503, 0.1)),
+            Err(anyhow::anyhow!("refused")),
+        ] {
+            let job = carry.job(&record("GET"), &[http::Method::GET]).unwrap();
+            report.add(Outcome { job, result });
+        }
+        assert_eq!(
+            report.render(),
+            "\n2 requests replayed, 1 with the same status, 1 failed\n\n\
+             duration      logged    replayed\n\
+             p50       250.000 ms  100.000 ms\n\
+             p95       250.000 ms  500.000 ms\n\
+             p99       250.000 ms  500.000 ms\n"
+        );
+    }
+}
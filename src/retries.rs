@@ -0,0 +1,151 @@
+use std::{collections::HashMap, time::Duration};
+
+use serde_json::{json, Value};
+
+use crate::{
+    locale::Locale,
+    record::{LogRecord, RemoteIp},
+    stats::format_table,
+};
+
+/// The same request of the same client.
+type Key = (RemoteIp, http::Method, String);
+
+/// Find the bursts of identical requests, the ones sent by the same client within the window of
+/// the previous one. They are usually retries, of a client misbehaving or after errors.
+pub struct Retries {
+    window: f64,
+    bursts: HashMap<Key, Burst>,
+    offenders: HashMap<Key, Offender>,
+}
+
+struct Burst {
+    start: f64,
+    last: f64,
+    count: u64,
+}
+
+/// The requests repeated the most, with their longest burst.
+#[derive(Default)]
+struct Offender {
+    retries: u64,
+    longest: u64,
+}
+
+/// A request repeated within the window.
+pub struct Retry {
+    /// The number of identical requests of the burst, this one included.
+    pub attempt: u64,
+    /// The time since the first request of the burst, in seconds.
+    pub elapsed: f64,
+}
+
+impl Retries {
+    const TOP: usize = 10;
+    /// The bursts are pruned when there are more, the ended ones are removed.
+    const MAX_BURSTS: usize = 10_000;
+
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window: window.as_secs_f64(),
+            bursts: HashMap::new(),
+            offenders: HashMap::new(),
+        }
+    }
+
+    /// Count the request, the retry is returned when it repeats a recent one.
+    pub fn observe(&mut self, record: &LogRecord) -> Option<Retry> {
+        let request = record.request.as_ref()?;
+        let timestamp = record.timestamp;
+        if self.bursts.len() >= Self::MAX_BURSTS {
+            let window = self.window;
+            self.bursts
+                .retain(|_, burst| timestamp - burst.last <= window);
+        }
+        let key = (
+            request.remote_ip,
+            request.method.clone(),
+            request.uri.clone(),
+        );
+        let burst = self.bursts.entry(key.clone()).or_insert(Burst {
+            start: timestamp,
+            last: f64::NEG_INFINITY,
+            count: 0,
+        });
+        if timestamp - burst.last > self.window {
+            burst.start = timestamp;
+            burst.count = 0;
+        }
+        burst.last = timestamp;
+        burst.count += 1;
+        if burst.count < 2 {
+            return None;
+        }
+        let retry = Retry {
+            attempt: burst.count,
+            elapsed: timestamp - burst.start,
+        };
+        let offender = self.offenders.entry(key).or_default();
+        offender.retries += 1;
+        offender.longest = offender.longest.max(retry.attempt);
+        Some(retry)
+    }
+
+    /// The requests repeated the most, with their retries.
+    fn top(&self) -> Vec<(&Key, &Offender)> {
+        let mut offenders = self.offenders.iter().collect::<Vec<_>>();
+        offenders.sort_by(|(a_key, a), (b_key, b)| {
+            let order = |(ip, method, uri): &Key| (*ip, method.to_string(), uri.clone());
+            (b.retries, b.longest)
+                .cmp(&(a.retries, a.longest))
+                .then_with(|| order(a_key).cmp(&order(b_key)))
+        });
+        offenders.truncate(Self::TOP);
+        offenders
+    }
+
+    /// The summary of the requests repeated the most. Nothing is returned when no request has
+    /// been repeated.
+    pub fn render(&self, locale: &Locale) -> Option<String> {
+        if self.offenders.is_empty() {
+            return None;
+        }
+        let mut rows = vec![vec![
+            "retrying client".to_string(),
+            "retries".into(),
+            "longest_burst".into(),
+        ]];
+        let mut requests = Vec::new();
+        for ((ip, method, uri), offender) in self.top() {
+            rows.push(vec![
+                ip.to_string(),
+                locale.integer(offender.retries),
+                locale.integer(offender.longest),
+            ]);
+            requests.push(format!("{method} {uri}"));
+        }
+        // the requests are appended after the aligned columns, they have variable length
+        let table = format_table(&rows);
+        let mut lines = table.lines();
+        let mut output = format!("{}  request\n", lines.next().unwrap_or_default());
+        for (line, request) in lines.zip(requests) {
+            output.push_str(&format!("{line}  {request}\n"));
+        }
+        Some(output)
+    }
+    /// The requests repeated the most, by client.
+    pub fn to_json(&self) -> Value {
+        self.top()
+            .into_iter()
+            .map(|((ip, method, uri), offender)| {
+                json!({
+                    "client": ip.to_string(),
+                    "method": method.as_str(),
+                    "uri": uri,
+                    "retries": offender.retries,
+                    "longest_burst": offender.longest,
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,278 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use ipnet::IpNet;
+use serde::Deserialize;
+
+use crate::{expr::Expr, filters::StatusPattern, record::LogRecord};
+
+/// The named filter definitions of a YAML or TOML file, e.g.
+///
+/// ```yaml
+/// internal:
+///   networks: [10.0.0.0/8, 192.168.0.0/16]
+/// shop:
+///   hosts: [shop.example.com, "*.shop.example.com"]
+///   none: [internal]
+/// default:
+///   any: [shop, errors]
+/// errors:
+///   expr: status >= 500
+/// ```
+///
+/// or, with the `.toml` extension:
+///
+/// ```toml
+/// [internal]
+/// networks = ["10.0.0.0/8", "192.168.0.0/16"]
+///
+/// [default]
+/// statuses = ["5xx"]
+/// uris = ["/api/*"]
+/// none = ["internal"]
+/// ```
+///
+/// The conditions of a definition must all hold, the definitions can reference each other with
+/// `all`, `any` and `none`.
+pub struct RuleSet {
+    definitions: BTreeMap<String, Definition>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Definition {
+    /// The host matches one of the glob patterns.
+    #[serde(default)]
+    hosts: Vec<String>,
+    /// The remote address belongs to one of the networks, in CIDR notation.
+    #[serde(default)]
+    networks: Vec<String>,
+    /// The status is one of the codes or of the classes, e.g. `404` or `5xx`.
+    #[serde(default)]
+    statuses: Vec<String>,
+    /// The request URI, the query included, matches one of the glob patterns.
+    #[serde(default)]
+    uris: Vec<String>,
+    /// The record satisfies the expression.
+    expr: Option<String>,
+    /// The record matches all the referenced definitions.
+    #[serde(default)]
+    all: Vec<String>,
+    /// The record matches at least one of the referenced definitions.
+    #[serde(default)]
+    any: Vec<String>,
+    /// The record matches none of the referenced definitions.
+    #[serde(default)]
+    none: Vec<String>,
+}
+
+/// A compiled definition, the references are replaced by the referenced rules.
+pub enum Rule {
+    All(Vec<Rule>),
+    Any(Vec<Rule>),
+    Not(Box<Rule>),
+    Hosts(Vec<glob::Pattern>),
+    Networks(Vec<IpNet>),
+    Statuses(Vec<StatusPattern>),
+    Uris(Vec<glob::Pattern>),
+    Expr(Expr),
+}
+
+impl RuleSet {
+    /// The name of the definition applied when none is chosen.
+    pub const DEFAULT: &'static str = "default";
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let definitions = match path
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+        {
+            true => parse_toml(&content),
+            false => serde_yaml::from_str(&content).map_err(Into::into),
+        }
+        .with_context(|| format!("invalid filter file: {}", path.display()))?;
+        Ok(Self { definitions })
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.definitions.contains_key(name)
+    }
+
+    /// Compile the definition with the given name and the ones it references.
+    pub fn rule(&self, name: &str) -> Result<Rule> {
+        self.compile(name, &mut Vec::new())
+    }
+
+    fn compile<'a>(&'a self, name: &'a str, stack: &mut Vec<&'a str>) -> Result<Rule> {
+        let Some(definition) = self.definitions.get(name) else {
+            bail!("undefined filter: {}", name);
+        };
+        if stack.contains(&name) {
+            bail!("circular filter: {} -> {}", stack.join(" -> "), name);
+        }
+        stack.push(name);
+        let rule = self
+            .compile_definition(definition, stack)
+            .with_context(|| format!("invalid filter: {}", name))?;
+        stack.pop();
+        Ok(rule)
+    }
+
+    fn compile_definition<'a>(
+        &'a self,
+        definition: &'a Definition,
+        stack: &mut Vec<&'a str>,
+    ) -> Result<Rule> {
+        let mut rules = Vec::new();
+        if !definition.hosts.is_empty() {
+            let patterns = definition
+                .hosts
+                .iter()
+                .map(|host| {
+                    glob::Pattern::new(host)
+                        .with_context(|| format!("invalid host pattern: {}", host))
+                })
+                .collect::<Result<_>>()?;
+            rules.push(Rule::Hosts(patterns));
+        }
+        if !definition.networks.is_empty() {
+            let networks = definition
+                .networks
+                .iter()
+                .map(|network| parse_network(network))
+                .collect::<Result<_>>()?;
+            rules.push(Rule::Networks(networks));
+        }
+        if !definition.statuses.is_empty() {
+            let statuses = definition
+                .statuses
+                .iter()
+                .map(|status| StatusPattern::parse(status))
+                .collect::<Result<_>>()?;
+            rules.push(Rule::Statuses(statuses));
+        }
+        if !definition.uris.is_empty() {
+            let patterns = definition
+                .uris
+                .iter()
+                .map(|uri| {
+                    glob::Pattern::new(uri).with_context(|| format!("invalid uri pattern: {}", uri))
+                })
+                .collect::<Result<_>>()?;
+            rules.push(Rule::Uris(patterns));
+        }
+        if let Some(expr) = &definition.expr {
+            rules.push(Rule::Expr(expr.parse()?));
+        }
+        for name in &definition.all {
+            rules.push(self.compile(name, stack)?);
+        }
+        if !definition.any.is_empty() {
+            let any = definition
+                .any
+                .iter()
+                .map(|name| self.compile(name, stack))
+                .collect::<Result<_>>()?;
+            rules.push(Rule::Any(any));
+        }
+        for name in &definition.none {
+            rules.push(Rule::Not(Box::new(self.compile(name, stack)?)));
+        }
+        Ok(Rule::All(rules))
+    }
+}
+
+impl Rule {
+    pub fn matches(&self, record: &LogRecord) -> bool {
+        let request = record.request.as_ref();
+        match self {
+            Rule::All(rules) => rules.iter().all(|rule| rule.matches(record)),
+            Rule::Any(rules) => rules.iter().any(|rule| rule.matches(record)),
+            Rule::Not(rule) => !rule.matches(record),
+            Rule::Hosts(patterns) => request.is_some_and(|request| {
+                patterns
+                    .iter()
+                    .any(|pattern| pattern.matches(&request.host))
+            }),
+            Rule::Networks(networks) => request.is_some_and(|request| {
+                networks.iter().any(|network| {
+                    request
+                        .remote_ip
+                        .ip()
+                        .is_some_and(|ip| network.contains(&ip))
+                })
+            }),
+            Rule::Statuses(statuses) => record.status.is_some_and(|status| {
+                statuses
+                    .iter()
+                    .any(|pattern| pattern.matches(status.as_u16()))
+            }),
+            Rule::Uris(patterns) => request.is_some_and(|request| {
+                patterns.iter().any(|pattern| pattern.matches(&request.uri))
+            }),
+            Rule::Expr(expr) => expr.matches(record),
+        }
+    }
+}
+
+/// A network in CIDR notation, a single address is a network of one address.
+pub fn parse_network(network: &str) -> Result<IpNet> {
+    if let Ok(address) = network.parse::<std::net::IpAddr>() {
+        return Ok(address.into());
+    }
+    network
+        .parse()
+        .with_context(|| format!("invalid network: {}", network))
+}
+
+/// Read the definitions of a TOML file, a table for each one. The tables are converted to
+/// JSON, which has the same shape.
+fn parse_toml(content: &str) -> Result<BTreeMap<String, Definition>> {
+    let document = content.parse::<toml_edit::DocumentMut>()?;
+    let definitions = document
+        .iter()
+        .map(|(name, item)| (name.to_string(), toml_item(item)))
+        .collect();
+    Ok(serde_json::from_value(serde_json::Value::Object(
+        definitions,
+    ))?)
+}
+
+fn toml_item(item: &toml_edit::Item) -> serde_json::Value {
+    match item {
+        toml_edit::Item::None => serde_json::Value::Null,
+        toml_edit::Item::Value(value) => toml_value(value),
+        toml_edit::Item::Table(table) => table
+            .iter()
+            .map(|(key, item)| (key.to_string(), toml_item(item)))
+            .collect(),
+        toml_edit::Item::ArrayOfTables(tables) => tables
+            .iter()
+            .map(|table| {
+                serde_json::Value::Object(
+                    table
+                        .iter()
+                        .map(|(key, item)| (key.to_string(), toml_item(item)))
+                        .collect(),
+                )
+            })
+            .collect(),
+    }
+}
+
+fn toml_value(value: &toml_edit::Value) -> serde_json::Value {
+    match value {
+        toml_edit::Value::String(value) => value.value().as_str().into(),
+        toml_edit::Value::Integer(value) => (*value.value()).into(),
+        toml_edit::Value::Float(value) => (*value.value()).into(),
+        toml_edit::Value::Boolean(value) => (*value.value()).into(),
+        toml_edit::Value::Datetime(value) => value.value().to_string().into(),
+        toml_edit::Value::Array(values) => values.iter().map(toml_value).collect(),
+        toml_edit::Value::InlineTable(table) => table
+            .iter()
+            .map(|(key, value)| (key.to_string(), toml_value(value)))
+            .collect(),
+    }
+}
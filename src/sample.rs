@@ -0,0 +1,165 @@
+use std::hash::{BuildHasher, Hash, Hasher, RandomState};
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use sha2::{Digest, Sha256};
+
+use crate::record::LogRecord;
+
+/// What identifies the records sampled together.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum SampleKey {
+    /// The address of the client, all the requests of the sampled clients are kept.
+    #[value(name = "client_ip")]
+    ClientIp,
+    /// The value of the `host` header.
+    Host,
+    /// The path of the request, without the query.
+    Path,
+    /// The value of the `User-Agent` header.
+    #[value(name = "user_agent")]
+    UserAgent,
+}
+
+/// Keep a random fraction of the records, or a fraction of the keys with all their records. The
+/// same keys are kept by every run, a key is sampled when its SHA-256 hash is below the ratio;
+/// without a key the records are hashed with a random seed, so each run keeps other records.
+#[derive(Clone, Debug)]
+pub struct Sample {
+    key: Option<SampleKey>,
+    /// The hashes below this threshold are kept.
+    threshold: u64,
+    seed: RandomState,
+}
+
+impl Sample {
+    pub fn new(key: Option<SampleKey>, ratio: f64) -> Self {
+        Self {
+            key,
+            threshold: (ratio * u64::MAX as f64) as u64,
+            seed: RandomState::new(),
+        }
+    }
+
+    /// Whether the record is kept, the records without the key are dropped.
+    pub fn matches(&self, record: &LogRecord) -> bool {
+        let Some(key) = self.key else {
+            let mut hasher = self.seed.build_hasher();
+            record.timestamp.to_bits().hash(&mut hasher);
+            record.message.hash(&mut hasher);
+            if let Some(request) = &record.request {
+                request.remote_ip.hash(&mut hasher);
+                request.uri.hash(&mut hasher);
+            }
+            return hasher.finish() <= self.threshold;
+        };
+        let Some(request) = &record.request else {
+            return false;
+        };
+        let digest = match key {
+            SampleKey::ClientIp => Sha256::digest(request.remote_ip.to_string().as_bytes()),
+            SampleKey::Host => Sha256::digest(request.host.as_bytes()),
+            SampleKey::Path => Sha256::digest(request.uri.split('?').next().unwrap().as_bytes()),
+            SampleKey::UserAgent => Sha256::digest(
+                request
+                    .headers
+                    .get(http::header::USER_AGENT)
+                    .map_or(&[][..], |value| value.as_bytes()),
+            ),
+        };
+        // the first 64 bits of the digest
+        u64::from_be_bytes(digest[..8].try_into().unwrap()) <= self.threshold
+    }
+}
+
+/// Parse the fraction of the sampled records, as a ratio like `1/100` or a number like `0.01`.
+pub fn parse_ratio(s: &str) -> Result<f64> {
+    let ratio = match s.split_once('/') {
+        Some((numerator, denominator)) => {
+            let numerator = numerator.trim().parse::<f64>();
+            let denominator = denominator.trim().parse::<f64>();
+            match (numerator, denominator) {
+                (Ok(numerator), Ok(denominator)) if denominator > 0.0 => numerator / denominator,
+                _ => bail!("invalid sample ratio: {}", s),
+            }
+        }
+        None => s
+            .parse::<f64>()
+            .with_context(|| format!("invalid sample ratio: {}", s))?,
+    };
+    if !(ratio > 0.0 && ratio <= 1.0) {
+        bail!(
+            "the sample ratio must be greater than 0 and at most 1: {}",
+            s
+        );
+    }
+    Ok(ratio)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn record(ip: &str, uri: &str, ts: f64) -> LogRecord {
+        let line = json!({
+            "level": "info",
+            "ts": ts,
+            "logger": "http.log.access",
+            "msg": "handled request",
+            "request": {
+                "remote_ip": ip,
+                "proto": "HTTP/1.1",
+                "method": "GET",
+                "host": "example.com",
+                "uri": uri,
+                "headers": {},
+            },
+            "duration": 0.25,
+            "size": 512,
+            "status": 200,
+            "resp_headers": {},
+        });
+        serde_json::from_value(line).unwrap()
+    }
+
+    #[test]
+    fn parse_the_ratios() {
+        assert_eq!(parse_ratio("1/100").unwrap(), 0.01);
+        assert_eq!(parse_ratio(" 1 / 4 ").unwrap(), 0.25);
+        assert_eq!(parse_ratio("0.5").unwrap(), 0.5);
+        assert_eq!(parse_ratio("1").unwrap(), 1.0);
+        for ratio in ["0", "2", "1/0", "-1/2", "half", "NaN"] {
+            assert!(parse_ratio(ratio).is_err(), "{ratio}");
+        }
+    }
+
+    #[test]
+    fn keep_all_the_records_of_a_key() {
+        let sample = Sample::new(Some(SampleKey::Path), 0.5);
+        let kept = (0..1000)
+            .filter(|path| {
+                let first = sample.matches(&record("10.0.0.1", &format!("/{path}?a"), 0.0));
+                // the other requests of the path have the same fate, in every run too
+                let other = Sample::new(Some(SampleKey::Path), 0.5);
+                let second = other.matches(&record("10.0.0.2", &format!("/{path}?b"), 1.0));
+                assert_eq!(first, second);
+                first
+            })
+            .count();
+        assert!((400..600).contains(&kept), "{kept}");
+
+        let sample = Sample::new(Some(SampleKey::ClientIp), 1.0);
+        assert!(sample.matches(&record("10.0.0.1", "/", 0.0)));
+    }
+
+    #[test]
+    fn keep_a_fraction_of_the_records() {
+        let sample = Sample::new(None, 0.1);
+        let kept = (0..10_000)
+            .filter(|&ts| sample.matches(&record("10.0.0.1", "/", ts as f64)))
+            .count();
+        assert!((800..1200).contains(&kept), "{kept}");
+    }
+}
@@ -0,0 +1,285 @@
+use std::str::FromStr;
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::syntax::SyntaxError;
+
+/// A jq-like path addressing a value inside a JSON document, e.g.
+/// `.request.headers["User-Agent"][0]`.
+#[derive(Clone, Debug)]
+pub struct Selector {
+    segments: Vec<Segment>,
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Key(String),
+    Index(i64),
+}
+
+impl Selector {
+    /// Select the value addressed by the path, if any.
+    pub fn select<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        self.segments
+            .iter()
+            .try_fold(value, |value, segment| match segment {
+                Segment::Key(key) => value.get(key),
+                Segment::Index(index) => {
+                    let values = value.as_array()?;
+                    let index = if *index < 0 {
+                        values.len().checked_sub(index.unsigned_abs() as usize)?
+                    } else {
+                        *index as usize
+                    };
+                    values.get(index)
+                }
+            })
+    }
+
+    /// Select the value and render it as plain text: strings are printed without quotes, other
+    /// values as compact JSON, missing values as an empty string.
+    pub fn extract(&self, value: &Value) -> String {
+        match self.select(value) {
+            None | Some(Value::Null) => String::new(),
+            Some(Value::String(value)) => value.clone(),
+            Some(value) => value.to_string(),
+        }
+    }
+
+    /// Remove the value addressed by the path and return it, the elements of the arrays are
+    /// replaced by null.
+    pub fn take(&self, value: &mut Value) -> Option<Value> {
+        let (last, parents) = self.segments.split_last()?;
+        let parent = parents
+            .iter()
+            .try_fold(value, |value, segment| match segment {
+                Segment::Key(key) => value.get_mut(key),
+                Segment::Index(index) => index_mut(value, *index),
+            })?;
+        match last {
+            Segment::Key(key) => parent.as_object_mut()?.remove(key),
+            Segment::Index(index) => index_mut(parent, *index).map(Value::take),
+        }
+    }
+
+    /// Set the value addressed by the path, the missing objects along the path are created.
+    /// Nothing is set when the path crosses a value which is not an object, or a missing
+    /// element of an array.
+    pub fn insert(&self, value: &mut Value, new: Value) {
+        let target = self
+            .segments
+            .iter()
+            .try_fold(value, |value, segment| match segment {
+                Segment::Key(key) => {
+                    if value.is_null() {
+                        *value = Value::Object(Default::default());
+                    }
+                    Some(
+                        value
+                            .as_object_mut()?
+                            .entry(key.clone())
+                            .or_insert(Value::Null),
+                    )
+                }
+                Segment::Index(index) => index_mut(value, *index),
+            });
+        if let Some(target) = target {
+            *target = new;
+        }
+    }
+
+    /// Parse the path at the start of the text, e.g. the left side of a condition. The length
+    /// of the path is returned with it.
+    pub fn parse_prefix(s: &str) -> Result<(Self, usize)> {
+        let mut parser = Parser { input: s, pos: 0 };
+        let selector = parser.path()?;
+        Ok((selector, parser.pos))
+    }
+}
+
+/// The element of the array at the index, counted from the end when negative.
+fn index_mut(value: &mut Value, index: i64) -> Option<&mut Value> {
+    let values = value.as_array_mut()?;
+    let index = if index < 0 {
+        values.len().checked_sub(index.unsigned_abs() as usize)?
+    } else {
+        index as usize
+    };
+    values.get_mut(index)
+}
+
+impl FromStr for Selector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parser = Parser { input: s, pos: 0 };
+        let selector = parser.path()?;
+        if parser.pos < s.len() {
+            parser.fail("expected '.' or '['")?;
+        }
+        Ok(selector)
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    /// The path ends before the first character which cannot continue it.
+    fn path(&mut self) -> Result<Selector> {
+        let mut segments = Vec::new();
+        if !self.eat('.') {
+            self.fail("expected '.'")?;
+        }
+        // the identity path `.` selects the whole document
+        if self.pos == self.input.len() || self.peek_is(' ') {
+            return Ok(Selector { segments });
+        }
+        if !self.peek_is('[') {
+            segments.push(self.key()?);
+        }
+        loop {
+            if self.eat('.') {
+                segments.push(self.key()?);
+            } else if self.eat('[') {
+                segments.push(self.bracket()?);
+            } else {
+                break;
+            }
+        }
+        Ok(Selector { segments })
+    }
+
+    fn key(&mut self) -> Result<Segment> {
+        if self.peek_is('"') {
+            return Ok(Segment::Key(self.string()?));
+        }
+        let rest = &self.input[self.pos..];
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            self.fail("expected a field name")?;
+        }
+        self.pos += len;
+        Ok(Segment::Key(rest[..len].to_string()))
+    }
+
+    fn bracket(&mut self) -> Result<Segment> {
+        let segment = if self.peek_is('"') {
+            Segment::Key(self.string()?)
+        } else {
+            let rest = &self.input[self.pos..];
+            let len = rest.find(']').unwrap_or(rest.len());
+            match rest[..len].trim().parse::<i64>() {
+                Ok(index) => {
+                    self.pos += len;
+                    Segment::Index(index)
+                }
+                Err(_) => self.fail("expected an array index or a quoted field name")?,
+            }
+        };
+        if !self.eat(']') {
+            self.fail("expected ']'")?;
+        }
+        Ok(segment)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let rest = &self.input[self.pos..];
+        let mut stream = serde_json::Deserializer::from_str(rest).into_iter::<String>();
+        match stream.next() {
+            Some(Ok(value)) => {
+                self.pos += stream.byte_offset();
+                Ok(value)
+            }
+            _ => self.fail("invalid quoted string"),
+        }
+    }
+
+    fn peek_is(&self, c: char) -> bool {
+        self.input[self.pos..].starts_with(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek_is(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn fail<T>(&self, message: &str) -> Result<T> {
+        Err(SyntaxError::new(message, self.input, self.pos).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn select(path: &str, value: &Value) -> Option<Value> {
+        path.parse::<Selector>().unwrap().select(value).cloned()
+    }
+
+    #[test]
+    fn select_keys_and_indexes() {
+        let value = json!({
+            "request": {
+                "headers": { "User-Agent": ["curl/8.4.0", "other"] },
+                "uri": "/"
+            },
+            "list": [1, 2, 3]
+        });
+        assert_eq!(select(".request.uri", &value), Some(json!("/")));
+        assert_eq!(
+            select(r#".request.headers["User-Agent"][0]"#, &value),
+            Some(json!("curl/8.4.0"))
+        );
+        assert_eq!(select(".list[-1]", &value), Some(json!(3)));
+        assert_eq!(select(".list[3]", &value), None);
+        assert_eq!(select(".list[-4]", &value), None);
+        assert_eq!(select(".missing.key", &value), None);
+        assert_eq!(select(".", &value), Some(value.clone()));
+    }
+
+    #[test]
+    fn take_and_insert() {
+        let mut value = json!({ "time": 1, "request": {} });
+        let time = ".time".parse::<Selector>().unwrap();
+        let ts = ".ts.value".parse::<Selector>().unwrap();
+        let taken = time.take(&mut value).unwrap();
+        ts.insert(&mut value, taken);
+        assert_eq!(value, json!({ "request": {}, "ts": { "value": 1 } }));
+    }
+
+    #[test]
+    fn parse_prefix() {
+        let (selector, len) = Selector::parse_prefix(".request.host == example").unwrap();
+        assert_eq!(len, ".request.host".len());
+        assert_eq!(
+            selector.select(&json!({ "request": { "host": "a" } })),
+            Some(&json!("a"))
+        );
+    }
+
+    #[test]
+    fn error_positions() {
+        let position = |path: &str| {
+            let err = path.parse::<Selector>().unwrap_err();
+            err.downcast_ref::<SyntaxError>().unwrap().position
+        };
+        assert_eq!(position("request"), 0);
+        assert_eq!(position(".request."), 9);
+        assert_eq!(position(".list[x]"), 6);
+        assert_eq!(position(".list[0"), 7);
+        assert_eq!(position(r#".headers["User-Agent]"#), 9);
+        assert_eq!(position(".request host"), 8);
+    }
+}
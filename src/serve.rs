@@ -0,0 +1,641 @@
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use clap::{CommandFactory, Parser};
+
+use crate::{
+    filters::Filters,
+    input::{InputArgs, Lines},
+    record::{FormatOptions, LogLevel, LogRecord},
+    units, FilterArgs,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct ServeArgs {
+    /// The address the web UI listens on.
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    bind: SocketAddr,
+
+    /// The most records kept in memory, and shown by the live pages, the oldest ones are dropped
+    /// when a followed log grows beyond them.
+    #[arg(long, value_name = "COUNT", default_value_t = 100_000)]
+    max_lines: usize,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    filters: FilterArgs,
+}
+
+/// The filters accepted as query parameters, the flags of the command line matching the records
+/// in memory. The ones reading files, like `--filter-file` or `--geoip-db`, are not available to
+/// the clients.
+#[derive(Debug, Parser)]
+#[command(no_binary_name = true)]
+struct Query {
+    /// Filter the records by `host` header value, the glob syntax can be used. The hosts
+    /// prefixed with `!` are hidden.
+    #[arg(long)]
+    host: Vec<String>,
+
+    /// Filter the records by response status, e.g. `404`, `4xx` or `!2xx`, multiple statuses as
+    /// a comma separated list.
+    #[arg(long, value_delimiter = ',')]
+    status: Vec<String>,
+
+    /// Filter the records by request method, e.g. `POST` or `!OPTIONS`.
+    #[arg(long)]
+    method: Vec<String>,
+
+    /// Filter the records by request URI, the glob syntax can be used, e.g. `/api/*`. The URIs
+    /// prefixed with `!` are hidden.
+    #[arg(long)]
+    uri: Vec<String>,
+
+    /// Show only the records at or above the given level.
+    #[arg(long, value_enum)]
+    level: Option<LogLevel>,
+
+    /// Show only the records logged at or after this time, an RFC 3339 timestamp or a duration
+    /// before now, e.g. `15m`.
+    #[arg(long)]
+    since: Option<String>,
+
+    /// Show only the records logged at or before this time, like `since`.
+    #[arg(long)]
+    until: Option<String>,
+}
+
+impl Query {
+    fn build(&self) -> Result<Filters> {
+        let mut filters = Filters::builder();
+        for host in &self.host {
+            filters.with_host(host)?;
+        }
+        for status in &self.status {
+            filters.with_status(status)?;
+        }
+        for method in &self.method {
+            filters.with_method(method)?;
+        }
+        for uri in &self.uri {
+            filters.with_uri(uri)?;
+        }
+        if let Some(level) = self.level {
+            filters.with_min_level(level);
+        }
+        let now = time::OffsetDateTime::now_utc();
+        if let Some(since) = &self.since {
+            filters.with_since(units::parse_time(since, now).context("invalid since")?);
+        }
+        if let Some(until) = &self.until {
+            filters.with_until(units::parse_time(until, now).context("invalid until")?);
+        }
+        filters.build()
+    }
+}
+
+/// A record read, parsed and formatted once for all the clients.
+struct Record {
+    line: String,
+    record: LogRecord,
+    text: String,
+}
+
+impl Record {
+    fn matches(&self, filters: &Filters) -> bool {
+        filters.matches_line(&self.line) && filters.matches(&self.record)
+    }
+}
+
+/// The last records read and the clients waiting for the new ones.
+struct State {
+    records: RwLock<VecDeque<Arc<Record>>>,
+    max_lines: usize,
+    subscribers: Mutex<Vec<Sender<Arc<Record>>>>,
+    connections: AtomicUsize,
+}
+
+impl State {
+    fn new(max_lines: usize) -> Self {
+        Self {
+            records: RwLock::default(),
+            max_lines,
+            subscribers: Mutex::default(),
+            connections: AtomicUsize::new(0),
+        }
+    }
+
+    fn read(&self, input: Lines, filters: Filters) {
+        let options = FormatOptions::default();
+        for line in input.map_while(Result::ok).map(|line| line.text) {
+            let Ok(record) = serde_json::from_str::<LogRecord>(&line) else {
+                continue;
+            };
+            if !filters.matches_line(&line) || !filters.matches(&record) {
+                continue;
+            }
+            let text = record.format(&options);
+            let record = Arc::new(Record { line, record, text });
+            self.subscribers
+                .lock()
+                .unwrap()
+                .retain(|subscriber| subscriber.send(Arc::clone(&record)).is_ok());
+            let mut records = self.records.write().unwrap();
+            if records.len() == self.max_lines {
+                records.pop_front();
+            }
+            records.push_back(record);
+        }
+    }
+
+    fn subscribe(&self) -> mpsc::Receiver<Arc<Record>> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+}
+
+/// The most clients served at the same time, the live pages keep their connection open.
+const MAX_CONNECTIONS: usize = 64;
+/// The time given to the clients to send their request.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// The time given to the clients to receive a response or an event.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+/// The largest request line and headers read.
+const MAX_REQUEST_SIZE: u64 = 16 * 1024;
+
+pub fn run(mut args: ServeArgs) -> Result<()> {
+    colored::control::set_override(false);
+
+    let filters = args.filters.build()?;
+    args.input.with_live();
+    let input = args.input.open(&filters)?;
+    if args.max_lines == 0 {
+        bail!("--max-lines must be greater than zero");
+    }
+    let state = Arc::new(State::new(args.max_lines));
+    {
+        let state = Arc::clone(&state);
+        std::thread::spawn(move || state.read(input, filters));
+    }
+
+    let listener = TcpListener::bind(args.bind)
+        .with_context(|| format!("failed to listen on {}", args.bind))?;
+    eprintln!("serving records on http://{}", listener.local_addr()?);
+    serve(listener, state);
+    Ok(())
+}
+
+/// Serve each client in its own thread, the clients beyond the limit are turned away.
+fn serve(listener: TcpListener, state: Arc<State>) {
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        if stream.set_read_timeout(Some(READ_TIMEOUT)).is_err()
+            || stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_err()
+        {
+            continue;
+        }
+        if state.connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            state.connections.fetch_sub(1, Ordering::SeqCst);
+            let _ = respond(stream, "503 Service Unavailable", "too many connections");
+            continue;
+        }
+        let state = Arc::clone(&state);
+        std::thread::spawn(move || {
+            if let Err(err) = handle(stream, &state) {
+                eprintln!("failed to serve request: {err:#}");
+            }
+            state.connections.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+}
+
+fn handle(stream: TcpStream, state: &State) -> Result<()> {
+    let request_line = match read_request(&stream) {
+        Ok(request_line) => request_line,
+        // the clients not sending their request in time are dropped
+        Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+            return Ok(());
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    if method != "GET" {
+        return respond(stream, "405 Method Not Allowed", "method not allowed");
+    }
+    let (pairs, filters) = match parse_query(query) {
+        Ok(parsed) => parsed,
+        Err(err) => return respond(stream, "400 Bad Request", &escape(&format!("{err:#}"))),
+    };
+    match path {
+        "/" => {
+            let page = render_page(query, &pairs, &filters, &state.records.read().unwrap());
+            respond(stream, "200 OK", &page)
+        }
+        "/live" => {
+            let page = LIVE_PAGE.replace("MAX_LINES", &state.max_lines.to_string());
+            respond(stream, "200 OK", &page)
+        }
+        "/events" => stream_events(stream, state, &filters),
+        _ => respond(stream, "404 Not Found", "not found"),
+    }
+}
+
+/// The request line, the headers are not used but they have to be consumed.
+fn read_request(stream: &TcpStream) -> io::Result<String> {
+    let mut reader = BufReader::new(stream).take(MAX_REQUEST_SIZE);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    Ok(request_line)
+}
+
+/// Send the new records as server-sent events, until the client disconnects.
+fn stream_events(mut stream: TcpStream, state: &State, filters: &Filters) -> Result<()> {
+    const KEEPALIVE: Duration = Duration::from_secs(15);
+
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n"
+    )?;
+    stream.flush()?;
+
+    let receiver = state.subscribe();
+    loop {
+        match receiver.recv_timeout(KEEPALIVE) {
+            Ok(record) => {
+                if record.matches(filters) {
+                    let data = serde_json::to_string(&record.text)?;
+                    write!(stream, "data: {data}\n\n")?;
+                }
+            }
+            // a comment is sent to detect the disconnected clients
+            Err(RecvTimeoutError::Timeout) => write!(stream, ": keepalive\n\n")?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        stream.flush()?;
+    }
+}
+
+fn respond(mut stream: TcpStream, status: &str, body: &str) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Convert the query parameters in command line flags and parse them.
+fn parse_query(query: &str) -> Result<(Vec<(String, String)>, Filters)> {
+    let pairs = form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .filter(|(_, value)| !value.is_empty())
+        .collect::<Vec<_>>();
+
+    let command = Query::command();
+    let mut flags = Vec::new();
+    for (key, value) in &pairs {
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == key.as_str())
+            .with_context(|| format!("unknown filter: {}", key))?;
+        flags.push(format!("--{}", arg.get_long().unwrap_or(key)));
+        if arg.get_action().takes_values() {
+            flags.push(value.clone());
+        }
+    }
+    let query = Query::try_parse_from(flags)?;
+    Ok((pairs, query.build()?))
+}
+
+fn render_page(
+    query: &str,
+    pairs: &[(String, String)],
+    filters: &Filters,
+    records: &VecDeque<Arc<Record>>,
+) -> String {
+    let total = records.len();
+    let records = records
+        .iter()
+        .filter(|record| record.matches(filters))
+        .map(|record| format!("<pre>{}</pre>\n", escape(&record.text)))
+        .collect::<Vec<_>>();
+
+    let mut inputs = String::new();
+    for arg in Query::command().get_arguments() {
+        let id = arg.get_id().as_str();
+        let help = arg
+            .get_help()
+            .map(|help| help.to_string())
+            .unwrap_or_default();
+        let values = pairs
+            .iter()
+            .filter(|(key, _)| key == id)
+            .map(|(_, value)| value.as_str());
+        if arg.get_action().takes_values() {
+            // an empty input is always available to add one more value
+            for value in values.chain([""]) {
+                inputs.push_str(&format!(
+                    "<label title=\"{}\">{id} <input name=\"{id}\" value=\"{}\"></label>\n",
+                    escape(&help),
+                    escape(value)
+                ));
+            }
+        } else {
+            let checked = if values.count() > 0 { " checked" } else { "" };
+            inputs.push_str(&format!(
+                "<label title=\"{}\">{id} <input type=\"checkbox\" name=\"{id}\" value=\"on\"{checked}></label>\n",
+                escape(&help)
+            ));
+        }
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>caddy-pretty-print</title>
+<style>
+body {{ font-family: sans-serif; margin: 1em; }}
+form label {{ margin-right: 1em; white-space: nowrap; }}
+pre {{ border-bottom: 1px solid #ddd; padding-bottom: 0.5em; }}
+</style>
+</head>
+<body>
+<form method="get">
+{inputs}<button type="submit">Filter</button>
+<a href="/live?{query}">live</a>
+</form>
+<p>{count} of {total} records</p>
+{records}</body>
+</html>
+"#,
+        query = escape(query),
+        count = records.len(),
+        records = records.concat(),
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const LIVE_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>caddy-pretty-print</title>
+<style>
+body { font-family: sans-serif; margin: 1em; }
+pre { border-bottom: 1px solid #ddd; padding-bottom: 0.5em; }
+</style>
+</head>
+<body>
+<button id="pause">Pause</button>
+<input id="search" placeholder="Filter text">
+<span id="status"></span>
+<div id="records"></div>
+<script>
+const records = document.getElementById("records");
+const search = document.getElementById("search");
+const pause = document.getElementById("pause");
+const status = document.getElementById("status");
+// the page keeps no more records than the server, the oldest ones are dropped
+const maxLines = MAX_LINES;
+let paused = false;
+let pending = [];
+
+function matches(element) {
+  return element.textContent.toLowerCase().includes(search.value.toLowerCase());
+}
+
+function append(text) {
+  const element = document.createElement("pre");
+  element.textContent = text;
+  element.hidden = !matches(element);
+  records.appendChild(element);
+  while (records.childElementCount > maxLines) {
+    records.firstElementChild.remove();
+  }
+  window.scrollTo(0, document.body.scrollHeight);
+}
+
+search.addEventListener("input", () => {
+  for (const element of records.children) {
+    element.hidden = !matches(element);
+  }
+});
+
+pause.addEventListener("click", () => {
+  paused = !paused;
+  pause.textContent = paused ? "Resume" : "Pause";
+  if (!paused) {
+    pending.forEach(append);
+    pending = [];
+  }
+  status.textContent = "";
+});
+
+const events = new EventSource("/events" + window.location.search);
+events.onmessage = (event) => {
+  const text = JSON.parse(event.data);
+  if (paused) {
+    pending.push(text);
+    if (pending.length > maxLines) {
+      pending.shift();
+    }
+    status.textContent = pending.length + " new records";
+  } else {
+    append(text);
+  }
+};
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use crate::input::{Line, Position};
+
+    use super::*;
+
+    fn record(host: &str, status: u16) -> String {
+        format!(
+            r#"{{"level":"info","ts":1710000000.5,"logger":"http.log.access","msg":"handled request","request":{{"remote_ip":"10.0.0.5","proto":"HTTP/1.1","method":"GET","host":"{host}","uri":"/<script>","headers":{{}}}},"status":{status}}}"#
+        )
+    }
+
+    fn input(lines: Vec<String>) -> Lines {
+        Box::new(lines.into_iter().enumerate().map(|(index, text)| {
+            Ok(Line {
+                source: None,
+                position: Position {
+                    path: "access.log".into(),
+                    number: index as u64 + 1,
+                },
+                text,
+            })
+        }))
+    }
+
+    /// A server with the records in memory, the address is returned.
+    fn start(state: Arc<State>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || serve(listener, state));
+        address
+    }
+
+    fn get(address: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn parse_the_filters_of_the_query() {
+        let (pairs, filters) = parse_query("host=shop.*&status=4xx&uri=").unwrap();
+        assert_eq!(
+            pairs,
+            [
+                ("host".to_string(), "shop.*".to_string()),
+                ("status".to_string(), "4xx".to_string()),
+            ]
+        );
+        let parse = |line: String| serde_json::from_str::<LogRecord>(&line).unwrap();
+        assert!(filters.matches(&parse(record("shop.example.com", 404))));
+        assert!(!filters.matches(&parse(record("shop.example.com", 200))));
+        assert!(!filters.matches(&parse(record("api.example.com", 404))));
+        assert!(parse_query("geoip_db=/etc/passwd").is_err());
+        assert!(parse_query("status=abc").is_err());
+    }
+
+    #[test]
+    fn keep_the_last_records() {
+        let state = State::new(2);
+        let lines = vec![
+            record("a.example.com", 200),
+            "not a record".to_string(),
+            record("b.example.com", 404),
+            record("c.example.com", 500),
+        ];
+        state.read(input(lines), Filters::builder().build().unwrap());
+        let records = state.records.read().unwrap();
+        let hosts = records
+            .iter()
+            .map(|record| record.record.request.as_ref().unwrap().host.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(hosts, ["b.example.com", "c.example.com"]);
+    }
+
+    #[test]
+    fn serve_the_pages() {
+        let state = Arc::new(State::new(10));
+        let lines = vec![record("a.example.com", 200), record("b.example.com", 404)];
+        state.read(input(lines), Filters::builder().build().unwrap());
+        let address = start(state);
+
+        let page = get(
+            address,
+            "GET /?status=404 HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        );
+        assert!(page.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(page.contains("<p>1 of 2 records</p>"));
+        assert!(page.contains("b.example.com"));
+        assert!(!page.contains("a.example.com"));
+        // the records are escaped
+        assert!(page.contains("/&lt;script&gt;"));
+
+        let page = get(address, "GET /live HTTP/1.1\r\n\r\n");
+        assert!(page.contains("const maxLines = 10;"));
+        let page = get(address, "GET /?status=abc HTTP/1.1\r\n\r\n");
+        assert!(page.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        let page = get(address, "POST / HTTP/1.1\r\n\r\n");
+        assert!(page.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        let page = get(address, "GET /missing HTTP/1.1\r\n\r\n");
+        assert!(page.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn stream_the_new_records() {
+        let state = Arc::new(State::new(10));
+        let address = start(Arc::clone(&state));
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .write_all(b"GET /events?status=5xx HTTP/1.1\r\n\r\n")
+            .unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut header = String::new();
+        while reader.read_line(&mut header).unwrap() > 2 {
+            header.clear();
+        }
+        // the client is subscribed once the headers are sent
+        while state.subscribers.lock().unwrap().is_empty() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let lines = vec![record("a.example.com", 200), record("b.example.com", 503)];
+        state.read(input(lines), Filters::builder().build().unwrap());
+        let mut event = String::new();
+        reader.read_line(&mut event).unwrap();
+        let data = event.strip_prefix("data: ").unwrap();
+        let text = serde_json::from_str::<String>(data).unwrap();
+        assert!(text.contains("b.example.com"));
+    }
+
+    #[test]
+    fn turn_away_the_clients_beyond_the_limit() {
+        let state = Arc::new(State::new(10));
+        let address = start(Arc::clone(&state));
+        let idle = (0..MAX_CONNECTIONS)
+            .map(|_| TcpStream::connect(address).unwrap())
+            .collect::<Vec<_>>();
+        while state.connections.load(Ordering::SeqCst) < MAX_CONNECTIONS {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        // the request is not read, the response is sent right away
+        let mut response = String::new();
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+
+        // the connections are released when the clients go away
+        drop(idle);
+        while state.connections.load(Ordering::SeqCst) > 0 {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let response = get(address, "GET / HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+}
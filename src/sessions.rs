@@ -0,0 +1,429 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+
+use crate::{
+    input::InputArgs,
+    inspect::ReportOutput,
+    locale::Locale,
+    record::{LogRecord, RemoteIp},
+    stats::{self, format_table},
+    units, FilterArgs,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct SessionsArgs {
+    /// The inactivity of a client which ends its session, e.g. `30m`.
+    #[arg(long, value_name = "DURATION", default_value = "30m")]
+    gap: String,
+
+    /// The number of entry paths, exit paths and flows reported.
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    top: usize,
+
+    /// Count every request in the sessions. By default only the pages are counted: the
+    /// successful `GET` requests of HTML documents or of paths without an extension.
+    #[arg(long)]
+    all_requests: bool,
+
+    /// Render the numbers with the conventions of a locale (e.g. `de_DE`), or of the locale of
+    /// the environment (`LC_ALL` or `LANG`) when no value is given.
+    #[arg(
+        long,
+        value_name = "LOCALE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    locale: Option<String>,
+
+    /// How the report is printed.
+    #[arg(long, default_value = "pretty")]
+    output: ReportOutput,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    filters: FilterArgs,
+}
+
+/// Group the requests of each client in sessions, and report their durations, the paths where
+/// they start and end, and the most common flows between pages.
+pub fn run(args: SessionsArgs) -> Result<()> {
+    let gap = units::parse_duration(&args.gap).context("invalid --gap")?;
+    let filters = args.filters.build()?;
+    let locale = Locale::from_arg(args.locale.as_deref())?;
+    let input = args.input.open(&filters)?;
+
+    let mut sessions = Sessions::new(gap.as_secs_f64());
+    for line in input {
+        let line = line?;
+        if !filters.matches_line(&line.text) {
+            continue;
+        }
+        let Ok(record) = serde_json::from_str::<LogRecord>(&line.text) else {
+            continue;
+        };
+        if !filters.matches(&record) || !(args.all_requests || is_page(&record)) {
+            continue;
+        }
+        sessions.observe(&record);
+    }
+    sessions.close_all();
+    match args.output {
+        ReportOutput::Pretty => print!("{}", sessions.report(args.top, &locale)),
+        ReportOutput::Json => println!("{}", sessions.to_json(args.top)),
+    }
+    Ok(())
+}
+
+/// Whether the request is a page view: a successful `GET` of an HTML document, or of a path
+/// without an extension.
+fn is_page(record: &LogRecord) -> bool {
+    let Some(request) = &record.request else {
+        return false;
+    };
+    if request.method != http::Method::GET
+        || !record.status.is_some_and(|status| status.is_success())
+    {
+        return false;
+    }
+    let path = request.uri.split('?').next().unwrap_or_default();
+    let name = path.rsplit('/').next().unwrap_or_default();
+    match name.rsplit_once('.') {
+        None => true,
+        Some((_, extension)) => matches!(
+            extension.to_ascii_lowercase().as_str(),
+            "html" | "htm" | "php"
+        ),
+    }
+}
+
+/// A client, told apart by its address and its user agent.
+type Client = (RemoteIp, String);
+
+/// A session still open, which ends after the gap.
+struct Session {
+    start: f64,
+    last: f64,
+    pages: u64,
+    entry: String,
+    /// The last three paths visited, the most recent last.
+    recent: Vec<String>,
+}
+
+/// The sessions of the clients, the ended ones are summarized as they end.
+struct Sessions {
+    gap: f64,
+    open: HashMap<Client, Session>,
+    sessions: u64,
+    pages: u64,
+    bounces: u64,
+    durations: Vec<f64>,
+    entries: HashMap<String, u64>,
+    exits: HashMap<String, u64>,
+    /// The sequences of two and three pages visited one after the other.
+    flows: HashMap<Vec<String>, u64>,
+}
+
+impl Sessions {
+    /// The open sessions are pruned when there are more, the ended ones are closed.
+    const MAX_OPEN: usize = 100_000;
+    /// The paths and the flows seen after this many are ignored, to bound the memory of the
+    /// logs with unique paths.
+    const MAX_PATHS: usize = 10_000;
+    /// The percentiles of the duration of the sessions.
+    const PERCENTILES: [f64; 3] = [50.0, 90.0, 99.0];
+
+    fn new(gap: f64) -> Self {
+        Self {
+            gap,
+            open: HashMap::new(),
+            sessions: 0,
+            pages: 0,
+            bounces: 0,
+            durations: Vec::new(),
+            entries: HashMap::new(),
+            exits: HashMap::new(),
+            flows: HashMap::new(),
+        }
+    }
+
+    fn observe(&mut self, record: &LogRecord) {
+        let Some(request) = &record.request else {
+            return;
+        };
+        let timestamp = record.timestamp;
+        if self.open.len() >= Self::MAX_OPEN {
+            let ended = self
+                .open
+                .iter()
+                .filter(|(_, session)| timestamp - session.last > self.gap)
+                .map(|(client, _)| client.clone())
+                .collect::<Vec<_>>();
+            for client in ended {
+                if let Some(session) = self.open.remove(&client) {
+                    self.close(session);
+                }
+            }
+        }
+        let user_agent = request
+            .headers
+            .get(http::header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let client = (request.remote_ip, user_agent.to_string());
+        let path = request.uri.split('?').next().unwrap_or_default();
+
+        if let Some(session) = self.open.get(&client) {
+            if timestamp - session.last > self.gap {
+                let session = self.open.remove(&client).expect("the session is open");
+                self.close(session);
+            }
+        }
+        let Some(session) = self.open.get_mut(&client) else {
+            self.open.insert(
+                client,
+                Session {
+                    start: timestamp,
+                    last: timestamp,
+                    pages: 1,
+                    entry: path.to_string(),
+                    recent: vec![path.to_string()],
+                },
+            );
+            return;
+        };
+        // the records of multiple sources may be slightly out of order
+        session.last = session.last.max(timestamp);
+        session.pages += 1;
+        // a page reloaded is not a step of the flow
+        if session.recent.last().is_some_and(|last| last == path) {
+            return;
+        }
+        session.recent.push(path.to_string());
+        if session.recent.len() > 3 {
+            session.recent.remove(0);
+        }
+        let steps = session.recent.len();
+        count(&mut self.flows, session.recent[steps - 2..].to_vec());
+        if steps == 3 {
+            count(&mut self.flows, session.recent.clone());
+        }
+    }
+
+    fn close(&mut self, session: Session) {
+        self.sessions += 1;
+        self.pages += session.pages;
+        if session.pages == 1 {
+            self.bounces += 1;
+        }
+        self.durations.push(session.last - session.start);
+        count(&mut self.entries, session.entry);
+        if let Some(exit) = session.recent.last() {
+            count(&mut self.exits, exit.clone());
+        }
+    }
+
+    fn close_all(&mut self) {
+        let open = std::mem::take(&mut self.open);
+        for session in open.into_values() {
+            self.close(session);
+        }
+        self.durations.sort_by(f64::total_cmp);
+    }
+
+    fn pages_per_session(&self) -> f64 {
+        self.pages as f64 / self.sessions.max(1) as f64
+    }
+
+    fn bounce_rate(&self) -> f64 {
+        self.bounces as f64 / self.sessions.max(1) as f64
+    }
+
+    fn report(&self, top: usize, locale: &Locale) -> String {
+        let mut output = format!(
+            "{} sessions, {} pages per session, {}% bounced\n\n",
+            locale.integer(self.sessions),
+            locale.decimal(self.pages_per_session(), 1),
+            locale.decimal(self.bounce_rate() * 100.0, 1)
+        );
+        let mut header = vec!["session duration".to_string()];
+        let mut durations = vec![String::new()];
+        for percentile in Self::PERCENTILES {
+            header.push(format!("p{percentile}"));
+            let duration = stats::percentile_of(&self.durations, percentile)
+                .map(|duration| locale.localize(&LogRecord::format_duration(duration)))
+                .unwrap_or_else(|| "-".into());
+            durations.push(duration);
+        }
+        output.push_str(&format_table(&[header, durations]));
+
+        for (name, paths) in [("entry path", &self.entries), ("exit path", &self.exits)] {
+            let mut rows = vec![vec![name.to_string(), "sessions".into(), "share".into()]];
+            for (path, count) in ranked(paths, top) {
+                let share = 100.0 * *count as f64 / self.sessions.max(1) as f64;
+                rows.push(vec![
+                    path.clone(),
+                    locale.integer(*count),
+                    format!("{}%", locale.decimal(share, 1)),
+                ]);
+            }
+            output.push('\n');
+            output.push_str(&format_table(&rows));
+        }
+
+        let mut rows = vec![vec!["count".to_string()]];
+        let mut flows = Vec::new();
+        for (flow, count) in ranked(&self.flows, top) {
+            rows.push(vec![locale.integer(*count)]);
+            flows.push(flow.join(" → "));
+        }
+        // the flows are appended after the aligned column, they have variable length
+        let table = format_table(&rows);
+        let mut lines = table.lines();
+        output.push_str(&format!("\n{}  flow\n", lines.next().unwrap_or_default()));
+        for (line, flow) in lines.zip(flows) {
+            output.push_str(&format!("{line}  {flow}\n"));
+        }
+        output
+    }
+
+    /// The summary of the sessions, the durations are in seconds.
+    fn to_json(&self, top: usize) -> Value {
+        let paths = |paths: &HashMap<String, u64>| {
+            ranked(paths, top)
+                .into_iter()
+                .map(|(path, count)| json!({ "path": path, "sessions": count }))
+                .collect::<Vec<_>>()
+        };
+        let flows = ranked(&self.flows, top)
+            .into_iter()
+            .map(|(flow, count)| json!({ "steps": flow, "count": count }))
+            .collect::<Vec<_>>();
+        let durations = Self::PERCENTILES
+            .into_iter()
+            .map(|p| {
+                let duration = stats::percentile_of(&self.durations, p);
+                (format!("p{p}"), json!(duration))
+            })
+            .collect::<serde_json::Map<_, _>>();
+        json!({
+            "sessions": self.sessions,
+            "pages": self.pages,
+            "pages_per_session": self.pages_per_session(),
+            "bounce_rate": self.bounce_rate(),
+            "duration": durations,
+            "entries": paths(&self.entries),
+            "exits": paths(&self.exits),
+            "flows": flows,
+        })
+    }
+}
+
+/// Count the key, the new keys are ignored when there are too many.
+fn count<K: std::hash::Hash + Eq>(counts: &mut HashMap<K, u64>, key: K) {
+    if counts.len() >= Sessions::MAX_PATHS && !counts.contains_key(&key) {
+        return;
+    }
+    *counts.entry(key).or_default() += 1;
+}
+
+/// The most frequent keys, the ties are sorted by key.
+fn ranked<K: Ord>(counts: &HashMap<K, u64>, top: usize) -> Vec<(&K, &u64)> {
+    let mut counts = counts.iter().collect::<Vec<_>>();
+    counts.sort_by(|(a_key, a), (b_key, b)| b.cmp(a).then(a_key.cmp(b_key)));
+    counts.truncate(top);
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(ip: &str, ts: f64, method: &str, uri: &str, status: u16) -> LogRecord {
+        let line = json!({
+            "level": "info",
+            "ts": ts,
+            "logger": "http.log.access",
+            "msg": "handled request",
+            "request": {
+                "remote_ip": ip,
+                "proto": "HTTP/1.1",
+                "method": method,
+                "host": "example.com",
+                "uri": uri,
+                "headers": { "User-Agent": ["Firefox"] },
+            },
+            "duration": 0.01,
+            "size": 512,
+            "status": status,
+            "resp_headers": {},
+        });
+        serde_json::from_value(line).unwrap()
+    }
+
+    #[test]
+    fn tell_the_pages() {
+        let page = |method, uri, status| is_page(&record("10.0.0.1", 0.0, method, uri, status));
+        assert!(page("GET", "/", 200));
+        assert!(page("GET", "/blog/post?page=2", 200));
+        assert!(page("GET", "/index.HTML", 200));
+        assert!(!page("GET", "/style.css", 200));
+        assert!(!page("GET", "/missing", 404));
+        assert!(!page("POST", "/login", 200));
+    }
+
+    #[test]
+    fn split_the_sessions_after_the_gap() {
+        let mut sessions = Sessions::new(1800.0);
+        let visits = [
+            ("10.0.0.1", 0.0, "/"),
+            ("10.0.0.1", 60.0, "/blog"),
+            // a reload is a page, not a step of the flow
+            ("10.0.0.1", 70.0, "/blog"),
+            ("10.0.0.1", 120.0, "/blog/post"),
+            ("10.0.0.2", 100.0, "/blog"),
+            ("10.0.0.1", 4000.0, "/"),
+        ];
+        for (ip, ts, uri) in visits {
+            sessions.observe(&record(ip, ts, "GET", uri, 200));
+        }
+        sessions.close_all();
+        let summary = sessions.to_json(10);
+        assert_eq!(summary["sessions"], 3);
+        assert_eq!(summary["pages"], 6);
+        assert_eq!(summary["pages_per_session"], 2.0);
+        assert_eq!(summary["bounce_rate"], 2.0 / 3.0);
+        assert_eq!(summary["duration"]["p50"], 0.0);
+        assert_eq!(summary["duration"]["p99"], 120.0);
+        assert_eq!(
+            summary["entries"],
+            json!([{ "path": "/", "sessions": 2 }, { "path": "/blog", "sessions": 1 }])
+        );
+        assert_eq!(
+            summary["exits"],
+            json!([
+                { "path": "/", "sessions": 1 },
+                { "path": "/blog", "sessions": 1 },
+                { "path": "/blog/post", "sessions": 1 },
+            ])
+        );
+        assert_eq!(
+            summary["flows"],
+            json!([
+                { "steps": ["/", "/blog"], "count": 1 },
+                { "steps": ["/", "/blog", "/blog/post"], "count": 1 },
+                { "steps": ["/blog", "/blog/post"], "count": 1 },
+            ])
+        );
+    }
+
+    #[test]
+    fn rank_the_counts() {
+        let counts = HashMap::from([("b", 2), ("a", 2), ("c", 5), ("d", 1)]);
+        assert_eq!(ranked(&counts, 3), [(&"c", &5), (&"a", &2), (&"b", &2)]);
+    }
+}
@@ -0,0 +1,161 @@
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use colored::ColoredString;
+use serde::Deserialize;
+
+use crate::{filters::StatusPattern, palette, record::LogRecord};
+
+/// How bad the response of a request is. By default it follows the class of its status, it is
+/// changed by the `severity` section of the config file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// The informational and the successful responses.
+    Success,
+    Redirect,
+    ClientError,
+    ServerError,
+    /// The requests abandoned by their clients, e.g. the 499 of the proxies, they are not
+    /// counted as errors.
+    ClientAbort,
+}
+
+impl Severity {
+    fn of_status(status: http::StatusCode) -> Self {
+        match status.as_u16() / 100 {
+            3 => Self::Redirect,
+            4 => Self::ClientError,
+            5 => Self::ServerError,
+            _ => Self::Success,
+        }
+    }
+
+    pub fn is_error(self) -> bool {
+        matches!(self, Self::ClientError | Self::ServerError)
+    }
+
+    /// Color a text, like the status, by the severity.
+    pub fn colorize(self, text: &str) -> ColoredString {
+        palette::severity(self, text)
+    }
+}
+
+/// The severities of the statuses changed by the `severity` section of the config file, e.g.
+///
+/// ```yaml
+/// severity:
+///   - status: 499
+///     severity: client_abort
+///   - status: 404
+///     uri: /favicon.ico
+///     severity: success
+/// ```
+///
+/// The requests are matched against the rules in order and the first matching one sets the
+/// severity, the class of the status sets it when none matches.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(try_from = "Vec<RuleConfig>")]
+pub struct SeverityMap {
+    rules: Vec<Rule>,
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    statuses: Vec<StatusPattern>,
+    /// The path of the request, without the query.
+    uri: Option<glob::Pattern>,
+    host: Option<glob::Pattern>,
+    severity: Severity,
+}
+
+/// A rule as written in the config file, the statuses are codes or classes like `4xx`
+/// separated by commas.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    status: StatusConfig,
+    uri: Option<String>,
+    host: Option<String>,
+    severity: Severity,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StatusConfig {
+    Code(u16),
+    Text(String),
+}
+
+impl TryFrom<Vec<RuleConfig>> for SeverityMap {
+    type Error = anyhow::Error;
+
+    fn try_from(config: Vec<RuleConfig>) -> Result<Self> {
+        let glob = |pattern: Option<String>| {
+            pattern
+                .map(|pattern| {
+                    glob::Pattern::new(&pattern)
+                        .with_context(|| format!("invalid severity pattern: {}", pattern))
+                })
+                .transpose()
+        };
+        let rules = config
+            .into_iter()
+            .map(|rule| {
+                let statuses = match rule.status {
+                    StatusConfig::Code(code) => code.to_string(),
+                    StatusConfig::Text(text) => text,
+                };
+                Ok(Rule {
+                    statuses: statuses
+                        .split(',')
+                        .map(|status| StatusPattern::parse(status.trim()))
+                        .collect::<Result<_>>()?,
+                    uri: glob(rule.uri)?,
+                    host: glob(rule.host)?,
+                    severity: rule.severity,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { rules })
+    }
+}
+
+impl SeverityMap {
+    fn severity(&self, record: &LogRecord) -> Option<Severity> {
+        let status = record.status?;
+        let rule = self.rules.iter().find(|rule| {
+            rule.statuses
+                .iter()
+                .any(|pattern| pattern.matches(status.as_u16()))
+                && rule.uri.as_ref().is_none_or(|uri| {
+                    record.request.as_ref().is_some_and(|request| {
+                        uri.matches(request.uri.split('?').next().unwrap_or_default())
+                    })
+                })
+                && rule.host.as_ref().is_none_or(|host| {
+                    record
+                        .request
+                        .as_ref()
+                        .is_some_and(|request| host.matches(&request.host))
+                })
+        });
+        Some(rule.map_or_else(|| Severity::of_status(status), |rule| rule.severity))
+    }
+}
+
+/// The map shared by all the outputs and the statistics, so they agree on what an error is.
+static SEVERITY_MAP: OnceLock<SeverityMap> = OnceLock::new();
+
+/// Set the map of the severities, once when the config is loaded.
+pub fn install(map: SeverityMap) {
+    let _ = SEVERITY_MAP.set(map);
+}
+
+/// The severity of the response of the request, none for the records without a status.
+pub fn of(record: &LogRecord) -> Option<Severity> {
+    match SEVERITY_MAP.get() {
+        Some(map) => map.severity(record),
+        None => record.status.map(Severity::of_status),
+    }
+}
@@ -0,0 +1,1135 @@
+use std::{
+    cell::OnceCell,
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use colored::Colorize;
+use serde_json::Value;
+use time::OffsetDateTime;
+
+#[cfg(unix)]
+use crate::journald::JournaldSink;
+use crate::{
+    aggregate::{AggregateSink, Aggregation, Format},
+    anomaly::{HeaderBaseline, LatencyBaseline},
+    budgets::BudgetChecks,
+    input::Position,
+    json,
+    limited::{self, RateLimitedClients},
+    management,
+    ndjson::NdjsonSink,
+    record::{self, Detail, FormatOptions, LogRecord},
+    retries::Retries,
+    selector::Selector,
+    severity::Severity,
+};
+
+/// A parsed log line, as seen by the sinks.
+pub struct Entry<'a> {
+    pub line: &'a str,
+    record: Option<&'a LogRecord>,
+    /// The label of the source, when multiple sources are read.
+    pub source: Option<&'a str>,
+    /// Where the line is in its file.
+    pub position: Option<&'a Position>,
+    parsed: OnceCell<LogRecord>,
+    value: OnceCell<Value>,
+}
+
+impl<'a> Entry<'a> {
+    pub fn new(line: &'a str, record: &'a LogRecord, source: Option<&'a str>) -> Self {
+        Self {
+            line,
+            record: Some(record),
+            source,
+            position: None,
+            parsed: OnceCell::new(),
+            value: OnceCell::new(),
+        }
+    }
+
+    /// An entry whose record has not been fully parsed, it is given only to the sinks which do
+    /// not need the record.
+    pub fn partial(line: &'a str, source: Option<&'a str>) -> Self {
+        Self {
+            line,
+            record: None,
+            source,
+            position: None,
+            parsed: OnceCell::new(),
+            value: OnceCell::new(),
+        }
+    }
+
+    /// Set where the line is in its file.
+    pub fn at(mut self, position: &'a Position) -> Self {
+        self.position = Some(position);
+        self
+    }
+
+    /// The parsed record, the record of a partial entry is parsed on first use.
+    pub fn record(&self) -> Result<&LogRecord> {
+        if let Some(record) = self.record {
+            return Ok(record);
+        }
+        if let Some(record) = self.parsed.get() {
+            return Ok(record);
+        }
+        let record =
+            serde_json::from_str(self.line).context("invalid record of a partial entry")?;
+        Ok(self.parsed.get_or_init(|| record))
+    }
+
+    /// The original JSON document, parsed on first use.
+    pub fn value(&self) -> Result<&Value> {
+        if let Some(value) = self.value.get() {
+            return Ok(value);
+        }
+        let value = serde_json::from_str(self.line).context("invalid JSON document of an entry")?;
+        Ok(self.value.get_or_init(|| value))
+    }
+}
+
+/// A destination for the matched records.
+pub trait Sink {
+    /// Write a record that passed the filters.
+    fn write(&mut self, entry: &Entry) -> Result<()>;
+
+    /// Whether the sink reads the parsed record, otherwise it is given partial entries when the
+    /// filters allow it, which saves the parsing of the whole record.
+    fn needs_record(&self) -> bool {
+        true
+    }
+
+    /// Whether the records are written as they come, unlike the reports written when the input
+    /// ends. The discovered log is followed only when all the sinks are live.
+    fn is_live(&self) -> bool {
+        true
+    }
+
+    /// Write a line that cannot be parsed as a log record, by default it is discarded.
+    fn write_unparsed(&mut self, _line: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called once the input is exhausted.
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum Output {
+    /// Human readable summary of each record.
+    #[default]
+    Pretty,
+    /// Original JSON record, one per line.
+    Json,
+    /// Original JSON record, indented and highlighted.
+    JsonPretty,
+    /// Normalized JSON record, one per line, with a versioned schema whatever the logging
+    /// options of Caddy.
+    Ndjson,
+    /// Request counters in Prometheus text format, written when the input ends.
+    Prometheus,
+    /// Structured entries sent to the systemd journal, the path is the socket of the journal.
+    Journald,
+    /// Aggregated requests in CSV format, it requires `--aggregate`.
+    Csv,
+    /// Aggregated requests in InfluxDB line protocol, it requires `--aggregate`.
+    Influx,
+}
+
+/// A sink specification in the form `OUTPUT[:PATH]`, without a path the sink writes on the
+/// standard output.
+#[derive(Clone, Debug)]
+pub struct SinkSpec {
+    output: Output,
+    path: Option<PathBuf>,
+    compression: Option<Compression>,
+    aggregation: Option<Aggregation>,
+}
+
+/// The compression of the files written by the sinks.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl FromStr for SinkSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (output, path) = match s.split_once(':') {
+            Some((output, path)) => (output, Some(PathBuf::from(path))),
+            None => (s, None),
+        };
+        let Ok(output) = Output::from_str(output, true) else {
+            bail!("unknown output: {}", output);
+        };
+        if matches!(path, Some(ref path) if path.as_os_str().is_empty()) {
+            bail!("empty path in sink: {}", s);
+        }
+        Ok(Self {
+            output,
+            path,
+            compression: None,
+            aggregation: None,
+        })
+    }
+}
+
+impl SinkSpec {
+    pub fn stdout(output: Output) -> Self {
+        Self {
+            output,
+            path: None,
+            compression: None,
+            aggregation: None,
+        }
+    }
+
+    /// Compress the file written by the sink, the standard output is never compressed.
+    pub fn compressed(self, compression: Option<Compression>) -> Self {
+        Self {
+            compression,
+            ..self
+        }
+    }
+
+    /// Aggregate the requests written by the metric sinks, the other ones are not affected.
+    pub fn aggregated(self, aggregation: Option<Aggregation>) -> Self {
+        Self {
+            aggregation,
+            ..self
+        }
+    }
+
+    pub fn open(&self, options: &FormatOptions) -> Result<Box<dyn Sink>> {
+        let colored = self.path.is_none();
+        let sink: Box<dyn Sink> = match self.output {
+            Output::Pretty => Box::new(PrettySink::new(
+                self.writer()?,
+                colored,
+                FormatOptions {
+                    width: options.width.filter(|_| self.path.is_none()),
+                    ..options.clone()
+                },
+            )),
+            Output::Json => Box::new(JsonSink {
+                writer: self.writer()?,
+            }),
+            Output::JsonPretty => Box::new(JsonPrettySink {
+                writer: self.writer()?,
+                colored,
+            }),
+            Output::Ndjson => Box::new(NdjsonSink::new(self.writer()?)),
+            Output::Prometheus => match &self.aggregation {
+                Some(aggregation) => self.aggregate(Format::Prometheus, aggregation)?,
+                None => Box::new(PrometheusSink::new(self.path.clone())),
+            },
+            Output::Csv | Output::Influx => {
+                let Some(aggregation) = &self.aggregation else {
+                    let output = format!("{:?}", self.output).to_lowercase();
+                    bail!("the {} output requires --aggregate", output);
+                };
+                let format = match self.output {
+                    Output::Csv => Format::Csv,
+                    _ => Format::Influx,
+                };
+                self.aggregate(format, aggregation)?
+            }
+            #[cfg(unix)]
+            Output::Journald => Box::new(JournaldSink::new(self.path.as_deref())?),
+            #[cfg(not(unix))]
+            Output::Journald => bail!("the systemd journal is not available on this platform"),
+        };
+        Ok(sink)
+    }
+
+    fn aggregate(&self, format: Format, aggregation: &Aggregation) -> Result<Box<dyn Sink>> {
+        let writer = self.writer()?;
+        Ok(Box::new(AggregateSink::new(
+            writer,
+            format,
+            aggregation.clone(),
+        )))
+    }
+
+    fn writer(&self) -> Result<Box<dyn OutputWriter>> {
+        match &self.path {
+            Some(path) => Ok(Box::new(open_file(path, self.compression)?)),
+            None => Ok(Box::new(std::io::stdout())),
+        }
+    }
+}
+
+/// Where the records are written, the outputs must be finished once the input is exhausted.
+pub trait OutputWriter: Write {
+    /// Flush the output, the compressed streams are completed too.
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl OutputWriter for io::Stdout {}
+
+/// An output file, compressed or not.
+struct OutputFile {
+    stream: Stream,
+    /// The compressed streams cannot be written once they are completed.
+    finished: bool,
+}
+
+enum Stream {
+    Plain(BufWriter<File>),
+    Gzip(BufWriter<flate2::write::GzEncoder<BufWriter<File>>>),
+    Zstd(BufWriter<zstd::Encoder<'static, BufWriter<File>>>),
+}
+
+fn open_file(path: &Path, compression: Option<Compression>) -> Result<OutputFile> {
+    let file = File::create(path)
+        .with_context(|| format!("failed to create output file: {}", path.display()))?;
+    let file = BufWriter::new(file);
+    let stream = match compression {
+        None => Stream::Plain(file),
+        Some(Compression::Gzip) => Stream::Gzip(BufWriter::new(flate2::write::GzEncoder::new(
+            file,
+            flate2::Compression::default(),
+        ))),
+        Some(Compression::Zstd) => Stream::Zstd(BufWriter::new(zstd::Encoder::new(file, 0)?)),
+    };
+    Ok(OutputFile {
+        stream,
+        finished: false,
+    })
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.stream {
+            Stream::Plain(file) => file.write(buf),
+            Stream::Gzip(encoder) => encoder.write(buf),
+            Stream::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.stream {
+            Stream::Plain(file) => file.flush(),
+            Stream::Gzip(encoder) => encoder.flush(),
+            Stream::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+impl OutputWriter for OutputFile {
+    fn finish(&mut self) -> io::Result<()> {
+        if std::mem::replace(&mut self.finished, true) {
+            return Ok(());
+        }
+        match &mut self.stream {
+            Stream::Plain(file) => file.flush(),
+            Stream::Gzip(encoder) => {
+                encoder.flush()?;
+                encoder.get_mut().try_finish()?;
+                encoder.get_mut().get_mut().flush()
+            }
+            Stream::Zstd(encoder) => {
+                encoder.flush()?;
+                encoder.get_mut().do_finish()?;
+                encoder.get_mut().get_mut().flush()
+            }
+        }
+    }
+}
+
+impl Drop for OutputFile {
+    /// The compressed streams are completed when the input ends with an error too, so the
+    /// records already written can be read. The errors are reported only by `finish`.
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// Run the closure with the terminal colors disabled when `colored` is false.
+fn with_colors<T>(colored: bool, f: impl FnOnce() -> T) -> T {
+    if colored {
+        return f();
+    }
+    let previous = colored::control::SHOULD_COLORIZE.should_colorize();
+    colored::control::set_override(false);
+    let result = f();
+    colored::control::set_override(previous);
+    result
+}
+
+struct PrettySink {
+    writer: Box<dyn OutputWriter>,
+    colored: bool,
+    options: FormatOptions,
+    timeline: Timeline,
+    labels: Labels,
+    baseline: Option<LatencyBaseline>,
+    headers: Option<HeaderBaseline>,
+    limited: RateLimitedClients,
+    retries: Option<Retries>,
+    budgets: BudgetChecks,
+    /// The formatted records of each group, printed when the input ends.
+    groups: BTreeMap<String, Vec<String>>,
+}
+
+/// A remark on a record, with its label.
+type Annotation = (&'static str, String);
+
+impl PrettySink {
+    const UNPARSED_GROUP: &'static str = "(not parsed)";
+
+    fn new(writer: Box<dyn OutputWriter>, colored: bool, options: FormatOptions) -> Self {
+        Self {
+            writer,
+            colored,
+            timeline: Timeline::default(),
+            labels: Labels::default(),
+            baseline: options.latency_anomalies.map(LatencyBaseline::new),
+            headers: options.unusual_headers.then(HeaderBaseline::default),
+            limited: RateLimitedClients::default(),
+            retries: options.retry_window.map(Retries::new),
+            budgets: BudgetChecks::new(options.budgets.clone()),
+            groups: BTreeMap::new(),
+            options,
+        }
+    }
+
+    /// The day banners and the hourly subtotals coming before the record.
+    fn write_banners(&mut self, record: &LogRecord) -> Result<()> {
+        let datetime = record.datetime();
+        let banners = with_colors(self.colored, || {
+            let mut banners = Vec::new();
+            if self.options.hourly_subtotals {
+                banners.extend(self.timeline.hour_subtotal(datetime));
+            }
+            if self.options.day_banners {
+                banners.extend(self.timeline.day_banner(datetime));
+            }
+            banners
+        });
+        for banner in banners {
+            writeln!(self.writer, "{banner}")?;
+        }
+        self.timeline.count(record);
+        Ok(())
+    }
+
+    /// The remarks written after the summary of the record, every record is observed by the
+    /// baselines even when it has nothing to remark.
+    fn annotations(&mut self, entry: &Entry, record: &LogRecord) -> Result<Vec<Annotation>> {
+        let colored = self.colored;
+        let mut annotations = Vec::new();
+        with_colors(colored, || {
+            annotations.extend(self.budget(record));
+            annotations.extend(self.rate_limited(record));
+            annotations.extend(self.retry(record));
+            annotations.extend(self.monitor(record));
+            annotations.extend(self.latency(record));
+            annotations.extend(self.unusual_headers(record));
+        });
+        if self.options.detail > Detail::Summary {
+            annotations.extend(self.options.detail.fields(entry.value()?, entry.line));
+        }
+        Ok(annotations)
+    }
+
+    fn budget(&mut self, record: &LogRecord) -> Option<Annotation> {
+        let violation = self.budgets.observe(record)?;
+        let remark = format!(
+            "{} over the {} of {} ({} of {} requests)",
+            LogRecord::format_duration(violation.excess),
+            LogRecord::format_duration(violation.limit),
+            violation.route,
+            violation.violations,
+            violation.requests
+        );
+        Some(("budget", remark.red().to_string()))
+    }
+
+    fn rate_limited(&mut self, record: &LogRecord) -> Option<Annotation> {
+        let client = self.limited.observe(record)?;
+        let remark = format!(
+            "{} times for this client since {}",
+            client.count,
+            limited::format_datetime(client.first_seen)
+        );
+        Some(("rate limited", remark.red().to_string()))
+    }
+
+    fn retry(&mut self, record: &LogRecord) -> Option<Annotation> {
+        let retry = self.retries.as_mut()?.observe(record)?;
+        let remark = format!(
+            "attempt {} within {}",
+            retry.attempt,
+            LogRecord::format_duration(retry.elapsed)
+        );
+        Some(("retry", remark.magenta().to_string()))
+    }
+
+    fn monitor(&self, record: &LogRecord) -> Option<Annotation> {
+        let monitor = self.options.monitors.find(record)?;
+        Some(("monitor", monitor.dimmed().to_string()))
+    }
+
+    fn latency(&mut self, record: &LogRecord) -> Option<Annotation> {
+        let anomaly = self.baseline.as_mut()?.observe(record)?;
+        let remark = format!(
+            "{:.1}σ slower than the usual {} of this path",
+            anomaly.deviation,
+            LogRecord::format_duration(anomaly.baseline)
+        );
+        Some(("latency", remark.yellow().to_string()))
+    }
+
+    /// The headers unusual for the host, only the first one is labeled.
+    fn unusual_headers(&mut self, record: &LogRecord) -> Vec<Annotation> {
+        let Some(headers) = self.headers.as_mut() else {
+            return Vec::new();
+        };
+        let mut annotations = Vec::new();
+        for (index, header) in headers.observe(record).into_iter().enumerate() {
+            let remark = match header.value {
+                Some(value) => format!("{}: {value}", header.name),
+                None => format!("{} missing", header.name),
+            };
+            let remark = format!("{} (usually {})", remark.yellow(), header.usual);
+            let label = if index == 0 { "unusual" } else { "" };
+            annotations.push((label, remark));
+        }
+        annotations
+    }
+
+    /// The line number and the label of the source before the record, the first line is still
+    /// truncated to the width.
+    fn add_prefix(&mut self, entry: &Entry, formatted: &mut String) {
+        let mut prefix = Vec::new();
+        if let (true, Some(position)) = (self.options.line_numbers, entry.position) {
+            prefix.push(with_colors(self.colored, || {
+                position.to_string().dimmed().to_string()
+            }));
+        }
+        if let Some(source) = entry.source {
+            let color = self.labels.color(source);
+            // the labels are aligned to the longest one seen so far
+            let padding = " ".repeat(self.labels.width() - source.chars().count());
+            prefix.push(with_colors(self.colored, || {
+                format!("{}{padding}", source.color(color).bold())
+            }));
+        }
+        if prefix.is_empty() {
+            return;
+        }
+        *formatted = format!("{} {formatted}", prefix.join(" "));
+        if let Some(width) = self.options.width {
+            let end = formatted.find('\n').unwrap_or(formatted.len());
+            let mut header = formatted[..end].to_string();
+            record::truncate_line(&mut header, width);
+            formatted.replace_range(..end, &header);
+        }
+    }
+
+    fn write_groups(&mut self) -> Result<()> {
+        for (group, records) in std::mem::take(&mut self.groups) {
+            let heading = with_colors(self.colored, || {
+                let noun = if records.len() == 1 {
+                    "record"
+                } else {
+                    "records"
+                };
+                let title = format!(" {} · {} {} ", group, records.len(), noun);
+                format!("{}{}{}", "═".repeat(4), title, "═".repeat(40))
+                    .bold()
+                    .to_string()
+            });
+            writeln!(self.writer, "{heading}")?;
+            for record in records {
+                writeln!(self.writer, "{record}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Sink for PrettySink {
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        let record = entry.record()?;
+        self.write_banners(record)?;
+        let annotations = self.annotations(entry, record)?;
+        // the records without a request can have a layout of their own
+        let value = match record.request {
+            Some(_) => None,
+            None => Some(entry.value()?),
+        };
+        let mut formatted = with_colors(self.colored, || {
+            match value.and_then(|value| management::summary(record, value)) {
+                Some((message, mut fields)) => {
+                    fields.extend(annotations);
+                    record.format_message(&self.options, message, fields)
+                }
+                None => record.format_annotated(&self.options, annotations),
+            }
+        });
+        self.add_prefix(entry, &mut formatted);
+        if let Some(group_by) = self.options.group_by {
+            let group = group_by.key(record);
+            self.groups.entry(group).or_default().push(formatted);
+            return Ok(());
+        }
+        writeln!(self.writer, "{formatted}")?;
+        Ok(())
+    }
+
+    fn write_unparsed(&mut self, line: &str) -> Result<()> {
+        if self.options.group_by.is_some() {
+            let group = self.groups.entry(Self::UNPARSED_GROUP.to_string());
+            group.or_default().push(line.to_string());
+            return Ok(());
+        }
+        writeln!(self.writer, "{line}")?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.write_groups()?;
+        if self.options.hourly_subtotals {
+            let subtotal = with_colors(self.colored, || self.timeline.subtotal());
+            if let Some(subtotal) = subtotal {
+                writeln!(self.writer, "{subtotal}")?;
+            }
+        }
+        if let Some(budgets) = self.budgets.render(&self.options.locale) {
+            writeln!(self.writer)?;
+            write!(self.writer, "{budgets}")?;
+        }
+        self.writer.finish()?;
+        Ok(())
+    }
+
+    /// The groups are written when the input ends.
+    fn is_live(&self) -> bool {
+        self.options.group_by.is_none()
+    }
+}
+
+/// The colors of the source labels, assigned in order of appearance.
+#[derive(Default)]
+struct Labels {
+    colors: Vec<String>,
+}
+
+impl Labels {
+    const PALETTE: &'static [colored::Color] = &[
+        colored::Color::Cyan,
+        colored::Color::Magenta,
+        colored::Color::Yellow,
+        colored::Color::Green,
+        colored::Color::Blue,
+        colored::Color::BrightRed,
+    ];
+
+    fn color(&mut self, label: &str) -> colored::Color {
+        let index = match self.colors.iter().position(|known| known == label) {
+            Some(index) => index,
+            None => {
+                self.colors.push(label.to_string());
+                self.colors.len() - 1
+            }
+        };
+        Self::PALETTE[index % Self::PALETTE.len()]
+    }
+
+    /// The width of the longest label.
+    fn width(&self) -> usize {
+        let widths = self.colors.iter().map(|label| label.chars().count());
+        widths.max().unwrap_or(0)
+    }
+}
+
+/// Keep track of the day and the hour of the last printed record, to separate the output in
+/// time periods.
+#[derive(Default)]
+struct Timeline {
+    day: Option<time::Date>,
+    hour: Option<(time::Date, u8)>,
+    records: u64,
+    client_errors: u64,
+    server_errors: u64,
+}
+
+impl Timeline {
+    /// A banner is returned when the record is the first one of a new day, the first day of
+    /// each week is marked by a heavier line.
+    fn day_banner(&mut self, datetime: OffsetDateTime) -> Option<String> {
+        let date = datetime.date();
+        let previous = self.day.replace(date);
+        if previous == Some(date) {
+            return None;
+        }
+        let new_week = previous.is_none_or(|previous| {
+            previous.iso_week() != date.iso_week() || previous.year() != date.year()
+        });
+        let title = format!(" {} {} · week {} ", date.weekday(), date, date.iso_week());
+        let rule = if new_week { "═" } else { "─" };
+        Some(
+            format!("{}{}{}", rule.repeat(4), title, rule.repeat(40))
+                .bold()
+                .to_string(),
+        )
+    }
+
+    /// The subtotal of the previous hour is returned when the record is the first one of a new
+    /// hour.
+    fn hour_subtotal(&mut self, datetime: OffsetDateTime) -> Option<String> {
+        let hour = (datetime.date(), datetime.hour());
+        if self.hour == Some(hour) {
+            return None;
+        }
+        let subtotal = self.subtotal();
+        self.hour = Some(hour);
+        self.records = 0;
+        self.client_errors = 0;
+        self.server_errors = 0;
+        subtotal
+    }
+
+    fn count(&mut self, record: &LogRecord) {
+        self.records += 1;
+        match record.severity() {
+            Some(Severity::ClientError) => self.client_errors += 1,
+            Some(Severity::ServerError) => self.server_errors += 1,
+            _ => {}
+        }
+    }
+
+    fn subtotal(&self) -> Option<String> {
+        let (date, hour) = self.hour?;
+        Some(
+            format!(
+                "  ·· {} {:02}:00-{:02}:00  {} records, {} client errors, {} server errors",
+                date,
+                hour,
+                (hour + 1) % 24,
+                self.records,
+                self.client_errors,
+                self.server_errors
+            )
+            .dimmed()
+            .to_string(),
+        )
+    }
+}
+
+struct JsonSink {
+    writer: Box<dyn OutputWriter>,
+}
+
+impl Sink for JsonSink {
+    fn needs_record(&self) -> bool {
+        false
+    }
+
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        writeln!(self.writer, "{}", entry.line)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+struct JsonPrettySink {
+    writer: Box<dyn OutputWriter>,
+    colored: bool,
+}
+
+impl Sink for JsonPrettySink {
+    fn needs_record(&self) -> bool {
+        false
+    }
+
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        let value = entry.value()?;
+        let formatted = with_colors(self.colored, || json::format_pretty(value));
+        writeln!(self.writer, "{formatted}")?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+/// Print the values selected by a list of jq-like paths, separated by tabs.
+pub struct ExtractSink {
+    writer: Box<dyn Write>,
+    selectors: Vec<Selector>,
+}
+
+impl ExtractSink {
+    pub fn new(selectors: Vec<Selector>) -> Self {
+        Self {
+            writer: Box::new(std::io::stdout()),
+            selectors,
+        }
+    }
+}
+
+impl Sink for ExtractSink {
+    fn needs_record(&self) -> bool {
+        false
+    }
+
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        let values = self
+            .selectors
+            .iter()
+            .map(|selector| Ok(selector.extract(entry.value()?)))
+            .collect::<Result<Vec<_>>>()?;
+        writeln!(self.writer, "{}", values.join("\t"))?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Count the requests by host and status code, the counters are written in the Prometheus text
+/// exposition format when the input ends.
+struct PrometheusSink {
+    path: Option<PathBuf>,
+    requests: BTreeMap<(String, u16), u64>,
+    durations: BTreeMap<String, (f64, u64)>,
+}
+
+impl PrometheusSink {
+    fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            path,
+            requests: BTreeMap::new(),
+            durations: BTreeMap::new(),
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut output = String::new();
+        output.push_str("# HELP caddy_requests_total Number of requests by host and status.\n");
+        output.push_str("# TYPE caddy_requests_total counter\n");
+        for ((host, status), count) in &self.requests {
+            output.push_str(&format!(
+                "caddy_requests_total{{host=\"{}\",status=\"{}\"}} {}\n",
+                escape_label(host),
+                status,
+                count
+            ));
+        }
+        output.push_str("# HELP caddy_request_duration_seconds Time spent handling requests.\n");
+        output.push_str("# TYPE caddy_request_duration_seconds summary\n");
+        for (host, (sum, count)) in &self.durations {
+            let host = escape_label(host);
+            output.push_str(&format!(
+                "caddy_request_duration_seconds_sum{{host=\"{host}\"}} {sum}\n"
+            ));
+            output.push_str(&format!(
+                "caddy_request_duration_seconds_count{{host=\"{host}\"}} {count}\n"
+            ));
+        }
+        output
+    }
+}
+
+impl Sink for PrometheusSink {
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        let record = entry.record()?;
+        let Some(request) = &record.request else {
+            return Ok(());
+        };
+        if let Some(status) = record.status {
+            *self
+                .requests
+                .entry((request.host.clone(), status.as_u16()))
+                .or_default() += 1;
+        }
+        if let Some(duration) = record.duration {
+            let (sum, count) = self.durations.entry(request.host.clone()).or_default();
+            *sum += duration;
+            *count += 1;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let output = self.render();
+        match &self.path {
+            Some(path) => replace_file(path, &output)?,
+            None => {
+                let mut stdout = std::io::stdout();
+                stdout.write_all(output.as_bytes())?;
+                stdout.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn is_live(&self) -> bool {
+        false
+    }
+}
+
+/// Write the file and rename it over the previous one, so that the collectors never read a
+/// partial file.
+pub fn replace_file(path: &Path, content: &str) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, content).with_context(|| format!("failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(())
+}
+
+pub fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, io::Read, rc::Rc};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::record::GroupBy;
+
+    /// An output kept in memory, shared with the test.
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Buffer {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl OutputWriter for Buffer {}
+
+    fn line(host: &str, status: u16, duration: f64) -> String {
+        json!({
+            "level": "info",
+            "ts": 1710000000.5,
+            "logger": "http.log.access",
+            "msg": "handled request",
+            "request": {
+                "remote_ip": "10.0.0.5",
+                "proto": "HTTP/1.1",
+                "method": "GET",
+                "host": host,
+                "uri": "/",
+                "headers": {},
+            },
+            "duration": duration,
+            "size": 512,
+            "status": status,
+            "resp_headers": {},
+        })
+        .to_string()
+    }
+
+    fn write(sink: &mut dyn Sink, line: &str) {
+        let record = serde_json::from_str::<LogRecord>(line).unwrap();
+        sink.write(&Entry::new(line, &record, None)).unwrap();
+    }
+
+    /// A file of the temporary directory, removed before the test.
+    fn temp_file(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("caddy-pretty-print-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn parse_sink_specs() {
+        let spec = "json:/var/log/export.log".parse::<SinkSpec>().unwrap();
+        assert!(matches!(spec.output, Output::Json));
+        assert_eq!(spec.path, Some(PathBuf::from("/var/log/export.log")));
+        let spec = "JSON-PRETTY".parse::<SinkSpec>().unwrap();
+        assert!(matches!(spec.output, Output::JsonPretty));
+        assert_eq!(spec.path, None);
+        assert!("yaml".parse::<SinkSpec>().is_err());
+        assert!("json:".parse::<SinkSpec>().is_err());
+        // the metric outputs cannot be written without an aggregation
+        let spec = "csv".parse::<SinkSpec>().unwrap();
+        assert!(spec.open(&FormatOptions::default()).is_err());
+    }
+
+    #[test]
+    fn group_the_records() {
+        let buffer = Buffer::default();
+        let options = FormatOptions {
+            group_by: Some(GroupBy::Host),
+            ..FormatOptions::default()
+        };
+        let mut sink = PrettySink::new(Box::new(buffer.clone()), false, options);
+        write(&mut sink, &line("b.example.com", 200, 0.1));
+        write(&mut sink, &line("a.example.com", 404, 0.1));
+        write(&mut sink, &line("b.example.com", 500, 0.1));
+        sink.write_unparsed("not a record").unwrap();
+        // nothing is written before the input ends
+        assert_eq!(buffer.text(), "");
+        sink.finish().unwrap();
+
+        let output = buffer.text();
+        let headings = output
+            .lines()
+            .filter(|line| line.starts_with('═'))
+            .map(|line| line.trim_matches('═').trim())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            headings,
+            [
+                "(not parsed) · 1 record",
+                "a.example.com · 1 record",
+                "b.example.com · 2 records"
+            ]
+        );
+        let not_parsed = output.find("not a record").unwrap();
+        let first = output.find("a.example.com").unwrap();
+        assert!(not_parsed < first);
+    }
+
+    #[test]
+    fn write_the_records_as_they_come() {
+        let buffer = Buffer::default();
+        let mut sink = PrettySink::new(Box::new(buffer.clone()), false, FormatOptions::default());
+        write(&mut sink, &line("example.com", 200, 0.1));
+        assert!(buffer.text().contains("example.com"));
+        sink.write_unparsed("not a record").unwrap();
+        assert!(buffer.text().ends_with("not a record\n"));
+    }
+
+    #[test]
+    fn extract_the_values() {
+        let buffer = Buffer::default();
+        let selectors = [".request.host", ".status", ".missing"]
+            .iter()
+            .map(|selector| selector.parse().unwrap())
+            .collect();
+        let mut sink = ExtractSink {
+            writer: Box::new(buffer.clone()),
+            selectors,
+        };
+        let line = line("example.com", 404, 0.1);
+        sink.write(&Entry::partial(&line, None)).unwrap();
+        assert_eq!(buffer.text(), "example.com\t404\t\n");
+        // the entries are only read from valid JSON documents
+        assert!(sink.write(&Entry::partial("{", None)).is_err());
+    }
+
+    #[test]
+    fn count_the_requests_for_prometheus() {
+        let mut sink = PrometheusSink::new(None);
+        write(&mut sink, &line("example.com", 200, 0.25));
+        write(&mut sink, &line("example.com", 200, 0.5));
+        write(&mut sink, &line("a\"b", 404, 1.0));
+        assert_eq!(
+            sink.render(),
+            "# HELP caddy_requests_total Number of requests by host and status.
+# TYPE caddy_requests_total counter
+caddy_requests_total{host=\"a\\\"b\",status=\"404\"} 1
+caddy_requests_total{host=\"example.com\",status=\"200\"} 2
+# HELP caddy_request_duration_seconds Time spent handling requests.
+# TYPE caddy_request_duration_seconds summary
+caddy_request_duration_seconds_sum{host=\"a\\\"b\"} 1
+caddy_request_duration_seconds_count{host=\"a\\\"b\"} 1
+caddy_request_duration_seconds_sum{host=\"example.com\"} 0.75
+caddy_request_duration_seconds_count{host=\"example.com\"} 2
+"
+        );
+    }
+
+    #[test]
+    fn compress_the_files() {
+        let lines = [line("example.com", 200, 0.1), line("example.com", 404, 0.2)];
+        let expected = format!("{}\n{}\n", lines[0], lines[1]);
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let path = temp_file(&format!("export-{compression:?}.log"));
+            let spec = SinkSpec::from_str(&format!("json:{}", path.display()))
+                .unwrap()
+                .compressed(Some(compression));
+            let mut sink = spec.open(&FormatOptions::default()).unwrap();
+            for line in &lines {
+                sink.write(&Entry::partial(line, None)).unwrap();
+            }
+            sink.finish().unwrap();
+            drop(sink);
+
+            let file = File::open(&path).unwrap();
+            let mut content = String::new();
+            match compression {
+                Compression::Gzip => flate2::read::GzDecoder::new(file)
+                    .read_to_string(&mut content)
+                    .unwrap(),
+                Compression::Zstd => zstd::Decoder::new(file)
+                    .unwrap()
+                    .read_to_string(&mut content)
+                    .unwrap(),
+            };
+            assert_eq!(content, expected);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    fn is_live(sink: &str, options: &FormatOptions) -> bool {
+        let spec = sink.parse::<SinkSpec>().unwrap();
+        spec.open(options).unwrap().is_live()
+    }
+
+    #[test]
+    fn reports_are_not_live() {
+        let options = FormatOptions::default();
+        assert!(is_live("pretty", &options));
+        assert!(is_live("json", &options));
+        assert!(is_live("ndjson", &options));
+        assert!(!is_live("prometheus", &options));
+        let grouped = FormatOptions {
+            group_by: Some(GroupBy::Host),
+            ..FormatOptions::default()
+        };
+        assert!(!is_live("pretty", &grouped));
+        let aggregation = Aggregation {
+            period: std::time::Duration::from_secs(60),
+            percentiles: vec![50.0],
+        };
+        let spec = SinkSpec::stdout(Output::Csv).aggregated(Some(aggregation));
+        assert!(!spec.open(&options).unwrap().is_live());
+    }
+}
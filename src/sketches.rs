@@ -0,0 +1,224 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    hash::{DefaultHasher, Hash, Hasher},
+    rc::Rc,
+};
+
+/// Estimate the number of distinct values with a fixed amount of memory, the standard error
+/// is about 0.8%.
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    const PRECISION: u32 = 14;
+
+    pub fn new() -> Self {
+        Self {
+            registers: vec![0; 1 << Self::PRECISION],
+        }
+    }
+
+    pub fn insert(&mut self, value: &str) {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        let index = (hash >> (64 - Self::PRECISION)) as usize;
+        // the position of the first set bit of the remaining bits
+        let rank = ((hash << Self::PRECISION) | (1 << (Self::PRECISION - 1))).leading_zeros() + 1;
+        self.registers[index] = self.registers[index].max(rank as u8);
+    }
+
+    pub fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum = self
+            .registers
+            .iter()
+            .map(|&register| 2f64.powi(-(register as i32)))
+            .sum::<f64>();
+        let estimate = alpha * m * m / sum;
+        let zeros = self
+            .registers
+            .iter()
+            .filter(|&&register| register == 0)
+            .count();
+        // the linear counting is more accurate for the small cardinalities
+        if estimate <= 2.5 * m && zeros > 0 {
+            return (m * (m / zeros as f64).ln()).round() as u64;
+        }
+        estimate.round() as u64
+    }
+}
+
+/// Find the most frequent values with a bounded number of counters, or a bounded memory (the
+/// space-saving algorithm). The counts are overestimated by at most the reported error.
+pub struct TopValues {
+    max_counters: usize,
+    /// The estimated memory of the counters, and its bound, in bytes.
+    memory: usize,
+    max_memory: usize,
+    counters: HashMap<Rc<str>, Counter>,
+    /// The values ordered by count, the least frequent first.
+    order: BTreeSet<(u64, Rc<str>)>,
+    /// The highest count of the evicted values, the values not counted occurred at most this
+    /// many times.
+    evicted: u64,
+}
+
+#[derive(Clone, Copy)]
+pub struct Counter {
+    pub count: u64,
+    pub error: u64,
+}
+
+impl TopValues {
+    /// The memory of a counter besides its value, in the map and in the order, estimated.
+    const OVERHEAD: usize = 96;
+
+    pub fn new(max_counters: usize) -> Self {
+        Self {
+            max_counters: max_counters.max(1),
+            memory: 0,
+            max_memory: usize::MAX,
+            counters: HashMap::new(),
+            order: BTreeSet::new(),
+            evicted: 0,
+        }
+    }
+
+    /// The counters are bounded by the memory they use, in bytes, instead of their number.
+    pub fn with_memory(max_memory: usize) -> Self {
+        Self {
+            max_counters: usize::MAX,
+            max_memory,
+            ..Self::new(1)
+        }
+    }
+
+    pub fn insert(&mut self, value: &str) {
+        if let Some((value, counter)) = self.counters.get_key_value(value) {
+            let count = counter.count;
+            let value = Rc::clone(value);
+            self.order.remove(&(count, Rc::clone(&value)));
+            self.order.insert((count + 1, Rc::clone(&value)));
+            if let Some(counter) = self.counters.get_mut(&value) {
+                counter.count += 1;
+            }
+            return;
+        }
+        // the least frequent values are replaced, the new one inherits their count
+        let cost = value.len() + Self::OVERHEAD;
+        let mut min = 0;
+        while self.counters.len() >= self.max_counters || self.memory + cost > self.max_memory {
+            let Some((count, evicted)) = self.order.pop_first() else {
+                break;
+            };
+            self.counters.remove(&evicted);
+            self.memory -= evicted.len() + Self::OVERHEAD;
+            self.evicted = self.evicted.max(count);
+            min = count;
+        }
+        if self.memory + cost > self.max_memory {
+            return;
+        }
+        let value = Rc::<str>::from(value);
+        let counter = Counter {
+            count: min + 1,
+            error: min,
+        };
+        self.order.insert((counter.count, Rc::clone(&value)));
+        self.counters.insert(value, counter);
+        self.memory += cost;
+    }
+
+    /// The `n` most frequent values, in descending order of count.
+    pub fn top(&self, n: usize) -> Vec<(&str, Counter)> {
+        let mut values = self
+            .counters
+            .iter()
+            .map(|(value, counter)| (&**value, *counter))
+            .collect::<Vec<_>>();
+        values.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(b.0)));
+        values.truncate(n);
+        values
+    }
+
+    /// The highest count a value not reported can have, zero when the counts are exact.
+    pub fn max_missing(&self) -> u64 {
+        self.evicted
+    }
+
+    /// The number of counters, and their estimated memory in bytes.
+    pub fn usage(&self) -> (usize, usize) {
+        (self.counters.len(), self.memory)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimate_the_distinct_values() {
+        let mut hll = HyperLogLog::new();
+        assert_eq!(hll.estimate(), 0);
+        for value in 0..100 {
+            hll.insert(&value.to_string());
+            hll.insert(&value.to_string());
+        }
+        assert_eq!(hll.estimate(), 100);
+        for value in 0..100_000 {
+            hll.insert(&format!("/path/{value}"));
+        }
+        let estimate = hll.estimate() as f64;
+        assert!((estimate / 100_100.0 - 1.0).abs() < 0.05, "{estimate}");
+    }
+
+    #[test]
+    fn count_the_values_exactly_with_enough_counters() {
+        let mut top = TopValues::new(10);
+        for value in ["a", "b", "a", "c", "a", "b"] {
+            top.insert(value);
+        }
+        let values = top
+            .top(2)
+            .into_iter()
+            .map(|(value, counter)| (value, counter.count, counter.error))
+            .collect::<Vec<_>>();
+        assert_eq!(values, [("a", 3, 0), ("b", 2, 0)]);
+        assert_eq!(top.max_missing(), 0);
+    }
+
+    #[test]
+    fn replace_the_least_frequent_values() {
+        let mut top = TopValues::new(2);
+        for value in ["a", "a", "a", "b", "c", "c"] {
+            top.insert(value);
+        }
+        // `c` took the counter of `b`, its count is overestimated by at most its error
+        let values = top
+            .top(2)
+            .into_iter()
+            .map(|(value, counter)| (value, counter.count, counter.error))
+            .collect::<Vec<_>>();
+        assert_eq!(values, [("a", 3, 0), ("c", 3, 1)]);
+        assert_eq!(top.max_missing(), 1);
+    }
+
+    #[test]
+    fn bound_the_memory_of_the_counters() {
+        let max_memory = 4 * (TopValues::OVERHEAD + 8);
+        let mut top = TopValues::with_memory(max_memory);
+        for value in 0..100 {
+            top.insert(&format!("/path/{value}"));
+        }
+        let (counters, memory) = top.usage();
+        assert!(counters <= 4);
+        assert!(memory <= max_memory);
+        // a value larger than the bound is not counted
+        let mut top = TopValues::with_memory(10);
+        top.insert("/path");
+        assert_eq!(top.usage(), (0, 0));
+    }
+}
@@ -0,0 +1,251 @@
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use crate::{
+    input::{Line, Lines},
+    units,
+};
+
+/// The clock offsets of the sources, set by `--source-offset`. They are added to the timestamps
+/// of the records, so the logs of servers with skewed clocks are merged in a single timeline.
+#[derive(Debug, Default)]
+pub struct SourceOffsets {
+    /// The offsets given for the sources, in seconds.
+    fixed: Vec<(String, f64)>,
+    /// Whether the offsets of the other sources are estimated.
+    auto: bool,
+}
+
+impl SourceOffsets {
+    /// Parse the offsets written as `SOURCE=OFFSET`, e.g. `web2=-1.5s`, or `auto`.
+    pub fn parse(args: &[String]) -> Result<Self> {
+        let mut offsets = Self::default();
+        for arg in args {
+            if arg == "auto" {
+                offsets.auto = true;
+                continue;
+            }
+            let (source, offset) = arg.split_once('=').with_context(|| {
+                format!(
+                    "invalid source offset: {}, expected SOURCE=OFFSET or auto",
+                    arg
+                )
+            })?;
+            let offset = offset.trim();
+            let (sign, duration) = match offset.strip_prefix('-') {
+                Some(duration) => (-1.0, duration),
+                None => (1.0, offset.strip_prefix('+').unwrap_or(offset)),
+            };
+            let duration = units::parse_duration(duration)
+                .with_context(|| format!("invalid offset of {}: {}", source, offset))?;
+            offsets
+                .fixed
+                .push((source.to_string(), sign * duration.as_secs_f64()));
+        }
+        Ok(offsets)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fixed.is_empty() && !self.auto
+    }
+
+    /// The offset given for the source of the line, named by its label, by the path of its file
+    /// or by the name of its file.
+    fn fixed(&self, line: &Line) -> Option<f64> {
+        let path = &*line.position.path;
+        let name = Path::new(path).file_name().and_then(|name| name.to_str());
+        self.fixed
+            .iter()
+            .find(|(source, _)| {
+                line.source.as_deref() == Some(source) || path == source || name == Some(source)
+            })
+            .map(|&(_, offset)| offset)
+    }
+}
+
+/// Shift the timestamps of the records by the offsets of their sources. The estimated offsets
+/// are reported when the input ends.
+pub fn shift(mut lines: Lines, offsets: SourceOffsets) -> Lines {
+    let mut estimates = offsets
+        .auto
+        .then(|| Estimates::new(!offsets.fixed.is_empty()));
+    Box::new(std::iter::from_fn(move || {
+        let Some(line) = lines.next() else {
+            if let Some(estimates) = estimates.take() {
+                estimates.report();
+            }
+            return None;
+        };
+        Some(line.map(|line| shift_line(line, &offsets, estimates.as_mut())))
+    }))
+}
+
+fn shift_line(mut line: Line, offsets: &SourceOffsets, estimates: Option<&mut Estimates>) -> Line {
+    let fixed = offsets.fixed(&line);
+    if estimates.is_none() && fixed.is_none_or(|offset| offset == 0.0) {
+        return line;
+    }
+    let Ok(mut value) = serde_json::from_str::<Value>(&line.text) else {
+        return line;
+    };
+    let Some(timestamp) = value.get("ts").and_then(Value::as_f64) else {
+        return line;
+    };
+    let offset = match estimates {
+        Some(estimates) => {
+            let source = line
+                .source
+                .clone()
+                .unwrap_or_else(|| Arc::clone(&line.position.path));
+            estimates.observe(source, timestamp, fixed, request_id(&value))
+        }
+        None => fixed.unwrap_or_default(),
+    };
+    if offset != 0.0 {
+        value["ts"] = Value::from(timestamp + offset);
+        line.text = value.to_string();
+    }
+    line
+}
+
+/// The `X-Request-Id` header of the request, which tells apart the same request in the logs of
+/// different servers.
+fn request_id(value: &Value) -> Option<String> {
+    let headers = value.pointer("/request/headers")?.as_object()?;
+    let (_, values) = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("x-request-id"))?;
+    let id = values.as_array()?.first()?.as_str()?;
+    (!id.is_empty()).then(|| id.to_string())
+}
+
+/// The offsets of the sources estimated from the requests logged by more than one of them. The
+/// sources with a given offset are the references, or the first source read when there is none,
+/// and the offset of another source is the median of the differences of the timestamps of the
+/// same requests.
+struct Estimates {
+    /// Whether the references are the sources with a given offset.
+    fixed: bool,
+    /// The source and the timestamp of the requests not yet seen in another source.
+    requests: HashMap<String, (Arc<str>, f64)>,
+    sources: HashMap<Arc<str>, Estimate>,
+}
+
+#[derive(Default)]
+struct Estimate {
+    reference: bool,
+    /// The offset, unknown until a request is seen in a source with a known offset.
+    offset: Option<f64>,
+    /// The differences of the timestamps of the same requests, sorted.
+    samples: Vec<f64>,
+}
+
+impl Estimate {
+    fn sample(&mut self, difference: f64) {
+        if self.samples.len() >= Estimates::MAX_SAMPLES {
+            return;
+        }
+        let index = self.samples.partition_point(|&sample| sample < difference);
+        self.samples.insert(index, difference);
+        self.offset = Some(self.samples[self.samples.len() / 2]);
+    }
+}
+
+impl Estimates {
+    /// The requests seen after this many are not looked for in the other sources, to bound the
+    /// memory. The first ones are kept, they are the ones logged by the files read later.
+    const MAX_REQUESTS: usize = 100_000;
+    /// The offset of a source does not change after this many samples.
+    const MAX_SAMPLES: usize = 1_000;
+
+    fn new(fixed: bool) -> Self {
+        Self {
+            fixed,
+            requests: HashMap::new(),
+            sources: HashMap::new(),
+        }
+    }
+
+    /// Look for the request in the other sources, the offset of the source is returned.
+    fn observe(
+        &mut self,
+        source: Arc<str>,
+        timestamp: f64,
+        fixed: Option<f64>,
+        id: Option<String>,
+    ) -> f64 {
+        if !self.sources.contains_key(&source) {
+            let reference = fixed.is_some() || (!self.fixed && self.sources.is_empty());
+            let estimate = Estimate {
+                reference,
+                offset: reference.then(|| fixed.unwrap_or_default()),
+                samples: Vec::new(),
+            };
+            self.sources.insert(Arc::clone(&source), estimate);
+        }
+        if let Some(id) = id {
+            match self.requests.remove(&id) {
+                Some((other, other_timestamp)) if other != source => {
+                    self.compare((&source, timestamp), (&other, other_timestamp));
+                }
+                Some(seen) => {
+                    self.requests.insert(id, seen);
+                }
+                None if self.requests.len() < Self::MAX_REQUESTS => {
+                    self.requests.insert(id, (Arc::clone(&source), timestamp));
+                }
+                None => {}
+            }
+        }
+        self.sources[&source].offset.unwrap_or_default()
+    }
+
+    /// Sample the difference of the timestamps of the same request in two sources, for the one
+    /// whose offset is estimated when the offset of the other one is known.
+    fn compare(&mut self, (a, a_timestamp): (&Arc<str>, f64), (b, b_timestamp): (&Arc<str>, f64)) {
+        let (a_reference, a_offset) = (self.sources[a].reference, self.sources[a].offset);
+        let (b_reference, b_offset) = (self.sources[b].reference, self.sources[b].offset);
+        match (a_offset, b_offset) {
+            (_, Some(b_offset)) if !a_reference => {
+                let difference = b_timestamp + b_offset - a_timestamp;
+                self.sources
+                    .get_mut(a)
+                    .expect("a known source")
+                    .sample(difference);
+            }
+            (Some(a_offset), _) if !b_reference => {
+                let difference = a_timestamp + a_offset - b_timestamp;
+                self.sources
+                    .get_mut(b)
+                    .expect("a known source")
+                    .sample(difference);
+            }
+            _ => {}
+        }
+    }
+
+    fn report(&self) {
+        let mut sources = self
+            .sources
+            .iter()
+            .filter(|(_, estimate)| !estimate.reference)
+            .collect::<Vec<_>>();
+        sources.sort_by_key(|&(source, _)| source);
+        for (source, estimate) in sources {
+            match estimate.offset {
+                Some(offset) => eprintln!(
+                    "estimated offset of {}: {:+.3}s, from {} requests",
+                    source,
+                    offset,
+                    estimate.samples.len()
+                ),
+                None => eprintln!(
+                    "the offset of {} cannot be estimated, its requests are not in the other sources",
+                    source
+                ),
+            }
+        }
+    }
+}
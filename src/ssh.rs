@@ -0,0 +1,147 @@
+use std::{
+    io::Read,
+    process::{Child, ChildStdout, Command, Stdio},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+
+/// A log file on a remote host, e.g. `user@host:/var/log/caddy/access.log`.
+#[derive(Clone, Debug)]
+pub struct Remote {
+    /// The destination given to `ssh`, with the user when present.
+    pub destination: String,
+    pub path: String,
+}
+
+impl Remote {
+    /// The host, without the user.
+    pub fn host(&self) -> &str {
+        match self.destination.split_once('@') {
+            Some((_, host)) => host,
+            None => &self.destination,
+        }
+    }
+}
+
+impl FromStr for Remote {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let Some((destination, path)) = s.split_once(':') else {
+            bail!("invalid remote file: {}, expected [USER@]HOST:PATH", s);
+        };
+        if destination.is_empty() || path.is_empty() {
+            bail!("invalid remote file: {}, expected [USER@]HOST:PATH", s);
+        }
+        Ok(Self {
+            destination: destination.to_string(),
+            path: path.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for Remote {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.destination, self.path)
+    }
+}
+
+/// A reader of a remote file followed with `tail -F` through the system `ssh` binary. The
+/// connection is opened again when it is lost, the lines written in the meantime are not read.
+pub struct RemoteTail {
+    remote: Remote,
+    tail_lines: usize,
+    session: Option<(Child, ChildStdout)>,
+    /// Whether any data has been read, the failures of the first connection end the input.
+    connected: bool,
+    backoff: Duration,
+}
+
+impl RemoteTail {
+    const MIN_BACKOFF: Duration = Duration::from_secs(1);
+    const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+    /// The connection is opened on the first read, starting from the last `tail_lines` lines.
+    pub fn new(remote: Remote, tail_lines: usize) -> Self {
+        Self {
+            remote,
+            tail_lines,
+            session: None,
+            connected: false,
+            backoff: Self::MIN_BACKOFF,
+        }
+    }
+
+    fn connect(&self) -> std::io::Result<(Child, ChildStdout)> {
+        // only the new lines are read after a reconnection
+        let tail_lines = match self.connected {
+            true => 0,
+            false => self.tail_lines,
+        };
+        let mut child = Command::new("ssh")
+            .args(["-o", "BatchMode=yes", "-o", "ServerAliveInterval=15"])
+            .arg(&self.remote.destination)
+            .arg(format!(
+                "tail -n {} -F {}",
+                tail_lines,
+                shell_quote(&self.remote.path)
+            ))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| std::io::Error::new(err.kind(), format!("failed to run ssh: {err}")))?;
+        let stdout = child.stdout.take().expect("the stdout is piped");
+        Ok((child, stdout))
+    }
+}
+
+impl Read for RemoteTail {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let (_, stdout) = match &mut self.session {
+                Some(session) => session,
+                None => self.session.insert(self.connect()?),
+            };
+            match stdout.read(buf) {
+                Ok(read) if read > 0 => {
+                    self.connected = true;
+                    self.backoff = Self::MIN_BACKOFF;
+                    return Ok(read);
+                }
+                Ok(_) | Err(_) => {}
+            }
+            // the connection has been lost
+            let (mut child, _) = self.session.take().expect("the session is open");
+            let status = child.wait()?;
+            if !self.connected {
+                return Err(std::io::Error::other(format!(
+                    "ssh to {} failed: {status}",
+                    self.remote.destination
+                )));
+            }
+            eprintln!(
+                "connection to {} lost ({status}), reconnecting in {}s",
+                self.remote.destination,
+                self.backoff.as_secs()
+            );
+            std::thread::sleep(self.backoff);
+            self.backoff = (self.backoff * 2).min(Self::MAX_BACKOFF);
+        }
+    }
+}
+
+impl Drop for RemoteTail {
+    fn drop(&mut self) {
+        if let Some((mut child, _)) = self.session.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Quote the argument for the shell of the remote host.
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
@@ -0,0 +1,613 @@
+use std::{collections::BTreeMap, fmt, io::Write, str::FromStr, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde_json::{json, Value};
+
+use crate::{
+    budgets::{BudgetChecks, Budgets},
+    concurrency::Concurrency,
+    json,
+    limited::RateLimitedClients,
+    locale::Locale,
+    record::{CacheStatus, LogRecord},
+    redirects::Redirects,
+    retries::Retries,
+    severity::Severity,
+    sink::{Entry, Output, Sink},
+    trends::PathTrends,
+};
+
+/// A column of the statistics table.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Column {
+    Host,
+    Count,
+    ErrorRate,
+    CacheHitRatio,
+    Percentile(f64),
+}
+
+impl FromStr for Column {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "host" => Ok(Self::Host),
+            "count" => Ok(Self::Count),
+            "error_rate" => Ok(Self::ErrorRate),
+            "cache_hit_ratio" => Ok(Self::CacheHitRatio),
+            _ => match s.strip_prefix('p') {
+                Some(percentile) => Ok(Self::Percentile(parse_percentile(percentile)?)),
+                None => bail!("unknown stats column: {}", s),
+            },
+        }
+    }
+}
+
+/// Parse a percentile, a number between 0 and 100.
+pub fn parse_percentile(s: &str) -> Result<f64> {
+    let percentile = s
+        .parse::<f64>()
+        .with_context(|| format!("invalid percentile: {}", s))?;
+    if !(0.0..=100.0).contains(&percentile) {
+        bail!("percentile out of range: {}", s);
+    }
+    Ok(percentile)
+}
+
+/// The segments of the time the requests are reported by, besides the hosts.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Segment {
+    /// The hours of the day, in UTC.
+    Hour,
+    /// The days of the week.
+    Weekday,
+}
+
+/// An hour of the day or a day of the week, from Monday.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Slot {
+    Hour(u8),
+    Weekday(u8),
+}
+
+impl Segment {
+    fn name(self) -> &'static str {
+        match self {
+            Segment::Hour => "hour",
+            Segment::Weekday => "weekday",
+        }
+    }
+
+    fn slot(self, record: &LogRecord) -> Slot {
+        let datetime = record.datetime();
+        match self {
+            Segment::Hour => Slot::Hour(datetime.hour()),
+            Segment::Weekday => Slot::Weekday(datetime.weekday().number_days_from_monday()),
+        }
+    }
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+        match *self {
+            Slot::Hour(hour) => write!(f, "{hour:02}:00"),
+            Slot::Weekday(day) => f.write_str(WEEKDAYS[day as usize]),
+        }
+    }
+}
+
+pub struct StatsOptions {
+    /// The percentiles of the duration reported for each host.
+    pub percentiles: Vec<f64>,
+    /// The column used to sort the table, numeric columns are sorted in descending order.
+    pub sort: Column,
+    /// The conventions used to render the numbers.
+    pub locale: Locale,
+    /// Summarize the identical requests of a client repeated within this window.
+    pub retry_window: Option<Duration>,
+    /// Report the trend of the duration of the busiest paths.
+    pub path_trends: bool,
+    /// Report the redirects from their sources to their targets.
+    pub redirects: bool,
+    /// Report the requests by hour of the day or by day of the week too.
+    pub segment: Option<Segment>,
+    /// Report the requests in flight over time.
+    pub concurrency: bool,
+    /// The latency expected for the routes, the requests over it are counted.
+    pub budgets: Budgets,
+    /// How the statistics are printed: as tables, or as a JSON document with `json` and
+    /// `json-pretty`.
+    pub output: Output,
+}
+
+impl StatsOptions {
+    pub fn new(percentiles: Vec<f64>, sort: Column, locale: Locale) -> Result<Self> {
+        if let Column::Percentile(percentile) = sort {
+            if !percentiles.contains(&percentile) {
+                bail!(
+                    "cannot sort by p{}, it is not a reported percentile",
+                    percentile
+                );
+            }
+        }
+        Ok(Self {
+            percentiles,
+            sort,
+            locale,
+            retry_window: None,
+            path_trends: false,
+            redirects: false,
+            segment: None,
+            concurrency: false,
+            budgets: Budgets::default(),
+            output: Output::Pretty,
+        })
+    }
+}
+
+#[derive(Default)]
+struct HostStats {
+    count: u64,
+    server_errors: u64,
+    /// The responses served by a cache, and the ones which were not; the bypassed ones are
+    /// not counted.
+    cache_hits: u64,
+    cache_misses: u64,
+    durations: Vec<f64>,
+}
+
+impl HostStats {
+    fn observe(&mut self, record: &LogRecord) {
+        self.count += 1;
+        if record.severity() == Some(Severity::ServerError) {
+            self.server_errors += 1;
+        }
+        match record.cache_status() {
+            Some(cache) if cache.is_hit() => self.cache_hits += 1,
+            Some(CacheStatus::Miss) => self.cache_misses += 1,
+            _ => {}
+        }
+        if let Some(duration) = record.duration {
+            self.durations.push(duration);
+        }
+    }
+
+    fn error_rate(&self) -> f64 {
+        self.server_errors as f64 / self.count as f64
+    }
+
+    /// The share of the cacheable responses served by a cache, when any has a cache status.
+    fn cache_hit_ratio(&self) -> Option<f64> {
+        let lookups = self.cache_hits + self.cache_misses;
+        (lookups > 0).then(|| self.cache_hits as f64 / lookups as f64)
+    }
+
+    /// The duration percentile, using the nearest-rank method. The durations are sorted.
+    fn percentile(&self, percentile: f64) -> Option<f64> {
+        percentile_of(&self.durations, percentile)
+    }
+}
+
+/// The nearest-rank percentile of sorted values.
+pub fn percentile_of(sorted: &[f64], percentile: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// Collect per-host statistics of the requests, the table is printed when the input ends. When
+/// multiple sources are read, the statistics of each source are reported too.
+pub struct StatsSink {
+    options: StatsOptions,
+    hosts: BTreeMap<String, HostStats>,
+    sources: BTreeMap<String, HostStats>,
+    segments: BTreeMap<Slot, HostStats>,
+    limited: RateLimitedClients,
+    retries: Option<Retries>,
+    trends: Option<PathTrends>,
+    redirects: Option<Redirects>,
+    concurrency: Option<Concurrency>,
+    budgets: Option<BudgetChecks>,
+}
+
+impl StatsSink {
+    pub fn new(options: StatsOptions) -> Self {
+        Self {
+            hosts: BTreeMap::new(),
+            sources: BTreeMap::new(),
+            segments: BTreeMap::new(),
+            limited: RateLimitedClients::default(),
+            retries: options.retry_window.map(Retries::new),
+            trends: options.path_trends.then(PathTrends::default),
+            redirects: options.redirects.then(Redirects::default),
+            concurrency: options.concurrency.then(Concurrency::default),
+            budgets: (!options.budgets.is_empty())
+                .then(|| BudgetChecks::new(options.budgets.clone())),
+            options,
+        }
+    }
+
+    fn value(&self, stats: &HostStats, column: Column) -> f64 {
+        match column {
+            Column::Host => 0.0,
+            Column::Count => stats.count as f64,
+            Column::ErrorRate => stats.error_rate(),
+            Column::CacheHitRatio => stats.cache_hit_ratio().unwrap_or(0.0),
+            Column::Percentile(percentile) => stats.percentile(percentile).unwrap_or(0.0),
+        }
+    }
+
+    fn render(&mut self) -> String {
+        let groups = self.hosts.values_mut().chain(self.sources.values_mut());
+        for stats in groups.chain(self.segments.values_mut()) {
+            stats.durations.sort_by(f64::total_cmp);
+        }
+        match self.options.output {
+            Output::Json => return format!("{}\n", self.to_json()),
+            Output::JsonPretty => return format!("{}\n", json::format_pretty(&self.to_json())),
+            _ => {}
+        }
+        let locale = &self.options.locale;
+        let mut output = self.format_stats("host", self.sorted(&self.hosts));
+        if !self.sources.is_empty() {
+            output.push('\n');
+            output.push_str(&self.format_stats("source", self.sorted(&self.sources)));
+        }
+        if let Some(segment) = self.options.segment {
+            output.push('\n');
+            output.push_str(&self.format_stats(segment.name(), self.segments()));
+        }
+        if let Some(concurrency) = self.concurrency.as_ref().and_then(|c| c.render(locale)) {
+            output.push('\n');
+            output.push_str(&concurrency);
+        }
+        if let Some(trends) = self.trends.as_ref().and_then(|t| t.render(locale)) {
+            output.push('\n');
+            output.push_str(&trends);
+        }
+        if let Some(redirects) = self.redirects.as_ref().and_then(|r| r.render(locale)) {
+            output.push('\n');
+            output.push_str(&redirects);
+        }
+        if let Some(budgets) = self.budgets.as_ref().and_then(|b| b.render(locale)) {
+            output.push('\n');
+            output.push_str(&budgets);
+        }
+        if let Some(limited) = self.limited.render(locale) {
+            output.push('\n');
+            output.push_str(&limited);
+        }
+        if let Some(retries) = self.retries.as_ref().and_then(|r| r.render(locale)) {
+            output.push('\n');
+            output.push_str(&retries);
+        }
+        output
+    }
+
+    /// The statistics as a JSON document, the durations are in seconds and the sections which
+    /// are not enabled are null. The durations are sorted.
+    fn to_json(&self) -> Value {
+        json!({
+            "hosts": self.groups_to_json("host", self.sorted(&self.hosts)),
+            "sources": self.groups_to_json("source", self.sorted(&self.sources)),
+            "segments": self
+                .options
+                .segment
+                .map(|segment| self.groups_to_json(segment.name(), self.segments())),
+            "concurrency": self.concurrency.as_ref().map(Concurrency::to_json),
+            "path_trends": self.trends.as_ref().map(PathTrends::to_json),
+            "redirects": self.redirects.as_ref().map(Redirects::to_json),
+            "redirect_loops": self.redirects.as_ref().map(Redirects::loops),
+            "budgets": self.budgets.as_ref().map(BudgetChecks::to_json),
+            "rate_limited": self.limited.to_json(),
+            "retries": self.retries.as_ref().map(Retries::to_json),
+        })
+    }
+
+    fn groups_to_json(&self, name: &str, groups: Vec<(String, &HostStats)>) -> Value {
+        groups
+            .into_iter()
+            .map(|(group, stats)| {
+                let percentiles = self
+                    .options
+                    .percentiles
+                    .iter()
+                    .map(|&p| (format!("p{p}"), json!(stats.percentile(p))))
+                    .collect::<serde_json::Map<_, _>>();
+                json!({
+                    name: group,
+                    "count": stats.count,
+                    "error_rate": stats.error_rate(),
+                    "cache_hit_ratio": stats.cache_hit_ratio(),
+                    "percentiles": percentiles,
+                })
+            })
+            .collect()
+    }
+
+    /// The hosts or the sources, in the order of the sort column.
+    fn sorted<'a>(&self, groups: &'a BTreeMap<String, HostStats>) -> Vec<(String, &'a HostStats)> {
+        let mut groups = groups
+            .iter()
+            .map(|(group, stats)| (group.clone(), stats))
+            .collect::<Vec<_>>();
+        if self.options.sort != Column::Host {
+            groups.sort_by(|(_, a), (_, b)| {
+                let column = self.options.sort;
+                self.value(b, column).total_cmp(&self.value(a, column))
+            });
+        }
+        groups
+    }
+
+    /// The hours or the days with requests, in the order of the time, whatever the sort
+    /// column: the shape of the traffic is what matters.
+    fn segments(&self) -> Vec<(String, &HostStats)> {
+        self.segments
+            .iter()
+            .map(|(slot, stats)| (slot.to_string(), stats))
+            .collect()
+    }
+
+    /// The table of the statistics, one row for each host, source or segment. The durations
+    /// are sorted.
+    fn format_stats(&self, name: &str, groups: Vec<(String, &HostStats)>) -> String {
+        // the ratio is reported only when the responses tell whether they are cached
+        let cached = groups
+            .iter()
+            .any(|(_, stats)| stats.cache_hit_ratio().is_some());
+
+        let mut header = vec![name.to_string(), "count".into(), "error_rate".into()];
+        if cached {
+            header.push("cache_hit_ratio".into());
+        }
+        header.extend(self.options.percentiles.iter().map(|p| format!("p{p}")));
+        let mut rows = vec![header];
+        let locale = &self.options.locale;
+        for (group, stats) in groups {
+            let mut row = vec![
+                group,
+                locale.integer(stats.count),
+                format!("{}%", locale.decimal(stats.error_rate() * 100.0, 2)),
+            ];
+            if cached {
+                row.push(match stats.cache_hit_ratio() {
+                    Some(ratio) => format!("{}%", locale.decimal(ratio * 100.0, 1)),
+                    None => "-".into(),
+                });
+            }
+            row.extend(self.options.percentiles.iter().map(|&p| {
+                stats
+                    .percentile(p)
+                    .map(|duration| locale.localize(&LogRecord::format_duration(duration)))
+                    .unwrap_or_else(|| "-".into())
+            }));
+            rows.push(row);
+        }
+        format_table(&rows)
+    }
+}
+
+impl Sink for StatsSink {
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        let record = entry.record()?;
+        let Some(request) = &record.request else {
+            return Ok(());
+        };
+        self.limited.observe(record);
+        if let Some(retries) = &mut self.retries {
+            retries.observe(record);
+        }
+        if let Some(trends) = &mut self.trends {
+            trends.observe(record);
+        }
+        if let Some(redirects) = &mut self.redirects {
+            redirects.observe(record);
+        }
+        if let Some(concurrency) = &mut self.concurrency {
+            concurrency.observe(record);
+        }
+        if let Some(budgets) = &mut self.budgets {
+            budgets.observe(record);
+        }
+        self.hosts
+            .entry(request.host.clone())
+            .or_default()
+            .observe(record);
+        if let Some(source) = entry.source {
+            self.sources
+                .entry(source.to_string())
+                .or_default()
+                .observe(record);
+        }
+        if let Some(segment) = self.options.segment {
+            self.segments
+                .entry(segment.slot(record))
+                .or_default()
+                .observe(record);
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let mut stdout = std::io::stdout();
+        stdout.write_all(self.render().as_bytes())?;
+        stdout.flush()?;
+        Ok(())
+    }
+
+    fn is_live(&self) -> bool {
+        false
+    }
+}
+
+/// Align the cells in columns, the first column is aligned to the left, the others to the
+/// right.
+pub fn format_table(rows: &[Vec<String>]) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths = (0..columns)
+        .map(|column| {
+            rows.iter()
+                .filter_map(|row| row.get(column))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+
+    let mut output = String::new();
+    for row in rows {
+        let cells = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(index, (cell, &width))| {
+                if index == 0 {
+                    format!("{cell:<width$}")
+                } else {
+                    format!("{cell:>width$}")
+                }
+            })
+            .collect::<Vec<_>>();
+        output.push_str(cells.join("  ").trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A request to the host at the timestamp, 2024-03-09 16:00:00 UTC is a Saturday.
+    fn line(host: &str, ts: f64, status: u16, duration: f64, cache: Option<&str>) -> String {
+        let resp_headers = match cache {
+            Some(cache) => json!({ "Cache-Status": [format!("caddy; {cache}")] }),
+            None => json!({}),
+        };
+        json!({
+            "level": "info",
+            "ts": ts,
+            "logger": "http.log.access",
+            "msg": "handled request",
+            "request": {
+                "remote_ip": "10.0.0.5",
+                "proto": "HTTP/1.1",
+                "method": "GET",
+                "host": host,
+                "uri": "/",
+                "headers": {},
+            },
+            "duration": duration,
+            "size": 512,
+            "status": status,
+            "resp_headers": resp_headers,
+        })
+        .to_string()
+    }
+
+    fn sink(options: StatsOptions, lines: &[String]) -> StatsSink {
+        let mut sink = StatsSink::new(options);
+        for line in lines {
+            let record = serde_json::from_str::<LogRecord>(line).unwrap();
+            sink.write(&Entry::new(line, &record, None)).unwrap();
+        }
+        sink
+    }
+
+    #[test]
+    fn parse_the_columns() {
+        assert_eq!("count".parse::<Column>().unwrap(), Column::Count);
+        assert_eq!("p99.5".parse::<Column>().unwrap(), Column::Percentile(99.5));
+        assert!("p101".parse::<Column>().is_err());
+        assert!("latency".parse::<Column>().is_err());
+        // only the reported percentiles sort the table
+        assert!(StatsOptions::new(vec![50.0], Column::Percentile(99.0), Locale::C).is_err());
+    }
+
+    #[test]
+    fn take_the_nearest_rank() {
+        let sorted = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(percentile_of(&sorted, 0.0), Some(1.0));
+        assert_eq!(percentile_of(&sorted, 50.0), Some(2.0));
+        assert_eq!(percentile_of(&sorted, 51.0), Some(3.0));
+        assert_eq!(percentile_of(&sorted, 100.0), Some(4.0));
+        assert_eq!(percentile_of(&[], 50.0), None);
+    }
+
+    #[test]
+    fn sort_the_hosts() {
+        let ts = 1710000000.0;
+        let lines = [
+            line("a.example.com", ts, 200, 0.1, None),
+            line("b.example.com", ts, 200, 0.3, None),
+            line("b.example.com", ts, 500, 0.2, None),
+        ];
+        let options = StatsOptions::new(vec![50.0], Column::Count, Locale::C).unwrap();
+        let table = sink(options, &lines).render();
+        assert_eq!(
+            table,
+            "host           count  error_rate         p50\n\
+             b.example.com      2      50.00%  200.000 ms\n\
+             a.example.com      1       0.00%  100.000 ms\n"
+        );
+    }
+
+    #[test]
+    fn report_the_cache_hit_ratio() {
+        let ts = 1710000000.0;
+        let lines = [
+            line("a.example.com", ts, 200, 0.1, Some("hit")),
+            line("a.example.com", ts, 200, 0.1, Some("fwd=miss")),
+            line("a.example.com", ts, 200, 0.1, Some("fwd=bypass")),
+            line("b.example.com", ts, 200, 0.1, None),
+        ];
+        let mut options = StatsOptions::new(vec![], Column::CacheHitRatio, Locale::C).unwrap();
+        options.output = Output::Json;
+        let stats = sink(options, &lines).to_json();
+        assert_eq!(stats["hosts"][0]["host"], "a.example.com");
+        // the bypassed responses are not counted
+        assert_eq!(stats["hosts"][0]["cache_hit_ratio"], 0.5);
+        assert_eq!(stats["hosts"][1]["cache_hit_ratio"], Value::Null);
+        assert_eq!(stats["segments"], Value::Null);
+    }
+
+    #[test]
+    fn segment_the_requests() {
+        let ts = 1710000000.0;
+        let lines = [
+            line("a.example.com", ts, 200, 0.1, None),
+            line("a.example.com", ts + 3600.0, 200, 0.1, None),
+            line("a.example.com", ts + 86400.0, 200, 0.1, None),
+        ];
+        let mut options = StatsOptions::new(vec![], Column::Count, Locale::C).unwrap();
+        options.segment = Some(Segment::Hour);
+        let stats = sink(options, &lines).to_json();
+        let hours = stats["segments"].as_array().unwrap();
+        assert_eq!(hours[0]["hour"], "16:00");
+        assert_eq!(hours[0]["count"], 2);
+        assert_eq!(hours[1]["hour"], "17:00");
+
+        let mut options = StatsOptions::new(vec![], Column::Count, Locale::C).unwrap();
+        options.segment = Some(Segment::Weekday);
+        let table = sink(options, &lines).render();
+        assert!(table.ends_with(
+            "weekday  count  error_rate\nSat          2       0.00%\nSun          1       0.00%\n"
+        ));
+    }
+
+    #[test]
+    fn align_the_columns() {
+        let rows = [
+            vec!["name".to_string(), "count".into()],
+            vec!["ünïcode".into(), "7".into()],
+            vec!["a".into(), String::new()],
+        ];
+        assert_eq!(format_table(&rows), "name     count\nünïcode      7\na\n");
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Result;
+
+use crate::{
+    expr::Expr,
+    rate::RateLimiter,
+    record::LogRecord,
+    severity::Severity,
+    sink::{Entry, Sink},
+    stats,
+};
+
+/// Print a one-line summary of the records of each period, and a line for each record matching
+/// the alert condition: with `--quiet` the viewer can watch the logs unattended, under a
+/// process supervisor. The periods follow the clock, not the timestamps of the records, so a
+/// summary is printed also when no record arrives.
+pub struct SummarySink {
+    window: Arc<Mutex<Window>>,
+    periodic: bool,
+    alert: Option<Expr>,
+    limiter: RateLimiter,
+    skipped: u64,
+}
+
+impl SummarySink {
+    pub fn new(period: Option<Duration>, alert: Option<Expr>, limiter: RateLimiter) -> Self {
+        let window = Arc::new(Mutex::new(Window::default()));
+        if let Some(period) = period {
+            let window = Arc::clone(&window);
+            thread::spawn(move || loop {
+                thread::sleep(period);
+                let line = window.lock().unwrap().take_line();
+                println!("{line}");
+            });
+        }
+        Self {
+            window,
+            periodic: period.is_some(),
+            alert,
+            limiter,
+            skipped: 0,
+        }
+    }
+
+    fn alert_line(record: &LogRecord) -> String {
+        let timestamp = LogRecord::format_timestamp(record.timestamp);
+        let Some(request) = &record.request else {
+            let level = format!("{:?}", record.level).to_uppercase();
+            return format!("[{timestamp}] ALERT {level} {}", record.message);
+        };
+        let mut line = format!("[{timestamp}] ALERT");
+        if let Some(status) = record.status {
+            line.push_str(&format!(" {}", status.as_u16()));
+        }
+        line.push_str(&format!(
+            " {} {}{}",
+            request.method, request.host, request.uri
+        ));
+        if let Some(duration) = record.duration {
+            line.push_str(&format!(" {}", LogRecord::format_duration(duration)));
+        }
+        line
+    }
+}
+
+/// The records of the current period.
+#[derive(Default)]
+struct Window {
+    records: u64,
+    requests: u64,
+    server_errors: u64,
+    durations: Vec<f64>,
+    alerts: u64,
+}
+
+impl Window {
+    /// The summary of the period, which is started again.
+    fn take_line(&mut self) -> String {
+        let mut window = std::mem::take(self);
+        window.durations.sort_by(f64::total_cmp);
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut line = format!(
+            "[{}] {} records, {} requests, {} server errors",
+            LogRecord::format_timestamp(now.as_secs_f64()),
+            window.records,
+            window.requests,
+            window.server_errors
+        );
+        if window.requests > 0 {
+            let rate = 100.0 * window.server_errors as f64 / window.requests as f64;
+            line.push_str(&format!(" ({rate:.2}%)"));
+        }
+        for percentile in [50.0, 95.0] {
+            if let Some(duration) = stats::percentile_of(&window.durations, percentile) {
+                let duration = LogRecord::format_duration(duration);
+                line.push_str(&format!(", p{percentile} {}", duration.trim()));
+            }
+        }
+        line.push_str(&format!(", {} alerts", window.alerts));
+        line
+    }
+}
+
+impl Sink for SummarySink {
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        let record = entry.record()?;
+        let alert = self
+            .alert
+            .as_ref()
+            .is_some_and(|condition| condition.matches(record));
+        {
+            let mut window = self.window.lock().unwrap();
+            window.records += 1;
+            if record.request.is_some() {
+                window.requests += 1;
+                if record.severity() == Some(Severity::ServerError) {
+                    window.server_errors += 1;
+                }
+                if let Some(duration) = record.duration {
+                    window.durations.push(duration);
+                }
+            }
+            if alert {
+                window.alerts += 1;
+            }
+        }
+        if alert {
+            match self.limiter.try_acquire() {
+                true => println!("{}", Self::alert_line(record)),
+                false => self.skipped += 1,
+            }
+        }
+        Ok(())
+    }
+
+    /// The summary of the last period, which is not complete, is printed too.
+    fn finish(&mut self) -> Result<()> {
+        if self.periodic {
+            let line = self.window.lock().unwrap().take_line();
+            println!("{line}");
+        }
+        if self.skipped > 0 {
+            eprintln!("{} alerts not printed by the rate limit", self.skipped);
+        }
+        Ok(())
+    }
+}
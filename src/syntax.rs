@@ -0,0 +1,43 @@
+use std::fmt;
+
+/// An error found while parsing an expression, with the position where it was found.
+#[derive(Debug)]
+pub struct SyntaxError {
+    pub message: String,
+    pub input: String,
+    /// The byte offset of the error in the input.
+    pub position: usize,
+}
+
+impl SyntaxError {
+    pub fn new(message: impl Into<String>, input: &str, position: usize) -> Self {
+        Self {
+            message: message.into(),
+            input: input.to_string(),
+            position,
+        }
+    }
+
+    /// The input with a caret pointing to the position of the error.
+    pub fn render(&self) -> String {
+        let column = self
+            .input
+            .get(..self.position)
+            .map_or(self.position, |prefix| prefix.chars().count());
+        format!("{}\n{:column$}^ {}", self.input, "", self.message)
+    }
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at position {} of `{}`",
+            self.message,
+            self.position + 1,
+            self.input
+        )
+    }
+}
+
+impl std::error::Error for SyntaxError {}
@@ -0,0 +1,413 @@
+use colored::Colorize;
+use http::header::USER_AGENT;
+use serde::Deserialize;
+
+use crate::{
+    enrich, palette,
+    record::{self, CacheStatus, LogRecord},
+};
+
+/// The columns of the table layout, one line per record. The layout is set by the `table`
+/// section of the config file, e.g.
+///
+/// ```yaml
+/// table:
+///   columns:
+///     - field: time
+///     - field: status
+///     - field: duration
+///       width: 12
+///     - field: uri
+///       width: 40
+///       truncate: start
+///     - field: user_agent
+///       priority: 0
+/// ```
+///
+/// When a line is wider than the terminal, the columns are shortened in the order of their
+/// priority, the lowest first: by default the user agent, then the host and the URI, while the
+/// status and the duration are kept.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TableLayout {
+    columns: Vec<Column>,
+    /// The text between the columns.
+    #[serde(default = "TableLayout::default_separator")]
+    separator: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Column {
+    field: Field,
+    /// The values are padded or truncated to this number of characters, they are left as they
+    /// are when missing.
+    width: Option<usize>,
+    /// The part of the values removed when they are too long, by default the query of the URIs
+    /// and the end of the other values.
+    truncate: Option<Truncate>,
+    /// The side the values are aligned to, by default the numbers are aligned to the right.
+    align: Option<Align>,
+    /// The columns with the lowest priority are shortened first when the line is too wide.
+    priority: Option<u8>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Field {
+    Timestamp,
+    Time,
+    Level,
+    Logger,
+    Status,
+    Duration,
+    Size,
+    Method,
+    Host,
+    Uri,
+    RemoteIp,
+    UserAgent,
+    /// Whether the response was served by a cache, e.g. `HIT` or `MISS`.
+    Cache,
+    /// The request line, or the message of the records without a request.
+    Message,
+    /// `bot` for the requests of the crawlers, the bots and the scanners.
+    Bot,
+    /// The family of the user agent, e.g. `Firefox` or `curl`.
+    UaFamily,
+    /// The server name requested by the client (SNI).
+    Sni,
+    /// The backend the reverse proxy forwarded the request to.
+    Upstream,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Truncate {
+    End,
+    Start,
+    /// The query of the URI is shortened first, then the end of the value.
+    Query,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Align {
+    Left,
+    Right,
+}
+
+impl Default for TableLayout {
+    fn default() -> Self {
+        let column = |field, width| Column {
+            field,
+            width,
+            truncate: None,
+            align: None,
+            priority: None,
+        };
+        Self {
+            columns: vec![
+                column(Field::Time, None),
+                column(Field::Level, Some(5)),
+                column(Field::Status, Some(3)),
+                column(Field::Duration, Some(11)),
+                column(Field::Method, Some(7)),
+                column(Field::Host, Some(24)),
+                column(Field::Message, None),
+            ],
+            separator: Self::default_separator(),
+        }
+    }
+}
+
+impl TableLayout {
+    fn default_separator() -> String {
+        "  ".to_string()
+    }
+
+    /// Format the record on a single line, the fields are appended after the columns. When the
+    /// line is wider than the given width, the columns are shortened by priority.
+    pub fn format(
+        &self,
+        record: &LogRecord,
+        message: &str,
+        fields: &[(&'static str, String)],
+        width: Option<usize>,
+    ) -> String {
+        let values = self
+            .columns
+            .iter()
+            .map(|column| column.field.value(record, message))
+            .collect::<Vec<_>>();
+        // the continuation lines of a field have no label
+        let fields = fields
+            .iter()
+            .map(|(label, value)| match label.is_empty() {
+                true => value.clone(),
+                false => format!("{}: {value}", label.dimmed()),
+            })
+            .collect::<Vec<_>>();
+        let mut widths = self
+            .columns
+            .iter()
+            .zip(&values)
+            .map(|(column, value)| column.width.unwrap_or_else(|| record::visible_width(value)))
+            .collect::<Vec<_>>();
+        if let Some(width) = width {
+            self.shrink(&mut widths, width);
+        }
+
+        let mut cells = self
+            .columns
+            .iter()
+            .zip(values)
+            .zip(widths)
+            .map(|((column, value), width)| {
+                // the columns without a width are truncated only when they are shortened
+                let width = Some(width).filter(|&width| {
+                    column.width.is_some() || width < record::visible_width(&value)
+                });
+                column.format(record, value, width)
+            })
+            .collect::<Vec<_>>();
+        cells.extend(fields);
+        cells.join(&self.separator).trim_end().to_string()
+    }
+
+    /// Shorten the columns with the lowest priority first, until they fit the width. The fields
+    /// after the columns are not counted, they are truncated with the end of the line.
+    fn shrink(&self, widths: &mut [usize], width: usize) {
+        let separators = widths.len().saturating_sub(1);
+        let length = widths.iter().sum::<usize>() + separators * self.separator.chars().count();
+        // the last character of the terminal is left empty, like the truncated lines
+        let mut excess = (length + 1).saturating_sub(width);
+        let mut order = (0..widths.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&index| self.columns[index].priority());
+        for index in order {
+            if excess == 0 {
+                break;
+            }
+            let shrink = excess.min(widths[index].saturating_sub(Column::MIN_WIDTH));
+            widths[index] -= shrink;
+            excess -= shrink;
+        }
+    }
+}
+
+impl Column {
+    /// The columns are not shortened below this width.
+    const MIN_WIDTH: usize = 5;
+
+    fn priority(&self) -> u8 {
+        self.priority.unwrap_or(self.field.priority())
+    }
+
+    fn format(&self, record: &LogRecord, value: String, width: Option<usize>) -> String {
+        let Some(width) = width else {
+            return self.field.colorize(record, value);
+        };
+        // the message of the records without a request may be colored
+        let length = record::visible_width(&value);
+        let value = match self.truncate.unwrap_or(self.field.truncate()) {
+            _ if length <= width => value,
+            Truncate::Query => {
+                let mut value = value;
+                record::elide_query(&mut value, length - width);
+                truncate_end(value, width)
+            }
+            Truncate::Start => {
+                let skipped = length - width + 1;
+                format!("…{}", value.chars().skip(skipped).collect::<String>())
+            }
+            Truncate::End => truncate_end(value, width),
+        };
+        // the padding is added after the colors, they have no width
+        let padding = " ".repeat(width.saturating_sub(record::visible_width(&value)));
+        let value = self.field.colorize(record, value);
+        match self.align.unwrap_or(self.field.align()) {
+            Align::Right => format!("{padding}{value}"),
+            Align::Left => format!("{value}{padding}"),
+        }
+    }
+}
+
+impl Field {
+    fn value(self, record: &LogRecord, message: &str) -> String {
+        let request = record.request.as_ref();
+        let value = match self {
+            Field::Timestamp => Some(LogRecord::format_timestamp(record.timestamp)),
+            Field::Time => {
+                let (hour, minute, second) = record.datetime().to_hms();
+                Some(format!("{hour:02}:{minute:02}:{second:02}"))
+            }
+            Field::Level => Some(format!("{:?}", record.level).to_uppercase()),
+            Field::Logger => record.logger.clone(),
+            Field::Status => record.status.map(|status| status.as_u16().to_string()),
+            Field::Duration => record.duration.map(LogRecord::format_duration),
+            Field::Size => record.size.map(|size| size.to_string()),
+            Field::Method => request.map(|request| request.method.to_string()),
+            Field::Host => request.map(|request| request.host.clone()),
+            Field::Uri => request.map(|request| request.uri.clone()),
+            Field::RemoteIp => request.map(|request| request.remote_ip.to_string()),
+            Field::UserAgent => request
+                .and_then(|request| request.headers.get(USER_AGENT))
+                .and_then(|value| value.to_str().ok())
+                .map(String::from),
+            Field::Cache => record.cache_status().map(|cache| cache.to_string()),
+            Field::Message => Some(message.to_string()),
+            Field::Bot => enrich::bot(record).map(|bot| match bot {
+                true => "bot".to_string(),
+                false => String::new(),
+            }),
+            Field::UaFamily => enrich::user_agent_family(record).map(String::from),
+            Field::Sni => request
+                .and_then(|request| request.tls.as_ref())
+                .and_then(|tls| tls.server_name.clone()),
+            Field::Upstream => record.upstream.clone(),
+        };
+        value.unwrap_or_else(|| "-".to_string())
+    }
+
+    fn truncate(self) -> Truncate {
+        match self {
+            Field::Uri | Field::Message => Truncate::Query,
+            _ => Truncate::End,
+        }
+    }
+
+    /// The order the columns are shortened in, the least important first.
+    fn priority(self) -> u8 {
+        match self {
+            Field::UserAgent | Field::UaFamily => 1,
+            Field::Logger => 2,
+            Field::Host | Field::RemoteIp | Field::Sni | Field::Upstream => 3,
+            Field::Uri | Field::Message => 4,
+            Field::Method | Field::Cache | Field::Size | Field::Bot => 5,
+            Field::Timestamp | Field::Time => 6,
+            Field::Level | Field::Duration => 7,
+            Field::Status => 8,
+        }
+    }
+
+    fn align(self) -> Align {
+        match self {
+            Field::Status | Field::Duration | Field::Size => Align::Right,
+            _ => Align::Left,
+        }
+    }
+
+    fn colorize(self, record: &LogRecord, value: String) -> String {
+        match self {
+            Field::Level => palette::level(record.level, &value).to_string(),
+            Field::Status => match record.severity() {
+                Some(severity) => severity.colorize(&value).to_string(),
+                None => value,
+            },
+            Field::Cache => match record.cache_status() {
+                Some(CacheStatus::Hit) => value.green().to_string(),
+                Some(CacheStatus::Stale | CacheStatus::Miss) => value.yellow().to_string(),
+                Some(CacheStatus::Bypass) => value.dimmed().to_string(),
+                None => value,
+            },
+            _ => value,
+        }
+    }
+}
+
+/// Remove the end of the value, so that it fits the width with an ellipsis.
+fn truncate_end(mut value: String, width: usize) -> String {
+    // the truncated lines leave a character empty
+    record::truncate_line(&mut value, width + 1);
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(uri: &str) -> LogRecord {
+        let line = serde_json::json!({
+            "level": "info",
+            "ts": 1710000000.5,
+            "logger": "http.log.access",
+            "msg": "handled request",
+            "request": {
+                "remote_ip": "10.0.0.5",
+                "proto": "HTTP/1.1",
+                "method": "GET",
+                "host": "www.example.com",
+                "uri": uri,
+                "headers": { "User-Agent": ["Mozilla/5.0 (X11; Linux x86_64)"] },
+            },
+            "duration": 0.25,
+            "size": 512,
+            "status": 200,
+            "resp_headers": {},
+        });
+        serde_json::from_value(line).unwrap()
+    }
+
+    fn parse(yaml: &str) -> TableLayout {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn pad_and_align_the_columns() {
+        let layout = parse(
+            "{ columns: [{ field: time }, { field: method, width: 6 }, { field: size, width: 5 }, \
+               { field: uri }, { field: upstream }], separator: ' | ' }",
+        );
+        let record = record("/");
+        let line = layout.format(&record, "", &[("", "continued".into())], None);
+        assert_eq!(line, "16:00:00 | GET    |   512 | / | - | continued");
+    }
+
+    #[test]
+    fn truncate_the_values() {
+        let layout = parse(
+            "columns: [{ field: host, width: 8 }, { field: host, width: 8, truncate: start }, \
+               { field: uri, width: 12, align: right }]",
+        );
+        let line = layout.format(&record("/search?q=caddy"), "", &[], None);
+        assert_eq!(line, "www.exa…  …ple.com  /search?q=c…");
+        let line = layout.format(&record("/a"), "", &[], None);
+        assert_eq!(line, "www.exa…  …ple.com            /a");
+    }
+
+    #[test]
+    fn shorten_the_lowest_priority_first() {
+        let layout = parse(
+            "columns: [{ field: method }, { field: host }, { field: uri }, { field: user_agent }]",
+        );
+        let record = record("/search?q=caddy");
+        let line = layout.format(&record, "", &[], None);
+        assert_eq!(
+            line,
+            "GET  www.example.com  /search?q=caddy  Mozilla/5.0 (X11; Linux x86_64)"
+        );
+        // the user agent is shortened to its minimum width, then the host
+        let line = layout.format(&record, "", &[], Some(40));
+        assert_eq!(line, "GET  www.examp…  /search?q=caddy  Mozi…");
+        assert!(record::visible_width(&line) < 40);
+
+        // the priority of a column can be changed
+        let layout = parse(
+            "columns: [{ field: method }, { field: host }, { field: uri, priority: 0 }, \
+               { field: user_agent }]",
+        );
+        let line = layout.format(&record, "", &[], Some(61));
+        assert_eq!(
+            line,
+            "GET  www.example.com  /sea…  Mozilla/5.0 (X11; Linux x86_64)"
+        );
+    }
+
+    #[test]
+    fn reject_the_unknown_settings() {
+        assert!(serde_yaml::from_str::<TableLayout>("columns: [{ field: referer }]").is_err());
+        assert!(
+            serde_yaml::from_str::<TableLayout>("columns: [{ field: uri, wrap: true }]").is_err()
+        );
+    }
+}
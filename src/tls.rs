@@ -0,0 +1,142 @@
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use serde_with::{serde_as, DefaultOnError};
+
+use crate::record::strip_port;
+
+/// The TLS connection of a request, the `request.tls` object of the access logs.
+#[serde_as]
+#[derive(Debug, Default, Deserialize)]
+pub struct LogTls {
+    /// The version, as the number of the protocol, e.g. `772` for TLS 1.3.
+    #[serde(default)]
+    #[serde_as(as = "DefaultOnError")]
+    pub version: Option<u16>,
+    /// The cipher suite, as its IANA number, e.g. `4865` for `TLS_AES_128_GCM_SHA256`.
+    #[serde(default)]
+    #[serde_as(as = "DefaultOnError")]
+    pub cipher_suite: Option<u16>,
+    /// The name of the server requested by the client (SNI).
+    #[serde(default)]
+    pub server_name: Option<String>,
+}
+
+impl LogTls {
+    /// Whether the client asked for a server other than the one of the `Host` header, the port
+    /// and the case are ignored. The connections without a server name are not a mismatch.
+    pub fn server_name_mismatch(&self, host: &str) -> bool {
+        match self.server_name.as_deref() {
+            Some(server_name) if !server_name.is_empty() => {
+                !server_name.eq_ignore_ascii_case(strip_port(host))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The versions of the protocol, with their numbers.
+const VERSIONS: &[(u16, &str)] = &[
+    (0x0300, "SSL 3.0"),
+    (0x0301, "TLS 1.0"),
+    (0x0302, "TLS 1.1"),
+    (0x0303, "TLS 1.2"),
+    (0x0304, "TLS 1.3"),
+];
+
+/// The cipher suites supported by Go, with their IANA numbers.
+const CIPHER_SUITES: &[(u16, &str)] = &[
+    (0x0005, "TLS_RSA_WITH_RC4_128_SHA"),
+    (0x000a, "TLS_RSA_WITH_3DES_EDE_CBC_SHA"),
+    (0x002f, "TLS_RSA_WITH_AES_128_CBC_SHA"),
+    (0x0035, "TLS_RSA_WITH_AES_256_CBC_SHA"),
+    (0x003c, "TLS_RSA_WITH_AES_128_CBC_SHA256"),
+    (0x009c, "TLS_RSA_WITH_AES_128_GCM_SHA256"),
+    (0x009d, "TLS_RSA_WITH_AES_256_GCM_SHA384"),
+    (0xc007, "TLS_ECDHE_ECDSA_WITH_RC4_128_SHA"),
+    (0xc009, "TLS_ECDHE_ECDSA_WITH_AES_128_CBC_SHA"),
+    (0xc00a, "TLS_ECDHE_ECDSA_WITH_AES_256_CBC_SHA"),
+    (0xc011, "TLS_ECDHE_RSA_WITH_RC4_128_SHA"),
+    (0xc012, "TLS_ECDHE_RSA_WITH_3DES_EDE_CBC_SHA"),
+    (0xc013, "TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA"),
+    (0xc014, "TLS_ECDHE_RSA_WITH_AES_256_CBC_SHA"),
+    (0xc023, "TLS_ECDHE_ECDSA_WITH_AES_128_CBC_SHA256"),
+    (0xc027, "TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA256"),
+    (0xc02b, "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256"),
+    (0xc02c, "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384"),
+    (0xc02f, "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256"),
+    (0xc030, "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384"),
+    (0xcca8, "TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256"),
+    (0xcca9, "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256"),
+    (0x1301, "TLS_AES_128_GCM_SHA256"),
+    (0x1302, "TLS_AES_256_GCM_SHA384"),
+    (0x1303, "TLS_CHACHA20_POLY1305_SHA256"),
+];
+
+/// The name of the version, e.g. `TLS 1.2`, or its number in hex when it is unknown.
+pub fn version_name(version: u16) -> String {
+    VERSIONS
+        .iter()
+        .find(|(number, _)| *number == version)
+        .map_or_else(|| format!("0x{version:04x}"), |(_, name)| name.to_string())
+}
+
+/// The IANA name of the cipher suite, or its number in hex when it is unknown.
+pub fn cipher_suite_name(cipher_suite: u16) -> String {
+    CIPHER_SUITES
+        .iter()
+        .find(|(number, _)| *number == cipher_suite)
+        .map_or_else(
+            || format!("0x{cipher_suite:04x}"),
+            |(_, name)| name.to_string(),
+        )
+}
+
+/// Parse a version written like `1.2`, `TLS 1.2` or `tlsv1.2`, its number is returned.
+pub fn parse_version(s: &str) -> Result<u16> {
+    let normalized = s.to_ascii_lowercase().replace([' ', 'v'], "");
+    let version = match normalized.as_str() {
+        "ssl3" | "ssl3.0" => 0x0300,
+        "1.0" | "tls1.0" | "tls1" => 0x0301,
+        "1.1" | "tls1.1" => 0x0302,
+        "1.2" | "tls1.2" => 0x0303,
+        "1.3" | "tls1.3" => 0x0304,
+        _ => bail!(
+            "invalid TLS version: {}, expected one of 1.0, 1.1, 1.2, 1.3",
+            s
+        ),
+    };
+    Ok(version)
+}
+
+/// A cipher suite, by its number (e.g. `0xc013`) or by a glob pattern of its name, or a part
+/// of its name, the case is ignored (e.g. `CBC` or `TLS_ECDHE_*_SHA`).
+pub enum CipherPattern {
+    Number(u16),
+    Glob(glob::Pattern),
+    Substring(String),
+}
+
+impl CipherPattern {
+    pub fn parse(s: &str) -> Result<Self> {
+        let number = match s.strip_prefix("0x") {
+            Some(hex) => u16::from_str_radix(hex, 16).ok(),
+            None => s.parse::<u16>().ok(),
+        };
+        if let Some(number) = number {
+            return Ok(Self::Number(number));
+        }
+        let s = s.to_ascii_uppercase();
+        Ok(match s.contains(['*', '?', '[']) {
+            true => Self::Glob(glob::Pattern::new(&s)?),
+            false => Self::Substring(s),
+        })
+    }
+
+    pub fn matches(&self, cipher_suite: u16) -> bool {
+        match self {
+            Self::Number(number) => *number == cipher_suite,
+            Self::Glob(pattern) => pattern.matches(&cipher_suite_name(cipher_suite)),
+            Self::Substring(part) => cipher_suite_name(cipher_suite).contains(part.as_str()),
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::HashSet;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde_json::{json, Value};
+
+use crate::{
+    enrich,
+    input::InputArgs,
+    inspect::ReportOutput,
+    locale::Locale,
+    record::LogRecord,
+    sketches::{HyperLogLog, TopValues},
+    stats::format_table,
+    units, FilterArgs,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct TopArgs {
+    /// The key the requests are counted by. This flag can be repeated to report multiple keys.
+    #[arg(long = "by", value_name = "KEY", default_value = "path")]
+    keys: Vec<TopKey>,
+
+    /// The number of most frequent values reported.
+    #[arg(short, long, value_name = "N", default_value_t = 10)]
+    number: usize,
+
+    /// The memory of the counters, shared by the keys, e.g. `64MB`. When the distinct values do
+    /// not fit, the least frequent ones are replaced and the counts become estimates, with
+    /// their error reported.
+    #[arg(long, value_name = "SIZE", default_value = "16MB")]
+    memory: String,
+
+    /// Render the numbers with the conventions of a locale (e.g. `de_DE`), or of the locale of
+    /// the environment (`LC_ALL` or `LANG`) when no value is given.
+    #[arg(
+        long,
+        value_name = "LOCALE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    locale: Option<String>,
+
+    /// How the report is printed.
+    #[arg(long, default_value = "pretty")]
+    output: ReportOutput,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    filters: FilterArgs,
+}
+
+/// The keys the requests can be counted by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, ValueEnum)]
+pub enum TopKey {
+    /// The path of the request, without the query.
+    Path,
+    /// The request URI, the query included.
+    Uri,
+    /// The address of the client.
+    #[value(name = "remote_ip")]
+    RemoteIp,
+    /// The value of the `host` header.
+    Host,
+    /// The value of the `User-Agent` header.
+    #[value(name = "user_agent")]
+    UserAgent,
+    /// The family of the user agent, e.g. `Firefox` or `curl`.
+    #[value(name = "ua_family")]
+    UaFamily,
+    /// The status code of the response.
+    Status,
+    /// The method of the request.
+    Method,
+    /// The version of HTTP of the request.
+    Proto,
+    /// The backend the reverse proxy forwarded the request to.
+    Upstream,
+}
+
+impl TopKey {
+    fn value(self, record: &LogRecord) -> Option<String> {
+        let request = record.request.as_ref()?;
+        let value = match self {
+            TopKey::Path => request
+                .uri
+                .split('?')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            TopKey::Uri => request.uri.clone(),
+            TopKey::RemoteIp => request.remote_ip.to_string(),
+            TopKey::Host => request.host.clone(),
+            TopKey::UserAgent => request
+                .headers
+                .get(http::header::USER_AGENT)?
+                .to_str()
+                .ok()?
+                .to_string(),
+            TopKey::UaFamily => enrich::user_agent_family(record)?.to_string(),
+            TopKey::Status => record.status?.as_u16().to_string(),
+            TopKey::Method => request.method.to_string(),
+            TopKey::Proto => format!("{:?}", request.version),
+            TopKey::Upstream => record.upstream.clone()?,
+        };
+        Some(value)
+    }
+
+    fn name(self) -> String {
+        self.to_possible_value()
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// Report the most frequent values of the keys of the requests, in a bounded memory: the
+/// counters of the least frequent values are reused (the space-saving algorithm), so the
+/// archives with many distinct values can be scanned, with an error estimated for each count.
+pub fn run(args: TopArgs) -> Result<()> {
+    let memory = units::parse_size(&args.memory).context("invalid --memory")?;
+    let filters = args.filters.build()?;
+    let locale = Locale::from_arg(args.locale.as_deref())?;
+    let input = args.input.open(&filters)?;
+
+    let keys = unique(&args.keys);
+    let share = memory as usize / keys.len().max(1);
+    let mut counts = keys
+        .into_iter()
+        .map(|key| KeyCounts::new(key, share))
+        .collect::<Vec<_>>();
+    for line in input {
+        let line = line?;
+        if !filters.matches_line(&line.text) {
+            continue;
+        }
+        let Ok(record) = serde_json::from_str::<LogRecord>(&line.text) else {
+            continue;
+        };
+        if !filters.matches(&record) {
+            continue;
+        }
+        for counts in &mut counts {
+            counts.observe(&record);
+        }
+    }
+    match args.output {
+        ReportOutput::Pretty => {
+            let reports = counts
+                .iter()
+                .map(|counts| counts.report(args.number, &locale))
+                .collect::<Vec<_>>();
+            print!("{}", reports.join("\n"));
+        }
+        ReportOutput::Json => {
+            let reports = counts
+                .iter()
+                .map(|counts| counts.to_json(args.number))
+                .collect::<Vec<_>>();
+            println!("{}", Value::Array(reports));
+        }
+    }
+    Ok(())
+}
+
+/// The keys repeated are reported once, in the order they are first given.
+fn unique(keys: &[TopKey]) -> Vec<TopKey> {
+    let mut seen = HashSet::new();
+    let mut keys = keys.to_vec();
+    keys.retain(|key| seen.insert(*key));
+    keys
+}
+
+/// The most frequent values of a key, and the number of its distinct values.
+struct KeyCounts {
+    key: TopKey,
+    requests: u64,
+    distinct: HyperLogLog,
+    top: TopValues,
+}
+
+impl KeyCounts {
+    fn new(key: TopKey, memory: usize) -> Self {
+        Self {
+            key,
+            requests: 0,
+            distinct: HyperLogLog::new(),
+            top: TopValues::with_memory(memory),
+        }
+    }
+
+    fn observe(&mut self, record: &LogRecord) {
+        let Some(value) = self.key.value(record) else {
+            return;
+        };
+        self.requests += 1;
+        self.distinct.insert(&value);
+        self.top.insert(&value);
+    }
+
+    fn report(&self, n: usize, locale: &Locale) -> String {
+        let (counters, memory) = self.top.usage();
+        let mut output = format!(
+            "{}: {} requests, ~{} distinct values, {} counters in {}\n",
+            self.key.name(),
+            locale.integer(self.requests),
+            locale.integer(self.distinct.estimate()),
+            locale.integer(counters as u64),
+            units::format_size(memory as f64)
+        );
+        match self.top.max_missing() {
+            0 => output.push_str("the counts are exact\n"),
+            missing => output.push_str(&format!(
+                "the counts are overestimated by at most their error, the values without a \
+                 counter occurred at most {} times\n",
+                locale.integer(missing)
+            )),
+        }
+        let mut rows = vec![vec![
+            self.key.name(),
+            "requests".into(),
+            "error".into(),
+            "share".into(),
+        ]];
+        for (value, counter) in self.top.top(n) {
+            let share = 100.0 * counter.count as f64 / self.requests.max(1) as f64;
+            rows.push(vec![
+                value.to_string(),
+                locale.integer(counter.count),
+                match counter.error {
+                    0 => "-".to_string(),
+                    error => format!("±{}", locale.integer(error)),
+                },
+                format!("{}%", locale.decimal(share, 1)),
+            ]);
+        }
+        output.push('\n');
+        output.push_str(&format_table(&rows));
+        output
+    }
+
+    /// The most frequent values, a count is at most `error` higher than the actual one.
+    fn to_json(&self, n: usize) -> Value {
+        let values = self
+            .top
+            .top(n)
+            .into_iter()
+            .map(|(value, counter)| {
+                json!({
+                    "value": value,
+                    "requests": counter.count,
+                    "error": counter.error,
+                    "share": counter.count as f64 / self.requests.max(1) as f64,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "key": self.key.name(),
+            "requests": self.requests,
+            "distinct": self.distinct.estimate(),
+            "max_missing": self.top.max_missing(),
+            "values": values,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(uri: &str, status: u16) -> LogRecord {
+        let line = json!({
+            "level": "info",
+            "ts": 1710000000.5,
+            "logger": "http.log.access",
+            "msg": "handled request",
+            "request": {
+                "remote_ip": "10.0.0.5",
+                "proto": "HTTP/2.0",
+                "method": "GET",
+                "host": "example.com",
+                "uri": uri,
+                "headers": { "User-Agent": ["curl/8.4.0"] },
+            },
+            "duration": 0.25,
+            "size": 512,
+            "status": status,
+            "resp_headers": {},
+        });
+        serde_json::from_value(line).unwrap()
+    }
+
+    #[test]
+    fn report_the_keys_once() {
+        use TopKey::*;
+        assert_eq!(
+            unique(&[Path, Status, Path, Host, Status]),
+            [Path, Status, Host]
+        );
+    }
+
+    #[test]
+    fn take_the_values_of_the_keys() {
+        let record = record("/search?q=caddy", 404);
+        let value = |key: TopKey| key.value(&record);
+        assert_eq!(value(TopKey::Path).as_deref(), Some("/search"));
+        assert_eq!(value(TopKey::Uri).as_deref(), Some("/search?q=caddy"));
+        assert_eq!(value(TopKey::Status).as_deref(), Some("404"));
+        assert_eq!(value(TopKey::Proto).as_deref(), Some("HTTP/2.0"));
+        assert_eq!(value(TopKey::UserAgent).as_deref(), Some("curl/8.4.0"));
+        assert_eq!(value(TopKey::Upstream), None);
+        assert_eq!(TopKey::UserAgent.name(), "user_agent");
+    }
+
+    #[test]
+    fn count_the_values() {
+        let mut counts = KeyCounts::new(TopKey::Path, 1 << 20);
+        for (uri, status) in [("/a", 200), ("/b?x", 200), ("/a?y", 404), ("/a", 200)] {
+            counts.observe(&record(uri, status));
+        }
+        assert_eq!(
+            counts.to_json(1),
+            json!({
+                "key": "path",
+                "requests": 4,
+                "distinct": 2,
+                "max_missing": 0,
+                "values": [{ "value": "/a", "requests": 3, "error": 0, "share": 0.75 }],
+            })
+        );
+        assert_eq!(
+            counts.report(2, &Locale::C),
+            "path: 4 requests, ~2 distinct values, 2 counters in 196 B\n\
+             the counts are exact\n\
+             \n\
+             path  requests  error  share\n\
+             /a           3      -  75.0%\n\
+             /b           1      -  25.0%\n"
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
+use crate::{
+    locale::Locale,
+    record::LogRecord,
+    stats::{self, format_table},
+};
+
+/// The p95 of the duration of the busiest paths over time, to see whether an endpoint is
+/// degrading during the analyzed window.
+#[derive(Default)]
+pub struct PathTrends {
+    /// The timestamp and the duration of the requests of each path.
+    paths: HashMap<String, Vec<(f64, f64)>>,
+}
+
+impl PathTrends {
+    const TOP: usize = 10;
+    const BUCKETS: usize = 16;
+    /// The paths seen after this many are ignored, to bound the memory of the logs with
+    /// unique paths.
+    const MAX_PATHS: usize = 10_000;
+
+    pub fn observe(&mut self, record: &LogRecord) {
+        let (Some(request), Some(duration)) = (&record.request, record.duration) else {
+            return;
+        };
+        let path = request.uri.split('?').next().unwrap_or_default();
+        if !self.paths.contains_key(path) && self.paths.len() >= Self::MAX_PATHS {
+            return;
+        }
+        let samples = self.paths.entry(path.to_string()).or_default();
+        samples.push((record.timestamp, duration));
+    }
+
+    /// The busiest paths, with the p95 of their duration over the whole window and in each
+    /// bucket of it. Nothing is returned when no duration has been logged.
+    fn busiest(&self) -> Option<Vec<Trend<'_>>> {
+        let timestamps = self
+            .paths
+            .values()
+            .flatten()
+            .map(|(timestamp, _)| *timestamp);
+        let start = timestamps.clone().min_by(f64::total_cmp)?;
+        let end = timestamps.max_by(f64::total_cmp)?;
+        let width = (end - start) / Self::BUCKETS as f64;
+
+        let mut paths = self.paths.iter().collect::<Vec<_>>();
+        paths.sort_by(|(a_path, a), (b_path, b)| b.len().cmp(&a.len()).then(a_path.cmp(b_path)));
+        let trends = paths
+            .into_iter()
+            .take(Self::TOP)
+            .map(|(path, samples)| {
+                let mut buckets = vec![Vec::new(); Self::BUCKETS];
+                for &(timestamp, duration) in samples {
+                    let index = match width > 0.0 {
+                        true => ((timestamp - start) / width) as usize,
+                        false => 0,
+                    };
+                    buckets[index.min(Self::BUCKETS - 1)].push(duration);
+                }
+                let trend = buckets
+                    .iter_mut()
+                    .map(|durations| {
+                        durations.sort_by(f64::total_cmp);
+                        stats::percentile_of(durations, 95.0)
+                    })
+                    .collect::<Vec<_>>();
+                let mut durations = samples
+                    .iter()
+                    .map(|(_, duration)| *duration)
+                    .collect::<Vec<_>>();
+                durations.sort_by(f64::total_cmp);
+                Trend {
+                    path,
+                    count: samples.len() as u64,
+                    p95: stats::percentile_of(&durations, 95.0),
+                    trend,
+                }
+            })
+            .collect();
+        Some(trends)
+    }
+
+    /// The table of the busiest paths with their sparklines. Nothing is returned when no
+    /// duration has been logged.
+    pub fn render(&self, locale: &Locale) -> Option<String> {
+        let mut rows = vec![vec![
+            "path".to_string(),
+            "count".into(),
+            "p95".into(),
+            "p95_trend".into(),
+        ]];
+        for trend in self.busiest()? {
+            let p95 = trend
+                .p95
+                .map(|p95| locale.localize(&LogRecord::format_duration(p95)))
+                .unwrap_or_else(|| "-".into());
+            rows.push(vec![
+                trend.path.to_string(),
+                locale.integer(trend.count),
+                p95,
+                sparkline(&trend.trend),
+            ]);
+        }
+        Some(format_table(&rows))
+    }
+
+    /// The busiest paths with the p95 of each bucket, in seconds. The buckets without requests
+    /// are null.
+    pub fn to_json(&self) -> Value {
+        let trends = self.busiest().unwrap_or_default();
+        trends
+            .into_iter()
+            .map(|trend| {
+                json!({
+                    "path": trend.path,
+                    "count": trend.count,
+                    "p95": trend.p95,
+                    "p95_trend": trend.trend,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The duration of a path over time.
+struct Trend<'a> {
+    path: &'a str,
+    count: u64,
+    p95: Option<f64>,
+    /// The p95 of each bucket of the window, when it has requests.
+    trend: Vec<Option<f64>>,
+}
+
+/// Draw the values with block characters scaled between their minimum and their maximum, the
+/// missing values are blank.
+pub fn sparkline(values: &[Option<f64>]) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let present = values.iter().flatten();
+    let min = present.clone().copied().fold(f64::INFINITY, f64::min);
+    let max = present.copied().fold(f64::NEG_INFINITY, f64::max);
+    values
+        .iter()
+        .map(|value| match value {
+            None => ' ',
+            Some(_) if max <= min => BLOCKS[0],
+            Some(value) => {
+                let level = (value - min) / (max - min) * (BLOCKS.len() - 1) as f64;
+                BLOCKS[level.round() as usize]
+            }
+        })
+        .collect()
+}
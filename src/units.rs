@@ -0,0 +1,175 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+/// Parse a size in bytes with an optional unit suffix, e.g. `512`, `10kB`, `1.5MiB`.
+pub fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number = number
+        .parse::<f64>()
+        .with_context(|| format!("invalid size: {}", s))?;
+    let multiplier = match unit.trim() {
+        "" | "B" => 1.0,
+        "k" | "kB" | "KB" => 1e3,
+        "M" | "MB" => 1e6,
+        "G" | "GB" => 1e9,
+        "Ki" | "KiB" => 1024.0,
+        "Mi" | "MiB" => 1024.0 * 1024.0,
+        "Gi" | "GiB" => 1024.0 * 1024.0 * 1024.0,
+        unit => bail!("invalid size unit: {}", unit),
+    };
+    Ok((number * multiplier) as u64)
+}
+
+/// Format a number of bytes using decimal units.
+pub fn format_size(bytes: f64) -> String {
+    const UNITS: &[&str] = &["B", "kB", "MB", "GB", "TB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", value, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Parse a point in time, as an RFC 3339 timestamp (e.g. `2024-03-09T16:00:00Z`) or as a
+/// duration before now (e.g. `15m` or `2h`). The time is returned as seconds since the epoch.
+pub fn parse_time(s: &str, now: OffsetDateTime) -> Result<f64> {
+    let s = s.trim();
+    let time = match OffsetDateTime::parse(s, &Rfc3339) {
+        Ok(time) => time,
+        Err(_) => {
+            let ago = parse_duration(s).with_context(|| {
+                format!(
+                    "invalid time: {}, expected an RFC 3339 timestamp or a duration like 2h",
+                    s
+                )
+            })?;
+            let ago = time::Duration::try_from(ago).ok();
+            match ago.and_then(|ago| now.checked_sub(ago)) {
+                Some(time) => time,
+                None => bail!("invalid time: {}, the duration is too long", s),
+            }
+        }
+    };
+    Ok(time.unix_timestamp_nanos() as f64 / 1e9)
+}
+
+/// Parse a timestamp written by Caddy with the `iso8601` or the `rfc3339` time format, the
+/// offset can lack the colon, e.g. `2024-03-09T16:00:00.123+0100`. The time is returned as
+/// seconds since the epoch.
+pub fn parse_timestamp(s: &str) -> Option<f64> {
+    let time = OffsetDateTime::parse(s, &Rfc3339).ok().or_else(|| {
+        let (datetime, offset) = s.split_at(s.len().checked_sub(5)?);
+        let (sign, offset) = offset.split_at(1);
+        if !matches!(sign, "+" | "-") || !offset.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let s = format!("{datetime}{sign}{}:{}", &offset[..2], &offset[2..]);
+        OffsetDateTime::parse(&s, &Rfc3339).ok()
+    })?;
+    Some(time.unix_timestamp_nanos() as f64 / 1e9)
+}
+
+/// Parse a duration in the format of Go, written by Caddy with the `string` duration format,
+/// e.g. `1.5ms` or `1m2.5s`. The duration is returned in seconds.
+pub fn parse_go_duration(s: &str) -> Option<f64> {
+    if s == "0" {
+        return Some(0.0);
+    }
+    let (sign, mut rest) = match s.strip_prefix('-') {
+        Some(rest) => (-1.0, rest),
+        None => (1.0, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let mut seconds = 0.0;
+    while !rest.is_empty() {
+        let split = rest.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+        let (number, tail) = rest.split_at(split);
+        let number = number.parse::<f64>().ok()?;
+        let unit = tail
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit);
+        seconds += number
+            * match unit {
+                "ns" => 1e-9,
+                "us" | "µs" | "μs" => 1e-6,
+                "ms" => 1e-3,
+                "s" => 1.0,
+                "m" => 60.0,
+                "h" => 3600.0,
+                _ => return None,
+            };
+        rest = tail;
+    }
+    Some(sign * seconds)
+}
+
+/// Parse a duration with a unit suffix, e.g. `250us`, `500ms`, `2s`, `15m`, `1h`, `7d`.
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number = number
+        .parse::<f64>()
+        .with_context(|| format!("invalid duration: {}", s))?;
+    let seconds = match unit.trim() {
+        "us" | "µs" => number / 1_000_000.0,
+        "ms" => number / 1_000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3_600.0,
+        "d" => number * 86_400.0,
+        "" => bail!("missing unit in duration: {}", s),
+        unit => bail!("invalid duration unit: {}", unit),
+    };
+    Duration::try_from_secs_f64(seconds).with_context(|| format!("invalid duration: {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_times() {
+        let now = OffsetDateTime::from_unix_timestamp(1_710_000_000).unwrap();
+        assert_eq!(parse_time("2h", now).unwrap(), 1_710_000_000.0 - 7200.0);
+        assert_eq!(
+            parse_time("2024-03-09T16:00:00Z", now).unwrap(),
+            1_710_000_000.0
+        );
+        for time in ["10000000d", "1e18s", "2h ago", "yesterday"] {
+            assert!(parse_time(time, now).is_err(), "{time}");
+        }
+    }
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(parse_duration("250us").unwrap(), Duration::from_micros(250));
+        assert_eq!(parse_duration("1.5s").unwrap(), Duration::from_millis(1500));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert_eq!(
+            parse_duration("7d").unwrap(),
+            Duration::from_secs(7 * 86_400)
+        );
+    }
+
+    #[test]
+    fn reject_invalid_durations() {
+        for duration in ["", "5", "5x", "x5s", "100000000000000000000000s", "1e400s"] {
+            assert!(parse_duration(duration).is_err(), "{duration}");
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    exec, expr::Expr, rate::Rate, selector::Selector, sink::SinkSpec, syntax::SyntaxError,
+    FilterArgs,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct ValidateArgs {
+    /// A condition, as given to `--exec-when`, `--notify-on` and `--filter`. This flag can be
+    /// repeated.
+    #[arg(long = "expr", value_name = "EXPR")]
+    exprs: Vec<String>,
+
+    /// A jq-like path, as given to `--extract`. This flag can be repeated.
+    #[arg(long = "extract", value_name = "PATH")]
+    selectors: Vec<String>,
+
+    /// A command, as given to `--exec`, its placeholders are checked. This flag can be repeated.
+    #[arg(long = "exec", value_name = "COMMAND")]
+    commands: Vec<String>,
+
+    /// A rate, as given to `--exec-rate` and `--notify-rate`. This flag can be repeated.
+    #[arg(long = "rate", value_name = "RATE")]
+    rates: Vec<String>,
+
+    /// A sink, as given to `--sink`. This flag can be repeated.
+    #[arg(long = "sink", value_name = "OUTPUT[:PATH]")]
+    sinks: Vec<String>,
+
+    #[command(flatten)]
+    filters: FilterArgs,
+}
+
+/// Check the definitions without reading any input, the errors are reported with their
+/// position.
+pub fn run(args: ValidateArgs) -> Result<()> {
+    let mut checks = Vec::<(&str, &str, Result<()>)>::new();
+    for expr in &args.exprs {
+        checks.push(("expression", expr, expr.parse::<Expr>().map(drop)));
+    }
+    for selector in &args.selectors {
+        checks.push(("path", selector, selector.parse::<Selector>().map(drop)));
+    }
+    for command in &args.commands {
+        checks.push(("command", command, exec::check_command(command)));
+    }
+    for rate in &args.rates {
+        checks.push(("rate", rate, rate.parse::<Rate>().map(drop)));
+    }
+    for sink in &args.sinks {
+        checks.push(("sink", sink, sink.parse::<SinkSpec>().map(drop)));
+    }
+    let filters = args.filters.build().context("invalid filters").map(drop);
+    checks.push(("filters", "", filters));
+
+    let mut failed = 0;
+    for (kind, definition, result) in checks {
+        match result {
+            Ok(()) if definition.is_empty() => println!("ok     {kind}"),
+            Ok(()) => println!("ok     {kind} `{definition}`"),
+            Err(err) => {
+                failed += 1;
+                println!("error  {kind}: {err:#}");
+                if let Some(err) = err
+                    .chain()
+                    .find_map(|err| err.downcast_ref::<SyntaxError>())
+                {
+                    for line in err.render().lines() {
+                        println!("         {line}");
+                    }
+                }
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("{} invalid definitions", failed);
+    }
+    Ok(())
+}